@@ -0,0 +1,9 @@
+# Contains the runtime dependencies and the delve debugger
+let
+  pkgs = import ./nixpkgs.nix {};
+in
+pkgs.stdenv.mkDerivation {
+  buildInputs = import ./deps.nix ++ [ pkgs.delve ];
+  LANG = "en_US.UTF-8";
+  name = "kubernix-debug-shell";
+}
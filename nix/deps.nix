@@ -1,7 +1,8 @@
 let
-  pkgs = import ./nixpkgs.nix { overlays = [(import ./overlay.nix)]; };
+  pkgs = import ./nixpkgs.nix { overlays = [(import ./overlay.nix) (import ./overrides.nix)]; };
   deps = with pkgs; [
     bash
+    buildah
     cacert
     cfssl
     cni-plugins
@@ -15,6 +16,7 @@ let
     iptables
     kmod
     kubernetes
+    openssl
     runc
     socat
     sysctl
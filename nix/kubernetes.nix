@@ -0,0 +1,11 @@
+{ version, sha256 }:
+let
+  pkgs = import ./nixpkgs.nix { overlays = [(import ./overlay.nix)]; };
+in
+pkgs.kubernetes.overrideAttrs (old: {
+  inherit version;
+  src = builtins.fetchTarball {
+    url = "https://github.com/kubernetes/kubernetes/archive/v${version}.tar.gz";
+    inherit sha256;
+  };
+})
@@ -0,0 +1,13 @@
+self: super:
+let
+  # Resolve a package like `nix build REF#ATTR` would do
+  package = ref: attr:
+    let
+      flake = builtins.getFlake ref;
+      system = builtins.currentSystem;
+    in
+    flake.packages.${system}.${attr} or flake.legacyPackages.${system}.${attr};
+in
+{
+  /* OVERRIDES */
+}
@@ -0,0 +1,189 @@
+use crate::Fallible;
+use getset::Getters;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{self, copy},
+    net::{Ipv4Addr, Shutdown, TcpListener, TcpStream},
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
+};
+
+#[derive(Clone, Copy, Debug, Getters, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The ports of a lazily started component
+pub struct Activation {
+    #[get = "pub"]
+    /// The address kubernix listens on, which defaults to the loopback
+    /// address
+    listen_address: Option<Ipv4Addr>,
+
+    #[get = "pub"]
+    /// The port kubernix listens on until the first connection arrives
+    listen_port: u16,
+
+    #[get = "pub"]
+    /// The local port the component itself listens on
+    target_port: u16,
+}
+
+impl Activation {
+    /// Create a new activation listening on the loopback address
+    pub fn new(listen_port: u16, target_port: u16) -> Self {
+        Self {
+            listen_address: None,
+            listen_port,
+            target_port,
+        }
+    }
+}
+
+/// A listening socket which stands in for a component until it gets started
+pub struct Stub {
+    name: String,
+    listener: TcpListener,
+    target_port: u16,
+}
+
+impl Stub {
+    /// The maximum time to wait for the started component to accept
+    /// connections
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Listen on behalf of the provided component, whereas only local
+    /// connections are accepted if no listen address is configured
+    pub fn bind(name: &str, activation: Activation) -> Fallible<Stub> {
+        let address = activation.listen_address().unwrap_or(Ipv4Addr::LOCALHOST);
+        let listener = TcpListener::bind((address, *activation.listen_port())).map_err(|e| {
+            format_err!(
+                "Unable to listen on {}:{} for component '{}': {}",
+                address,
+                *activation.listen_port(),
+                name,
+                e
+            )
+        })?;
+        listener.set_nonblocking(true)?;
+        info!(
+            "Component '{}' gets started on the first connection to {}:{}",
+            name,
+            address,
+            *activation.listen_port()
+        );
+        Ok(Stub {
+            name: name.into(),
+            listener,
+            target_port: *activation.target_port(),
+        })
+    }
+
+    /// The name of the represented component
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Retrieve a pending connection without blocking
+    pub fn accept(&self) -> Fallible<Option<TcpStream>> {
+        match self.listener.accept() {
+            Ok((stream, addr)) => {
+                debug!("Connection from {} for component '{}'", addr, self.name);
+                Ok(Some(stream))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Forward the first and all further connections to the started
+    /// component in the background
+    pub fn serve(self, first: TcpStream) -> Fallible<()> {
+        self.listener.set_nonblocking(false)?;
+        let target = self.target_port;
+        Self::forward(first, target);
+        spawn(move || {
+            for stream in self.listener.incoming() {
+                match stream {
+                    Ok(x) => Self::forward(x, target),
+                    Err(e) => debug!("Unable to accept connection: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Proxy the client connection to the target port
+    fn forward(client: TcpStream, target: u16) {
+        spawn(move || {
+            let server = match Self::connect(target) {
+                Ok(x) => x,
+                Err(e) => {
+                    debug!("Unable to connect to port {}: {}", target, e);
+                    return;
+                }
+            };
+            let (mut client_read, mut server_write) = match (client.try_clone(), server.try_clone())
+            {
+                (Ok(c), Ok(s)) => (c, s),
+                _ => return,
+            };
+            let (mut server_read, mut client_write) = (server, client);
+            let upstream = spawn(move || {
+                copy(&mut client_read, &mut server_write).ok();
+                server_write.shutdown(Shutdown::Write).ok();
+            });
+            copy(&mut server_read, &mut client_write).ok();
+            client_write.shutdown(Shutdown::Write).ok();
+            upstream.join().ok();
+        });
+    }
+
+    /// Connect to the target port, which may need some time to be opened
+    fn connect(target: u16) -> io::Result<TcpStream> {
+        let now = Instant::now();
+        loop {
+            match TcpStream::connect((Ipv4Addr::LOCALHOST, target)) {
+                Ok(x) => return Ok(x),
+                Err(e) if now.elapsed() >= Self::CONNECT_TIMEOUT => return Err(e),
+                Err(_) => sleep(Duration::from_millis(200)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn serve_success() -> Fallible<()> {
+        let target = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let target_port = target.local_addr()?.port();
+        spawn(move || {
+            if let Ok((mut stream, _)) = target.accept() {
+                stream.write_all(b"pong").ok();
+            }
+        });
+
+        let stub = Stub::bind("test", Activation::new(0, target_port))?;
+        let address = stub.listener.local_addr()?;
+        assert!(address.ip().is_loopback());
+        let port = address.port();
+        let mut client = TcpStream::connect((Ipv4Addr::LOCALHOST, port))?;
+
+        let now = Instant::now();
+        let first = loop {
+            if let Some(x) = stub.accept()? {
+                break x;
+            }
+            assert!(now.elapsed() < Duration::from_secs(5));
+            sleep(Duration::from_millis(10));
+        };
+        stub.serve(first)?;
+
+        let mut response = String::new();
+        client.read_to_string(&mut response)?;
+        assert_eq!(response, "pong");
+        Ok(())
+    }
+}
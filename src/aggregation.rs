@@ -0,0 +1,230 @@
+use crate::{
+    config::RegisterApiService, frontproxy::FrontProxy, kubeconfig::KubeConfig, pki::Pki,
+    process::Process, system::System, Config, Fallible,
+};
+use base64::encode;
+use log::info;
+use serde_json::{json, to_string_pretty, Value};
+use std::{fs, path::Path};
+
+/// The aggregation layer of the API Server, which proxies API groups to
+/// extension API servers
+pub struct Aggregation;
+
+impl Aggregation {
+    /// The namespace of the services pointing to extension API servers
+    const NAMESPACE: &'static str = "kube-system";
+
+    /// The port of the services, which gets forwarded to the local port of the
+    /// extension API server
+    const SERVICE_PORT: u16 = 443;
+
+    /// Retrieve the API Server arguments to enable the aggregation layer and
+    /// the request header authentication of forwarded requests
+    pub fn apiserver_args(config: &Config, pki: &Pki) -> Vec<String> {
+        let mut allowed_names = vec![Pki::FRONT_PROXY_CLIENT];
+        if *config.api_record() {
+            allowed_names.push(FrontProxy::CLIENT_NAME);
+        }
+        vec![
+            format!(
+                "--requestheader-client-ca-file={}",
+                pki.ca().cert().display()
+            ),
+            format!("--requestheader-allowed-names={}", allowed_names.join(",")),
+            "--requestheader-username-headers=X-Remote-User".into(),
+            "--requestheader-group-headers=X-Remote-Group".into(),
+            "--requestheader-extra-headers-prefix=X-Remote-Extra-".into(),
+            format!(
+                "--proxy-client-cert-file={}",
+                pki.front_proxy_client().cert().display()
+            ),
+            format!(
+                "--proxy-client-key-file={}",
+                pki.front_proxy_client().key().display()
+            ),
+            // Extension API servers on the host are not reachable via the
+            // service network from the API Server
+            "--enable-aggregator-routing=true".into(),
+        ]
+    }
+
+    /// Register or remove the API service of an extension API server running
+    /// on the local host
+    pub fn register(config: &Config, args: &RegisterApiService) -> Fallible<()> {
+        let (version, group) = Self::split(args.apiservice())?;
+        let service = Self::service_name(group);
+        let admin = KubeConfig::admin_path(config);
+
+        if *args.delete() {
+            info!("Removing API service {}", args.apiservice());
+            return Self::kubectl(
+                &admin,
+                &[
+                    "delete",
+                    "--ignore-not-found",
+                    &format!("apiservice/{}", args.apiservice()),
+                    &format!("--namespace={}", Self::NAMESPACE),
+                    &format!("service/{}", service),
+                    &format!("endpoints/{}", service),
+                ],
+                None,
+            );
+        }
+
+        let ca_bundle = match args.ca_bundle() {
+            Some(x) => Some(encode(&fs::read(x).map_err(|e| {
+                format_err!("Unable to read CA bundle '{}': {}", x.display(), e)
+            })?)),
+            None => None,
+        };
+
+        // Endpoints must not use loopback addresses, which is why the
+        // extension API server has to listen on the host IP as well
+        let ip = System::new().ip()?;
+        let manifest = Self::manifest(args, version, group, &service, &ip, ca_bundle);
+        info!(
+            "Registering API service {} for {}:{}",
+            args.apiservice(),
+            ip,
+            args.port()
+        );
+        Self::kubectl(
+            &admin,
+            &["apply", "-f", "-"],
+            Some(&to_string_pretty(&manifest)?),
+        )
+    }
+
+    /// Split the provided API service name into its version and group
+    fn split(apiservice: &str) -> Fallible<(&str, &str)> {
+        let mut split = apiservice.splitn(2, '.');
+        match (split.next(), split.next()) {
+            (Some(version), Some(group)) if !version.is_empty() && !group.is_empty() => {
+                Ok((version, group))
+            }
+            _ => bail!(
+                "Invalid API service '{}', expected 'VERSION.GROUP' like \
+                 'v1alpha1.metrics.example.com'",
+                apiservice
+            ),
+        }
+    }
+
+    /// Retrieve the name of the service pointing to the extension API server
+    /// of the provided group
+    fn service_name(group: &str) -> String {
+        let name = format!("apiservice-{}", group.replace('.', "-"));
+        name.chars()
+            .take(63)
+            .collect::<String>()
+            .trim_end_matches('-')
+            .into()
+    }
+
+    /// Render the service, endpoints and API service of the extension API
+    /// server
+    fn manifest(
+        args: &RegisterApiService,
+        version: &str,
+        group: &str,
+        service: &str,
+        ip: &str,
+        ca_bundle: Option<String>,
+    ) -> Value {
+        let mut apiservice = json!({
+          "apiVersion": "apiregistration.k8s.io/v1",
+          "kind": "APIService",
+          "metadata": { "name": args.apiservice() },
+          "spec": {
+            "group": group,
+            "version": version,
+            "groupPriorityMinimum": args.group_priority_minimum(),
+            "versionPriority": args.version_priority(),
+            "service": {
+              "namespace": Self::NAMESPACE,
+              "name": service,
+              "port": Self::SERVICE_PORT
+            }
+          }
+        });
+        match ca_bundle {
+            Some(x) => apiservice["spec"]["caBundle"] = json!(x),
+            None => apiservice["spec"]["insecureSkipTLSVerify"] = json!(true),
+        }
+
+        json!({
+          "apiVersion": "v1",
+          "kind": "List",
+          "items": [
+            {
+              "apiVersion": "v1",
+              "kind": "Service",
+              "metadata": { "namespace": Self::NAMESPACE, "name": service },
+              "spec": {
+                "ports": [{
+                  "protocol": "TCP",
+                  "port": Self::SERVICE_PORT,
+                  "targetPort": args.port()
+                }]
+              }
+            },
+            {
+              "apiVersion": "v1",
+              "kind": "Endpoints",
+              "metadata": { "namespace": Self::NAMESPACE, "name": service },
+              "subsets": [{
+                "addresses": [{ "ip": ip }],
+                "ports": [{ "protocol": "TCP", "port": args.port() }]
+              }]
+            },
+            apiservice
+          ]
+        })
+    }
+
+    /// Run kubectl with the admin credentials, optionally providing the
+    /// input via stdin
+    fn kubectl(admin: &Path, args: &[&str], input: Option<&str>) -> Fallible<()> {
+        let mut kubectl = Process::run_to_completion("kubectl")
+            .arg(format!("--kubeconfig={}", admin.display()))
+            .args(args);
+        if let Some(input) = input {
+            kubectl = kubectl.input(input);
+        }
+        kubectl.run()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_success() -> Fallible<()> {
+        assert_eq!(
+            Aggregation::split("v1alpha1.metrics.example.com")?,
+            ("v1alpha1", "metrics.example.com")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn split_failure() {
+        assert!(Aggregation::split("v1alpha1").is_err());
+        assert!(Aggregation::split("v1.").is_err());
+        assert!(Aggregation::split(".metrics.example.com").is_err());
+    }
+
+    #[test]
+    fn service_name_success() {
+        assert_eq!(
+            Aggregation::service_name("metrics.example.com"),
+            "apiservice-metrics-example-com"
+        );
+        let name = Aggregation::service_name(&format!("{}.example.com", "a".repeat(60)));
+        assert_eq!(name.len(), 63);
+        assert!(!name.ends_with('-'));
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{Config, Fallible};
+use log::{debug, error, info};
+use std::{
+    fs::{remove_file, OpenOptions},
+    io::Write,
+    os::unix::net::UnixDatagram,
+    path::{Path, PathBuf},
+    thread::spawn,
+};
+
+/// The recording of all REST calls which pass the API Server front proxy
+pub struct ApiRecord;
+
+impl ApiRecord {
+    /// The file name of the recording within the root directory
+    const FILENAME: &'static str = "api-record.log";
+
+    /// The HAProxy log format, which results in a single JSON record per
+    /// request
+    pub const LOG_FORMAT: &'static str = "{\"time\":\"%tr\",\"user\":\"%[ssl_c_s_dn(CN)]\",\
+        \"method\":\"%HM\",\"path\":\"%HU\",\"status\":%ST,\"request_bytes\":%U,\
+        \"response_bytes\":%B,\"latency_ms\":%Ta}";
+
+    /// The path of the recording
+    pub fn file(config: &Config) -> PathBuf {
+        config.root().join(Self::FILENAME)
+    }
+
+    /// Receive the log records of the front proxy on the provided socket in
+    /// the background and append them to the recording
+    pub fn listen(config: &Config, socket: &Path) -> Fallible<()> {
+        if socket.exists() {
+            remove_file(socket)?;
+        }
+        let receiver = UnixDatagram::bind(socket)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Self::file(config))?;
+        info!(
+            "Recording {}% of the API requests to '{}'",
+            config.api_record_sample(),
+            Self::file(config).display()
+        );
+
+        spawn(move || {
+            let mut buf = vec![0; 64 * 1024];
+            loop {
+                let len = match receiver.recv(&mut buf) {
+                    Ok(x) => x,
+                    Err(e) => {
+                        error!("Unable to receive API record: {}", e);
+                        return;
+                    }
+                };
+                let record = String::from_utf8_lossy(&buf[..len]);
+                if let Err(e) = writeln!(file, "{}", record.trim_end()) {
+                    debug!("Unable to write API record: {}", e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Verify the sampling percentage
+    pub fn validate(config: &Config) -> Fallible<()> {
+        let sample = *config.api_record_sample();
+        if sample == 0 || sample > 100 {
+            bail!(
+                "API record sample has to be between 1 and 100 percent, not {}",
+                sample
+            )
+        }
+        Ok(())
+    }
+}
@@ -1,22 +1,27 @@
 use crate::{
+    aggregation::Aggregation,
+    component::ComponentKind,
     config::Config,
     encryptionconfig::EncryptionConfig,
+    etcd::Etcd,
+    frontproxy::FrontProxy,
     kubeconfig::KubeConfig,
     network::Network,
     pki::Pki,
     process::{Process, Startable, Stoppable},
+    profile::Preset,
+    verbosity::Verbosity,
+    Fallible,
 };
-use failure::{bail, Fallible};
 use log::{debug, info};
 use std::{
     fs::{self, create_dir_all},
-    net::Ipv4Addr,
     path::Path,
-    process::Command,
 };
 
 pub struct ApiServer {
-    process: Process,
+    replicas: Vec<Process>,
+    front_proxy: Option<Process>,
 }
 
 impl ApiServer {
@@ -33,90 +38,254 @@ impl ApiServer {
         let dir = config.root().join("apiserver");
         create_dir_all(&dir)?;
 
-        let mut process = Process::start(
-            config,
-            &dir,
-            "kube-apiserver",
-            &[
-                &format!("--advertise-address={}", ip),
-                "--allow-privileged=true",
-                "--audit-log-maxage=30",
-                "--audit-log-maxbackup=3",
-                "--audit-log-maxsize=100",
-                &format!("--audit-log-path={}", dir.join("audit.log").display()),
-                "--authorization-mode=Node,RBAC",
-                "--bind-address=0.0.0.0",
-                &format!("--client-ca-file={}", pki.ca().cert().display()),
-                &format!("--etcd-cafile={}", pki.ca().cert().display()),
-                &format!("--etcd-certfile={}", pki.apiserver().cert().display()),
-                &format!("--etcd-keyfile={}", pki.apiserver().key().display()),
-                &format!(
-                    "--etcd-servers=https://{}:2379",
-                    Ipv4Addr::LOCALHOST.to_string(),
-                ),
-                "--event-ttl=1h",
-                &format!(
-                    "--encryption-provider-config={}",
-                    encryptionconfig.path().display()
-                ),
-                &format!(
-                    "--kubelet-certificate-authority={}",
-                    pki.ca().cert().display()
-                ),
-                &format!(
-                    "--kubelet-client-certificate={}",
-                    pki.apiserver().cert().display()
-                ),
-                &format!("--kubelet-client-key={}", pki.apiserver().key().display()),
-                "--kubelet-https=true",
-                "--runtime-config=api/all",
-                &format!(
-                    "--service-account-key-file={}",
-                    pki.service_account().cert().display()
+        let replicas = *config.apiserver_replicas();
+        if replicas == 0 {
+            bail!("At least one API Server replica is required");
+        }
+
+        // The front proxy takes over the public port, whereas the replicas use
+        // the subsequent ports of the backend one
+        let (ports, mut front_proxy) = if FrontProxy::enabled(config) {
+            let ports: Vec<u16> = (0..u16::from(replicas))
+                .map(|x| config.ports().apiserver_backend() + x)
+                .collect();
+            let front_proxy = FrontProxy::start(config, &dir, pki, &ports)?;
+            (ports, Some(front_proxy))
+        } else {
+            (vec![*config.ports().apiserver()], None)
+        };
+
+        let mut args = vec![
+            format!("--advertise-address={}", ip),
+            "--allow-privileged=true".into(),
+            "--audit-log-maxage=30".into(),
+            "--audit-log-maxbackup=3".into(),
+            "--audit-log-maxsize=100".into(),
+            "--authorization-mode=Node,RBAC".into(),
+            "--bind-address=0.0.0.0".into(),
+            format!("--client-ca-file={}", pki.ca().cert().display()),
+            format!("--etcd-cafile={}", pki.ca().cert().display()),
+            format!("--etcd-certfile={}", pki.apiserver().cert().display()),
+            format!("--etcd-keyfile={}", pki.apiserver().key().display()),
+            format!("--etcd-servers={}", Etcd::client_url(config)),
+            "--event-ttl=1h".into(),
+            format!(
+                "--encryption-provider-config={}",
+                encryptionconfig.path().display()
+            ),
+            format!(
+                "--kubelet-certificate-authority={}",
+                pki.ca().cert().display()
+            ),
+            format!(
+                "--kubelet-client-certificate={}",
+                pki.apiserver().cert().display()
+            ),
+            format!("--kubelet-client-key={}", pki.apiserver().key().display()),
+            "--kubelet-https=true".into(),
+            "--kubelet-preferred-address-types=InternalIP,Hostname,ExternalIP".into(),
+            "--runtime-config=api/all".into(),
+            format!(
+                "--service-account-key-file={}",
+                pki.service_account().cert().display()
+            ),
+            format!(
+                "--service-account-signing-key-file={}",
+                pki.service_account().key().display()
+            ),
+            format!(
+                "--service-account-issuer={}",
+                config.service_account_issuer()
+            ),
+            format!("--api-audiences={}", Self::api_audiences(config)),
+            format!("--service-cluster-ip-range={}", network.service()),
+            format!("--tls-cert-file={}", pki.apiserver().cert().display()),
+            format!("--tls-private-key-file={}", pki.apiserver().key().display()),
+        ];
+        args.extend(Verbosity::args(config, ComponentKind::ApiServer));
+        if *config.cloud_provider_external() {
+            args.push("--cloud-provider=external".into());
+        }
+        if !config.enable_admission_plugins().is_empty() {
+            args.push(format!(
+                "--enable-admission-plugins={}",
+                config.enable_admission_plugins().join(",")
+            ));
+        }
+        if !config.disable_admission_plugins().is_empty() {
+            args.push(format!(
+                "--disable-admission-plugins={}",
+                config.disable_admission_plugins().join(",")
+            ));
+        }
+        if let Some(admission_config) = config.admission_config() {
+            // Render the configuration into the run root to keep it reproducible
+            let target = dir.join("admission.yml");
+            debug!(
+                "Using admission configuration '{}'",
+                admission_config.display()
+            );
+            fs::copy(admission_config, &target).map_err(|e| {
+                format_err!(
+                    "Unable to copy admission configuration '{}': {}",
+                    admission_config.display(),
+                    e
+                )
+            })?;
+            args.push(format!(
+                "--admission-control-config-file={}",
+                target.display()
+            ));
+        }
+
+        args.extend(Aggregation::apiserver_args(config, pki));
+        args.push(format!("--anonymous-auth={}", config.anonymous_auth()));
+        args.push(format!("--profiling={}", config.profiling()));
+        args.extend(Preset::for_config(config).args(ComponentKind::ApiServer));
+        if let Some(x) = config.max_requests_inflight() {
+            args.push(format!("--max-requests-inflight={}", x));
+        }
+        if let Some(x) = config.max_mutating_requests_inflight() {
+            args.push(format!("--max-mutating-requests-inflight={}", x));
+        }
+        if *config.priority_and_fairness() {
+            args.push("--feature-gates=APIPriorityAndFairness=true".into());
+            args.push("--enable-priority-and-fairness=true".into());
+        }
+
+        let mut processes = vec![];
+        for (replica, port) in ports.iter().enumerate() {
+            let (replica_dir, name) = if replica == 0 {
+                (dir.clone(), "kube-apiserver".to_owned())
+            } else {
+                (
+                    dir.join(format!("replica-{}", replica)),
+                    format!("kube-apiserver-{}", replica),
+                )
+            };
+
+            // The policy gets referenced relative to the working directory
+            create_dir_all(&replica_dir)?;
+            fs::write(
+                replica_dir.join(Preset::AUDIT_POLICY),
+                include_str!("assets/audit-policy.yml"),
+            )?;
+            let mut replica_args = vec![
+                format!(
+                    "--audit-log-path={}",
+                    replica_dir.join("audit.log").display()
                 ),
-                &format!("--service-cluster-ip-range={}", network.service()),
-                &format!("--tls-cert-file={}", pki.apiserver().cert().display()),
-                &format!("--tls-private-key-file={}", pki.apiserver().key().display()),
-                "--v=2",
-            ],
-        )?;
-
-        process.wait_ready("etcd ok")?;
-        Self::setup_rbac(&dir, kubeconfig.admin())?;
+                format!("--secure-port={}", port),
+            ];
+            // Only the first replica serves the insecure port on localhost
+            if replica == 0 && *config.insecure_port() {
+                replica_args.push(format!(
+                    "--insecure-port={}",
+                    config.ports().apiserver_insecure()
+                ));
+            } else {
+                replica_args.push("--insecure-port=0".into());
+            }
+            if replica > 0 {
+                // The kubernetes service endpoint is owned by the first replica,
+                // since all of them share the same address
+                replica_args.push("--endpoint-reconciler-type=none".into());
+            }
+
+            match Process::builder(config, &replica_dir, "kube-apiserver")
+                .name(&name)
+                .args(&args)
+                .args(&replica_args)
+                .depends_on(ComponentKind::Etcd)
+                .ready_on("etcd ok")
+                .defer_ready()
+                .spawn()
+            {
+                Ok(x) => processes.push(x),
+                Err(e) => {
+                    // Do not leave the already started processes behind
+                    for mut x in processes.into_iter().rev().chain(front_proxy.take()) {
+                        x.stop()?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        // Dependent components must not start before the RBAC rules exist
+        let rbac = Self::setup_rbac(&dir, kubeconfig.admin())
+            .and_then(|_| Self::setup_rbac_extra(config, kubeconfig.admin()))
+            .and_then(|_| processes.iter().try_for_each(Process::mark_ready));
+        if let Err(e) = rbac {
+            for mut x in processes.into_iter().rev().chain(front_proxy.take()) {
+                x.stop()?;
+            }
+            return Err(e);
+        }
         info!("API Server is ready");
-        Ok(Box::new(ApiServer { process }))
+        Ok(Box::new(ApiServer {
+            replicas: processes,
+            front_proxy,
+        }))
+    }
+
+    /// Retrieve the API audiences, which default to the service account issuer
+    fn api_audiences(config: &Config) -> String {
+        if config.api_audiences().is_empty() {
+            config.service_account_issuer().to_owned()
+        } else {
+            config.api_audiences().join(",")
+        }
     }
 
     fn setup_rbac(dir: &Path, admin_config: &Path) -> Fallible<()> {
         debug!("Creating API Server RBAC rule for kubelet");
         let yml_file = dir.join("rbac.yml");
         fs::write(&yml_file, include_str!("assets/apiserver.yml"))?;
+        Self::apply(&yml_file, admin_config)?;
+        debug!("API Server RBAC rule created");
+        Ok(())
+    }
 
-        let output = Command::new("kubectl")
-            .arg("apply")
-            .arg(format!("--kubeconfig={}", admin_config.display()))
-            .arg("-f")
-            .arg(yml_file)
-            .output()?;
-        if !output.status.success() {
-            debug!(
-                "kubectl apply stdout: {}",
-                String::from_utf8(output.stdout)?
+    /// Apply additional RBAC rules before any other component needs them
+    fn setup_rbac_extra(config: &Config, admin_config: &Path) -> Fallible<()> {
+        if let Some(rbac_extra) = config.rbac_extra() {
+            info!(
+                "Applying additional RBAC rules from '{}'",
+                rbac_extra.display()
             );
-            debug!(
-                "kubectl apply stderr: {}",
-                String::from_utf8(output.stderr)?
-            );
-            bail!("kubectl apply command failed");
+            if !rbac_extra.is_dir() {
+                bail!(
+                    "Additional RBAC rules path '{}' is not a directory",
+                    rbac_extra.display()
+                )
+            }
+            Self::apply(rbac_extra, admin_config)?;
+            debug!("Additional RBAC rules applied");
         }
+        Ok(())
+    }
 
-        debug!("API Server RBAC rule created");
+    /// Apply a manifest file or directory via kubectl
+    fn apply(path: &Path, admin_config: &Path) -> Fallible<()> {
+        Process::run_to_completion("kubectl")
+            .arg("apply")
+            .arg(format!("--kubeconfig={}", admin_config.display()))
+            .arg("-f")
+            .arg(path)
+            .retries(3)
+            .run()?;
         Ok(())
     }
 }
 
 impl Stoppable for ApiServer {
     fn stop(&mut self) -> Fallible<()> {
-        self.process.stop()
+        Process::stop_all(
+            self.replicas
+                .iter_mut()
+                .rev()
+                .chain(self.front_proxy.as_mut()),
+        )
     }
 }
@@ -0,0 +1,344 @@
+use crate::{
+    config::Bench as BenchArgs,
+    etcd::Etcd,
+    pki::{Pair, Pki},
+    process::Process,
+    Config, Error, Fallible,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    fmt::{self, Display},
+    fs::{self, create_dir_all},
+    net::Ipv4Addr,
+    str::FromStr,
+    time::Duration,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The object kinds which can be benchmarked
+pub enum BenchKind {
+    /// ConfigMaps containing a single key
+    ConfigMap,
+
+    /// Secrets containing a single key
+    Secret,
+
+    /// ServiceAccounts, which also trigger the token controller
+    ServiceAccount,
+}
+
+impl Display for BenchKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            BenchKind::ConfigMap => "configmap",
+            BenchKind::Secret => "secret",
+            BenchKind::ServiceAccount => "serviceaccount",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for BenchKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "configmap" => Ok(BenchKind::ConfigMap),
+            "secret" => Ok(BenchKind::Secret),
+            "serviceaccount" => Ok(BenchKind::ServiceAccount),
+            _ => Err(format_err!("Unsupported benchmark kind '{}'", s)),
+        }
+    }
+}
+
+impl BenchKind {
+    /// The API resource of the kind
+    fn resource(self) -> &'static str {
+        match self {
+            BenchKind::ConfigMap => "configmaps",
+            BenchKind::Secret => "secrets",
+            BenchKind::ServiceAccount => "serviceaccounts",
+        }
+    }
+
+    /// Create a new object of the kind with the provided name
+    fn object(self, name: &str) -> String {
+        let metadata = json!({ "name": name });
+        match self {
+            BenchKind::ConfigMap => json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": metadata,
+                "data": { "key": "value" },
+            }),
+            BenchKind::Secret => json!({
+                "apiVersion": "v1",
+                "kind": "Secret",
+                "metadata": metadata,
+                "stringData": { "key": "value" },
+            }),
+            BenchKind::ServiceAccount => json!({
+                "apiVersion": "v1",
+                "kind": "ServiceAccount",
+                "metadata": metadata,
+            }),
+        }
+        .to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The operations executed for every object
+enum Operation {
+    Create,
+    Patch,
+    Delete,
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Operation::Create => "create",
+            Operation::Patch => "patch",
+            Operation::Delete => "delete",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A single API request
+struct Request {
+    method: &'static str,
+    path: String,
+    content_type: &'static str,
+    data: Option<String>,
+}
+
+/// The control plane benchmark
+pub struct Bench<'a> {
+    config: &'a Config,
+    ca: Pair,
+    admin: Pair,
+}
+
+impl<'a> Bench<'a> {
+    /// The percentiles to be reported
+    const PERCENTILES: [u8; 3] = [50, 90, 99];
+
+    /// Run the benchmark with the provided arguments
+    pub fn run(config: &Config, args: &BenchArgs) -> Fallible<()> {
+        let dir = config.root().join(Pki::DIR);
+        let bench = Bench {
+            config,
+            ca: Pair::new(&dir, "ca"),
+            admin: Pair::new(&dir, "admin"),
+        };
+        for file in &[bench.ca.cert(), bench.admin.cert(), bench.admin.key()] {
+            if !file.exists() {
+                bail!(
+                    "Credential '{}' does not exist, please bootstrap the cluster first",
+                    file.display()
+                )
+            }
+        }
+
+        let kinds = if args.kinds().is_empty() {
+            vec![BenchKind::ConfigMap]
+        } else {
+            args.kinds().clone()
+        };
+        let namespace = args.namespace();
+        info!(
+            "Benchmarking {} objects per kind in namespace '{}'",
+            args.count(),
+            namespace
+        );
+
+        // The namespace may already exist from a previous run
+        bench.execute(&[Request {
+            method: "POST",
+            path: "/api/v1/namespaces".into(),
+            content_type: "application/json",
+            data: Some(json!({ "metadata": { "name": namespace } }).to_string()),
+        }])?;
+
+        let size = Etcd::data_size(config)?;
+        for kind in kinds {
+            for operation in &[Operation::Create, Operation::Patch, Operation::Delete] {
+                let requests: Vec<Request> = (0..*args.count())
+                    .map(|i| Self::request(kind, *operation, namespace, i))
+                    .collect();
+                let results = bench.execute(&requests)?;
+                let failed = results.iter().filter(|(code, _)| !Self::success(*code));
+                if let Some((code, _)) = failed.clone().next() {
+                    bail!(
+                        "{} of {} {} requests failed, for example with HTTP status {}",
+                        failed.count(),
+                        requests.len(),
+                        operation,
+                        code
+                    );
+                }
+                let mut latencies: Vec<Duration> = results.into_iter().map(|(_, x)| x).collect();
+                info!("{} {}: {}", kind, operation, Self::report(&mut latencies));
+            }
+        }
+        let growth = Etcd::data_size(config)?.saturating_sub(size);
+        info!("etcd grew by {} KiB", growth / 1024);
+
+        bench.execute(&[Request {
+            method: "DELETE",
+            path: format!("/api/v1/namespaces/{}", namespace),
+            content_type: "application/json",
+            data: None,
+        }])?;
+        Ok(())
+    }
+
+    /// Build the request for a single object
+    fn request(kind: BenchKind, operation: Operation, namespace: &str, index: u32) -> Request {
+        let name = format!("kubernix-bench-{}", index);
+        let collection = format!("/api/v1/namespaces/{}/{}", namespace, kind.resource());
+        match operation {
+            Operation::Create => Request {
+                method: "POST",
+                path: collection,
+                content_type: "application/json",
+                data: Some(kind.object(&name)),
+            },
+            Operation::Patch => Request {
+                method: "PATCH",
+                path: format!("{}/{}", collection, name),
+                content_type: "application/merge-patch+json",
+                data: Some(json!({ "metadata": { "labels": { "bench": "patched" } } }).to_string()),
+            },
+            Operation::Delete => Request {
+                method: "DELETE",
+                path: format!("{}/{}", collection, name),
+                content_type: "application/json",
+                data: None,
+            },
+        }
+    }
+
+    /// Execute the requests sequentially within a single curl process and
+    /// return their status codes and latencies
+    fn execute(&self, requests: &[Request]) -> Fallible<Vec<(u16, Duration)>> {
+        let dir = self.config.root().join("bench");
+        create_dir_all(&dir)?;
+        let file = dir.join("curl.conf");
+        fs::write(&file, self.curl_config(requests)?)?;
+
+        let stdout = Process::run_to_completion("curl")
+            .arg("--config")
+            .arg(&file)
+            // Leave every request up to a second within larger runs
+            .timeout(Duration::from_secs(60 + requests.len() as u64))
+            .run()?;
+
+        stdout
+            .lines()
+            .map(|line| {
+                let mut split = line.split_whitespace();
+                match (
+                    split.next().and_then(|x| x.parse().ok()),
+                    split.next().and_then(|x| x.parse::<f64>().ok()),
+                ) {
+                    (Some(code), Some(secs)) => Ok((code, Duration::from_secs_f64(secs))),
+                    _ => Err(format_err!("Unexpected curl output '{}'", line)),
+                }
+            })
+            .collect()
+    }
+
+    /// Render the curl configuration for the provided requests
+    fn curl_config(&self, requests: &[Request]) -> Fallible<String> {
+        let url = format!(
+            "https://{}:{}",
+            Ipv4Addr::LOCALHOST,
+            self.config.ports().apiserver()
+        );
+        let mut config = String::new();
+        for (i, request) in requests.iter().enumerate() {
+            if i > 0 {
+                config.push_str("next\n");
+            }
+            config.push_str(&format!(
+                "silent\nshow-error\noutput = \"/dev/null\"\n\
+                 write-out = \"%{{http_code}} %{{time_total}}\\n\"\n\
+                 cacert = \"{}\"\ncert = \"{}\"\nkey = \"{}\"\n\
+                 request = \"{}\"\nheader = \"Content-Type: {}\"\nurl = \"{}{}\"\n",
+                self.ca.cert().display(),
+                self.admin.cert().display(),
+                self.admin.key().display(),
+                request.method,
+                request.content_type,
+                url,
+                request.path,
+            ));
+            if let Some(data) = &request.data {
+                config.push_str(&format!("data = {}\n", serde_json::to_string(data)?));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Check if the provided HTTP status indicates success, whereas already
+    /// existing objects are fine
+    fn success(code: u16) -> bool {
+        (200..300).contains(&code) || code == 409
+    }
+
+    /// Summarize the provided latencies
+    fn report(latencies: &mut [Duration]) -> String {
+        latencies.sort();
+        let mut parts: Vec<String> = Self::PERCENTILES
+            .iter()
+            .map(|&p| format!("p{} {:?}", p, Self::percentile(latencies, p)))
+            .collect();
+        if let Some(max) = latencies.last() {
+            parts.push(format!("max {:?}", max));
+        }
+        parts.join(", ")
+    }
+
+    /// Retrieve the nearest rank percentile of the sorted latencies
+    fn percentile(sorted: &[Duration], p: u8) -> Duration {
+        if sorted.is_empty() {
+            return Duration::default();
+        }
+        let rank = (usize::from(p) * sorted.len() + 99) / 100;
+        sorted[rank.max(1) - 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentile_success() {
+        let sorted: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(Bench::percentile(&sorted, 50), Duration::from_millis(50));
+        assert_eq!(Bench::percentile(&sorted, 99), Duration::from_millis(99));
+        assert_eq!(Bench::percentile(&[], 90), Duration::default());
+    }
+
+    #[test]
+    fn request_success() {
+        let r = Bench::request(BenchKind::Secret, Operation::Patch, "ns", 3);
+        assert_eq!(r.method, "PATCH");
+        assert_eq!(r.path, "/api/v1/namespaces/ns/secrets/kubernix-bench-3");
+        assert_eq!(r.content_type, "application/merge-patch+json");
+    }
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        assert_eq!("secret".parse::<BenchKind>()?, BenchKind::Secret);
+        assert!("pod".parse::<BenchKind>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{component::ComponentKind, Config, Fallible};
+use log::{debug, info};
+use nix::{
+    errno::Errno,
+    sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor},
+    unistd::close,
+    Error::Sys,
+};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    os::unix::io::AsRawFd,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// Detects rebuilds of the local binaries, like after running `make` within
+/// a Kubernetes checkout
+pub struct BinaryWatch {
+    inotify: Inotify,
+    watches: Vec<(WatchDescriptor, OsString, ComponentKind)>,
+    changed: HashMap<ComponentKind, Instant>,
+}
+
+impl BinaryWatch {
+    /// The time without further changes after which a binary is considered
+    /// to be completely written
+    const SETTLE: Duration = Duration::from_secs(2);
+
+    /// Watch the local binaries of the provided configuration if requested
+    pub fn new(config: &Config) -> Fallible<Option<Self>> {
+        if !*config.watch_binaries() {
+            return Ok(None);
+        }
+        let mut watch = Self {
+            inotify: Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?,
+            watches: vec![],
+            changed: HashMap::new(),
+        };
+        for x in config.local_binaries() {
+            watch.add(x.path(), *x.component())?;
+        }
+        Ok(Some(watch))
+    }
+
+    /// Retrieve the components whose binaries changed and settled since the
+    /// last poll
+    pub fn poll(&mut self) -> Fallible<Vec<ComponentKind>> {
+        loop {
+            let events = match self.inotify.read_events() {
+                Ok(x) => x,
+                Err(Sys(Errno::EAGAIN)) => break,
+                Err(e) => return Err(e.into()),
+            };
+            for event in events {
+                for (wd, name, kind) in &self.watches {
+                    if event.wd == *wd && event.name.as_ref() == Some(name) {
+                        debug!("Binary of {} changed", kind);
+                        self.changed.insert(*kind, Instant::now());
+                    }
+                }
+            }
+        }
+        Ok(self.settled(Instant::now()))
+    }
+
+    /// Watch the directory of the binary, since builds usually replace the
+    /// file instead of writing it in place
+    fn add(&mut self, path: &Path, kind: ComponentKind) -> Fallible<()> {
+        let (dir, name) = match (path.parent(), path.file_name()) {
+            (Some(dir), Some(name)) => (dir, name),
+            _ => bail!("Invalid local binary path '{}'", path.display()),
+        };
+        let wd = self.inotify.add_watch(
+            dir,
+            AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO | AddWatchFlags::IN_ATTRIB,
+        )?;
+        info!("Watching binary '{}' of {}", path.display(), kind);
+        self.watches.push((wd, name.to_owned(), kind));
+        Ok(())
+    }
+
+    /// Take the changed components which did not change for a while
+    fn settled(&mut self, now: Instant) -> Vec<ComponentKind> {
+        let mut settled: Vec<ComponentKind> = self
+            .changed
+            .iter()
+            .filter(|(_, x)| now.saturating_duration_since(**x) >= Self::SETTLE)
+            .map(|(k, _)| *k)
+            .collect();
+        settled.sort_by_key(|x| ComponentKind::ALL.iter().position(|y| y == x));
+        for kind in &settled {
+            self.changed.remove(kind);
+        }
+        settled
+    }
+}
+
+impl Drop for BinaryWatch {
+    fn drop(&mut self) {
+        if let Err(e) = close(self.inotify.as_raw_fd()) {
+            debug!("Unable to close binary watch: {}", e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, thread::sleep};
+    use tempfile::tempdir;
+
+    fn watch() -> Fallible<BinaryWatch> {
+        Ok(BinaryWatch {
+            inotify: Inotify::init(InitFlags::IN_NONBLOCK)?,
+            watches: vec![],
+            changed: HashMap::new(),
+        })
+    }
+
+    #[test]
+    fn poll_success() -> Fallible<()> {
+        let d = tempdir()?;
+        let binary = d.path().join("kube-apiserver");
+        let mut w = watch()?;
+        w.add(&binary, ComponentKind::ApiServer)?;
+        assert!(w.poll()?.is_empty());
+
+        // Other files of the directory are ignored
+        fs::write(d.path().join("kubelet"), "")?;
+        fs::write(&binary, "")?;
+        assert!(w.poll()?.is_empty());
+        assert_eq!(w.changed.len(), 1);
+        assert!(w.changed.contains_key(&ComponentKind::ApiServer));
+
+        // Renamed files count as changes as well
+        let first = w.changed[&ComponentKind::ApiServer];
+        let tmp = d.path().join("tmp");
+        fs::write(&tmp, "")?;
+        sleep(Duration::from_millis(10));
+        fs::rename(tmp, &binary)?;
+        w.poll()?;
+        assert!(w.changed[&ComponentKind::ApiServer] > first);
+        Ok(())
+    }
+
+    #[test]
+    fn settled_success() -> Fallible<()> {
+        let mut w = watch()?;
+        let now = Instant::now();
+        w.changed.insert(ComponentKind::Kubelet, now);
+        w.changed.insert(ComponentKind::ApiServer, now);
+        w.changed
+            .insert(ComponentKind::Proxy, now + BinaryWatch::SETTLE);
+        assert!(w.settled(now).is_empty());
+        assert_eq!(
+            w.settled(now + BinaryWatch::SETTLE),
+            vec![ComponentKind::ApiServer, ComponentKind::Kubelet]
+        );
+        assert_eq!(w.changed.len(), 1);
+        Ok(())
+    }
+}
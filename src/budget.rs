@@ -0,0 +1,259 @@
+use crate::{Config, Error, Fallible};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs::{self, create_dir_all, read_dir, remove_dir},
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+/// A human readable memory size like `512M` or `4G`
+pub struct MemorySize(u64);
+
+impl MemorySize {
+    /// Create a new size from the provided amount of gibibytes
+    pub fn from_gib(gib: u64) -> Self {
+        MemorySize(gib << 30)
+    }
+
+    /// Retrieve the size in bytes
+    pub fn bytes(self) -> u64 {
+        self.0
+    }
+}
+
+impl Display for MemorySize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let units = [(1 << 30, "G"), (1 << 20, "M"), (1 << 10, "K")];
+        match units.iter().find(|(x, _)| self.0 != 0 && self.0 % x == 0) {
+            Some((x, unit)) => write!(f, "{}{}", self.0 / x, unit),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl FromStr for MemorySize {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let s = s.trim();
+        let (value, factor) = match s.chars().last() {
+            Some('K') | Some('k') => (&s[..s.len() - 1], 1 << 10),
+            Some('M') | Some('m') => (&s[..s.len() - 1], 1 << 20),
+            Some('G') | Some('g') => (&s[..s.len() - 1], 1 << 30),
+            _ => (s, 1),
+        };
+        value
+            .parse::<u64>()
+            .ok()
+            .and_then(|x| x.checked_mul(factor))
+            .map(MemorySize)
+            .ok_or_else(|| format_err!("Invalid memory size '{}'", s))
+    }
+}
+
+/// The resource budget of the whole cluster, which is enforced by a cgroup
+/// containing all components and pods
+pub struct Budget;
+
+impl Budget {
+    /// The mount point of the unified cgroup hierarchy
+    const CGROUP_ROOT: &'static str = "/sys/fs/cgroup";
+
+    /// The name prefix of the cgroup below the hierarchy root
+    const PREFIX: &'static str = "kubernix";
+
+    /// The cgroup period for the CPU quota in microseconds
+    const CPU_PERIOD: u64 = 100_000;
+
+    /// Check if a budget is configured
+    pub fn enabled(config: &Config) -> bool {
+        config.max_memory().is_some() || config.max_cpu().is_some()
+    }
+
+    /// Verify that the host is able to provide the configured budget
+    pub fn preflight(config: &Config) -> Fallible<()> {
+        if !Self::enabled(config) {
+            return Ok(());
+        }
+        if !Path::new(Self::CGROUP_ROOT)
+            .join("cgroup.controllers")
+            .exists()
+        {
+            bail!("The resource budget requires the unified cgroup v2 hierarchy")
+        }
+        if let Some(memory) = config.max_memory() {
+            let available = Self::available_memory(&fs::read_to_string("/proc/meminfo")?)?;
+            debug!("Available memory is {}, budget is {}", available, memory);
+            if available < memory.bytes() {
+                bail!(
+                    "Only {} bytes of memory are available, which is less than the budget of {}",
+                    available,
+                    memory
+                )
+            }
+        }
+        if let Some(cpu) = config.max_cpu() {
+            if cpu.is_nan() || *cpu <= 0.0 {
+                bail!("The CPU budget has to be greater than zero, got {}", cpu)
+            }
+            let available = num_cpus::get();
+            if (available as f64) < *cpu {
+                bail!(
+                    "Only {} CPUs are available, which is less than the budget of {}",
+                    available,
+                    cpu
+                )
+            }
+        }
+        Ok(())
+    }
+
+    /// Create the cgroup with the configured limits and move the current
+    /// process into it, which makes all further children inherit the budget
+    pub fn apply(config: &Config) -> Fallible<()> {
+        if !Self::enabled(config) {
+            return Ok(());
+        }
+        let dir = Self::dir(config);
+        info!("Applying resource budget via cgroup '{}'", dir.display());
+
+        // Processes can only live in the leaves of the hierarchy
+        Self::enable_controllers(Path::new(Self::CGROUP_ROOT))?;
+        create_dir_all(&dir)?;
+        Self::enable_controllers(&dir)?;
+        if let Some(memory) = config.max_memory() {
+            Self::write(&dir, "memory.max", &memory.bytes().to_string())?;
+        }
+        if let Some(cpu) = config.max_cpu() {
+            let quota = (cpu * Self::CPU_PERIOD as f64) as u64;
+            Self::write(&dir, "cpu.max", &format!("{} {}", quota, Self::CPU_PERIOD))?;
+        }
+
+        let components = dir.join("components");
+        create_dir_all(&components)?;
+        Self::write(&components, "cgroup.procs", &process::id().to_string())?;
+
+        let pods = dir.join("pods");
+        create_dir_all(&pods)?;
+        Self::enable_controllers(&pods)
+    }
+
+    /// Retrieve the kubelet arguments to place all pods within the budget
+    pub fn kubelet_args(config: &Config) -> Vec<String> {
+        if Self::enabled(config) {
+            vec![format!("--cgroup-root=/{}/pods", Self::name(config))]
+        } else {
+            vec![]
+        }
+    }
+
+    /// Move the current process out of the cgroup and remove it
+    pub fn cleanup(config: &Config) -> Fallible<()> {
+        if !Self::enabled(config) {
+            return Ok(());
+        }
+        Self::write(
+            Path::new(Self::CGROUP_ROOT),
+            "cgroup.procs",
+            &process::id().to_string(),
+        )?;
+        Self::remove(&Self::dir(config))
+    }
+
+    /// The cgroup name of the cluster, which is unique per root directory to
+    /// not let multiple clusters share the same cgroup
+    fn name(config: &Config) -> String {
+        let root: String = config
+            .root()
+            .display()
+            .to_string()
+            .chars()
+            .map(|x| match x {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '_' => x,
+                _ => '-',
+            })
+            .collect();
+        format!("{}{}", Self::PREFIX, root)
+    }
+
+    /// The cgroup directory of the cluster
+    fn dir(config: &Config) -> PathBuf {
+        Path::new(Self::CGROUP_ROOT).join(Self::name(config))
+    }
+
+    /// Remove a cgroup including all of its children
+    fn remove(dir: &Path) -> Fallible<()> {
+        if !dir.exists() {
+            return Ok(());
+        }
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                Self::remove(&path)?;
+            }
+        }
+        remove_dir(dir)
+            .map_err(|e| format_err!("Unable to remove cgroup '{}': {}", dir.display(), e))
+    }
+
+    /// Delegate the memory and CPU controllers to the children of the cgroup
+    fn enable_controllers(dir: &Path) -> Fallible<()> {
+        Self::write(dir, "cgroup.subtree_control", "+memory +cpu +pids")
+    }
+
+    fn write(dir: &Path, file: &str, content: &str) -> Fallible<()> {
+        let path = dir.join(file);
+        debug!("Writing '{}' to '{}'", content, path.display());
+        fs::write(&path, content)
+            .map_err(|e| format_err!("Unable to write '{}': {}", path.display(), e))
+    }
+
+    /// Parse the available memory in bytes from the provided meminfo
+    fn available_memory(meminfo: &str) -> Fallible<u64> {
+        meminfo
+            .lines()
+            .find(|x| x.starts_with("MemAvailable:"))
+            .and_then(|x| x.split_whitespace().nth(1))
+            .and_then(|x| x.parse::<u64>().ok())
+            .map(|x| x * 1024)
+            .ok_or_else(|| format_err!("Unable to retrieve available memory"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+
+    #[test]
+    fn memory_size_from_str_success() -> Fallible<()> {
+        assert_eq!("1024".parse::<MemorySize>()?.bytes(), 1024);
+        assert_eq!("512M".parse::<MemorySize>()?.bytes(), 512 << 20);
+        assert_eq!("4G".parse::<MemorySize>()?.to_string(), "4G");
+        assert!("4T".parse::<MemorySize>().is_err());
+        assert!("99999999999G".parse::<MemorySize>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn name_success() -> Fallible<()> {
+        let c = test_config()?;
+        let name = Budget::name(&c);
+        assert!(name.starts_with("kubernix-"));
+        assert!(!name.contains('/'));
+        assert_ne!(name, Budget::name(&test_config()?));
+        Ok(())
+    }
+
+    #[test]
+    fn available_memory_success() -> Fallible<()> {
+        let meminfo = "MemTotal:       16303428 kB\nMemAvailable:    8000000 kB\n";
+        assert_eq!(Budget::available_memory(meminfo)?, 8_000_000 * 1024);
+        assert!(Budget::available_memory("MemTotal: 1 kB").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,334 @@
+use crate::{
+    component::{Component, ComponentKind},
+    config::{Export, ExportFormat},
+    runinfo::RunInfo,
+    Config, Fallible,
+};
+use log::info;
+use std::{
+    collections::BTreeMap,
+    fs::{self, canonicalize, create_dir_all, metadata, read_dir, set_permissions},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+/// A single process of the cluster, reproducible without kubernix
+struct Service {
+    name: String,
+    dependencies: Vec<String>,
+    script: String,
+}
+
+/// A standalone bundle of the generated setup, which reproduces the cluster
+/// without kubernix
+pub struct Bundle;
+
+impl Bundle {
+    /// The name of the files containing the full command lines
+    const RUN_FILE: &'static str = "run.sh";
+
+    /// The prefix of all systemd units
+    const UNIT_PREFIX: &'static str = "kubernix-";
+
+    /// The image used for all compose services, which has to provide bash
+    /// while the binaries are taken from the nix store of the host
+    const COMPOSE_IMAGE: &'static str = "nixos/nix";
+
+    /// Export the generated setup of the cluster into the provided directory
+    pub fn export(config: &Config, args: &Export) -> Fallible<()> {
+        let services = Self::services(config)?;
+        if services.is_empty() {
+            bail!(
+                "No processes found in '{}', please bootstrap the cluster or \
+                 do a dry run first",
+                config.root().display()
+            )
+        }
+
+        create_dir_all(args.out())?;
+        let out = canonicalize(args.out())?;
+        info!(
+            "Exporting {} processes as {:?} bundle to '{}'",
+            services.len(),
+            args.format(),
+            out.display()
+        );
+
+        for service in &services {
+            Self::write_executable(&out.join(format!("{}.sh", service.name)), &service.script)?;
+        }
+        match args.format() {
+            ExportFormat::Shell => {
+                Self::write_executable(&out.join("start.sh"), &Self::shell(&services))?
+            }
+            ExportFormat::Systemd => {
+                for service in &services {
+                    fs::write(
+                        out.join(format!("{}{}.service", Self::UNIT_PREFIX, service.name)),
+                        Self::systemd_unit(service, &services, &out),
+                    )?;
+                }
+                fs::write(
+                    out.join(format!("{}cluster.target", Self::UNIT_PREFIX)),
+                    include_str!("assets/kubernix-cluster.target"),
+                )?;
+            }
+            ExportFormat::Compose => fs::write(
+                out.join("docker-compose.yml"),
+                Self::compose(&services, config.root(), &out),
+            )?,
+        }
+        Ok(())
+    }
+
+    /// Collect all processes of the root directory in their startup order
+    fn services(config: &Config) -> Fallible<Vec<Service>> {
+        let mut dependencies: BTreeMap<String, Vec<String>> = ComponentKind::ALL
+            .iter()
+            .map(|x| (x.name(), x.dependencies()))
+            .collect();
+        for process in config.processes() {
+            dependencies.insert(process.name().clone(), process.dependencies());
+        }
+
+        let mut run_files = vec![];
+        Self::run_files(config.root(), &mut run_files)?;
+
+        let mut services = vec![];
+        for file in run_files {
+            let dir = file.parent().unwrap_or_else(|| config.root());
+            let relative = dir.strip_prefix(config.root()).unwrap_or(dir);
+            let component = relative
+                .components()
+                .next()
+                .map(|x| x.as_os_str().to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let name = relative
+                .components()
+                .map(|x| x.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("-");
+            // The environment is part of the metadata written on spawn
+            let env = RunInfo::load(dir)
+                .map(|x| x.env().clone())
+                .unwrap_or_default();
+            services.push(Service {
+                script: Self::script(&env, &fs::read_to_string(&file)?),
+                dependencies: dependencies.get(&component).cloned().unwrap_or_default(),
+                name,
+            });
+        }
+
+        // Built-in components first, additional processes afterwards
+        let order = |name: &str| {
+            ComponentKind::ALL
+                .iter()
+                .position(|x| name == x.to_string() || name.starts_with(&format!("{}-", x)))
+                .unwrap_or(ComponentKind::ALL.len())
+        };
+        services.sort_by(|a, b| {
+            order(&a.name)
+                .cmp(&order(&b.name))
+                .then(a.name.cmp(&b.name))
+        });
+        Ok(services)
+    }
+
+    /// Find all run files below the provided directory
+    fn run_files(dir: &Path, files: &mut Vec<PathBuf>) -> Fallible<()> {
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            if fs::symlink_metadata(&path)?.is_dir() {
+                Self::run_files(&path, files)?;
+            } else if path.ends_with(Self::RUN_FILE) {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a standalone script from the environment and the run file of
+    /// a process
+    fn script(env: &BTreeMap<String, String>, run_file: &str) -> String {
+        let mut script = String::from("#!/usr/bin/env bash\nset -euo pipefail\n\n");
+        for (key, value) in env {
+            script.push_str(&format!("export {}={}\n", key, Self::quote(value)));
+        }
+        script.push('\n');
+        // The command replaces the shell to receive the signals directly
+        let mut command = false;
+        for line in run_file
+            .lines()
+            .skip_while(|x| x.starts_with("#!") || x.starts_with("set ") || x.is_empty())
+        {
+            if !command
+                && !["cd ", "umask ", "ulimit "]
+                    .iter()
+                    .any(|x| line.starts_with(x))
+            {
+                command = true;
+                script.push_str("exec ");
+            }
+            script.push_str(line);
+            script.push('\n');
+        }
+        script
+    }
+
+    /// Quote the provided value for the shell
+    fn quote(value: &str) -> String {
+        format!("'{}'", value.replace('\'', r#"'\''"#))
+    }
+
+    /// Render a script starting all processes in the background
+    fn shell(services: &[Service]) -> String {
+        let mut script = String::from(
+            "#!/usr/bin/env bash\nset -euo pipefail\n\n\
+             cd \"$(dirname \"$0\")\"\nmkdir -p log\n\n\
+             # Stop all processes if this script exits\n\
+             trap 'kill $(jobs -p) 2>/dev/null' EXIT\n\n",
+        );
+        for service in services {
+            script.push_str(&format!(
+                "echo 'Starting {0}'\n./{0}.sh &> log/{0}.log &\n",
+                service.name
+            ));
+        }
+        script.push_str("\nwait -n\n");
+        script
+    }
+
+    /// Render the systemd unit of a single process
+    fn systemd_unit(service: &Service, services: &[Service], out: &Path) -> String {
+        let units = Self::dependency_names(service, services)
+            .iter()
+            .map(|x| format!("{}{}.service", Self::UNIT_PREFIX, x))
+            .collect::<Vec<_>>();
+        let mut unit = format!(
+            "[Unit]\nDescription=kubernix {}\nPartOf={}cluster.target\n",
+            service.name,
+            Self::UNIT_PREFIX
+        );
+        if units.is_empty() {
+            unit.push_str("After=network-online.target\n");
+        } else {
+            unit.push_str(&format!(
+                "After=network-online.target {0}\nWants={0}\n",
+                units.join(" ")
+            ));
+        }
+        unit.push_str(&format!(
+            "\n[Service]\nExecStart={}\nRestart=on-failure\n\n\
+             [Install]\nWantedBy={}cluster.target\n",
+            out.join(format!("{}.sh", service.name)).display(),
+            Self::UNIT_PREFIX
+        ));
+        unit
+    }
+
+    /// Render a compose file running every process within the host
+    /// namespaces, whereas the nix store and the root are shared
+    fn compose(services: &[Service], root: &Path, out: &Path) -> String {
+        let mut compose = String::from("version: \"3\"\nservices:\n");
+        for service in services {
+            compose.push_str(&format!(
+                "  {name}:\n    image: {image}\n    command: [\"{exec}\"]\n    \
+                 network_mode: host\n    pid: host\n    privileged: true\n    volumes:\n",
+                name = service.name,
+                image = Self::COMPOSE_IMAGE,
+                exec = out.join(format!("{}.sh", service.name)).display(),
+            ));
+            for path in &[Path::new("/nix"), root, out] {
+                compose.push_str(&format!("      - \"{0}:{0}\"\n", path.display()));
+            }
+            let dependencies = Self::dependency_names(service, services);
+            if !dependencies.is_empty() {
+                compose.push_str("    depends_on:\n");
+                for dependency in dependencies {
+                    compose.push_str(&format!("      - {}\n", dependency));
+                }
+            }
+        }
+        compose
+    }
+
+    /// Retrieve the names of all exported services the provided one depends on
+    fn dependency_names(service: &Service, services: &[Service]) -> Vec<String> {
+        services
+            .iter()
+            .filter(|x| {
+                service
+                    .dependencies
+                    .iter()
+                    .any(|d| &x.name == d || x.name.starts_with(&format!("{}-", d)))
+            })
+            .map(|x| x.name.clone())
+            .collect()
+    }
+
+    /// Write the provided content into an executable file
+    fn write_executable(file: &Path, content: &str) -> Fallible<()> {
+        fs::write(file, content)?;
+        let mut perms = metadata(file)?.permissions();
+        perms.set_mode(0o755);
+        set_permissions(file, perms)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(name: &str, dependencies: &[&str]) -> Service {
+        Service {
+            name: name.into(),
+            dependencies: dependencies.iter().map(|x| x.to_string()).collect(),
+            script: String::new(),
+        }
+    }
+
+    #[test]
+    fn script_success() {
+        let env = [("PATH", "/nix/store/bin"), ("TZ", "it's")]
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect();
+        let script = Bundle::script(
+            &env,
+            "#!/usr/bin/env bash\nset -euo pipefail\n\ncd /root\numask 0022\netcd \\\n    --name=etcd\n",
+        );
+        assert_eq!(
+            script,
+            "#!/usr/bin/env bash\nset -euo pipefail\n\n\
+             export PATH='/nix/store/bin'\nexport TZ='it'\\''s'\n\n\
+             cd /root\numask 0022\nexec etcd \\\n    --name=etcd\n"
+        );
+    }
+
+    #[test]
+    fn dependency_names_success() {
+        let services = vec![
+            service("etcd", &[]),
+            service("apiserver", &["etcd"]),
+            service("apiserver-replica-1", &["etcd"]),
+            service("kubelet", &["crio", "apiserver"]),
+        ];
+        assert_eq!(
+            Bundle::dependency_names(&services[3], &services),
+            vec!["apiserver", "apiserver-replica-1"]
+        );
+        assert!(Bundle::dependency_names(&services[0], &services).is_empty());
+    }
+
+    #[test]
+    fn systemd_unit_success() {
+        let services = vec![service("etcd", &[]), service("apiserver", &["etcd"])];
+        let unit = Bundle::systemd_unit(&services[1], &services, Path::new("/out"));
+        assert!(unit.contains("After=network-online.target kubernix-etcd.service\n"));
+        assert!(unit.contains("Wants=kubernix-etcd.service\n"));
+        assert!(unit.contains("ExecStart=/out/apiserver.sh\n"));
+        let unit = Bundle::systemd_unit(&services[0], &services, Path::new("/out"));
+        assert!(!unit.contains("Wants="));
+    }
+}
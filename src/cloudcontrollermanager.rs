@@ -0,0 +1,126 @@
+use crate::{config::Config, kubeconfig::KubeConfig, process::Process, Fallible};
+use log::{debug, info, warn};
+use serde_json::Value;
+use std::time::Duration;
+
+pub struct CloudControllerManager;
+
+impl CloudControllerManager {
+    /// The taint the kubelet applies to nodes for an external cloud provider
+    const UNINITIALIZED_TAINT: &'static str = "node.cloudprovider.kubernetes.io/uninitialized";
+
+    /// The interval of initializing newly registered nodes
+    pub const INTERVAL: Duration = Duration::from_secs(10);
+
+    /// Check if the nodes get initialized by the stub cloud controller manager
+    pub fn enabled(config: &Config) -> bool {
+        *config.cloud_provider_external() && *config.stub_cloud_controller_manager()
+    }
+
+    /// Initialize all nodes like a cloud controller manager would do
+    pub fn apply(config: &Config, kubeconfig: &KubeConfig) -> Fallible<()> {
+        if !*config.cloud_provider_external() {
+            if *config.stub_cloud_controller_manager() {
+                warn!("Stub cloud controller manager requires an external cloud provider");
+            }
+            return Ok(());
+        }
+        if !*config.stub_cloud_controller_manager() {
+            info!(
+                "Nodes are tainted with '{}' until a cloud controller manager initializes them",
+                Self::UNINITIALIZED_TAINT
+            );
+            return Ok(());
+        }
+
+        info!("Initializing nodes via stub cloud controller manager");
+        Self::initialize(kubeconfig)?;
+        info!("Nodes initialized");
+        Ok(())
+    }
+
+    /// Initialize all registered nodes which are not initialized yet, which
+    /// runs periodically to cover nodes registering later on
+    pub fn initialize(kubeconfig: &KubeConfig) -> Fallible<()> {
+        let kubeconfig_arg = format!("--kubeconfig={}", kubeconfig.admin().display());
+
+        let nodes: Value = serde_json::from_str(
+            &Process::run_to_completion("kubectl")
+                .arg(&kubeconfig_arg)
+                .arg("get")
+                .arg("nodes")
+                .arg("--output=json")
+                .retries(3)
+                .run()?,
+        )?;
+
+        for node in nodes["items"].as_array().into_iter().flatten() {
+            let name = node["metadata"]["name"]
+                .as_str()
+                .ok_or_else(|| format_err!("Unable to retrieve node name"))?;
+            let has_provider_id = node["spec"]["providerID"].is_string();
+            let uninitialized = Self::is_uninitialized(node);
+            if has_provider_id && !uninitialized {
+                continue;
+            }
+
+            debug!("Initializing node {}", name);
+            if !has_provider_id {
+                Process::run_to_completion("kubectl")
+                    .arg(&kubeconfig_arg)
+                    .arg("patch")
+                    .arg("node")
+                    .arg(name)
+                    .arg("--type=merge")
+                    .arg(format!(
+                        r#"--patch={{"spec":{{"providerID":"kubernix://{}"}}}}"#,
+                        name
+                    ))
+                    .retries(3)
+                    .run()?;
+            }
+
+            // Removing a taint fails if it does not exist, so a retry would
+            // never succeed
+            if uninitialized {
+                Process::run_to_completion("kubectl")
+                    .arg(&kubeconfig_arg)
+                    .arg("taint")
+                    .arg("node")
+                    .arg(name)
+                    .arg(format!("{}-", Self::UNINITIALIZED_TAINT))
+                    .run()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if the node still carries the uninitialized taint
+    fn is_uninitialized(node: &Value) -> bool {
+        node["spec"]["taints"].as_array().map_or(false, |x| {
+            x.iter().any(|t| t["key"] == Self::UNINITIALIZED_TAINT)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn is_uninitialized_success() {
+        let node = json!({
+            "spec": {
+                "taints": [
+                    { "key": "node.kubernetes.io/not-ready", "effect": "NoSchedule" },
+                    { "key": CloudControllerManager::UNINITIALIZED_TAINT, "effect": "NoSchedule" }
+                ]
+            }
+        });
+        assert!(CloudControllerManager::is_uninitialized(&node));
+        assert!(!CloudControllerManager::is_uninitialized(
+            &json!({ "spec": {} })
+        ));
+    }
+}
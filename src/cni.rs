@@ -0,0 +1,194 @@
+use crate::{
+    config::Config, kubeconfig::KubeConfig, network::Network, process::Process, proxy::Proxy,
+    Error, Fallible, CRIO_DIR,
+};
+use log::{debug, info};
+use nix::mount::{mount, MsFlags};
+use proc_mounts::MountIter;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs::create_dir_all,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The available network plugins
+pub enum Cni {
+    /// The plain CNI bridge plugin configured by CRI-O
+    Bridge,
+
+    /// The eBPF based dataplane of Cilium
+    Cilium,
+}
+
+impl Display for Cni {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Cni::Bridge => "bridge",
+            Cni::Cilium => "cilium",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Cni {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "bridge" => Ok(Cni::Bridge),
+            "cilium" => Ok(Cni::Cilium),
+            _ => Err(format_err!("Unknown CNI '{}'", s)),
+        }
+    }
+}
+
+impl Cni {
+    /// The BPF file system of the host
+    const BPF_ROOT: &'static str = "/sys/fs/bpf";
+
+    /// The unified cgroup hierarchy of the host, if available
+    const CGROUP_ROOT: &'static str = "/sys/fs/cgroup";
+
+    /// The Nix package providing the installer of the plugin, if any
+    pub fn package(self) -> Option<&'static str> {
+        match self {
+            Cni::Bridge => None,
+            Cni::Cilium => Some("cilium-cli"),
+        }
+    }
+
+    /// The writable directory for CNI plugin binaries installed by the
+    /// network plugin itself
+    pub fn plugin_dir(config: &Config) -> PathBuf {
+        config.root().join(CRIO_DIR).join("cni-bin")
+    }
+
+    /// Mount the BPF and cgroup v2 file systems required by the plugin
+    pub fn prepare(self, config: &Config) -> Fallible<()> {
+        if self == Cni::Bridge {
+            return Ok(());
+        }
+        create_dir_all(Self::plugin_dir(config))?;
+
+        let bpf = Path::new(Self::BPF_ROOT);
+        if !Self::is_mounted(bpf, "bpf")? {
+            Self::mount_fs(bpf, "bpf")?;
+        }
+        let cgroup = Self::cgroup_dir(config)?;
+        if !Self::is_mounted(&cgroup, "cgroup2")? {
+            Self::mount_fs(&cgroup, "cgroup2")?;
+        }
+        Ok(())
+    }
+
+    /// Deploy the network plugin into the running cluster
+    pub fn apply(
+        self,
+        config: &Config,
+        network: &Network,
+        kubeconfig: &KubeConfig,
+        ip: &str,
+    ) -> Fallible<()> {
+        if self == Cni::Bridge {
+            return Ok(());
+        }
+        info!("Deploying Cilium");
+
+        // The installation may wait for the agent, which needs some time to
+        // pull its images
+        Process::run_to_completion("cilium")
+            .arg("install")
+            .args(&Self::cilium_args(config, network, ip)?)
+            .env("KUBECONFIG", kubeconfig.admin())
+            .timeout(Duration::from_secs(600))
+            .run()?;
+        info!("Cilium deployed, the network gets ready once its agent is running");
+        Ok(())
+    }
+
+    /// Retrieve the Helm values for the Cilium installation
+    fn cilium_args(config: &Config, network: &Network, ip: &str) -> Fallible<Vec<String>> {
+        let values = vec![
+            format!("k8sServiceHost={}", ip),
+            format!("k8sServicePort={}", config.ports().apiserver()),
+            format!("kubeProxyReplacement={}", !Proxy::enabled(config)),
+            "ipam.mode=cluster-pool".into(),
+            format!(
+                "ipam.operator.clusterPoolIPv4PodCIDRList={}",
+                network.crio()
+            ),
+            format!(
+                "ipam.operator.clusterPoolIPv4MaskSize={}",
+                config.node_cidr_mask_size().unwrap_or(24)
+            ),
+            "operator.replicas=1".into(),
+            format!("cni.binPath={}", Self::plugin_dir(config).display()),
+            format!(
+                "cni.confPath={}",
+                config.root().join(CRIO_DIR).join("cni").display()
+            ),
+            "cgroup.autoMount.enabled=false".into(),
+            format!("cgroup.hostRoot={}", Self::cgroup_dir(config)?.display()),
+        ];
+        Ok(values
+            .into_iter()
+            .flat_map(|x| vec!["--set".to_owned(), x])
+            .collect())
+    }
+
+    /// The cgroup v2 hierarchy, which is a dedicated mount point inside the
+    /// root if the host does not provide a unified one
+    fn cgroup_dir(config: &Config) -> Fallible<PathBuf> {
+        let host = Path::new(Self::CGROUP_ROOT);
+        if Self::is_mounted(host, "cgroup2")? {
+            Ok(host.to_path_buf())
+        } else {
+            Ok(config.root().join("cilium").join("cgroupv2"))
+        }
+    }
+
+    /// Check if the provided file system type is mounted at the path
+    fn is_mounted(path: &Path, fstype: &str) -> Fallible<bool> {
+        Ok(MountIter::new()?
+            .filter_map(|x| x.ok())
+            .any(|x| x.dest == path && x.fstype == fstype))
+    }
+
+    /// Mount a pseudo file system of the provided type to the path
+    fn mount_fs(path: &Path, fstype: &str) -> Fallible<()> {
+        debug!("Mounting {} file system to '{}'", fstype, path.display());
+        create_dir_all(path)?;
+        mount(
+            Some(fstype),
+            path,
+            Some(fstype),
+            MsFlags::empty(),
+            None::<&str>,
+        )
+        .map_err(|e| {
+            format_err!(
+                "Unable to mount {} file system to '{}': {}",
+                fstype,
+                path.display(),
+                e
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        assert_eq!("cilium".parse::<Cni>()?, Cni::Cilium);
+        assert!("flannel".parse::<Cni>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+use crate::{
+    activation::Activation, apiserver::ApiServer, controllermanager::ControllerManager, crio::Crio,
+    encryptionconfig::EncryptionConfig, etcd::Etcd, kubeconfig::KubeConfig, kubelet::Kubelet,
+    network::Network, pki::Pki, process::Startable, proxy::Proxy, readiness::Readiness,
+    scheduler::Scheduler, Config, Error, Fallible,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// A component which can be supervised by kubernix
+pub trait Component: Send + Sync {
+    /// The unique name of the component, which is also used as its directory
+    /// name inside the root
+    fn name(&self) -> String;
+
+    /// The names of all components which have to be ready before this one
+    fn dependencies(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Start the component
+    fn start(&self, context: &Context) -> Fallible<Startable>;
+
+    /// The socket which starts the component on its first connection
+    /// instead of during the bootstrap, if any
+    fn activation(&self) -> Option<Activation> {
+        None
+    }
+
+    /// Retrieve the current health of the component, which defaults to its
+    /// readiness marker
+    fn health(&self, context: &Context) -> Health {
+        if Readiness::is_marked(&context.dir(&self.name())) {
+            Health::Ready
+        } else {
+            Health::NotReady
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The health state of a component
+pub enum Health {
+    /// The component is running and ready
+    Ready,
+
+    /// The component is not running or not ready yet
+    NotReady,
+
+    /// The component is running but repeatedly fails its liveness probes
+    Unhealthy,
+}
+
+impl Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Health::Ready => write!(f, "ready"),
+            Health::NotReady => write!(f, "not ready"),
+            Health::Unhealthy => write!(f, "unhealthy"),
+        }
+    }
+}
+
+/// The shared cluster state available to all components during startup
+pub struct Context<'a> {
+    pub(crate) config: &'a Config,
+    pub(crate) network: &'a Network,
+    pub(crate) pki: &'a Pki,
+    pub(crate) kubeconfig: &'a KubeConfig,
+    pub(crate) encryptionconfig: &'a EncryptionConfig,
+    pub(crate) crio_socket: &'a Path,
+    pub(crate) ip: &'a str,
+}
+
+impl<'a> Context<'a> {
+    /// The configuration of the cluster
+    pub fn config(&self) -> &Config {
+        self.config
+    }
+
+    /// The directory of the provided component inside the root
+    pub fn dir(&self, name: &str) -> PathBuf {
+        self.config.root().join(name)
+    }
+
+    /// The path to the cluster admin kubeconfig
+    pub fn admin_kubeconfig(&self) -> &Path {
+        self.kubeconfig.admin()
+    }
+
+    /// The path to the container runtime socket
+    pub fn crio_socket(&self) -> &Path {
+        self.crio_socket
+    }
+
+    /// The local IP address of the cluster
+    pub fn ip(&self) -> &str {
+        self.ip
+    }
+}
+
+/// An owned copy of the shared cluster state, which allows starting
+/// components in the background
+pub struct OwnedContext {
+    config: Config,
+    network: Network,
+    pki: Pki,
+    kubeconfig: KubeConfig,
+    encryptionconfig: EncryptionConfig,
+    crio_socket: PathBuf,
+    ip: String,
+}
+
+impl OwnedContext {
+    /// Copy the provided context
+    pub fn new(context: &Context) -> Self {
+        Self {
+            config: context.config.clone(),
+            network: context.network.clone(),
+            pki: context.pki.clone(),
+            kubeconfig: context.kubeconfig.clone(),
+            encryptionconfig: context.encryptionconfig.clone(),
+            crio_socket: context.crio_socket.to_owned(),
+            ip: context.ip.to_owned(),
+        }
+    }
+
+    /// Borrow the copy as regular context
+    pub fn context(&self) -> Context {
+        Context {
+            config: &self.config,
+            network: &self.network,
+            pki: &self.pki,
+            kubeconfig: &self.kubeconfig,
+            encryptionconfig: &self.encryptionconfig,
+            crio_socket: &self.crio_socket,
+            ip: &self.ip,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// All components kubernix is able to run
+pub enum ComponentKind {
+    /// The container runtime
+    Crio,
+
+    /// The database backend
+    Etcd,
+
+    /// The Kubernetes API Server
+    ApiServer,
+
+    /// The Kubernetes Controller Manager
+    ControllerManager,
+
+    /// The Kubernetes Scheduler
+    Scheduler,
+
+    /// The Kubernetes node agent
+    Kubelet,
+
+    /// The Kubernetes network proxy
+    Proxy,
+}
+
+impl ComponentKind {
+    /// All available components in their startup order
+    pub const ALL: [ComponentKind; 7] = [
+        ComponentKind::Crio,
+        ComponentKind::Etcd,
+        ComponentKind::ApiServer,
+        ComponentKind::ControllerManager,
+        ComponentKind::Scheduler,
+        ComponentKind::Kubelet,
+        ComponentKind::Proxy,
+    ];
+
+    /// The order in which components have to be restarted during an upgrade
+    pub const UPGRADE_ORDER: [ComponentKind; 6] = [
+        ComponentKind::Etcd,
+        ComponentKind::ApiServer,
+        ComponentKind::ControllerManager,
+        ComponentKind::Scheduler,
+        ComponentKind::Kubelet,
+        ComponentKind::Proxy,
+    ];
+
+    /// Check if the component should be run for the provided configuration
+    pub fn enabled(self, config: &Config) -> bool {
+        match self {
+            ComponentKind::Crio | ComponentKind::Kubelet => true,
+            ComponentKind::Proxy => Proxy::enabled(config),
+            _ => !*config.kubelet_only(),
+        }
+    }
+
+    /// Retrieve the executable name of the component
+    pub fn command(self) -> &'static str {
+        match self {
+            ComponentKind::Crio => "crio",
+            ComponentKind::Etcd => "etcd",
+            ComponentKind::ApiServer => "kube-apiserver",
+            ComponentKind::ControllerManager => "kube-controller-manager",
+            ComponentKind::Scheduler => "kube-scheduler",
+            ComponentKind::Kubelet => "kubelet",
+            ComponentKind::Proxy => "kube-proxy",
+        }
+    }
+
+    /// Find the component for the provided executable name
+    pub fn from_command(command: &str) -> Option<ComponentKind> {
+        Self::ALL.iter().find(|x| x.command() == command).cloned()
+    }
+}
+
+impl Component for ComponentKind {
+    fn name(&self) -> String {
+        self.to_string()
+    }
+
+    fn dependencies(&self) -> Vec<String> {
+        let dependencies: &[ComponentKind] = match self {
+            ComponentKind::Crio | ComponentKind::Etcd => &[],
+            ComponentKind::ApiServer => &[ComponentKind::Etcd],
+            ComponentKind::ControllerManager | ComponentKind::Scheduler | ComponentKind::Proxy => {
+                &[ComponentKind::ApiServer]
+            }
+            ComponentKind::Kubelet => &[ComponentKind::Crio, ComponentKind::ApiServer],
+        };
+        dependencies.iter().map(ToString::to_string).collect()
+    }
+
+    fn start(&self, c: &Context) -> Fallible<Startable> {
+        match self {
+            ComponentKind::Crio => Crio::start(c.config, c.network, c.ip, c.crio_socket),
+            ComponentKind::Etcd => Etcd::start(c.config, c.pki),
+            ComponentKind::ApiServer => ApiServer::start(
+                c.config,
+                c.network,
+                c.ip,
+                c.pki,
+                c.encryptionconfig,
+                c.kubeconfig,
+            ),
+            ComponentKind::ControllerManager => {
+                ControllerManager::start(c.config, c.network, c.pki, c.kubeconfig)
+            }
+            ComponentKind::Scheduler => Scheduler::start(c.config, c.kubeconfig),
+            ComponentKind::Kubelet => Kubelet::start(
+                c.config,
+                c.network,
+                c.ip,
+                c.pki,
+                c.kubeconfig,
+                c.crio_socket,
+            ),
+            ComponentKind::Proxy => Proxy::start(c.config, c.network, c.kubeconfig),
+        }
+    }
+}
+
+/// The kubelet of the kubelet-only mode, which runs without any control plane
+/// and therefore only depends on the container runtime
+pub struct StandaloneKubelet;
+
+impl Component for StandaloneKubelet {
+    fn name(&self) -> String {
+        ComponentKind::Kubelet.to_string()
+    }
+
+    fn dependencies(&self) -> Vec<String> {
+        vec![ComponentKind::Crio.to_string()]
+    }
+
+    fn start(&self, context: &Context) -> Fallible<Startable> {
+        ComponentKind::Kubelet.start(context)
+    }
+}
+
+impl Display for ComponentKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ComponentKind::Crio => "crio",
+            ComponentKind::Etcd => "etcd",
+            ComponentKind::ApiServer => "apiserver",
+            ComponentKind::ControllerManager => "controllermanager",
+            ComponentKind::Scheduler => "scheduler",
+            ComponentKind::Kubelet => "kubelet",
+            ComponentKind::Proxy => "proxy",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ComponentKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "crio" => Ok(ComponentKind::Crio),
+            "etcd" => Ok(ComponentKind::Etcd),
+            "apiserver" => Ok(ComponentKind::ApiServer),
+            "controllermanager" => Ok(ComponentKind::ControllerManager),
+            "scheduler" => Ok(ComponentKind::Scheduler),
+            "kubelet" => Ok(ComponentKind::Kubelet),
+            "proxy" => Ok(ComponentKind::Proxy),
+            _ => Err(format_err!("Unknown component '{}'", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        for kind in &ComponentKind::UPGRADE_ORDER {
+            assert_eq!(&kind.to_string().parse::<ComponentKind>()?, kind);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn from_command_success() {
+        for kind in &ComponentKind::ALL {
+            assert_eq!(ComponentKind::from_command(kind.command()), Some(*kind));
+        }
+        assert!(ComponentKind::from_command("invalid").is_none());
+    }
+
+    #[test]
+    fn dependencies_success() {
+        for kind in &ComponentKind::ALL {
+            for dependency in kind.dependencies() {
+                assert!(dependency.parse::<ComponentKind>().is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn from_str_failure() {
+        assert!("invalid".parse::<ComponentKind>().is_err())
+    }
+}
@@ -1,17 +1,47 @@
 //! Configuration related structures
+use crate::{
+    bench::BenchKind,
+    budget::MemorySize,
+    cni::Cni,
+    component::ComponentKind,
+    credentialprovider::CredentialProvider,
+    duration::HumanDuration,
+    extraprocess::ExtraProcess,
+    hook::{Hook, HookFailure},
+    layout::Layout,
+    lazyaddon::LazyAddon,
+    localbinary::LocalBinary,
+    logger::{LogColor, ModuleLevel},
+    mirror::RegistryMirror,
+    packageoverride::PackageOverride,
+    ports::Ports,
+    process::{ProcessBackend, RealBackend},
+    profile::Profile,
+    proxy::ProxyMode,
+    rlimit::RLimit,
+    runtimeclass::RuntimeClass,
+    secrets::SecretBackend,
+    selinux::SelinuxMode,
+    sync::SyncTarget,
+    verbosity::Verbosity,
+    Error, Fallible,
+};
 use clap::{crate_version, AppSettings, Clap};
-use failure::{format_err, Fallible};
 use getset::Getters;
 use ipnetwork::Ipv4Network;
 use log::LevelFilter;
 use serde::{Deserialize, Serialize};
 use std::{
+    fmt::{self, Display},
     fs::{self, canonicalize, create_dir_all, read_to_string},
+    net::Ipv4Addr,
     path::PathBuf,
+    str::FromStr,
+    sync::Arc,
 };
 use toml;
 
-#[derive(Clap, Deserialize, Getters, Serialize)]
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
 #[serde(rename_all = "kebab-case")]
 #[clap(
     after_help = "More info at: https://github.com/saschagrunert/kubernix",
@@ -22,97 +52,2425 @@ use toml;
 /// The global configuration
 pub struct Config {
     #[get = "pub"]
-    #[clap(subcommand)]
-    /// All available subcommands
-    subcommand: Option<SubCommand>,
+    #[clap(subcommand)]
+    #[serde(skip)]
+    /// All available subcommands
+    subcommand: Option<SubCommand>,
+
+    #[get = "pub"]
+    #[clap(
+        help = "Continue a previous run from its last successful bootstrap phase",
+        long = "continue"
+    )]
+    #[serde(skip)]
+    /// Continue a previous run from its last successful bootstrap phase
+    resume: bool,
+
+    #[get = "pub"]
+    #[clap(
+        help = "Render all configurations, certificates and command lines into the root \
+                and print the plan without starting anything",
+        long = "dry-run"
+    )]
+    #[serde(skip)]
+    /// Render the cluster and print the plan without starting anything
+    dry_run: bool,
+
+    #[clap(skip)]
+    #[serde(skip)]
+    /// The backend spawning all supervised processes, which is only
+    /// replaceable via the library API
+    backend: Option<Arc<dyn ProcessBackend>>,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "kubernix-run",
+        env = "KUBERNIX_RUN",
+        global = true,
+        help = "Path where all the runtime data is stored",
+        long = "root",
+        short = "r",
+        value_name = "PATH"
+    )]
+    /// The root path during runtime
+    root: PathBuf,
+
+    #[clap(
+        env = "KUBERNIX_NAME",
+        global = true,
+        help = "Name of the cluster, which stores its runtime data in the root path \
+                suffixed by '-<NAME>'",
+        long = "name",
+        value_name = "NAME"
+    )]
+    #[serde(skip)]
+    /// The name of the cluster, which selects a separate root path
+    name: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "info",
+        env = "KUBERNIX_LOG_LEVEL",
+        help = "Set the log level verbosity",
+        long = "log-level",
+        raw(possible_values = r#"&["trace", "debug", "info", "warn", "error", "off"]"#),
+        short = "l",
+        value_name = "LEVEL"
+    )]
+    /// The logging level of the application
+    log_level: LevelFilter,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_LOG_MODULES",
+        help = "Override the log level for a single module, like \
+                'kubernix::process=debug'",
+        long = "log-module",
+        multiple = true,
+        value_name = "MODULE=LEVEL"
+    )]
+    #[serde(default)]
+    /// The log level overrides for single modules
+    log_modules: Vec<ModuleLevel>,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "auto",
+        env = "KUBERNIX_COLOR",
+        help = "Colorize the log output",
+        long = "color",
+        raw(possible_values = r#"&["auto", "always", "never"]"#),
+        value_name = "WHEN"
+    )]
+    #[serde(default = "default_log_color")]
+    /// The color behavior of the log output
+    log_color: LogColor,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_JOURNALD",
+        help = "Log directly into the systemd journal",
+        long = "journald"
+    )]
+    #[serde(default)]
+    /// Log directly into the systemd journal
+    journald: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_OTLP_ENDPOINT",
+        help = "The OpenTelemetry collector to export traces to, like \
+                'http://localhost:4318'",
+        long = "otlp-endpoint",
+        value_name = "URL"
+    )]
+    /// The OpenTelemetry collector to export traces to
+    otlp_endpoint: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "10.10.0.0/16",
+        env = "KUBERNIX_CIDR",
+        help = "The CIDR used for the cluster",
+        long = "cidr",
+        short = "c",
+        value_name = "CIDR"
+    )]
+    /// The CIDR used for the cluster
+    cidr: Ipv4Network,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_POD_CIDR",
+        help = "The CIDR used for the pods, instead of deriving it from --cidr",
+        long = "pod-cidr",
+        value_name = "CIDR"
+    )]
+    /// The CIDR used for the pods
+    pod_cidr: Option<Ipv4Network>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_SERVICE_CIDR",
+        help = "The CIDR used for the services, instead of deriving it from --cidr",
+        long = "service-cidr",
+        value_name = "CIDR"
+    )]
+    /// The CIDR used for the services
+    service_cidr: Option<Ipv4Network>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_NODE_CIDR_MASK_SIZE",
+        help = "The mask size of the pod CIDR allocated to every node by the \
+                Controller Manager",
+        long = "node-cidr-mask-size",
+        value_name = "SIZE"
+    )]
+    /// The mask size of the pod CIDR allocated to every node
+    node_cidr_mask_size: Option<u8>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_OVERLAY",
+        help = "The Nix package overlay to be used",
+        long = "overlay",
+        short = "o",
+        value_name = "PATH"
+    )]
+    /// The Nix package overlay to be used
+    overlay: Option<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        help = "Do not clear the current env during bootstrap",
+        long = "impure",
+        short = "i"
+    )]
+    /// Do not clear the current env during bootstrap
+    impure: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_PACKAGES",
+        help = "Additional Nix dependencies to be added to the environment",
+        long = "packages",
+        multiple = true,
+        short = "p",
+        value_name = "PACKAGE"
+    )]
+    /// Additional dependencies to be added to the environment
+    packages: Vec<String>,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "https://kubernetes.default.svc",
+        env = "KUBERNIX_SERVICE_ACCOUNT_ISSUER",
+        help = "The issuer of the service account tokens",
+        long = "service-account-issuer",
+        value_name = "ISSUER"
+    )]
+    #[serde(default = "default_service_account_issuer")]
+    /// The issuer of the service account tokens
+    service_account_issuer: String,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_API_AUDIENCES",
+        help = "The audiences of the API, defaults to the service account issuer",
+        long = "api-audiences",
+        multiple = true,
+        value_name = "AUDIENCE"
+    )]
+    #[serde(default)]
+    /// The audiences of the API, defaults to the service account issuer
+    api_audiences: Vec<String>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_SERVICE_ACCOUNT_CERT",
+        help = "The service account certificate to be used instead of a generated one",
+        long = "service-account-cert",
+        value_name = "PATH"
+    )]
+    /// The service account certificate to be used instead of a generated one
+    service_account_cert: Option<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_SERVICE_ACCOUNT_KEY",
+        help = "The service account private key to be used instead of a generated one",
+        long = "service-account-key",
+        value_name = "PATH"
+    )]
+    /// The service account private key to be used instead of a generated one
+    service_account_key: Option<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        help = "Run the cluster components with an external cloud provider",
+        long = "cloud-provider-external"
+    )]
+    #[serde(default)]
+    /// Run the cluster components with an external cloud provider
+    cloud_provider_external: bool,
+
+    #[get = "pub"]
+    #[clap(
+        help = "Initialize the nodes via a stub cloud controller manager, \
+                requires --cloud-provider-external",
+        long = "stub-cloud-controller-manager"
+    )]
+    #[serde(default)]
+    /// Initialize the nodes via a stub cloud controller manager
+    stub_cloud_controller_manager: bool,
+
+    #[get = "pub"]
+    #[clap(
+        help = "Prepare the cluster for developing a locally running CSI driver",
+        long = "csi-dev"
+    )]
+    #[serde(default)]
+    /// Prepare the cluster for developing a locally running CSI driver
+    csi_dev: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_ENABLE_ADMISSION_PLUGINS",
+        help = "Admission plugins to be enabled in addition to the default ones",
+        long = "enable-admission-plugins",
+        multiple = true,
+        value_name = "PLUGIN"
+    )]
+    #[serde(default)]
+    /// Admission plugins to be enabled in addition to the default ones
+    enable_admission_plugins: Vec<String>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_DISABLE_ADMISSION_PLUGINS",
+        help = "Admission plugins to be disabled",
+        long = "disable-admission-plugins",
+        multiple = true,
+        value_name = "PLUGIN"
+    )]
+    #[serde(default)]
+    /// Admission plugins to be disabled
+    disable_admission_plugins: Vec<String>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_ADMISSION_CONFIG",
+        help = "The AdmissionConfiguration file to be used by the API Server",
+        long = "admission-config",
+        value_name = "PATH"
+    )]
+    /// The AdmissionConfiguration file to be used by the API Server
+    admission_config: Option<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_RBAC_EXTRA",
+        help = "Directory of additional RBAC rules applied directly after the API Server is ready",
+        long = "rbac-extra",
+        value_name = "PATH"
+    )]
+    /// Directory of additional RBAC rules applied directly after the API Server is ready
+    rbac_extra: Option<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_FAKETIME",
+        help = "Run all components under libfaketime with the provided offset, like '+2d'",
+        long = "faketime",
+        value_name = "OFFSET"
+    )]
+    /// Run all components under libfaketime with the provided offset
+    faketime: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_RLIMITS",
+        help = "Resource limits for components, like 'etcd:nofile=65536' or \
+                'kubelet:nproc=4096:8192' (soft:hard)",
+        long = "rlimit",
+        multiple = true,
+        value_name = "COMPONENT:RESOURCE=LIMIT"
+    )]
+    #[serde(default)]
+    /// Resource limits for the components
+    rlimits: Vec<RLimit>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_HOOKS",
+        help = "Scripts to be executed during the cluster lifecycle, like 'post-up=seed.sh', \
+                where the point is one of pre-up, post-up, pre-down or post-down",
+        long = "hook",
+        multiple = true,
+        value_name = "POINT=SCRIPT"
+    )]
+    #[serde(default)]
+    /// Scripts to be executed during the cluster lifecycle
+    hooks: Vec<Hook>,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "fatal",
+        env = "KUBERNIX_HOOK_FAILURE",
+        help = "The behavior if a hook fails",
+        long = "hook-failure",
+        raw(possible_values = r#"&["fatal", "warn"]"#),
+        value_name = "BEHAVIOR"
+    )]
+    #[serde(default = "default_hook_failure")]
+    /// The behavior if a hook fails
+    hook_failure: HookFailure,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_RUNTIME_CLASSES",
+        help = "Additional OCI runtimes to be available as RuntimeClass",
+        long = "runtime-class",
+        multiple = true,
+        raw(possible_values = r#"&["runc", "kata", "gvisor"]"#),
+        value_name = "RUNTIME"
+    )]
+    #[serde(default)]
+    /// Additional OCI runtimes to be available as RuntimeClass
+    runtime_classes: Vec<RuntimeClass>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_PAUSE_IMAGE",
+        help = "The pause image to be used for the pod sandboxes",
+        long = "pause-image",
+        value_name = "IMAGE"
+    )]
+    /// The pause image to be used for the pod sandboxes
+    pause_image: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_REGISTRY_MIRRORS",
+        help = "Mirrors to be used for pulling images, like 'docker.io=mirror.local:5000'",
+        long = "registry-mirror",
+        multiple = true,
+        value_name = "REGISTRY=MIRROR"
+    )]
+    #[serde(default)]
+    /// Mirrors to be used for pulling images
+    registry_mirrors: Vec<RegistryMirror>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_HTTP_PROXY",
+        help = "The proxy to be used for HTTP requests of the runtime, \
+                the kubelet and Nix",
+        long = "http-proxy",
+        value_name = "URL"
+    )]
+    /// The proxy to be used for HTTP requests
+    http_proxy: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_HTTPS_PROXY",
+        help = "The proxy to be used for HTTPS requests of the runtime, \
+                the kubelet and Nix",
+        long = "https-proxy",
+        value_name = "URL"
+    )]
+    /// The proxy to be used for HTTPS requests
+    https_proxy: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_NO_PROXY",
+        help = "Additional hosts or networks to be accessed without proxy, \
+                the cluster network is always excluded",
+        long = "no-proxy",
+        multiple = true,
+        value_name = "HOST"
+    )]
+    #[serde(default)]
+    /// Additional hosts or networks to be accessed without proxy
+    no_proxy: Vec<String>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_REGISTRY_AUTH",
+        help = "The docker config.json containing the credentials for pulling images",
+        long = "registry-auth",
+        value_name = "PATH"
+    )]
+    /// The docker config.json containing the credentials for pulling images
+    registry_auth: Option<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_SECCOMP_PROFILES",
+        help = "Seccomp profiles to be available as 'localhost/<file>' for pods",
+        long = "seccomp-profile",
+        multiple = true,
+        value_name = "PATH"
+    )]
+    #[serde(default)]
+    /// Seccomp profiles to be available for pods
+    seccomp_profiles: Vec<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_DEFAULT_SECCOMP_PROFILE",
+        help = "The file name of the seccomp profile to be used by default for all containers",
+        long = "default-seccomp-profile",
+        value_name = "FILE"
+    )]
+    /// The seccomp profile to be used by default for all containers
+    default_seccomp_profile: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_APPARMOR_PROFILES",
+        help = "AppArmor profiles to be loaded if supported by the host",
+        long = "apparmor-profile",
+        multiple = true,
+        value_name = "PATH"
+    )]
+    #[serde(default)]
+    /// AppArmor profiles to be loaded if supported by the host
+    apparmor_profiles: Vec<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_DEFAULT_APPARMOR_PROFILE",
+        help = "The name of the AppArmor profile to be used by default for all containers",
+        long = "default-apparmor-profile",
+        value_name = "NAME"
+    )]
+    /// The AppArmor profile to be used by default for all containers
+    default_apparmor_profile: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        help = "Run the API Server behind a local HAProxy load balancer",
+        long = "apiserver-load-balancer"
+    )]
+    #[serde(default)]
+    /// Run the API Server behind a local load balancer
+    apiserver_load_balancer: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_API_RECORD",
+        help = "Record all REST calls to the API Server via the front proxy",
+        long = "api-record"
+    )]
+    #[serde(default)]
+    /// Record all REST calls to the API Server
+    api_record: bool,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "100",
+        env = "KUBERNIX_API_RECORD_SAMPLE",
+        help = "The percentage of recorded API requests",
+        long = "api-record-sample",
+        value_name = "PERCENT"
+    )]
+    #[serde(default = "default_api_record_sample")]
+    /// The percentage of recorded API requests
+    api_record_sample: u8,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "1",
+        env = "KUBERNIX_APISERVER_REPLICAS",
+        help = "The number of API Server replicas, which enables the load balancer if \
+                greater than one",
+        long = "apiserver-replicas",
+        value_name = "REPLICAS"
+    )]
+    #[serde(default = "default_apiserver_replicas")]
+    /// The number of API Server replicas
+    apiserver_replicas: u8,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_LEADER_ELECT",
+        help = "Enable the leader election of the Scheduler and Controller Manager",
+        long = "leader-elect"
+    )]
+    #[serde(default)]
+    /// Enable the leader election of the Scheduler and Controller Manager
+    leader_elect: bool,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "1",
+        env = "KUBERNIX_CONTROL_PLANE_REPLICAS",
+        help = "The number of Scheduler and Controller Manager replicas, which \
+                enables the leader election if greater than one",
+        long = "control-plane-replicas",
+        value_name = "REPLICAS"
+    )]
+    #[serde(default = "default_control_plane_replicas")]
+    /// The number of Scheduler and Controller Manager replicas
+    control_plane_replicas: u8,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "default",
+        env = "KUBERNIX_PROFILE",
+        help = "The preset of additional component arguments",
+        long = "profile",
+        raw(possible_values = r#"&["default", "secure", "performance", "minimal"]"#),
+        value_name = "PROFILE"
+    )]
+    #[serde(default = "default_profile")]
+    /// The preset of additional component arguments
+    profile: Profile,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_MAX_REQUESTS_INFLIGHT",
+        help = "The maximum number of non-mutating requests in flight of the API Server",
+        long = "max-requests-inflight",
+        value_name = "REQUESTS"
+    )]
+    /// The maximum number of non-mutating requests in flight of the API Server
+    max_requests_inflight: Option<u32>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_MAX_MUTATING_REQUESTS_INFLIGHT",
+        help = "The maximum number of mutating requests in flight of the API Server",
+        long = "max-mutating-requests-inflight",
+        value_name = "REQUESTS"
+    )]
+    /// The maximum number of mutating requests in flight of the API Server
+    max_mutating_requests_inflight: Option<u32>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_PRIORITY_AND_FAIRNESS",
+        help = "Enable the API Priority and Fairness of the API Server, requires \
+                Kubernetes 1.18 or later",
+        long = "priority-and-fairness"
+    )]
+    #[serde(default)]
+    /// Enable the API Priority and Fairness of the API Server
+    priority_and_fairness: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_KUBE_API_QPS",
+        help = "The QPS of the Controller Manager and kubelet when talking to the API Server",
+        long = "kube-api-qps",
+        value_name = "QPS"
+    )]
+    /// The QPS of the Controller Manager and kubelet when talking to the API Server
+    kube_api_qps: Option<u32>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_KUBE_API_BURST",
+        help = "The burst of the Controller Manager and kubelet when talking to the API Server",
+        long = "kube-api-burst",
+        value_name = "BURST"
+    )]
+    /// The burst of the Controller Manager and kubelet when talking to the API Server
+    kube_api_burst: Option<u32>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_SOAK",
+        help = "Keep the cluster running without a shell, probe all components \
+                periodically, restart unhealthy ones and write the health history \
+                to the root",
+        long = "soak"
+    )]
+    #[serde(default)]
+    /// Keep the cluster running and restart unhealthy components
+    soak: bool,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "iptables",
+        env = "KUBERNIX_PROXY_MODE",
+        help = "The mode of kube-proxy, whereas nftables requires Kubernetes 1.29 or later",
+        long = "proxy-mode",
+        raw(possible_values = r#"&["iptables", "ipvs", "nftables"]"#),
+        value_name = "MODE"
+    )]
+    #[serde(default = "default_proxy_mode")]
+    /// The mode of kube-proxy
+    proxy_mode: ProxyMode,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_NO_KUBE_PROXY",
+        help = "Do not run kube-proxy, for example if a network plugin replaces it",
+        long = "no-kube-proxy"
+    )]
+    #[serde(default)]
+    /// Do not run kube-proxy
+    no_kube_proxy: bool,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "bridge",
+        env = "KUBERNIX_CNI",
+        help = "The network plugin of the cluster, whereas cilium replaces kube-proxy if \
+                used together with --no-kube-proxy",
+        long = "cni",
+        raw(possible_values = r#"&["bridge", "cilium"]"#),
+        value_name = "CNI"
+    )]
+    #[serde(default = "default_cni")]
+    /// The network plugin of the cluster
+    cni: Cni,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_NETWORK_POLICY",
+        help = "Enforce NetworkPolicies by deploying kube-router as firewall for the bridge \
+                network plugin",
+        long = "network-policy"
+    )]
+    #[serde(default)]
+    /// Enforce NetworkPolicies for the bridge network plugin
+    network_policy: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_LB_POOL",
+        help = "The address pool for LoadBalancer services, which is reachable from the host \
+                via the cluster bridge",
+        long = "lb-pool",
+        value_name = "CIDR"
+    )]
+    /// The address pool for LoadBalancer services
+    lb_pool: Option<Ipv4Network>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_KUBELET_SERVING_ROTATION",
+        help = "Let the kubelet request rotated serving certificates from the API Server, \
+                which get approved automatically",
+        long = "kubelet-serving-rotation"
+    )]
+    #[serde(default)]
+    /// Let the kubelet request rotated serving certificates
+    kubelet_serving_rotation: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_DNS_WATCHDOG",
+        help = "Periodically resolve the API Server service via the cluster DNS and restart \
+                CoreDNS if it fails",
+        long = "dns-watchdog"
+    )]
+    #[serde(default)]
+    /// Periodically check the cluster DNS and restart it if it fails
+    dns_watchdog: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_TTL",
+        help = "The lifetime of the cluster, like '2h', after which it gets torn down \
+                automatically",
+        long = "ttl",
+        value_name = "DURATION"
+    )]
+    /// The lifetime of the cluster
+    ttl: Option<HumanDuration>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_MAX_MEMORY",
+        help = "The memory limit of the whole cluster including all pods, like '4G'",
+        long = "max-memory",
+        value_name = "SIZE"
+    )]
+    /// The memory limit of the whole cluster
+    max_memory: Option<MemorySize>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_MAX_CPU",
+        help = "The CPU limit of the whole cluster including all pods, like '2.5'",
+        long = "max-cpu",
+        value_name = "CPUS"
+    )]
+    /// The CPU limit of the whole cluster
+    max_cpu: Option<f64>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_KUBECONFIG_MERGE",
+        help = "Merge the admin credentials into '~/.kube/config' as context \
+                'kubernix-<name>', which gets removed again on teardown",
+        long = "kubeconfig-merge"
+    )]
+    #[serde(default)]
+    /// Merge the admin credentials into the kubeconfig of the user
+    kubeconfig_merge: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_PERSIST_VOLUMES",
+        help = "Provision persistent volumes into the volumes directory, which survives \
+                the recreation of the cluster",
+        long = "persist-volumes"
+    )]
+    #[serde(default)]
+    /// Provision persistent volumes into the volumes directory
+    persist_volumes: bool,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "kubernix-volumes",
+        env = "KUBERNIX_VOLUMES_DIR",
+        help = "Path where persistent volumes are stored, outside of the root",
+        long = "volumes-dir",
+        value_name = "PATH"
+    )]
+    #[serde(default = "default_volumes_dir")]
+    /// Path where persistent volumes are stored
+    volumes_dir: PathBuf,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "plain",
+        env = "KUBERNIX_SECRET_BACKEND",
+        help = "Where generated keys and credentials are stored at rest, whereas 'age' \
+                encrypts them into a file and 'keyring' uses a key of the kernel keyring",
+        long = "secret-backend",
+        raw(possible_values = r#"&["plain", "age", "keyring"]"#),
+        value_name = "BACKEND"
+    )]
+    #[serde(default = "default_secret_backend")]
+    /// Where generated keys and credentials are stored at rest
+    secret_backend: SecretBackend,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_SECRET_AGE_IDENTITY",
+        help = "The age identity file used by the 'age' secret backend",
+        long = "secret-age-identity",
+        value_name = "PATH"
+    )]
+    /// The age identity file used by the 'age' secret backend
+    secret_age_identity: Option<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "auto",
+        env = "KUBERNIX_SELINUX",
+        help = "The SELinux behavior, whereas 'auto' labels the root if SELinux is enforcing, \
+                'permissive-warn' only warns and 'enforcing' requires it",
+        long = "selinux",
+        raw(possible_values = r#"&["auto", "permissive-warn", "enforcing"]"#),
+        value_name = "MODE"
+    )]
+    #[serde(default = "default_selinux")]
+    /// The SELinux behavior
+    selinux: SelinuxMode,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_VM",
+        help = "Run the cluster inside a lightweight virtual machine, like on non-Linux hosts",
+        long = "vm"
+    )]
+    #[serde(default)]
+    /// Run the cluster inside a lightweight virtual machine
+    vm: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_VM_IMAGE",
+        help = "The Linux cloud image with cloud-init and Nix used as base disk of the VM",
+        long = "vm-image",
+        value_name = "PATH"
+    )]
+    /// The Linux cloud image used as base disk of the VM
+    vm_image: Option<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_VM_CLOSURE",
+        help = "The Nix store path of a Linux kubernix build to be copied into the VM, \
+                defaults to the one of the running executable",
+        long = "vm-closure",
+        value_name = "PATH"
+    )]
+    /// The Nix store path of a Linux kubernix build
+    vm_closure: Option<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "2",
+        env = "KUBERNIX_VM_CPUS",
+        help = "The number of CPUs of the VM",
+        long = "vm-cpus",
+        value_name = "CPUS"
+    )]
+    #[serde(default = "default_vm_cpus")]
+    /// The number of CPUs of the VM
+    vm_cpus: u32,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "4G",
+        env = "KUBERNIX_VM_MEMORY",
+        help = "The memory of the VM, like '4G'",
+        long = "vm-memory",
+        value_name = "SIZE"
+    )]
+    #[serde(default = "default_vm_memory")]
+    /// The memory of the VM
+    vm_memory: MemorySize,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_CONTAINERIZED",
+        help = "Adapt the setup for running inside a privileged container, like in CI jobs",
+        long = "containerized"
+    )]
+    #[serde(default)]
+    /// Adapt the setup for running inside a privileged container
+    containerized: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_KUBELET_ONLY",
+        help = "Run only CRI-O and a standalone kubelet without any control plane, which \
+                runs the static pods of its manifests directory",
+        long = "kubelet-only"
+    )]
+    #[serde(default)]
+    /// Run only CRI-O and a standalone kubelet without any control plane
+    kubelet_only: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_CRDS",
+        help = "Directory of CustomResourceDefinitions applied and established before any \
+                other addon",
+        long = "crds",
+        value_name = "PATH"
+    )]
+    /// Directory of CustomResourceDefinitions applied before any other addon
+    crds: Option<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_COMPONENT_VERBOSITY",
+        help = "Log verbosity levels for components, like 'apiserver=4,kubelet=3'",
+        long = "component-verbosity",
+        multiple = true,
+        use_delimiter = true,
+        value_name = "COMPONENT=LEVEL"
+    )]
+    #[serde(default)]
+    /// Log verbosity levels for the components
+    component_verbosity: Vec<Verbosity>,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "kubernix",
+        env = "KUBERNIX_LAYOUT",
+        help = "The layout of the certificates and kubeconfigs, whereas 'kubeadm' \
+                additionally provides them with the directory structure and file \
+                names of kubeadm",
+        long = "layout",
+        raw(possible_values = r#"&["kubernix", "kubeadm"]"#),
+        value_name = "LAYOUT"
+    )]
+    #[serde(default = "default_layout")]
+    /// The layout of the certificates and kubeconfigs
+    layout: Layout,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_CONTROLLERS",
+        help = "Controllers of the Controller Manager passed through as '--controllers', \
+                like '*' or 'bootstrapsigner'",
+        long = "controllers",
+        multiple = true,
+        value_name = "CONTROLLER"
+    )]
+    #[serde(default)]
+    /// Controllers of the Controller Manager to be enabled
+    controllers: Vec<String>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_DISABLE_CONTROLLERS",
+        help = "Controllers of the Controller Manager to be disabled, like 'nodelifecycle' \
+                to prevent pod evictions after suspending the host",
+        long = "disable-controllers",
+        multiple = true,
+        value_name = "CONTROLLER"
+    )]
+    #[serde(default)]
+    /// Controllers of the Controller Manager to be disabled
+    disable_controllers: Vec<String>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_LAPTOP_MODE",
+        help = "Lengthen the node lease and the eviction timeouts, so that suspending the \
+                host does not mark the node as not ready and evict its pods on resume",
+        long = "laptop-mode"
+    )]
+    #[serde(default)]
+    /// Lengthen the node lease and the eviction timeouts
+    laptop_mode: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_AUTO_RECONFIGURE_NETWORK",
+        help = "Regenerate the certificates and kubeconfigs and restart the affected components \
+                if the primary IP of the host changes",
+        long = "auto-reconfigure-network"
+    )]
+    #[serde(default)]
+    /// Reconfigure the cluster if the primary IP of the host changes
+    auto_reconfigure_network: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_SKIP_FIREWALL",
+        help = "Do not add rules for the cluster networks to a detected firewalld or nftables \
+                firewall of the host",
+        long = "skip-firewall"
+    )]
+    #[serde(default)]
+    /// Do not touch the firewall of the host
+    skip_firewall: bool,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "127.0.0.1",
+        env = "KUBERNIX_ETCD_ADDRESS",
+        help = "The loopback address etcd listens on, like 127.0.0.2 to not conflict with an \
+                etcd already running on the host",
+        long = "etcd-address",
+        value_name = "ADDRESS"
+    )]
+    #[serde(default = "default_etcd_address")]
+    /// The loopback address etcd listens on
+    etcd_address: Ipv4Addr,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_ETCD_UNIX_SOCKET",
+        help = "Let etcd listen on unix sockets inside the root instead of TCP ports",
+        long = "etcd-unix-socket"
+    )]
+    #[serde(default)]
+    /// Let etcd listen on unix sockets instead of TCP ports
+    etcd_unix_socket: bool,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "cluster.local",
+        env = "KUBERNIX_CLUSTER_DOMAIN",
+        help = "The DNS domain of the cluster",
+        long = "cluster-domain",
+        value_name = "DOMAIN"
+    )]
+    #[serde(default = "default_cluster_domain")]
+    /// The DNS domain of the cluster
+    cluster_domain: String,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_ANONYMOUS_AUTH",
+        help = "Allow anonymous requests to the API Server and kubelet",
+        long = "anonymous-auth"
+    )]
+    #[serde(default)]
+    /// Allow anonymous requests to the API Server and kubelet
+    anonymous_auth: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_INSECURE_PORT",
+        help = "Serve the legacy insecure port of the API Server on localhost and the \
+                read-only port of the kubelet",
+        long = "insecure-port"
+    )]
+    #[serde(default)]
+    /// Serve the legacy insecure ports of the API Server and kubelet
+    insecure_port: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_PROFILING",
+        help = "Serve the profiling endpoints of the API Server, Controller Manager and Scheduler",
+        long = "profiling"
+    )]
+    #[serde(default)]
+    /// Serve the profiling endpoints of the control plane
+    profiling: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_SEED",
+        help = "Seed for generated secrets and names to get reproducible cluster files, \
+                which must never be used for clusters holding real data",
+        long = "seed",
+        value_name = "SEED"
+    )]
+    /// The seed for deterministic generation of secrets and names
+    seed: Option<u64>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_SCHEDULER_EXTENDER",
+        help = "URL of an HTTP scheduler extender serving the 'filter' and 'prioritize' verbs",
+        long = "scheduler-extender",
+        value_name = "URL"
+    )]
+    /// URL of an HTTP scheduler extender
+    scheduler_extender: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_SECONDARY_SCHEDULER",
+        help = "Path of a scheduler binary supervised alongside the built-in Scheduler",
+        long = "secondary-scheduler",
+        value_name = "PATH"
+    )]
+    /// Path of a scheduler binary supervised alongside the built-in Scheduler
+    secondary_scheduler: Option<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "secondary-scheduler",
+        env = "KUBERNIX_SECONDARY_SCHEDULER_NAME",
+        help = "The scheduler name handled by the secondary scheduler",
+        long = "secondary-scheduler-name",
+        value_name = "NAME"
+    )]
+    #[serde(default = "default_secondary_scheduler_name")]
+    /// The scheduler name handled by the secondary scheduler
+    secondary_scheduler_name: String,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "10",
+        env = "KUBERNIX_LIVENESS_INTERVAL",
+        help = "The interval in seconds between two liveness probes of the running components, \
+                whereas 0 disables them",
+        long = "liveness-interval",
+        value_name = "SECONDS"
+    )]
+    #[serde(default = "default_liveness_interval")]
+    /// The interval in seconds between two liveness probes
+    liveness_interval: u64,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "3",
+        env = "KUBERNIX_LIVENESS_THRESHOLD",
+        help = "The number of consecutive failed liveness probes after which a component is \
+                unhealthy",
+        long = "liveness-threshold",
+        value_name = "COUNT"
+    )]
+    #[serde(default = "default_liveness_threshold")]
+    /// The number of consecutive failed liveness probes of an unhealthy
+    /// component
+    liveness_threshold: u32,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_LIVENESS_RESTART",
+        help = "Restart components which became unhealthy",
+        long = "liveness-restart"
+    )]
+    #[serde(default)]
+    /// Restart components which became unhealthy
+    liveness_restart: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_OVERRIDE_PACKAGES",
+        help = "Packages to be taken from another flake, like \
+                'kubernetes=github:me/nixpkgs#kubernetes'",
+        long = "override-package",
+        multiple = true,
+        value_name = "PACKAGE=FLAKE"
+    )]
+    #[serde(default)]
+    /// Packages to be taken from another flake
+    package_overrides: Vec<PackageOverride>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_LOCAL_BINARIES",
+        help = "Locally built binaries to be run instead of the packaged ones, like \
+                'apiserver=/path/to/kube-apiserver'",
+        long = "local-binary",
+        multiple = true,
+        value_name = "COMPONENT=PATH"
+    )]
+    #[serde(default)]
+    /// Locally built binaries to be run instead of the packaged ones
+    local_binaries: Vec<LocalBinary>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_WATCH_BINARIES",
+        help = "Restart components automatically if their local binary gets rebuilt",
+        long = "watch-binaries"
+    )]
+    #[serde(default)]
+    /// Restart components automatically if their local binary gets rebuilt
+    watch_binaries: bool,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_COVERAGE",
+        help = "Components writing Go coverage data into the root, like 'apiserver,kubelet', \
+                which requires binaries built with '-cover'",
+        long = "coverage",
+        multiple = true,
+        use_delimiter = true,
+        value_name = "COMPONENT"
+    )]
+    #[serde(default)]
+    /// Components writing Go coverage data into the root
+    coverage: Vec<ComponentKind>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_PPROF",
+        help = "Components serving their profiling endpoints, whose profiles get collected into \
+                the root on stop",
+        long = "pprof",
+        multiple = true,
+        use_delimiter = true,
+        value_name = "COMPONENT"
+    )]
+    #[serde(default)]
+    /// Components serving their profiling endpoints
+    pprof: Vec<ComponentKind>,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_LAZY_ADDONS",
+        help = "Addons which get deployed on the first connection to their local port",
+        long = "lazy-addons",
+        multiple = true,
+        raw(possible_values = r#"&["dashboard", "registry", "metrics-server"]"#),
+        use_delimiter = true,
+        value_name = "ADDON"
+    )]
+    #[serde(default)]
+    /// Addons which get deployed on the first connection to their local port
+    lazy_addons: Vec<LazyAddon>,
+
+    #[get = "pub"]
+    #[clap(skip)]
+    #[serde(default)]
+    /// The ports of all components, which are only configurable via the
+    /// configuration file. This has to be one of the last fields since it
+    /// gets serialized as table.
+    ports: Ports,
+
+    #[get = "pub"]
+    #[clap(skip)]
+    #[serde(
+        default,
+        rename = "credential-provider",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    /// Image credential provider plugins of the kubelet, which are only
+    /// configurable via the configuration file. This has to be one of the
+    /// last fields since it gets serialized as array of tables, whereas an
+    /// empty one is skipped to not end up as plain value after the ports.
+    credential_providers: Vec<CredentialProvider>,
+
+    #[get = "pub"]
+    #[clap(skip)]
+    #[serde(default, rename = "process", skip_serializing_if = "Vec::is_empty")]
+    /// Additional processes to be supervised, which are only configurable via
+    /// the configuration file. This has to be the last field since it gets
+    /// serialized as array of tables, whereas an empty one is skipped to not
+    /// end up as plain value after the ports.
+    processes: Vec<ExtraProcess>,
+}
+
+fn default_service_account_issuer() -> String {
+    "https://kubernetes.default.svc".into()
+}
+
+fn default_hook_failure() -> HookFailure {
+    HookFailure::Fatal
+}
+
+fn default_api_record_sample() -> u8 {
+    100
+}
+
+fn default_apiserver_replicas() -> u8 {
+    1
+}
+
+fn default_control_plane_replicas() -> u8 {
+    1
+}
+
+fn default_profile() -> Profile {
+    Profile::Default
+}
+
+fn default_proxy_mode() -> ProxyMode {
+    ProxyMode::Iptables
+}
+
+fn default_cni() -> Cni {
+    Cni::Bridge
+}
+
+fn default_volumes_dir() -> PathBuf {
+    "kubernix-volumes".into()
+}
+
+fn default_secret_backend() -> SecretBackend {
+    SecretBackend::Plain
+}
+
+fn default_selinux() -> SelinuxMode {
+    SelinuxMode::Auto
+}
+
+fn default_vm_cpus() -> u32 {
+    2
+}
+
+fn default_vm_memory() -> MemorySize {
+    MemorySize::from_gib(4)
+}
+
+fn default_secondary_scheduler_name() -> String {
+    "secondary-scheduler".into()
+}
+
+fn default_layout() -> Layout {
+    Layout::Kubernix
+}
+
+fn default_etcd_address() -> Ipv4Addr {
+    Ipv4Addr::LOCALHOST
+}
+
+fn default_cluster_domain() -> String {
+    "cluster.local".into()
+}
+
+fn default_liveness_interval() -> u64 {
+    10
+}
+
+fn default_liveness_threshold() -> u32 {
+    3
+}
+
+fn default_log_color() -> LogColor {
+    LogColor::Auto
+}
+
+/// Possible subcommands
+#[derive(Clap, Clone, Deserialize, Serialize)]
+pub enum SubCommand {
+    /// `shell` subcommand specified
+    #[clap(name = "shell", about = "Spawn an additional shell session")]
+    Shell,
+
+    /// `export-credentials` subcommand specified
+    #[clap(
+        name = "export-credentials",
+        about = "Export the admin credentials and the CA into a single bundle"
+    )]
+    ExportCredentials(ExportCredentials),
+
+    /// `upgrade` subcommand specified
+    #[clap(
+        name = "upgrade",
+        about = "Upgrade Kubernetes of the running cluster via a rolling restart"
+    )]
+    Upgrade(Upgrade),
+
+    /// `bench` subcommand specified
+    #[clap(
+        name = "bench",
+        about = "Benchmark the control plane by creating, patching and deleting objects"
+    )]
+    Bench(Bench),
+
+    /// `netem` subcommand specified
+    #[clap(
+        name = "netem",
+        about = "Shape the pod network of the running cluster by delay, loss and bandwidth"
+    )]
+    Netem(Netem),
+
+    /// `diff` subcommand specified
+    #[clap(
+        name = "diff",
+        about = "Show the configuration changes since the cluster got started"
+    )]
+    Diff(Diff),
+
+    /// `gc` subcommand specified
+    #[clap(
+        name = "gc",
+        about = "Remove old run roots, rotated logs, unused images and stale Nix GC roots"
+    )]
+    Gc(Gc),
+
+    /// `doctor` subcommand specified
+    #[clap(
+        name = "doctor",
+        about = "Diagnose common problems of the running cluster"
+    )]
+    Doctor(Doctor),
+
+    /// `verify` subcommand specified
+    #[clap(
+        name = "verify",
+        about = "Verify that logs, exec, attach and port-forward work for a pod"
+    )]
+    Verify(Verify),
+
+    /// `build-and-load` subcommand specified
+    #[clap(
+        name = "build-and-load",
+        about = "Build a container image and load it into the container runtime of the cluster"
+    )]
+    BuildAndLoad(BuildAndLoad),
+
+    /// `sync` subcommand specified
+    #[clap(
+        name = "sync",
+        about = "Synchronize a local directory into a running pod"
+    )]
+    Sync(Sync),
+
+    /// `apply-crds` subcommand specified
+    #[clap(
+        name = "apply-crds",
+        about = "Apply CustomResourceDefinitions and grant their access to a service account"
+    )]
+    ApplyCrds(ApplyCrds),
+
+    /// `register-apiservice` subcommand specified
+    #[clap(
+        name = "register-apiservice",
+        about = "Register an extension API server running on the local host"
+    )]
+    RegisterApiService(RegisterApiService),
+
+    /// `wait` subcommand specified
+    #[clap(
+        name = "wait",
+        about = "Wait for the cluster to fulfill the provided conditions"
+    )]
+    Wait(Wait),
+
+    /// `check` subcommand specified
+    #[clap(
+        name = "check",
+        about = "Check the conditions of the wait subcommand once without waiting"
+    )]
+    Check(Check),
+
+    /// `status` subcommand specified
+    #[clap(
+        name = "status",
+        about = "Show the status of the running cluster and its components"
+    )]
+    Status(Status),
+
+    /// `list` subcommand specified
+    #[clap(name = "list", about = "List the components of the running cluster")]
+    List(List),
+
+    /// `restart` subcommand specified
+    #[clap(
+        name = "restart",
+        about = "Restart a single component of the running cluster"
+    )]
+    Restart(Restart),
+
+    /// `logs` subcommand specified
+    #[clap(
+        name = "logs",
+        about = "Show the logs of a single component of the running cluster"
+    )]
+    Logs(Logs),
+
+    /// `stop` subcommand specified
+    #[clap(name = "stop", about = "Stop the running cluster")]
+    Stop,
+
+    /// `ui` subcommand specified
+    #[clap(
+        name = "ui",
+        about = "Show an interactive dashboard of the running cluster"
+    )]
+    Ui,
+
+    /// `events` subcommand specified
+    #[clap(
+        name = "events",
+        about = "Show the timeline of significant events of the cluster"
+    )]
+    Events(Events),
+
+    /// `export` subcommand specified
+    #[clap(
+        name = "export",
+        about = "Export the generated setup as standalone scripts or manifests"
+    )]
+    Export(Export),
+
+    /// `token` subcommand specified
+    #[clap(
+        name = "token",
+        about = "Mint a short-lived service account token via the TokenRequest API"
+    )]
+    Token(Token),
+
+    /// `debug` subcommand specified
+    #[clap(
+        name = "debug",
+        about = "Run a single component of the running cluster under the delve debugger"
+    )]
+    Debug(Debug),
+
+    /// `conformance` subcommand specified
+    #[clap(
+        name = "conformance",
+        about = "Run a subset of the upstream conformance tests against the cluster"
+    )]
+    Conformance(Conformance),
+
+    /// `matrix` subcommand specified
+    #[clap(
+        name = "matrix",
+        about = "Boot a cluster for every combination of a matrix and run a command against it"
+    )]
+    Matrix(Matrix),
+
+    /// `migrate-root` subcommand specified
+    #[clap(
+        name = "migrate-root",
+        about = "Move the root of a stopped cluster and fix all absolute paths inside it"
+    )]
+    MigrateRoot(MigrateRoot),
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `export-credentials` subcommand
+pub struct ExportCredentials {
+    #[get = "pub"]
+    #[clap(
+        default_value = "p12",
+        help = "The format of the exported bundle",
+        long = "format",
+        raw(possible_values = r#"&["p12", "pem"]"#),
+        short = "f",
+        value_name = "FORMAT"
+    )]
+    /// The format of the exported bundle
+    format: CredentialsFormat,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The path of the exported bundle",
+        long = "out",
+        short = "o",
+        value_name = "PATH"
+    )]
+    /// The path of the exported bundle
+    out: PathBuf,
+
+    #[get = "pub"]
+    #[clap(
+        env = "KUBERNIX_EXPORT_PASSWORD",
+        help = "The password used to protect the PKCS#12 bundle",
+        long = "password",
+        value_name = "PASSWORD"
+    )]
+    /// The password used to protect the PKCS#12 bundle
+    password: Option<String>,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `upgrade` subcommand
+pub struct Upgrade {
+    #[get = "pub"]
+    #[clap(
+        help = "The Kubernetes version to upgrade to, like '1.16.0'",
+        long = "to",
+        value_name = "VERSION"
+    )]
+    /// The Kubernetes version to upgrade to
+    to: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The Nix sha256 of the source tarball of the version to upgrade to",
+        long = "sha256",
+        requires = "to",
+        value_name = "HASH"
+    )]
+    /// The Nix sha256 of the source tarball of the version to upgrade to
+    sha256: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        help = "Restore the state recorded before the last upgrade",
+        long = "rollback"
+    )]
+    /// Restore the state recorded before the last upgrade
+    rollback: bool,
+
+    #[get = "pub"]
+    #[clap(
+        help = "Allow upgrading to a lower version than the running one",
+        long = "allow-downgrade"
+    )]
+    /// Allow upgrading to a lower version than the running one
+    allow_downgrade: bool,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `diff` subcommand
+pub struct Diff {
+    #[get = "pub"]
+    #[clap(
+        help = "Restart the running cluster to apply the changes",
+        long = "apply"
+    )]
+    /// Restart the running cluster to apply the changes
+    apply: bool,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `gc` subcommand
+pub struct Gc {
+    #[get = "pub"]
+    #[clap(
+        help = "Only list what would be removed without removing anything",
+        long = "dry-run"
+    )]
+    /// Only list what would be removed
+    dry_run: bool,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "7d",
+        help = "Only remove run roots, logs and Nix GC roots which are unused for \
+                longer than this, like '12h' or '7d'",
+        long = "older-than",
+        value_name = "DURATION"
+    )]
+    /// The minimum age of garbage to be removed
+    older_than: HumanDuration,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The directory to search for run roots, defaults to the parent of the root",
+        long = "dir",
+        value_name = "DIR"
+    )]
+    /// The directory to search for run roots
+    dir: Option<PathBuf>,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `wait` subcommand
+pub struct Wait {
+    #[get = "pub"]
+    #[clap(
+        default_value = "120s",
+        help = "The maximum time to wait, like '90s' or '2m'",
+        long = "timeout",
+        short = "t",
+        value_name = "DURATION"
+    )]
+    /// The maximum time to wait
+    timeout: HumanDuration,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The condition to wait for, like 'component=apiserver' or \
+                'condition=nodes-ready', defaults to all components",
+        long = "for",
+        multiple = true,
+        value_name = "CONDITION"
+    )]
+    /// The conditions to wait for
+    conditions: Vec<WaitCondition>,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `check` subcommand
+pub struct Check {
+    #[get = "pub"]
+    #[clap(
+        help = "The condition to check, like 'component=apiserver' or \
+                'condition=nodes-ready', defaults to all components",
+        long = "for",
+        multiple = true,
+        value_name = "CONDITION"
+    )]
+    /// The conditions to check
+    conditions: Vec<WaitCondition>,
+
+    #[get = "pub"]
+    #[clap(flatten)]
+    #[serde(flatten)]
+    // The output format
+    output: Output,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `bench` subcommand
+pub struct Bench {
+    #[get = "pub"]
+    #[clap(
+        default_value = "100",
+        help = "The number of objects per kind",
+        long = "count",
+        short = "n",
+        value_name = "COUNT"
+    )]
+    /// The number of objects per kind
+    count: u32,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The kinds of objects to benchmark, defaults to configmap",
+        long = "kind",
+        multiple = true,
+        raw(possible_values = r#"&["configmap", "secret", "serviceaccount"]"#),
+        value_name = "KIND"
+    )]
+    /// The kinds of objects to benchmark
+    kinds: Vec<BenchKind>,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "kubernix-bench",
+        help = "The namespace to be used, which gets removed afterwards",
+        long = "namespace",
+        value_name = "NAMESPACE"
+    )]
+    /// The namespace to be used
+    namespace: String,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `verify` subcommand
+pub struct Verify {
+    #[get = "pub"]
+    #[clap(
+        default_value = "docker.io/library/busybox:1.31",
+        help = "The image of the verification pod, which has to provide busybox",
+        long = "image",
+        value_name = "IMAGE"
+    )]
+    /// The image of the verification pod
+    image: String,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "kubernix-verify",
+        help = "The namespace to be used, which gets removed afterwards",
+        long = "namespace",
+        value_name = "NAMESPACE"
+    )]
+    /// The namespace to be used
+    namespace: String,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "120s",
+        help = "The maximum time to wait for the pod, like '90s' or '2m'",
+        long = "timeout",
+        short = "t",
+        value_name = "DURATION"
+    )]
+    /// The maximum time to wait for the pod
+    timeout: HumanDuration,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `build-and-load` subcommand
+pub struct BuildAndLoad {
+    #[get = "pub"]
+    #[clap(
+        help = "The name and tag of the image, like 'localhost/app:dev'",
+        long = "tag",
+        short = "t",
+        value_name = "TAG"
+    )]
+    /// The name and tag of the image
+    tag: String,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The Dockerfile or Containerfile, defaults to the one inside the context",
+        long = "file",
+        short = "f",
+        value_name = "PATH"
+    )]
+    /// The Dockerfile or Containerfile
+    file: Option<PathBuf>,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = ".",
+        help = "The build context directory",
+        value_name = "CONTEXT"
+    )]
+    /// The build context directory
+    context: PathBuf,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `sync` subcommand
+pub struct Sync {
+    #[get = "pub"]
+    #[clap(
+        help = "The local directory to be synchronized",
+        value_name = "LOCAL_DIR"
+    )]
+    /// The local directory to be synchronized
+    local: PathBuf,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The pod directory to synchronize into, like 'namespace/pod:/path'",
+        value_name = "TARGET"
+    )]
+    /// The pod directory to synchronize into
+    target: SyncTarget,
+
+    #[get = "pub"]
+    #[clap(
+        help = "Keep running and synchronize again on every local change",
+        long = "watch",
+        short = "w"
+    )]
+    #[serde(default)]
+    /// Keep running and synchronize again on every local change
+    watch: bool,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "1s",
+        help = "The interval between two checks for local changes",
+        long = "interval",
+        value_name = "DURATION"
+    )]
+    /// The interval between two checks for local changes
+    interval: HumanDuration,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `apply-crds` subcommand
+pub struct ApplyCrds {
+    #[get = "pub"]
+    #[clap(
+        help = "The directory containing the CustomResourceDefinitions",
+        value_name = "DIR"
+    )]
+    /// The directory containing the CustomResourceDefinitions
+    dir: PathBuf,
+
+    #[get = "pub"]
+    #[clap(
+        help = "Wait until all CustomResourceDefinitions are established",
+        long = "wait-established"
+    )]
+    /// Wait until all CustomResourceDefinitions are established
+    wait_established: bool,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "60s",
+        help = "The maximum time to wait, like '90s' or '2m'",
+        long = "timeout",
+        short = "t",
+        value_name = "DURATION"
+    )]
+    /// The maximum time to wait
+    timeout: HumanDuration,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The service account granted full access to the custom resources, like \
+                'operators/my-operator'",
+        long = "service-account",
+        value_name = "[NAMESPACE/]NAME"
+    )]
+    /// The service account granted full access to the custom resources
+    service_account: Option<String>,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `register-apiservice` subcommand
+pub struct RegisterApiService {
+    #[get = "pub"]
+    #[clap(
+        help = "The API service to be registered, like 'v1alpha1.metrics.example.com'",
+        value_name = "VERSION.GROUP"
+    )]
+    /// The API service to be registered
+    apiservice: String,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "8443",
+        help = "The local port of the extension API server",
+        long = "port",
+        short = "p",
+        value_name = "PORT"
+    )]
+    /// The local port of the extension API server
+    port: u16,
 
     #[get = "pub"]
     #[clap(
-        default_value = "kubernix-run",
-        env = "KUBERNIX_RUN",
-        global = true,
-        help = "Path where all the runtime data is stored",
-        long = "root",
-        short = "r",
+        help = "The CA certificate to verify the extension API server, which skips the \
+                verification if not provided",
+        long = "ca-bundle",
         value_name = "PATH"
     )]
-    /// The root path during runtime
-    root: PathBuf,
+    /// The CA certificate to verify the extension API server
+    ca_bundle: Option<PathBuf>,
 
     #[get = "pub"]
     #[clap(
-        default_value = "info",
-        env = "KUBERNIX_LOG_LEVEL",
-        help = "Set the log level verbosity",
-        long = "log-level",
-        raw(possible_values = r#"&["trace", "debug", "info", "warn", "error", "off"]"#),
-        short = "l",
-        value_name = "LEVEL"
+        default_value = "1000",
+        help = "The priority of the group in relation to other groups",
+        long = "group-priority-minimum",
+        value_name = "PRIORITY"
     )]
-    /// The logging level of the application
-    log_level: LevelFilter,
+    /// The priority of the group in relation to other groups
+    group_priority_minimum: u32,
 
     #[get = "pub"]
     #[clap(
-        default_value = "10.10.0.0/16",
-        env = "KUBERNIX_CIDR",
-        help = "The CIDR used for the cluster",
-        long = "cidr",
-        short = "c",
-        value_name = "CIDR"
+        default_value = "15",
+        help = "The priority of the version within its group",
+        long = "version-priority",
+        value_name = "PRIORITY"
     )]
-    /// The CIDR used for the cluster
-    cidr: Ipv4Network,
+    /// The priority of the version within its group
+    version_priority: u32,
 
+    #[get = "pub"]
+    #[clap(help = "Remove a previously registered API service", long = "delete")]
+    /// Remove a previously registered API service
+    delete: bool,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `restart` subcommand
+pub struct Restart {
     #[get = "pub"]
     #[clap(
-        env = "KUBERNIX_OVERLAY",
-        help = "The Nix package overlay to be used",
-        long = "overlay",
+        help = "The component to be restarted, like 'apiserver'",
+        value_name = "COMPONENT"
+    )]
+    /// The component to be restarted
+    component: String,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `debug` subcommand
+pub struct Debug {
+    #[get = "pub"]
+    #[clap(
+        help = "The component to be debugged, like 'scheduler'",
+        value_name = "COMPONENT"
+    )]
+    /// The component to be debugged
+    component: String,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `conformance` subcommand
+pub struct Conformance {
+    #[get = "pub"]
+    #[clap(
+        default_value = r"\[Conformance\]",
+        help = "The regular expression of the tests to be run",
+        long = "focus",
+        short = "f",
+        value_name = "REGEX"
+    )]
+    /// The regular expression of the tests to be run
+    focus: String,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = r"\[Serial\]|\[Disruptive\]|\[Slow\]|\[Flaky\]",
+        help = "The regular expression of the tests to be skipped",
+        long = "skip",
+        short = "s",
+        value_name = "REGEX"
+    )]
+    /// The regular expression of the tests to be skipped
+    skip: String,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The image running the tests, defaults to the conformance image of the \
+                cluster version",
+        long = "image",
+        value_name = "IMAGE"
+    )]
+    /// The image running the tests
+    image: Option<String>,
+
+    #[get = "pub"]
+    #[clap(help = "Run the tests in parallel", long = "parallel", short = "p")]
+    /// Run the tests in parallel
+    parallel: bool,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "kubernix-conformance",
+        help = "The namespace of the test pod, which gets removed afterwards",
+        long = "namespace",
+        value_name = "NAMESPACE"
+    )]
+    /// The namespace of the test pod
+    namespace: String,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "1h",
+        help = "The maximum time to wait for the tests, like '30m' or '2h'",
+        long = "timeout",
+        short = "t",
+        value_name = "DURATION"
+    )]
+    /// The maximum time to wait for the tests
+    timeout: HumanDuration,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `events` subcommand
+pub struct Events {
+    #[get = "pub"]
+    #[clap(help = "Wait for new events", long = "follow", short = "f")]
+    /// Wait for new events
+    follow: bool,
+
+    #[get = "pub"]
+    #[clap(flatten)]
+    #[serde(flatten)]
+    // The output format
+    output: Output,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `status` subcommand
+pub struct Status {
+    #[get = "pub"]
+    #[clap(flatten)]
+    #[serde(flatten)]
+    // The output format
+    output: Output,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `list` subcommand
+pub struct List {
+    #[get = "pub"]
+    #[clap(flatten)]
+    #[serde(flatten)]
+    // The output format
+    output: Output,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The output format of the informational subcommands
+pub struct Output {
+    #[get = "pub"]
+    #[clap(
+        default_value = "text",
+        help = "The output format, whereas 'json' and 'yaml' are meant for scripts",
+        long = "output",
+        raw(possible_values = r#"&["text", "json", "yaml"]"#),
         short = "o",
-        value_name = "PATH"
+        value_name = "FORMAT"
     )]
-    /// The Nix package overlay to be used
-    overlay: Option<PathBuf>,
+    #[serde(rename = "output")]
+    /// The output format
+    format: OutputFormat,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `doctor` subcommand
+pub struct Doctor {
+    #[get = "pub"]
+    #[clap(flatten)]
+    #[serde(flatten)]
+    // The output format
+    output: Output,
+}
 
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `export` subcommand
+pub struct Export {
     #[get = "pub"]
     #[clap(
-        help = "Do not clear the current env during bootstrap",
-        long = "impure",
-        short = "i"
+        default_value = "shell",
+        help = "The format of the exported bundle",
+        long = "format",
+        raw(possible_values = r#"&["shell", "systemd", "compose"]"#),
+        short = "f",
+        value_name = "FORMAT"
     )]
-    /// Do not clear the current env during bootstrap
-    impure: bool,
+    /// The format of the exported bundle
+    format: ExportFormat,
 
     #[get = "pub"]
     #[clap(
-        env = "KUBERNIX_PACKAGES",
-        help = "Additional Nix dependencies to be added to the environment",
-        long = "packages",
+        help = "The directory of the exported bundle",
+        long = "out",
+        short = "o",
+        value_name = "DIR"
+    )]
+    /// The directory of the exported bundle
+    out: PathBuf,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `logs` subcommand
+pub struct Logs {
+    #[get = "pub"]
+    #[clap(
+        help = "The component to show the logs for, like 'apiserver'",
+        value_name = "COMPONENT"
+    )]
+    /// The component to show the logs for
+    component: String,
+
+    #[get = "pub"]
+    #[clap(help = "Follow the log output", long = "follow", short = "f")]
+    /// Follow the log output
+    follow: bool,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `token` subcommand
+pub struct Token {
+    #[get = "pub"]
+    #[clap(
+        help = "The service account to mint the token for",
+        value_name = "SERVICE_ACCOUNT"
+    )]
+    /// The service account to mint the token for
+    service_account: String,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "default",
+        help = "The namespace of the service account",
+        long = "namespace",
+        short = "n",
+        value_name = "NAMESPACE"
+    )]
+    /// The namespace of the service account
+    namespace: String,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The intended audience of the token, defaults to the API Server",
+        long = "audience",
         multiple = true,
+        short = "a",
+        value_name = "AUDIENCE"
+    )]
+    /// The intended audiences of the token
+    audiences: Vec<String>,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "10m",
+        help = "The lifetime of the token, at least '10m'",
+        long = "duration",
+        short = "d",
+        value_name = "DURATION"
+    )]
+    /// The lifetime of the token
+    duration: HumanDuration,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `matrix` subcommand
+pub struct Matrix {
+    #[get = "pub"]
+    #[clap(
+        help = "The TOML file specifying the command and the axes of the matrix",
+        long = "spec",
+        value_name = "FILE"
+    )]
+    /// The file specifying the matrix
+    spec: PathBuf,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "1",
+        help = "The amount of clusters running at the same time, which get \
+                distinct ports and CIDRs if greater than one",
+        long = "parallel",
         short = "p",
-        value_name = "PACKAGE"
+        value_name = "COUNT"
     )]
-    /// Additional dependencies to be added to the environment
-    packages: Vec<String>,
+    /// The amount of clusters running at the same time
+    parallel: usize,
+
+    #[get = "pub"]
+    #[clap(
+        default_value = "15m",
+        help = "The maximum time to wait for a single cluster to be up, like '10m'",
+        long = "timeout",
+        short = "t",
+        value_name = "DURATION"
+    )]
+    /// The maximum time to wait for a single cluster
+    timeout: HumanDuration,
 }
 
-/// Possible subcommands
-#[derive(Clap, Deserialize, Serialize)]
-pub enum SubCommand {
-    /// `shell` subcommand specified
-    #[clap(name = "shell", about = "Spawn an additional shell session")]
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `migrate-root` subcommand
+pub struct MigrateRoot {
+    #[get = "pub"]
+    #[clap(help = "The current root of the cluster", value_name = "OLD_ROOT")]
+    /// The current root of the cluster
+    old_root: PathBuf,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The new root of the cluster, which must not exist",
+        value_name = "NEW_ROOT"
+    )]
+    /// The new root of the cluster
+    new_root: PathBuf,
+}
+
+#[derive(Clap, Clone, Deserialize, Getters, Serialize)]
+/// The arguments of the `netem` subcommand
+pub struct Netem {
+    #[get = "pub"]
+    #[clap(
+        help = "The network interface to be shaped, defaults to the cluster bridge or \
+                eth0 inside a network namespace",
+        long = "interface",
+        short = "i",
+        value_name = "INTERFACE"
+    )]
+    /// The network interface to be shaped
+    interface: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The network namespace of a pod to be shaped, like listed by `ip netns`",
+        long = "netns",
+        value_name = "NAME"
+    )]
+    /// The network namespace of a pod to be shaped
+    netns: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The delay added to every packet in milliseconds",
+        long = "delay",
+        value_name = "MS"
+    )]
+    /// The delay added to every packet in milliseconds
+    delay: Option<u32>,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The random variation of the delay in milliseconds",
+        long = "jitter",
+        requires = "delay",
+        value_name = "MS"
+    )]
+    /// The random variation of the delay in milliseconds
+    jitter: Option<u32>,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The percentage of randomly dropped packets",
+        long = "loss",
+        value_name = "PERCENT"
+    )]
+    /// The percentage of randomly dropped packets
+    loss: Option<f32>,
+
+    #[get = "pub"]
+    #[clap(
+        help = "The bandwidth limit, like '1mbit' or '500kbit'",
+        long = "rate",
+        value_name = "RATE"
+    )]
+    /// The bandwidth limit
+    rate: Option<String>,
+
+    #[get = "pub"]
+    #[clap(
+        help = "Remove all previously applied rules",
+        long = "clear",
+        raw(conflicts_with_all = r#"&["delay", "jitter", "loss", "rate"]"#)
+    )]
+    /// Remove all previously applied rules
+    clear: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Possible conditions to wait for
+pub enum WaitCondition {
+    /// A single component is ready
+    Component(ComponentKind),
+
+    /// All nodes are ready
+    NodesReady,
+}
+
+impl Display for WaitCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WaitCondition::Component(kind) => write!(f, "component={}", kind),
+            WaitCondition::NodesReady => write!(f, "condition=nodes-ready"),
+        }
+    }
+}
+
+impl FromStr for WaitCondition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let mut split = s.splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some("component"), Some(kind)) => Ok(WaitCondition::Component(kind.parse()?)),
+            (Some("condition"), Some("nodes-ready")) => Ok(WaitCondition::NodesReady),
+            _ => Err(format_err!(
+                "Invalid wait condition '{}', expected 'component=<name>' or \
+                 'condition=nodes-ready'",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Possible credential export formats
+pub enum CredentialsFormat {
+    /// PKCS#12 bundle containing the admin key pair and the CA
+    P12,
+
+    /// Concatenated PEM bundle containing the CA and the admin key pair
+    Pem,
+}
+
+impl FromStr for CredentialsFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "p12" => Ok(CredentialsFormat::P12),
+            "pem" => Ok(CredentialsFormat::Pem),
+            _ => Err(format_err!("Unsupported credentials format '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Possible setup export formats
+pub enum ExportFormat {
+    /// Shell scripts and a script starting all of them
     Shell,
+
+    /// Shell scripts and systemd units
+    Systemd,
+
+    /// Shell scripts and a compose file
+    Compose,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "shell" => Ok(ExportFormat::Shell),
+            "systemd" => Ok(ExportFormat::Systemd),
+            "compose" => Ok(ExportFormat::Compose),
+            _ => Err(format_err!("Unsupported export format '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Possible output formats of the informational subcommands
+pub enum OutputFormat {
+    /// Human readable log messages
+    Text,
+
+    /// JSON, whereas streamed items are written one per line
+    Json,
+
+    /// YAML, whereas streamed items are written as sequence entries
+    Yaml,
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+            OutputFormat::Yaml => "yaml",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "yaml" => Ok(OutputFormat::Yaml),
+            _ => Err(format_err!("Unsupported output format '{}'", s)),
+        }
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
-        Self::parse()
+        let mut config = Self::parse();
+        config.apply_name();
+        config
     }
 }
 
 impl Config {
-    const FILENAME: &'static str = "kubernix.toml";
+    /// The file name of the configuration within the root directory
+    pub const FILENAME: &'static str = "kubernix.toml";
+
+    /// The port offset between the replicas of a component
+    pub const REPLICA_PORT_OFFSET: u16 = 100;
+
+    /// Check if the Scheduler and Controller Manager use leader election,
+    /// which is always the case for multiple replicas
+    pub fn leader_election(&self) -> bool {
+        self.leader_elect || self.control_plane_replicas > 1
+    }
 
     /// Make the configs root path absolute
     pub fn canonicalize_root(&mut self) -> Fallible<()> {
@@ -130,9 +2488,40 @@ impl Config {
         Ok(())
     }
 
+    /// The backend spawning all supervised processes, which defaults to the
+    /// real one
+    pub fn backend(&self) -> Arc<dyn ProcessBackend> {
+        self.backend
+            .clone()
+            .unwrap_or_else(|| Arc::new(RealBackend))
+    }
+
+    /// Use the provided backend for all supervised processes of the cluster,
+    /// for example to fake or fail single components
+    pub fn set_backend(&mut self, backend: Arc<dyn ProcessBackend>) {
+        self.backend = Some(backend);
+    }
+
+    /// Replace the cluster CIDR and persist it, so that continued runs use
+    /// the same network
+    pub fn update_cidr(&mut self, cidr: Ipv4Network) -> Fallible<()> {
+        self.cidr = cidr;
+        self.to_file()
+    }
+
+    /// Replace the ports of all components and persist them
+    pub fn update_ports(&mut self, ports: Ports) -> Fallible<()> {
+        self.ports = ports;
+        self.to_file()
+    }
+
     /// Read the configuration from the internal set root path
     pub fn update_from_file(&mut self) -> Fallible<()> {
         let file = self.root().join(Self::FILENAME);
+        let subcommand = self.subcommand.take();
+        let resume = self.resume;
+        let dry_run = self.dry_run;
+        let backend = self.backend.take();
         *self = toml::from_str(&read_to_string(&file).map_err(|e| {
             format_err!(
                 "Unable to read expected configuration file '{}': {}",
@@ -141,9 +2530,22 @@ impl Config {
             )
         })?)
         .map_err(|e| format_err!("Unable to load config file '{}': {}", file.display(), e))?;
+        self.subcommand = subcommand;
+        self.resume = resume;
+        self.dry_run = dry_run;
+        self.backend = backend;
         Ok(())
     }
 
+    /// Suffix the root path with the name of the cluster, if provided
+    fn apply_name(&mut self) {
+        if let Some(name) = &self.name {
+            let mut root = self.root.clone().into_os_string();
+            root.push(format!("-{}", name));
+            self.root = root.into();
+        }
+    }
+
     fn create_root_dir(&self) -> Fallible<()> {
         create_dir_all(self.root())
             .map_err(|e| format_err!("Unable to create root directory: {}", e))
@@ -175,6 +2577,12 @@ pub mod tests {
         Ok(c)
     }
 
+    pub fn test_config_seed() -> Fallible<Config> {
+        let mut c = test_config()?;
+        c.seed = Some(42);
+        Ok(c)
+    }
+
     #[test]
     fn canonicalize_root_success() -> Fallible<()> {
         let mut c = Config::default();
@@ -189,6 +2597,18 @@ pub mod tests {
         assert!(c.canonicalize_root().is_err())
     }
 
+    #[test]
+    fn apply_name_success() {
+        let mut c = Config::default();
+        c.root = "kubernix-run".into();
+        c.apply_name();
+        assert_eq!(c.root(), Path::new("kubernix-run"));
+
+        c.name = Some("dev".into());
+        c.apply_name();
+        assert_eq!(c.root(), Path::new("kubernix-run-dev"));
+    }
+
     #[test]
     fn to_file_success() -> Fallible<()> {
         let mut c = Config::default();
@@ -196,6 +2616,23 @@ pub mod tests {
         c.to_file()
     }
 
+    #[test]
+    fn to_file_success_roundtrip() -> Fallible<()> {
+        let mut c = Config::default();
+        c.root = tempdir()?.into_path();
+        c.to_file()?;
+
+        let mut d = Config::default();
+        d.root = c.root.clone();
+        d.update_from_file()?;
+        assert_eq!(d.root(), c.root());
+        assert_eq!(d.ports(), c.ports());
+        assert!(d.credential_providers().is_empty());
+        assert!(d.processes().is_empty());
+        assert_eq!(toml::to_string(&d)?, toml::to_string(&c)?);
+        Ok(())
+    }
+
     #[test]
     fn to_file_failure() {
         let mut c = Config::default();
@@ -224,6 +2661,34 @@ packages = []
         Ok(())
     }
 
+    #[test]
+    fn credentials_format_from_str() -> Fallible<()> {
+        assert_eq!("p12".parse::<CredentialsFormat>()?, CredentialsFormat::P12);
+        assert_eq!("pem".parse::<CredentialsFormat>()?, CredentialsFormat::Pem);
+        assert!("der".parse::<CredentialsFormat>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn wait_condition_from_str_success() -> Fallible<()> {
+        assert_eq!(
+            "component=apiserver".parse::<WaitCondition>()?,
+            WaitCondition::Component(ComponentKind::ApiServer)
+        );
+        assert_eq!(
+            "condition=nodes-ready".parse::<WaitCondition>()?,
+            WaitCondition::NodesReady
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn wait_condition_from_str_failure() {
+        assert!("component=invalid".parse::<WaitCondition>().is_err());
+        assert!("condition=invalid".parse::<WaitCondition>().is_err());
+        assert!("invalid".parse::<WaitCondition>().is_err());
+    }
+
     #[test]
     fn update_from_file_failure() -> Fallible<()> {
         let mut c = Config::default();
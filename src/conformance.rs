@@ -0,0 +1,299 @@
+use crate::{
+    config::Conformance as ConformanceArgs, kubeconfig::KubeConfig, process::Process,
+    upgrade::Version, Config, Fallible,
+};
+use log::{debug, error, info};
+use serde_json::{json, to_string_pretty, Value};
+use std::{
+    fs::{self, create_dir_all, remove_dir_all},
+    path::PathBuf,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// A run of the upstream conformance tests inside the cluster
+pub struct Conformance<'a> {
+    args: &'a ConformanceArgs,
+    admin: PathBuf,
+    results: PathBuf,
+}
+
+#[derive(Debug, Default, PartialEq)]
+/// The summarized results of a single test run
+struct Summary {
+    passed: usize,
+    skipped: usize,
+    failed: Vec<String>,
+}
+
+impl<'a> Conformance<'a> {
+    /// The directory of the test results within the root
+    const DIR: &'static str = "conformance";
+
+    /// The name of the test pod, its service account and role binding
+    const POD: &'static str = "kubernix-conformance";
+
+    /// The directory the test pod writes its results to
+    const RESULTS_DIR: &'static str = "/tmp/results";
+
+    /// The JUnit report written by the tests
+    const REPORT: &'static str = "junit_01.xml";
+
+    /// The interval of checking whether the tests are done
+    const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+    /// Run the tests with the provided arguments and summarize the results
+    pub fn run(config: &Config, args: &ConformanceArgs) -> Fallible<()> {
+        let results = config.root().join(Self::DIR);
+        if results.exists() {
+            remove_dir_all(&results)?;
+        }
+        create_dir_all(&results)?;
+        let conformance = Conformance {
+            args,
+            admin: KubeConfig::admin_path(config),
+            results,
+        };
+
+        let image = match args.image() {
+            Some(x) => x.clone(),
+            None => format!("k8s.gcr.io/conformance:v{}", conformance.version()?),
+        };
+        info!(
+            "Running conformance tests matching '{}' via image '{}'",
+            args.focus(),
+            image
+        );
+        let result = conformance.setup(&image).and_then(|_| conformance.wait());
+        conformance.cleanup();
+        result?;
+
+        let report = conformance.results.join(Self::REPORT);
+        let summary = Summary::parse(
+            &fs::read_to_string(&report)
+                .map_err(|e| format_err!("Unable to read '{}': {}", report.display(), e))?,
+        );
+        info!(
+            "Conformance tests finished: {} passed, {} failed, {} skipped",
+            summary.passed,
+            summary.failed.len(),
+            summary.skipped
+        );
+        for test in &summary.failed {
+            error!("Failed: {}", test);
+        }
+        info!(
+            "Test results written to '{}'",
+            conformance.results.display()
+        );
+        if !summary.failed.is_empty() {
+            bail!("{} conformance tests failed", summary.failed.len())
+        }
+        Ok(())
+    }
+
+    /// Retrieve the version of the running API Server, which selects the
+    /// matching conformance image
+    fn version(&self) -> Fallible<Version> {
+        let output: Value =
+            serde_json::from_str(&self.kubectl(&["version", "--output=json"], None)?)?;
+        output["serverVersion"]["gitVersion"]
+            .as_str()
+            .ok_or_else(|| format_err!("Unable to retrieve the API Server version"))?
+            .parse()
+    }
+
+    /// Create the test pod and its permissions
+    fn setup(&self, image: &str) -> Fallible<()> {
+        let manifest = self.manifest(image);
+        debug!("Conformance manifest: {}", manifest);
+        self.kubectl(&["apply", "-f", "-"], Some(&to_string_pretty(&manifest)?))?;
+        Ok(())
+    }
+
+    /// Wait for the test pod to terminate
+    fn wait(&self) -> Fallible<()> {
+        let start = Instant::now();
+        loop {
+            let phase = self.kubectl(
+                &[
+                    "get",
+                    "pod",
+                    Self::POD,
+                    &format!("--namespace={}", self.args.namespace()),
+                    "--output=jsonpath={.status.phase}",
+                ],
+                None,
+            )?;
+            match phase.trim() {
+                // The pod fails if any test failed
+                "Succeeded" | "Failed" => return Ok(()),
+                x => debug!("Conformance pod is in phase '{}'", x),
+            }
+            if start.elapsed() >= self.args.timeout().duration() {
+                bail!(
+                    "Conformance tests did not finish within {}",
+                    self.args.timeout()
+                )
+            }
+            sleep(Self::POLL_INTERVAL);
+        }
+    }
+
+    /// Remove the test pod and its permissions
+    fn cleanup(&self) {
+        let cleanups: &[&[&str]] = &[
+            &["delete", "namespace", self.args.namespace(), "--wait=false"],
+            &["delete", "clusterrolebinding", Self::POD],
+        ];
+        for args in cleanups {
+            if let Err(e) = self.kubectl(args, None) {
+                debug!("Unable to remove conformance resources: {}", e);
+            }
+        }
+    }
+
+    /// Render the namespace, permissions and pod running the tests, which
+    /// writes its results directly into the root
+    fn manifest(&self, image: &str) -> Value {
+        let namespace = self.args.namespace();
+        let env = |name: &str, value: &str| json!({ "name": name, "value": value });
+        json!({
+            "apiVersion": "v1",
+            "kind": "List",
+            "items": [
+                {
+                    "apiVersion": "v1",
+                    "kind": "Namespace",
+                    "metadata": { "name": namespace },
+                },
+                {
+                    "apiVersion": "v1",
+                    "kind": "ServiceAccount",
+                    "metadata": { "name": Self::POD, "namespace": namespace },
+                },
+                {
+                    "apiVersion": "rbac.authorization.k8s.io/v1",
+                    "kind": "ClusterRoleBinding",
+                    "metadata": { "name": Self::POD },
+                    "roleRef": {
+                        "apiGroup": "rbac.authorization.k8s.io",
+                        "kind": "ClusterRole",
+                        "name": "cluster-admin",
+                    },
+                    "subjects": [{
+                        "kind": "ServiceAccount",
+                        "name": Self::POD,
+                        "namespace": namespace,
+                    }],
+                },
+                {
+                    "apiVersion": "v1",
+                    "kind": "Pod",
+                    "metadata": { "name": Self::POD, "namespace": namespace },
+                    "spec": {
+                        "serviceAccountName": Self::POD,
+                        "restartPolicy": "Never",
+                        "tolerations": [{ "operator": "Exists" }],
+                        "containers": [{
+                            "name": "conformance",
+                            "image": image,
+                            "env": [
+                                env("E2E_FOCUS", self.args.focus()),
+                                env("E2E_SKIP", self.args.skip()),
+                                env("E2E_PARALLEL", &self.args.parallel().to_string()),
+                                env("E2E_PROVIDER", "local"),
+                                env("E2E_USE_GO_RUNNER", "true"),
+                                env("RESULTS_DIR", Self::RESULTS_DIR),
+                            ],
+                            "volumeMounts": [{
+                                "name": "results",
+                                "mountPath": Self::RESULTS_DIR,
+                            }],
+                        }],
+                        "volumes": [{
+                            "name": "results",
+                            "hostPath": {
+                                "path": self.results.display().to_string(),
+                                "type": "Directory",
+                            },
+                        }],
+                    },
+                },
+            ],
+        })
+    }
+
+    fn kubectl(&self, args: &[&str], input: Option<&str>) -> Fallible<String> {
+        let mut kubectl = Process::run_to_completion("kubectl")
+            .arg(format!("--kubeconfig={}", self.admin.display()))
+            .args(args);
+        if let Some(input) = input {
+            kubectl = kubectl.input(input);
+        }
+        kubectl.run()
+    }
+}
+
+impl Summary {
+    /// Summarize the provided JUnit report
+    fn parse(report: &str) -> Summary {
+        let mut summary = Summary::default();
+        for testcase in report.split("<testcase ").skip(1) {
+            let testcase = testcase.split("</testcase>").next().unwrap_or_default();
+            if testcase.contains("<failure") || testcase.contains("<error") {
+                summary.failed.push(Self::name(testcase));
+            } else if testcase.contains("<skipped") {
+                summary.skipped += 1;
+            } else {
+                summary.passed += 1;
+            }
+        }
+        summary
+    }
+
+    /// Retrieve the unescaped name of a single test case
+    fn name(testcase: &str) -> String {
+        testcase
+            .split("name=\"")
+            .nth(1)
+            .and_then(|x| x.split('"').next())
+            .unwrap_or_default()
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_success() {
+        let report = r#"<?xml version="1.0" encoding="UTF-8"?>
+  <testsuite name="Kubernetes e2e suite" tests="4" skipped="1" failures="1" errors="0" time="42.1">
+      <testcase name="[sig-node] Pods should get a host IP [NodeConformance] [Conformance]" classname="Kubernetes e2e suite" time="3.2"></testcase>
+      <testcase name="[sig-network] DNS should provide &quot;DNS&quot; for services  [Conformance]" classname="Kubernetes e2e suite" time="20.5">
+          <failure type="Failure">timed out</failure>
+      </testcase>
+      <testcase name="[sig-apps] Deployment should run [Conformance]" classname="Kubernetes e2e suite" time="18.4"></testcase>
+      <testcase name="[sig-storage] Slow test [Slow]" classname="Kubernetes e2e suite" time="0">
+          <skipped></skipped>
+      </testcase>
+  </testsuite>"#;
+        assert_eq!(
+            Summary::parse(report),
+            Summary {
+                passed: 2,
+                skipped: 1,
+                failed: vec![
+                    "[sig-network] DNS should provide \"DNS\" for services  [Conformance]".into()
+                ],
+            }
+        );
+        assert_eq!(Summary::parse(""), Summary::default());
+    }
+}
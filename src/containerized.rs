@@ -0,0 +1,143 @@
+use crate::{Config, Fallible};
+use log::{info, warn};
+use nix::{
+    mount::{mount, MsFlags},
+    unistd::{access, AccessFlags},
+};
+use proc_mounts::MountIter;
+use std::{fs::read_to_string, os::unix::fs::symlink, path::Path};
+
+/// Support for running kubernix inside a container, like a CI job which only
+/// provides Docker
+pub struct Containerized;
+
+impl Containerized {
+    /// The kernel log device required by the kubelet OOM watcher
+    const KMSG: &'static str = "/dev/kmsg";
+
+    /// The alternative kernel log device provided by container runtimes
+    const CONSOLE: &'static str = "/dev/console";
+
+    /// The cgroup file system, which has to be writable
+    const CGROUP_DIR: &'static str = "/sys/fs/cgroup";
+
+    /// Markers found in the cgroup paths of containerized processes
+    const CGROUP_MARKERS: [&'static str; 5] = ["docker", "kubepods", "containerd", "libpod", "lxc"];
+
+    /// Check if kubernix is running inside a container
+    pub fn detect() -> bool {
+        Path::new("/.dockerenv").exists()
+            || Path::new("/run/.containerenv").exists()
+            || read_to_string("/proc/1/environ")
+                .map(|x| x.split('\0').any(|x| x.starts_with("container=")))
+                .unwrap_or(false)
+            || read_to_string("/proc/1/cgroup")
+                .map(|x| Self::is_container_cgroup(&x))
+                .unwrap_or(false)
+    }
+
+    /// Verify that the container provides everything required
+    pub fn preflight(config: &Config) -> Fallible<()> {
+        let detected = Self::detect();
+        if !*config.containerized() {
+            if detected {
+                warn!("Running inside a container, consider using --containerized");
+            }
+            return Ok(());
+        }
+        if !detected {
+            warn!("Containerized mode enabled, but no container environment detected");
+        }
+        if access(Self::CGROUP_DIR, AccessFlags::W_OK).is_err() {
+            bail!(
+                "The cgroup file system is read-only, please run the container with \
+                 --privileged"
+            )
+        }
+        Ok(())
+    }
+
+    /// Prepare the container environment for the cluster components
+    pub fn setup(config: &Config) -> Fallible<()> {
+        if !*config.containerized() {
+            return Ok(());
+        }
+
+        // The kubelet fails to start without a kernel log device
+        if !Path::new(Self::KMSG).exists() {
+            info!("Linking {} to {}", Self::KMSG, Self::CONSOLE);
+            symlink(Self::CONSOLE, Self::KMSG)?;
+        }
+
+        // Mount propagation into pods requires a shared root mount
+        mount(
+            None::<&str>,
+            "/",
+            None::<&str>,
+            MsFlags::MS_SHARED | MsFlags::MS_REC,
+            None::<&str>,
+        )
+        .map_err(|e| format_err!("Unable to make root mount shared: {}", e))?;
+        Ok(())
+    }
+
+    /// The storage driver of the container runtime, whereas overlay on top
+    /// of the overlay file system of a container is not supported
+    pub fn storage_driver(config: &Config) -> &'static str {
+        if *config.containerized() && Self::root_fstype(config).as_deref() == Some("overlay") {
+            "vfs"
+        } else {
+            "overlay"
+        }
+    }
+
+    /// Additional kube-proxy configuration, which avoids setting the
+    /// conntrack sysctls of the host
+    pub fn proxy_config(config: &Config) -> &'static str {
+        if *config.containerized() {
+            "conntrack:\n  maxPerCore: 0\n  tcpEstablishedTimeout: 0s\n  \
+             tcpCloseWaitTimeout: 0s\n"
+        } else {
+            ""
+        }
+    }
+
+    /// Retrieve the file system type of the mount containing the root
+    fn root_fstype(config: &Config) -> Option<String> {
+        MountIter::new()
+            .ok()?
+            .filter_map(|x| x.ok())
+            .filter(|x| config.root().starts_with(&x.dest))
+            .max_by_key(|x| x.dest.components().count())
+            .map(|x| x.fstype)
+    }
+
+    fn is_container_cgroup(content: &str) -> bool {
+        content
+            .lines()
+            .any(|x| Self::CGROUP_MARKERS.iter().any(|m| x.contains(m)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_container_cgroup_success() {
+        assert!(Containerized::is_container_cgroup(
+            "12:pids:/docker/4f1c0e7bd2a1\n0::/docker/4f1c0e7bd2a1\n"
+        ));
+        assert!(Containerized::is_container_cgroup(
+            "0::/kubepods/besteffort/pod1234\n"
+        ));
+    }
+
+    #[test]
+    fn is_container_cgroup_failure() {
+        assert!(!Containerized::is_container_cgroup(
+            "0::/user.slice/user-1000.slice/session-2.scope\n"
+        ));
+        assert!(!Containerized::is_container_cgroup("0::/\n"));
+    }
+}
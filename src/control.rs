@@ -0,0 +1,429 @@
+use crate::{signals::Signals, Config, Fallible};
+use log::debug;
+use serde_json::{json, Value};
+use std::{
+    fs::{self, File},
+    io::{self, copy, stdout, BufRead, BufReader, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
+};
+
+/// The local control API of a running cluster, which gets served via a UNIX
+/// socket inside the root directory and speaks plain HTTP with JSON bodies
+pub struct Control {
+    listener: UnixListener,
+    socket: PathBuf,
+    started: Instant,
+}
+
+/// A single request to the control API
+pub struct ControlRequest {
+    route: Route,
+    stream: UnixStream,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// The supported endpoints of the control API
+pub enum Route {
+    /// `GET /v1/status`: the status of the cluster and its components
+    Status,
+
+    /// `GET /v1/components`: the health of all components
+    Components,
+
+    /// `POST /v1/components/<name>/restart`: restart a single component
+    Restart(String),
+
+    /// `POST /v1/components/<name>/stop`: stop a single component
+    StopComponent(String),
+
+    /// `GET /v1/components/<name>/logs[?follow=true][&tail=N]`: stream the
+    /// log of a single component, optionally starting at its last lines
+    Logs(String, bool, Option<usize>),
+
+    /// `POST /v1/stop`: stop the cluster
+    Stop,
+
+    /// Any unknown endpoint
+    NotFound,
+}
+
+impl Control {
+    /// The file name of the socket inside the root directory
+    const SOCKET: &'static str = "kubernix.sock";
+
+    /// The maximum time to wait for a client to send its request
+    const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// The interval of checking followed logs for new output
+    const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Retrieve the path of the control socket for the provided configuration
+    pub fn socket(config: &Config) -> PathBuf {
+        config.root().join(Self::SOCKET)
+    }
+
+    /// Listen on the control socket, whereas a stale socket of a previous run
+    /// gets replaced
+    pub fn bind(config: &Config) -> Fallible<Control> {
+        let socket = Self::socket(config);
+        if socket.exists() {
+            fs::remove_file(&socket)?;
+        }
+        let listener = UnixListener::bind(&socket).map_err(|e| {
+            format_err!(
+                "Unable to listen on control socket '{}': {}",
+                socket.display(),
+                e
+            )
+        })?;
+        listener.set_nonblocking(true)?;
+        debug!("Serving control API on '{}'", socket.display());
+        Ok(Control {
+            listener,
+            socket,
+            started: Instant::now(),
+        })
+    }
+
+    /// The time since the control API got started
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Retrieve a pending request without blocking
+    pub fn accept(&self) -> Fallible<Option<ControlRequest>> {
+        let stream = match self.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Self::READ_TIMEOUT))?;
+
+        // Only the request line is relevant, headers and body get ignored
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        let mut split = line.split_whitespace();
+        let route = match (split.next(), split.next()) {
+            (Some(method), Some(target)) => Route::parse(method, target),
+            _ => Route::NotFound,
+        };
+        debug!("Control request: {}", line.trim());
+        Ok(Some(ControlRequest { route, stream }))
+    }
+
+    /// Retrieve the memory in bytes and the consumed CPU time in seconds of
+    /// the provided process
+    pub fn usage(pid: i32) -> Value {
+        let (ticks, page_size) = unsafe {
+            (
+                libc::sysconf(libc::_SC_CLK_TCK),
+                libc::sysconf(libc::_SC_PAGESIZE),
+            )
+        };
+        fs::read_to_string(format!("/proc/{}/stat", pid))
+            .ok()
+            .and_then(|x| Self::parse_stat(&x, ticks as f64, page_size as u64))
+            .map_or(
+                Value::Null,
+                |(memory, cpu)| json!({ "memory": memory, "cpu": cpu }),
+            )
+    }
+
+    /// Parse the resident memory and the consumed CPU time from the provided
+    /// content of `/proc/<pid>/stat`
+    fn parse_stat(stat: &str, ticks: f64, page_size: u64) -> Option<(u64, f64)> {
+        // The command name may contain spaces, all other fields follow it
+        let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+        let utime: u64 = fields.get(11)?.parse().ok()?;
+        let stime: u64 = fields.get(12)?.parse().ok()?;
+        let rss: u64 = fields.get(21)?.parse().ok()?;
+        Some((rss * page_size, (utime + stime) as f64 / ticks))
+    }
+
+    /// Send a request to the control API of the running cluster and retrieve
+    /// the response body
+    pub fn request(config: &Config, method: &str, path: &str) -> Fallible<Value> {
+        let (status, mut reader) = Self::send(config, method, path)?;
+        let mut body = String::new();
+        reader.read_to_string(&mut body)?;
+        let value: Value = serde_json::from_str(&body)?;
+        if status != 200 {
+            bail!(
+                "{}",
+                value["error"].as_str().unwrap_or("Control request failed")
+            )
+        }
+        Ok(value)
+    }
+
+    /// Send a request to the control API of the running cluster and write the
+    /// response body to stdout until the cluster closes the connection
+    pub fn stream(config: &Config, path: &str) -> Fallible<()> {
+        let (status, mut reader) = Self::send(config, "GET", path)?;
+        if status != 200 {
+            let mut body = String::new();
+            reader.read_to_string(&mut body)?;
+            let value: Value = serde_json::from_str(&body)?;
+            bail!(
+                "{}",
+                value["error"].as_str().unwrap_or("Control request failed")
+            )
+        }
+        copy(&mut reader, &mut stdout())?;
+        Ok(())
+    }
+
+    /// Send a request to the control API of the running cluster and retrieve
+    /// the plain text response body
+    pub fn text(config: &Config, path: &str) -> Fallible<String> {
+        let (status, mut reader) = Self::send(config, "GET", path)?;
+        let mut body = vec![];
+        reader.read_to_end(&mut body)?;
+        let body = String::from_utf8_lossy(&body).into_owned();
+        if status != 200 {
+            let value: Value = serde_json::from_str(&body)?;
+            bail!(
+                "{}",
+                value["error"].as_str().unwrap_or("Control request failed")
+            )
+        }
+        Ok(body)
+    }
+
+    /// Send a request and retrieve the status code and the reader positioned
+    /// at the response body
+    fn send(config: &Config, method: &str, path: &str) -> Fallible<(u16, impl Read)> {
+        let socket = Self::socket(config);
+        let mut stream = UnixStream::connect(&socket).map_err(|e| {
+            format_err!(
+                "No running cluster found in '{}': {}",
+                config.root().display(),
+                e
+            )
+        })?;
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: kubernix\r\nConnection: close\r\n\r\n",
+            method, path
+        )?;
+
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let status = line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|x| x.parse::<u16>().ok())
+            .ok_or_else(|| format_err!("Invalid control response '{}'", line.trim()))?;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+        }
+        Ok((status, reader))
+    }
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.socket) {
+            debug!("Unable to remove control socket: {}", e)
+        }
+    }
+}
+
+impl ControlRequest {
+    /// The requested endpoint
+    pub fn route(&self) -> &Route {
+        &self.route
+    }
+
+    /// Respond with the provided status code and JSON body
+    pub fn respond(mut self, status: u16, body: &Value) -> Fallible<()> {
+        let body = body.to_string();
+        write!(
+            self.stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            Self::reason(status),
+            body.len(),
+            body
+        )?;
+        Ok(())
+    }
+
+    /// Respond with the provided error message
+    pub fn fail(self, status: u16, message: &str) -> Fallible<()> {
+        self.respond(status, &json!({ "error": message }))
+    }
+
+    /// Stream the provided log file in the background, optionally following
+    /// it until the client disconnects or kubernix gets stopped
+    pub fn stream_log(
+        mut self,
+        log_file: &Path,
+        follow: bool,
+        tail: Option<usize>,
+    ) -> Fallible<()> {
+        let mut file = match File::open(log_file) {
+            Ok(x) => x,
+            Err(e) => {
+                return self.fail(
+                    404,
+                    &format!("Unable to open log '{}': {}", log_file.display(), e),
+                )
+            }
+        };
+        write!(
+            self.stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\n"
+        )?;
+        if let Some(tail) = tail {
+            let mut content = vec![];
+            file.read_to_end(&mut content)?;
+            self.stream.write_all(Self::tail(&content, tail))?;
+        }
+        spawn(move || -> Fallible<()> {
+            loop {
+                copy(&mut file, &mut self.stream)?;
+                if !follow {
+                    return Ok(());
+                }
+                Signals::check()?;
+                sleep(Control::FOLLOW_INTERVAL);
+            }
+        });
+        Ok(())
+    }
+
+    /// Retrieve the last lines of the provided content
+    fn tail(content: &[u8], lines: usize) -> &[u8] {
+        if lines == 0 {
+            return &content[content.len()..];
+        }
+        let end = match content.last() {
+            Some(b'\n') => content.len() - 1,
+            _ => content.len(),
+        };
+        let start = content[..end]
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, x)| **x == b'\n')
+            .nth(lines - 1)
+            .map_or(0, |(i, _)| i + 1);
+        &content[start..]
+    }
+
+    fn reason(status: u16) -> &'static str {
+        match status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+impl Route {
+    /// Parse the route from the method and target of the request line
+    pub fn parse(method: &str, target: &str) -> Route {
+        let mut split = target.splitn(2, '?');
+        let path = split.next().unwrap_or_default().trim_end_matches('/');
+        let mut follow = false;
+        let mut tail = None;
+        for parameter in split.next().unwrap_or_default().split('&') {
+            let mut parameter = parameter.splitn(2, '=');
+            match (parameter.next(), parameter.next()) {
+                (Some("follow"), None) | (Some("follow"), Some("true")) => follow = true,
+                (Some("tail"), Some(x)) => tail = x.parse().ok(),
+                _ => {}
+            }
+        }
+
+        let parts: Vec<&str> = path.split('/').skip(1).collect();
+        match (method, parts.as_slice()) {
+            ("GET", ["v1", "status"]) => Route::Status,
+            ("GET", ["v1", "components"]) => Route::Components,
+            ("POST", ["v1", "components", name, "restart"]) => Route::Restart((*name).into()),
+            ("POST", ["v1", "components", name, "stop"]) => Route::StopComponent((*name).into()),
+            ("GET", ["v1", "components", name, "logs"]) => {
+                Route::Logs((*name).into(), follow, tail)
+            }
+            ("POST", ["v1", "stop"]) => Route::Stop,
+            _ => Route::NotFound,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_success() {
+        assert_eq!(Route::parse("GET", "/v1/status"), Route::Status);
+        assert_eq!(Route::parse("GET", "/v1/components/"), Route::Components);
+        assert_eq!(
+            Route::parse("POST", "/v1/components/etcd/restart"),
+            Route::Restart("etcd".into())
+        );
+        assert_eq!(
+            Route::parse("GET", "/v1/components/kubelet/logs"),
+            Route::Logs("kubelet".into(), false, None)
+        );
+        assert_eq!(
+            Route::parse("GET", "/v1/components/kubelet/logs?follow=true&tail=10"),
+            Route::Logs("kubelet".into(), true, Some(10))
+        );
+        assert_eq!(
+            Route::parse("POST", "/v1/components/proxy/stop"),
+            Route::StopComponent("proxy".into())
+        );
+        assert_eq!(Route::parse("POST", "/v1/stop"), Route::Stop);
+    }
+
+    #[test]
+    fn parse_failure() {
+        assert_eq!(Route::parse("POST", "/v1/status"), Route::NotFound);
+        assert_eq!(Route::parse("GET", "/v1/stop"), Route::NotFound);
+        assert_eq!(Route::parse("GET", "/v2/components"), Route::NotFound);
+        assert_eq!(
+            Route::parse("GET", "/v1/components/proxy/stop"),
+            Route::NotFound
+        );
+        assert_eq!(Route::parse("GET", "/"), Route::NotFound);
+    }
+
+    #[test]
+    fn parse_stat_success() {
+        let stat = "4242 (kube apiserver) S 1 4242 4242 0 -1 4194560 12 0 0 0 \
+                    250 50 0 0 20 0 12 0 100 1000000 2048 18446744073709551615";
+        assert_eq!(
+            Control::parse_stat(stat, 100.0, 4096),
+            Some((2048 * 4096, 3.0))
+        );
+    }
+
+    #[test]
+    fn parse_stat_failure() {
+        assert!(Control::parse_stat("", 100.0, 4096).is_none());
+        assert!(Control::parse_stat("4242 (etcd) S 1 2 3", 100.0, 4096).is_none());
+    }
+
+    #[test]
+    fn tail_success() {
+        assert_eq!(ControlRequest::tail(b"a\nb\nc\n", 2), b"b\nc\n");
+        assert_eq!(ControlRequest::tail(b"a\nb\nc", 2), b"b\nc");
+        assert_eq!(ControlRequest::tail(b"a\nb\n", 5), b"a\nb\n");
+        assert_eq!(ControlRequest::tail(b"a\nb\n", 0), b"");
+        assert_eq!(ControlRequest::tail(b"", 3), b"");
+    }
+}
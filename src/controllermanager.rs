@@ -1,19 +1,63 @@
 use crate::{
+    component::ComponentKind,
     config::Config,
     kubeconfig::KubeConfig,
     network::Network,
     pki::Pki,
-    process::{Process, Startable, Stoppable},
+    process::{Process, RestartPolicy, Startable, Stoppable},
+    profile::Preset,
+    verbosity::Verbosity,
+    Fallible,
 };
-use failure::Fallible;
 use log::info;
 use std::fs::create_dir_all;
 
 pub struct ControllerManager {
-    process: Process,
+    replicas: Vec<Process>,
 }
 
 impl ControllerManager {
+    /// The controllers which can be disabled selectively
+    const CONTROLLERS: &'static [&'static str] = &[
+        "attachdetach",
+        "bootstrapsigner",
+        "cloud-node-lifecycle",
+        "clusterrole-aggregation",
+        "cronjob",
+        "csrapproving",
+        "csrcleaner",
+        "csrsigning",
+        "daemonset",
+        "deployment",
+        "disruption",
+        "endpoint",
+        "endpointslice",
+        "endpointslicemirroring",
+        "garbagecollector",
+        "horizontalpodautoscaling",
+        "job",
+        "namespace",
+        "nodeipam",
+        "nodelifecycle",
+        "persistentvolume-binder",
+        "persistentvolume-expander",
+        "podgc",
+        "pv-protection",
+        "pvc-protection",
+        "replicaset",
+        "replicationcontroller",
+        "resourcequota",
+        "root-ca-cert-publisher",
+        "route",
+        "service",
+        "serviceaccount",
+        "serviceaccount-token",
+        "statefulset",
+        "tokencleaner",
+        "ttl",
+        "ttl-after-finished",
+    ];
+
     pub fn start(
         config: &Config,
         network: &Network,
@@ -25,37 +69,141 @@ impl ControllerManager {
         let dir = config.root().join("controllermanager");
         create_dir_all(&dir)?;
 
-        let mut process = Process::start(
-            config,
-            &dir,
-            "kube-controller-manager",
-            &[
-                "--bind-address=0.0.0.0",
-                &format!("--cluster-cidr={}", network.cluster()),
-                "--cluster-name=kubernetes",
-                &format!("--cluster-signing-cert-file={}", pki.ca().cert().display()),
-                &format!("--cluster-signing-key-file={}", pki.ca().key().display()),
-                &format!("--kubeconfig={}", kubeconfig.controller_manager().display()),
-                "--leader-elect=false",
-                &format!("--root-ca-file={}", pki.ca().cert().display()),
-                &format!(
-                    "--service-account-private-key-file={}",
-                    pki.service_account().key().display()
-                ),
-                &format!("--service-cluster-ip-range={}", network.service()),
-                "--use-service-account-credentials=true",
-                "--v=2",
-            ],
-        )?;
-
-        process.wait_ready("Serving securely")?;
+        let mut args = vec![
+            "--bind-address=0.0.0.0".into(),
+            format!("--cluster-cidr={}", network.cluster()),
+            "--cluster-name=kubernetes".into(),
+            format!("--cluster-signing-cert-file={}", pki.ca().cert().display()),
+            format!("--cluster-signing-key-file={}", pki.ca().key().display()),
+            format!("--kubeconfig={}", kubeconfig.controller_manager().display()),
+            format!("--leader-elect={}", config.leader_election()),
+            format!("--profiling={}", config.profiling()),
+            format!("--root-ca-file={}", pki.ca().cert().display()),
+            format!(
+                "--service-account-private-key-file={}",
+                pki.service_account().key().display()
+            ),
+            format!("--service-cluster-ip-range={}", network.service()),
+            "--use-service-account-credentials=true".into(),
+        ];
+        args.extend(Verbosity::args(config, ComponentKind::ControllerManager));
+        if *config.cloud_provider_external() {
+            args.push("--cloud-provider=external".into());
+        }
+        if let Some(x) = Self::controllers(config.controllers(), config.disable_controllers())? {
+            args.push(format!("--controllers={}", x));
+        }
+        if let Some(x) = config.node_cidr_mask_size() {
+            args.push("--allocate-node-cidrs=true".into());
+            args.push(format!("--node-cidr-mask-size={}", x));
+        }
+
+        args.extend(Preset::for_config(config).args(ComponentKind::ControllerManager));
+        if let Some(x) = config.kube_api_qps() {
+            args.push(format!("--kube-api-qps={}", x));
+        }
+        if let Some(x) = config.kube_api_burst() {
+            args.push(format!("--kube-api-burst={}", x));
+        }
+
+        if *config.control_plane_replicas() == 0 {
+            bail!("At least one control plane replica is required");
+        }
+
+        let mut replicas = vec![];
+        for replica in 0..*config.control_plane_replicas() {
+            // Additional replicas only serve securely on distinct ports
+            let port = config.ports().controller_manager()
+                + u16::from(replica) * Config::REPLICA_PORT_OFFSET;
+            let (replica_dir, name, mut replica_args) = if replica == 0 {
+                (dir.clone(), "kube-controller-manager".to_owned(), vec![])
+            } else {
+                (
+                    dir.join(format!("replica-{}", replica)),
+                    format!("kube-controller-manager-{}", replica),
+                    vec!["--port=0".to_owned()],
+                )
+            };
+            replica_args.push(format!("--secure-port={}", port));
+
+            // The Controller Manager exits if it loses its leader election
+            match Process::builder(config, &replica_dir, "kube-controller-manager")
+                .name(&name)
+                .args(&args)
+                .args(&replica_args)
+                .depends_on(ComponentKind::ApiServer)
+                .restart(RestartPolicy::OnFailure(3))
+                .ready_on("Serving securely")
+                .spawn()
+            {
+                Ok(x) => replicas.push(x),
+                Err(e) => {
+                    for mut x in replicas.into_iter().rev() {
+                        x.stop()?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
         info!("Controller Manager is ready");
-        Ok(Box::new(ControllerManager { process }))
+        Ok(Box::new(ControllerManager { replicas }))
+    }
+
+    /// Retrieve the value of the `--controllers` flag, whereas the disabled
+    /// controllers get appended to the passed through ones
+    fn controllers(enabled: &[String], disabled: &[String]) -> Fallible<Option<String>> {
+        if enabled.is_empty() && disabled.is_empty() {
+            return Ok(None);
+        }
+        let mut controllers = if enabled.is_empty() {
+            vec!["*".to_owned()]
+        } else {
+            enabled.to_vec()
+        };
+        for controller in disabled {
+            if !Self::CONTROLLERS.contains(&controller.as_str()) {
+                bail!(
+                    "Unknown controller '{}', possible values are: {}",
+                    controller,
+                    Self::CONTROLLERS.join(", ")
+                )
+            }
+            controllers.push(format!("-{}", controller));
+        }
+        Ok(Some(controllers.join(",")))
     }
 }
 
 impl Stoppable for ControllerManager {
     fn stop(&mut self) -> Fallible<()> {
-        self.process.stop()
+        Process::stop_all(self.replicas.iter_mut().rev())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn controllers_success() -> Fallible<()> {
+        assert_eq!(ControllerManager::controllers(&[], &[])?, None);
+        assert_eq!(
+            ControllerManager::controllers(&[], &["nodelifecycle".into()])?,
+            Some("*,-nodelifecycle".into())
+        );
+        assert_eq!(
+            ControllerManager::controllers(
+                &["*".into(), "bootstrapsigner".into()],
+                &["ttl".into()]
+            )?,
+            Some("*,bootstrapsigner,-ttl".into())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn controllers_failure() {
+        assert!(ControllerManager::controllers(&[], &["unknown".into()]).is_err());
     }
 }
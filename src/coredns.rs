@@ -1,10 +1,6 @@
-use crate::{config::Config, kubeconfig::KubeConfig, network::Network};
-use failure::{bail, Fallible};
-use log::{debug, info};
-use std::{
-    fs::{self, create_dir_all},
-    process::Command,
-};
+use crate::{config::Config, kubeconfig::KubeConfig, network::Network, process::Process, Fallible};
+use log::info;
+use std::fs::{self, create_dir_all};
 
 pub struct CoreDNS;
 
@@ -15,27 +11,21 @@ impl CoreDNS {
         let dir = config.root().join("coredns");
         create_dir_all(&dir)?;
 
-        let yml = format!(include_str!("assets/coredns.yml"), network.dns()?);
+        let yml = format!(
+            include_str!("assets/coredns.yml"),
+            config.cluster_domain(),
+            network.dns()?
+        );
         let yml_file = dir.join("coredns.yml");
         fs::write(&yml_file, yml)?;
 
-        let output = Command::new("kubectl")
+        Process::run_to_completion("kubectl")
             .arg("apply")
             .arg(format!("--kubeconfig={}", kubeconfig.admin().display()))
             .arg("-f")
             .arg(yml_file)
-            .output()?;
-        if !output.status.success() {
-            debug!(
-                "kubectl apply stdout: {}",
-                String::from_utf8(output.stdout)?
-            );
-            debug!(
-                "kubectl apply stderr: {}",
-                String::from_utf8(output.stderr)?
-            );
-            bail!("kubectl apply command failed");
-        }
+            .retries(3)
+            .run()?;
 
         info!("CoreDNS deployed");
         Ok(())
@@ -0,0 +1,267 @@
+use crate::{config::ApplyCrds, kubeconfig::KubeConfig, process::Process, Config, Fallible};
+use log::{debug, info, warn};
+use serde_json::{json, to_string_pretty, Value};
+use std::{path::Path, time::Duration};
+
+/// CustomResourceDefinitions of operators under development
+pub struct Crds;
+
+impl Crds {
+    /// The maximum time to wait for the configured definitions during the
+    /// bootstrap
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// The resource type prefix of the applied definitions
+    const RESOURCE: &'static str = "customresourcedefinition.apiextensions.k8s.io/";
+
+    /// Apply the configured definitions, which are established before any
+    /// other addon gets applied
+    pub fn apply(config: &Config, kubeconfig: &KubeConfig) -> Fallible<()> {
+        let dir = match config.crds() {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        info!(
+            "Applying CustomResourceDefinitions from '{}'",
+            dir.display()
+        );
+        let crds = Self::apply_dir(kubeconfig.admin(), dir)?;
+        Self::wait_established(kubeconfig.admin(), &crds, Self::TIMEOUT)?;
+        info!("CustomResourceDefinitions established");
+        Ok(())
+    }
+
+    /// Apply the definitions of the provided arguments to the running cluster
+    pub fn run(config: &Config, args: &ApplyCrds) -> Fallible<()> {
+        let admin = KubeConfig::admin_path(config);
+        info!(
+            "Applying CustomResourceDefinitions from '{}'",
+            args.dir().display()
+        );
+        let crds = Self::apply_dir(&admin, args.dir())?;
+        if crds.is_empty() {
+            warn!(
+                "No CustomResourceDefinitions found in '{}'",
+                args.dir().display()
+            );
+            return Ok(());
+        }
+        for crd in &crds {
+            info!("Applied {}", crd);
+        }
+
+        if *args.wait_established() {
+            Self::wait_established(&admin, &crds, args.timeout().duration())?;
+            info!("All CustomResourceDefinitions established");
+        }
+        if let Some(service_account) = args.service_account() {
+            let (namespace, name) = Self::split_service_account(service_account)?;
+            info!(
+                "Granting access to the custom resources for service account {}/{}",
+                namespace, name
+            );
+            let manifest = Self::rbac(namespace, name, &crds)?;
+            Self::kubectl(
+                &admin,
+                &["apply", "-f", "-"],
+                Some(&to_string_pretty(&manifest)?),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Apply all manifests of the provided directory and retrieve the names
+    /// of the contained definitions
+    fn apply_dir(admin: &Path, dir: &Path) -> Fallible<Vec<String>> {
+        if !dir.is_dir() {
+            bail!(
+                "CustomResourceDefinitions path '{}' is not a directory",
+                dir.display()
+            )
+        }
+        let output = Self::kubectl(
+            admin,
+            &[
+                "apply",
+                "--recursive",
+                "--output=name",
+                "-f",
+                &dir.display().to_string(),
+            ],
+            None,
+        )?;
+        Ok(output
+            .lines()
+            .filter(|x| x.starts_with(Self::RESOURCE))
+            .map(|x| x[Self::RESOURCE.len()..].to_owned())
+            .collect())
+    }
+
+    /// Wait until the API Server serves the provided definitions
+    fn wait_established(admin: &Path, crds: &[String], timeout: Duration) -> Fallible<()> {
+        if crds.is_empty() {
+            return Ok(());
+        }
+        debug!("Waiting for {} to be established", crds.join(", "));
+        // kubectl enforces the timeout on its own, the command only gets some
+        // additional time to report it
+        Process::run_to_completion("kubectl")
+            .arg(format!("--kubeconfig={}", admin.display()))
+            .arg("wait")
+            .arg("--for=condition=Established")
+            .arg(format!("--timeout={}s", timeout.as_secs()))
+            .args(
+                &crds
+                    .iter()
+                    .map(|x| format!("crd/{}", x))
+                    .collect::<Vec<_>>(),
+            )
+            .timeout(timeout + Duration::from_secs(10))
+            .run()
+            .map_err(|_| format_err!("CustomResourceDefinitions not established in time"))?;
+        Ok(())
+    }
+
+    /// Split the provided service account into its namespace and name,
+    /// whereas the namespace defaults to `default`
+    fn split_service_account(service_account: &str) -> Fallible<(&str, &str)> {
+        let (namespace, name) = match service_account.find('/') {
+            Some(i) => (&service_account[..i], &service_account[i + 1..]),
+            None => ("default", service_account),
+        };
+        if namespace.is_empty() || name.is_empty() || name.contains('/') {
+            bail!(
+                "Invalid service account '{}', expected '[namespace/]name'",
+                service_account
+            )
+        }
+        Ok((namespace, name))
+    }
+
+    /// Render the service account and its full access to the resources of
+    /// the provided definitions, whereas events and leases are required for
+    /// most controllers as well
+    fn rbac(namespace: &str, name: &str, crds: &[String]) -> Fallible<Value> {
+        let mut rules = vec![];
+        for crd in crds {
+            // The name of a definition is always `<plural>.<group>`
+            let mut split = crd.splitn(2, '.');
+            let (plural, group) = match (split.next(), split.next()) {
+                (Some(plural), Some(group)) => (plural, group),
+                _ => bail!("Invalid CustomResourceDefinition name '{}'", crd),
+            };
+            rules.push(json!({
+              "apiGroups": [group],
+              "resources": [
+                plural,
+                format!("{}/status", plural),
+                format!("{}/finalizers", plural)
+              ],
+              "verbs": ["*"]
+            }));
+        }
+        rules.push(json!({
+          "apiGroups": ["", "events.k8s.io"],
+          "resources": ["events"],
+          "verbs": ["create", "patch", "update"]
+        }));
+        rules.push(json!({
+          "apiGroups": ["coordination.k8s.io"],
+          "resources": ["leases"],
+          "verbs": ["get", "list", "watch", "create", "update", "patch"]
+        }));
+
+        let role = format!("kubernix:crds:{}:{}", namespace, name);
+        Ok(json!({
+          "apiVersion": "v1",
+          "kind": "List",
+          "items": [
+            {
+              "apiVersion": "v1",
+              "kind": "Namespace",
+              "metadata": { "name": namespace }
+            },
+            {
+              "apiVersion": "v1",
+              "kind": "ServiceAccount",
+              "metadata": { "namespace": namespace, "name": name }
+            },
+            {
+              "apiVersion": "rbac.authorization.k8s.io/v1",
+              "kind": "ClusterRole",
+              "metadata": { "name": role },
+              "rules": rules
+            },
+            {
+              "apiVersion": "rbac.authorization.k8s.io/v1",
+              "kind": "ClusterRoleBinding",
+              "metadata": { "name": role },
+              "roleRef": {
+                "apiGroup": "rbac.authorization.k8s.io",
+                "kind": "ClusterRole",
+                "name": role
+              },
+              "subjects": [{
+                "kind": "ServiceAccount",
+                "namespace": namespace,
+                "name": name
+              }]
+            }
+          ]
+        }))
+    }
+
+    /// Run kubectl with the admin credentials and retrieve its output,
+    /// optionally providing the input via stdin
+    fn kubectl(admin: &Path, args: &[&str], input: Option<&str>) -> Fallible<String> {
+        let mut kubectl = Process::run_to_completion("kubectl")
+            .arg(format!("--kubeconfig={}", admin.display()))
+            .args(args);
+        if let Some(input) = input {
+            kubectl = kubectl.input(input);
+        }
+        kubectl.run()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_service_account_success() -> Fallible<()> {
+        assert_eq!(
+            Crds::split_service_account("operators/my-operator")?,
+            ("operators", "my-operator")
+        );
+        assert_eq!(
+            Crds::split_service_account("my-operator")?,
+            ("default", "my-operator")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn split_service_account_failure() {
+        assert!(Crds::split_service_account("").is_err());
+        assert!(Crds::split_service_account("operators/").is_err());
+        assert!(Crds::split_service_account("/my-operator").is_err());
+        assert!(Crds::split_service_account("a/b/c").is_err());
+    }
+
+    #[test]
+    fn rbac_success() -> Fallible<()> {
+        let rbac = Crds::rbac("operators", "op", &["foos.example.com".into()])?;
+        let role = &rbac["items"][2];
+        assert_eq!(role["metadata"]["name"], "kubernix:crds:operators:op");
+        assert_eq!(role["rules"][0]["apiGroups"][0], "example.com");
+        assert_eq!(role["rules"][0]["resources"][1], "foos/status");
+        assert_eq!(rbac["items"][3]["subjects"][0]["namespace"], "operators");
+        Ok(())
+    }
+
+    #[test]
+    fn rbac_failure() {
+        assert!(Crds::rbac("default", "op", &["foos".into()]).is_err());
+    }
+}
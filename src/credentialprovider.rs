@@ -0,0 +1,166 @@
+use crate::{Config, Fallible};
+use getset::Getters;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, to_string_pretty, Value};
+use std::{
+    collections::BTreeMap,
+    fs::{self, create_dir_all, remove_file, symlink_metadata},
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Debug, Deserialize, Getters, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// An image credential provider plugin, which gets executed by the kubelet
+pub struct CredentialProvider {
+    #[get = "pub"]
+    /// The name of the provider, which is also the executable name within
+    /// the plugin directory
+    name: String,
+
+    #[get = "pub"]
+    /// The path of the plugin executable
+    plugin: PathBuf,
+
+    #[get = "pub"]
+    /// The image patterns the provider is invoked for, like `*.dkr.ecr.*.amazonaws.com`
+    match_images: Vec<String>,
+
+    #[get = "pub"]
+    #[serde(default = "default_cache_duration")]
+    /// The cache duration of credentials without own cache duration
+    default_cache_duration: String,
+
+    #[get = "pub"]
+    #[serde(default = "default_api_version")]
+    /// The version of the exec plugin API
+    api_version: String,
+
+    #[get = "pub"]
+    #[serde(default)]
+    /// The arguments of the plugin
+    args: Vec<String>,
+
+    #[get = "pub"]
+    #[serde(default)]
+    /// Additional environment variables of the plugin
+    env: BTreeMap<String, String>,
+}
+
+fn default_cache_duration() -> String {
+    "1m".into()
+}
+
+fn default_api_version() -> String {
+    "credentialprovider.kubelet.k8s.io/v1alpha1".into()
+}
+
+impl CredentialProvider {
+    /// The plugin directory within the kubelet directory
+    const BIN_DIR: &'static str = "credential-providers";
+
+    /// The file name of the rendered CredentialProviderConfig
+    const CONFIG_FILE: &'static str = "credential-provider.json";
+
+    /// Render the CredentialProviderConfig and the plugin directory into the
+    /// provided kubelet directory and retrieve the kubelet arguments
+    pub fn kubelet_args(config: &Config, dir: &Path) -> Fallible<Vec<String>> {
+        let providers = config.credential_providers();
+        if providers.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // The plugins are linked to pick up rebuilt ones on the next pull
+        let bin_dir = dir.join(Self::BIN_DIR);
+        create_dir_all(&bin_dir)?;
+        for provider in providers {
+            if !provider.plugin.is_file() {
+                bail!(
+                    "Credential provider plugin '{}' does not exist",
+                    provider.plugin.display()
+                )
+            }
+            let link = bin_dir.join(&provider.name);
+            if symlink_metadata(&link).is_ok() {
+                remove_file(&link)?;
+            }
+            debug!(
+                "Linking credential provider plugin '{}' to '{}'",
+                provider.plugin.display(),
+                link.display()
+            );
+            symlink(&provider.plugin, &link)?;
+        }
+
+        let file = dir.join(Self::CONFIG_FILE);
+        fs::write(&file, to_string_pretty(&Self::render(providers))?)?;
+        Ok(vec![
+            "--feature-gates=KubeletCredentialProviders=true".into(),
+            format!("--image-credential-provider-config={}", file.display()),
+            format!("--image-credential-provider-bin-dir={}", bin_dir.display()),
+        ])
+    }
+
+    /// Render the CredentialProviderConfig of the provided providers
+    fn render(providers: &[CredentialProvider]) -> Value {
+        let providers: Vec<Value> = providers
+            .iter()
+            .map(|x| {
+                json!({
+                    "name": x.name,
+                    "matchImages": x.match_images,
+                    "defaultCacheDuration": x.default_cache_duration,
+                    "apiVersion": x.api_version,
+                    "args": x.args,
+                    "env": x.env
+                        .iter()
+                        .map(|(name, value)| json!({"name": name, "value": value}))
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        json!({
+            "apiVersion": "kubelet.config.k8s.io/v1alpha1",
+            "kind": "CredentialProviderConfig",
+            "providers": providers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Spec {
+        #[serde(rename = "credential-provider")]
+        providers: Vec<CredentialProvider>,
+    }
+
+    #[test]
+    fn render_success() -> Fallible<()> {
+        let spec: Spec = toml::from_str(
+            r#"
+            [[credential-provider]]
+            name = "ecr-credential-provider"
+            plugin = "/home/dev/ecr-credential-provider"
+            match-images = ["*.dkr.ecr.*.amazonaws.com"]
+            env = { AWS_PROFILE = "dev" }
+            "#,
+        )?;
+        let config = CredentialProvider::render(&spec.providers);
+        assert_eq!(config["kind"], "CredentialProviderConfig");
+        let provider = &config["providers"][0];
+        assert_eq!(provider["name"], "ecr-credential-provider");
+        assert_eq!(provider["matchImages"][0], "*.dkr.ecr.*.amazonaws.com");
+        assert_eq!(provider["defaultCacheDuration"], "1m");
+        assert_eq!(
+            provider["apiVersion"],
+            "credentialprovider.kubelet.k8s.io/v1alpha1"
+        );
+        assert_eq!(provider["env"][0]["name"], "AWS_PROFILE");
+        assert_eq!(provider["env"][0]["value"], "dev");
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{
+    config::{CredentialsFormat, ExportCredentials},
+    pki::{Pair, Pki},
+    Config, Fallible, Kubernix,
+};
+use log::{debug, info};
+use std::{
+    fs::{self, remove_file, File, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+
+pub struct Credentials;
+
+impl Credentials {
+    /// Export the admin credentials together with the CA into a single bundle
+    pub fn export(config: &Config, args: &ExportCredentials) -> Fallible<()> {
+        let dir = config.root().join(Pki::DIR);
+        let ca = Pair::new(&dir, "ca");
+        let admin = Pair::new(&dir, "admin");
+        for file in &[ca.cert(), admin.cert(), admin.key()] {
+            if !file.exists() {
+                bail!(
+                    "Credential '{}' does not exist, please bootstrap the cluster first",
+                    file.display()
+                )
+            }
+        }
+
+        if args.out().exists() {
+            bail!(
+                "Export target '{}' already exists, please remove it first",
+                args.out().display()
+            )
+        }
+
+        info!(
+            "Exporting admin credentials as {:?} bundle to '{}'",
+            args.format(),
+            args.out().display()
+        );
+        match args.format() {
+            CredentialsFormat::Pem => {
+                let mut bundle = String::new();
+                for file in &[ca.cert(), admin.cert(), admin.key()] {
+                    bundle.push_str(&fs::read_to_string(file)?);
+                }
+                Self::create_secret(args.out())?.write_all(bundle.as_bytes())?;
+            }
+            CredentialsFormat::P12 => {
+                // Generate the bundle inside the PKI dir first, so that a
+                // failing openssl run never leaves a partial export behind
+                let bundle = dir.join("admin.p12");
+                let password_file = dir.join("admin.p12.pass");
+                for file in &[&bundle, &password_file] {
+                    if file.exists() {
+                        remove_file(file)?;
+                    }
+                }
+
+                // Pre-create the bundle to let openssl write into a private
+                // file, and pass the password via a file to avoid any shell
+                // quoting
+                Self::create_secret(&bundle)?;
+                Self::create_secret(&password_file)?.write_all(
+                    args.password()
+                        .as_ref()
+                        .map_or("", String::as_str)
+                        .as_bytes(),
+                )?;
+
+                let res = Kubernix::nix_shell_run(
+                    config,
+                    &format!(
+                        "openssl pkcs12 -export -name kubernix-admin -in {} -inkey {} \
+                         -certfile {} -out {} -passout {}",
+                        Self::quote(admin.cert()),
+                        Self::quote(admin.key()),
+                        Self::quote(ca.cert()),
+                        Self::quote(&bundle),
+                        shell_words::quote(&format!("file:{}", password_file.display())),
+                    ),
+                );
+                remove_file(&password_file)?;
+                if let Err(e) = res {
+                    remove_file(&bundle)?;
+                    return Err(e);
+                }
+
+                if bundle.metadata()?.len() == 0 {
+                    remove_file(&bundle)?;
+                    bail!("Unable to create PKCS#12 bundle via openssl")
+                }
+                debug!("Created PKCS#12 bundle '{}'", bundle.display());
+                io::copy(
+                    &mut File::open(&bundle)?,
+                    &mut Self::create_secret(args.out())?,
+                )?;
+                remove_file(&bundle)?;
+            }
+        }
+
+        info!("Credentials exported");
+        Ok(())
+    }
+
+    /// Create a new file which is only accessible by the current user, before
+    /// any secret gets written into it
+    /// Quote the provided path to be safely used within a shell command
+    fn quote(path: &Path) -> String {
+        shell_words::quote(&path.display().to_string()).into_owned()
+    }
+
+    fn create_secret(path: &Path) -> Fallible<File> {
+        Ok(OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+    use clap::Clap;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn export_pem_success() -> Fallible<()> {
+        let c = test_config()?;
+        let dir = c.root().join(Pki::DIR);
+        fs::create_dir_all(&dir)?;
+        for name in &["ca", "admin"] {
+            let pair = Pair::new(&dir, name);
+            fs::write(pair.cert(), "cert\n")?;
+            fs::write(pair.key(), "key\n")?;
+        }
+
+        let out = c.root().join("bundle.pem");
+        let out_arg = format!("--out={}", out.display());
+        let args = ExportCredentials::try_parse_from(&[
+            "export-credentials",
+            "--format=pem",
+            out_arg.as_str(),
+        ])
+        .map_err(|e| format_err!("{}", e))?;
+        Credentials::export(&c, &args)?;
+
+        assert_eq!(fs::read_to_string(&out)?, "cert\ncert\nkey\n");
+        assert_eq!(out.metadata()?.permissions().mode() & 0o777, 0o600);
+        assert!(Credentials::export(&c, &args).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn quote_success() {
+        assert_eq!(
+            Credentials::quote(Path::new("/my root/$(id)/ca.pem")),
+            "'/my root/$(id)/ca.pem'"
+        );
+    }
+
+    #[test]
+    fn create_secret_success() -> Fallible<()> {
+        let c = test_config()?;
+        let file = c.root().join("secret");
+        Credentials::create_secret(&file)?.write_all(b"secret")?;
+        assert_eq!(file.metadata()?.permissions().mode() & 0o777, 0o600);
+        assert!(Credentials::create_secret(&file).is_err());
+        Ok(())
+    }
+}
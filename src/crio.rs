@@ -1,9 +1,18 @@
 use crate::{
+    cni::Cni,
+    component::ComponentKind,
+    containerized::Containerized,
+    httpproxy::HttpProxy,
+    mirror::RegistryMirror,
     network::Network,
     process::{Process, Startable, Stoppable},
-    Config, Kubernix, CRIO_DIR, RUNTIME_ENV,
+    registryauth::RegistryAuth,
+    runtimeclass::RuntimeClass,
+    securityprofile::SecurityProfile,
+    selinux::Selinux,
+    verbosity::Verbosity,
+    Config, Fallible, Kubernix, CRIO_DIR, RUNTIME_ENV,
 };
-use failure::{bail, format_err, Fallible};
 use log::{debug, info};
 use nix::{
     sys::signal::{kill, Signal},
@@ -14,7 +23,6 @@ use serde_json::{json, to_string_pretty};
 use std::{
     fs::{self, create_dir_all},
     path::{Path, PathBuf},
-    process::Command,
     thread::sleep,
     time::{Duration, Instant},
 };
@@ -25,7 +33,12 @@ pub struct Crio {
 }
 
 impl Crio {
-    pub fn start(config: &Config, network: &Network, socket: &Path) -> Fallible<Startable> {
+    pub fn start(
+        config: &Config,
+        network: &Network,
+        ip: &str,
+        socket: &Path,
+    ) -> Fallible<Startable> {
         info!("Starting CRI-O");
         let conmon = Kubernix::find_executable("conmon")?;
         let bridge = Kubernix::find_executable("bridge")?;
@@ -38,24 +51,34 @@ impl Crio {
 
         let cni_config = dir.join("cni");
         create_dir_all(&cni_config)?;
+
+        // Network plugins like Cilium install their own configuration
         let bridge_json = cni_config.join("bridge.json");
-        fs::write(
-            bridge_json,
-            to_string_pretty(&json!({
-              "cniVersion": "0.3.1",
-              "name": "crio-kubernix",
-              "type": "bridge",
-              "bridge": Network::BRIDGE,
-              "isGateway": true,
-              "ipMasq": true,
-              "hairpinMode": true,
-              "ipam": {
-                "type": "host-local",
-                "routes": [{ "dst": "0.0.0.0/0" }],
-                "ranges": [[{ "subnet": network.crio() }]]
-              }
-            }))?,
-        )?;
+        let mut cni_dirs = vec![cni.to_path_buf()];
+        if *config.cni() == Cni::Bridge {
+            fs::write(
+                bridge_json,
+                to_string_pretty(&json!({
+                  "cniVersion": "0.3.1",
+                  "name": "crio-kubernix",
+                  "type": "bridge",
+                  "bridge": Network::BRIDGE,
+                  "isGateway": true,
+                  "ipMasq": true,
+                  "hairpinMode": true,
+                  "ipam": {
+                    "type": "host-local",
+                    "routes": [{ "dst": "0.0.0.0/0" }],
+                    "ranges": [[{ "subnet": network.crio() }]]
+                  }
+                }))?,
+            )?;
+        } else {
+            if bridge_json.exists() {
+                fs::remove_file(&bridge_json)?;
+            }
+            cni_dirs.push(Cni::plugin_dir(config));
+        }
 
         let policy_json = dir.join("policy.json");
         fs::write(
@@ -65,31 +88,55 @@ impl Crio {
             }))?,
         )?;
 
-        let mut process = Process::start(
-            config,
-            &dir,
-            "crio",
-            &[
-                "--log-level=debug",
-                "--storage-driver=overlay",
+        let registries_conf = dir.join("registries.conf");
+        fs::write(
+            &registries_conf,
+            RegistryMirror::registries_conf(config.registry_mirrors()),
+        )?;
+
+        // The runc runtime is always available as default
+        let mut args = vec![format!(
+            "--runtimes={}",
+            RuntimeClass::Runc.crio_runtime(&dir)?
+        )];
+        for class in config.runtime_classes() {
+            if *class != RuntimeClass::Runc {
+                args.push(format!("--runtimes={}", class.crio_runtime(&dir)?));
+            }
+        }
+        args.extend(
+            cni_dirs
+                .iter()
+                .map(|x| format!("--cni-plugin-dir={}", x.display())),
+        );
+        if let Some(image) = config.pause_image() {
+            args.push(format!("--pause-image={}", image));
+        }
+        args.extend(SecurityProfile::crio_args(config)?);
+        args.extend(Selinux::crio_args(config));
+        if let Some(auth) = RegistryAuth::install(config, &dir)? {
+            args.push(format!("--global-auth-file={}", auth.display()));
+        }
+
+        let process = Process::builder(config, &dir, "crio")
+            .args(&[
+                &format!("--storage-driver={}", Containerized::storage_driver(config)),
                 &format!("--conmon={}", conmon.display()),
                 &format!("--listen={}", socket.display()),
                 &format!("--root={}", dir.join("storage").display()),
                 &format!("--runroot={}", dir.join("run").display()),
                 &format!("--cni-config-dir={}", cni_config.display()),
-                &format!("--cni-plugin-dir={}", cni.display()),
                 "--registry=docker.io",
                 &format!("--signature-policy={}", policy_json.display()),
-                &format!(
-                    "--runtimes=local-runc:{}:{}",
-                    Kubernix::find_executable("runc")?.display(),
-                    dir.join("runc").display()
-                ),
+                &format!("--registries-conf={}", registries_conf.display()),
                 "--default-runtime=local-runc",
-            ],
-        )?;
+            ])
+            .args(&args)
+            .args(&Verbosity::args(config, ComponentKind::Crio))
+            .envs(&HttpProxy::env(config, &[ip]))
+            .ready_on("sandboxes:")
+            .spawn()?;
 
-        process.wait_ready("sandboxes:")?;
         info!("CRI-O is ready");
         Ok(Box::new(Crio {
             process,
@@ -132,31 +179,23 @@ impl Crio {
         debug!("Removing all CRI-O workloads");
         let env_value = format!("unix://{}", self.socket.display());
 
-        let output = Command::new("crictl")
+        // The workloads get removed on teardown, which a signal may have caused
+        let stdout = Process::run_to_completion("crictl")
             .env(RUNTIME_ENV, &env_value)
             .arg("pods")
             .arg("-q")
-            .output()?;
-        let stdout = String::from_utf8(output.stdout)?;
-        if !output.status.success() {
-            debug!("critcl stdout: {}", stdout);
-            debug!("critcl stderr: {}", String::from_utf8(output.stderr)?);
-            bail!("crictl pods command failed");
-        }
+            .interruptible(false)
+            .run()?;
 
         for x in stdout.lines() {
             debug!("Removing pod {}", x);
-            let output = Command::new("crictl")
+            Process::run_to_completion("crictl")
                 .env(RUNTIME_ENV, &env_value)
                 .arg("rmp")
                 .arg("-f")
                 .arg(x)
-                .output()?;
-            if !output.status.success() {
-                debug!("critcl stdout: {}", String::from_utf8(output.stdout)?);
-                debug!("critcl stderr: {}", String::from_utf8(output.stderr)?);
-                bail!("crictl rmp command failed");
-            }
+                .interruptible(false)
+                .run()?;
         }
 
         debug!("All workloads removed");
@@ -0,0 +1,48 @@
+use crate::{config::Config, kubeconfig::KubeConfig, process::Process, Fallible};
+use log::info;
+use std::fs::{self, create_dir_all};
+
+pub struct Csi;
+
+impl Csi {
+    /// Prepare the kubelet plugin directories and deploy the CSI sidecar RBAC
+    pub fn apply(config: &Config, kubeconfig: &KubeConfig) -> Fallible<()> {
+        if !*config.csi_dev() {
+            return Ok(());
+        }
+        info!("Preparing CSI driver development mode");
+
+        // The kubelet root dir contains all plugin related directories
+        let kubelet_dir = config.root().join("kubelet").join("run");
+        let registry_dir = kubelet_dir.join("plugins_registry");
+        let plugins_dir = kubelet_dir.join("plugins");
+        create_dir_all(&registry_dir)?;
+        create_dir_all(&plugins_dir)?;
+
+        let dir = config.root().join("csi");
+        create_dir_all(&dir)?;
+        let yml_file = dir.join("rbac.yml");
+        fs::write(&yml_file, include_str!("assets/csi.yml"))?;
+
+        Process::run_to_completion("kubectl")
+            .arg("apply")
+            .arg(format!("--kubeconfig={}", kubeconfig.admin().display()))
+            .arg("-f")
+            .arg(yml_file)
+            .retries(3)
+            .run()?;
+
+        info!("CSI driver development mode prepared, a local driver should use:");
+        info!("  Registration directory: {}", registry_dir.display());
+        info!(
+            "  Driver socket:          {}",
+            plugins_dir.join("<driver-name>").join("csi.sock").display()
+        );
+        info!(
+            "  Pods directory:         {}",
+            kubelet_dir.join("pods").display()
+        );
+        info!("  Service account:        kube-system/csi-driver");
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{kubeconfig::KubeConfig, process::Process, Fallible};
+use log::info;
+use serde_json::Value;
+
+pub struct CsrApprover;
+
+impl CsrApprover {
+    /// The signer of kubelet serving certificates
+    const SIGNER: &'static str = "kubernetes.io/kubelet-serving";
+
+    /// Approve all pending serving certificate requests of the nodes
+    pub fn approve(kubeconfig: &KubeConfig) -> Fallible<()> {
+        let output = Self::kubectl(kubeconfig, &["get", "csr", "--output=json"])?;
+        for name in Self::pending(&serde_json::from_str(&output)?) {
+            info!("Approving kubelet serving certificate request {}", name);
+            Self::kubectl(kubeconfig, &["certificate", "approve", &name])?;
+        }
+        Ok(())
+    }
+
+    /// Retrieve the names of all pending kubelet serving certificate requests
+    /// which have been created by a node
+    fn pending(list: &Value) -> Vec<String> {
+        list["items"]
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter(|x| {
+                        // Older API versions do not contain the signer name
+                        let signer = x["spec"]["signerName"].as_str();
+                        signer.is_none() || signer == Some(Self::SIGNER)
+                    })
+                    .filter(|x| {
+                        x["spec"]["usages"]
+                            .as_array()
+                            .map_or(false, |u| u.iter().any(|u| u == "server auth"))
+                    })
+                    .filter(|x| {
+                        x["spec"]["username"]
+                            .as_str()
+                            .map_or(false, |u| u.starts_with("system:node:"))
+                    })
+                    .filter(|x| x["status"]["conditions"].is_null())
+                    .filter_map(|x| x["metadata"]["name"].as_str())
+                    .map(ToOwned::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn kubectl(kubeconfig: &KubeConfig, args: &[&str]) -> Fallible<String> {
+        Process::run_to_completion("kubectl")
+            .arg(format!("--kubeconfig={}", kubeconfig.admin().display()))
+            .args(args)
+            .run()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn pending_success() {
+        let list = json!({
+            "items": [
+                {
+                    "metadata": { "name": "csr-serving" },
+                    "spec": {
+                        "signerName": "kubernetes.io/kubelet-serving",
+                        "usages": ["digital signature", "key encipherment", "server auth"],
+                        "username": "system:node:host"
+                    },
+                    "status": {}
+                },
+                {
+                    "metadata": { "name": "csr-approved" },
+                    "spec": {
+                        "signerName": "kubernetes.io/kubelet-serving",
+                        "usages": ["server auth"],
+                        "username": "system:node:host"
+                    },
+                    "status": { "conditions": [{ "type": "Approved" }] }
+                },
+                {
+                    "metadata": { "name": "csr-user" },
+                    "spec": {
+                        "usages": ["server auth"],
+                        "username": "admin"
+                    },
+                    "status": {}
+                }
+            ]
+        });
+        assert_eq!(CsrApprover::pending(&list), vec!["csr-serving".to_owned()]);
+    }
+}
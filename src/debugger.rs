@@ -0,0 +1,173 @@
+use crate::{
+    component::ComponentKind, config::Debug, control::Control, runinfo::RunInfo, Config, Fallible,
+    Kubernix,
+};
+use log::{info, warn};
+use nix::sys::{
+    signal::{SigSet, Signal},
+    stat::{umask, Mode},
+};
+use std::{
+    fs::read_dir,
+    io,
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Runs a single component of the running cluster under the delve debugger,
+/// whereas the cluster keeps supervising all other components
+pub struct Debugger;
+
+impl Debugger {
+    /// The signals which belong to the debug session, like interrupting the
+    /// debugged process
+    const SESSION_SIGNALS: [Signal; 2] = [Signal::SIGINT, Signal::SIGQUIT];
+
+    /// Stop the supervised component, run it under delve in the current
+    /// terminal and hand it back to the supervision afterwards
+    pub fn run(config: &Config, args: &Debug) -> Fallible<()> {
+        let name = Self::process_name(args.component());
+        let info = Self::run_info(config.root(), &name)?;
+        let command = Self::command(&info)?;
+
+        info!("Stopping component '{}' for debugging", args.component());
+        Control::request(
+            config,
+            "POST",
+            &format!("/v1/components/{}/stop", args.component()),
+        )?;
+
+        info!(
+            "Debugging '{}', supervision resumes after quitting delve",
+            command.display()
+        );
+        let result = Self::exec(&command, &info);
+
+        info!("Resuming supervision of component '{}'", args.component());
+        Control::request(
+            config,
+            "POST",
+            &format!("/v1/components/{}/restart", args.component()),
+        )?;
+        result
+    }
+
+    /// Retrieve the process name of the provided component name, which
+    /// differs for the built-in components
+    fn process_name(component: &str) -> String {
+        match component.parse::<ComponentKind>() {
+            Ok(kind) => kind.command().into(),
+            Err(_) => component.into(),
+        }
+    }
+
+    /// Load the metadata of the process, whereas additional replicas live
+    /// below the directory of the first one and are not considered
+    fn run_info(root: &Path, name: &str) -> Fallible<RunInfo> {
+        for entry in read_dir(root)? {
+            let dir = entry?.path();
+            if !dir.join(RunInfo::FILE).exists() {
+                continue;
+            }
+            if let Ok(info) = RunInfo::load(&dir) {
+                if info.name() == name {
+                    return Ok(info);
+                }
+            }
+        }
+        bail!("No process '{}' found in '{}'", name, root.display())
+    }
+
+    /// Resolve the executable of the process, which is searched in $PATH
+    /// if not being a local binary
+    fn command(info: &RunInfo) -> Fallible<PathBuf> {
+        let command = Path::new(info.command());
+        if command.is_absolute() {
+            Ok(command.to_owned())
+        } else {
+            Kubernix::find_executable(info.command())
+        }
+    }
+
+    /// Build the arguments of delve to execute the provided command
+    fn delve_args(command: &Path, info: &RunInfo) -> Vec<String> {
+        let mut args = vec!["exec".into(), command.display().to_string()];
+        if !info.args().is_empty() {
+            args.push("--".into());
+            args.extend(info.args().iter().cloned());
+        }
+        args
+    }
+
+    /// Run delve within the current terminal and wait for it to exit
+    fn exec(command: &Path, info: &RunInfo) -> Fallible<()> {
+        // Interrupts within the debug session should not abort the resumption
+        let mut signals = SigSet::empty();
+        for signal in &Self::SESSION_SIGNALS {
+            signals.add(*signal);
+        }
+        signals.thread_block()?;
+        umask(Mode::from_bits_truncate(*info.umask()));
+
+        let mut cmd = Command::new(Kubernix::find_executable("dlv")?);
+        cmd.args(Self::delve_args(command, info))
+            .current_dir(info.cwd())
+            .envs(info.env());
+        unsafe {
+            cmd.pre_exec(move || {
+                signals
+                    .thread_unblock()
+                    .map_err(|_| io::Error::last_os_error())
+            });
+        }
+        let status = cmd.status();
+        signals.thread_unblock()?;
+
+        let status = status?;
+        if !status.success() {
+            warn!("Debug session exited with {}", status);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeMap, fs::create_dir};
+    use tempfile::tempdir;
+
+    #[test]
+    fn process_name_success() {
+        assert_eq!(Debugger::process_name("scheduler"), "kube-scheduler");
+        assert_eq!(Debugger::process_name("crio"), "crio");
+        assert_eq!(Debugger::process_name("my-controller"), "my-controller");
+    }
+
+    #[test]
+    fn run_info_success() -> Fallible<()> {
+        let d = tempdir()?;
+        let dir = d.path().join("scheduler");
+        create_dir(&dir)?;
+        create_dir(d.path().join("log"))?;
+        let args = vec!["--v=2".to_owned()];
+        RunInfo::new(
+            "kube-scheduler",
+            "kube-scheduler",
+            &args,
+            BTreeMap::new(),
+            &dir,
+            0o022,
+        )
+        .write(&dir)?;
+
+        let info = Debugger::run_info(d.path(), "kube-scheduler")?;
+        assert_eq!(
+            Debugger::delve_args(Path::new("/bin/kube-scheduler"), &info),
+            vec!["exec", "/bin/kube-scheduler", "--", "--v=2"]
+        );
+        assert!(Debugger::run_info(d.path(), "kubelet").is_err());
+        Ok(())
+    }
+}
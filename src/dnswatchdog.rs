@@ -0,0 +1,142 @@
+use crate::{
+    component::Health, kubeconfig::KubeConfig, network::Network, process::Process, Config, Fallible,
+};
+use log::{debug, info, warn};
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+pub struct DnsWatchdog;
+
+impl DnsWatchdog {
+    /// The interval between two DNS checks
+    pub const INTERVAL: Duration = Duration::from_secs(30);
+
+    /// The service which always has to be resolvable inside the cluster
+    const SERVICE: &'static str = "kubernetes.default.svc";
+
+    /// The maximum time to wait for a DNS response
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// The transaction ID of the queries
+    const ID: u16 = 0x6b78;
+
+    /// Resolve the API Server service via the cluster DNS
+    pub fn health(config: &Config, network: &Network) -> Health {
+        match Self::resolve(config, network) {
+            Ok(()) => Health::Ready,
+            Err(e) => {
+                debug!("Unable to resolve '{}': {}", Self::name(config), e);
+                Health::NotReady
+            }
+        }
+    }
+
+    /// Check the cluster DNS and restart it if the resolution fails
+    pub fn check(config: &Config, network: &Network, kubeconfig: &KubeConfig) -> Fallible<Health> {
+        let health = Self::health(config, network);
+        if health == Health::NotReady {
+            warn!("Cluster DNS is {}, restarting CoreDNS", health);
+            Self::restart(kubeconfig)?;
+        }
+        Ok(health)
+    }
+
+    /// Trigger a rolling restart of the DNS addon
+    fn restart(kubeconfig: &KubeConfig) -> Fallible<()> {
+        Process::run_to_completion("kubectl")
+            .arg(format!("--kubeconfig={}", kubeconfig.admin().display()))
+            .arg("--namespace=kube-system")
+            .arg("rollout")
+            .arg("restart")
+            .arg("deployment/coredns")
+            .run()?;
+        info!("CoreDNS restarted");
+        Ok(())
+    }
+
+    /// The fully qualified name of the API Server service
+    fn name(config: &Config) -> String {
+        format!("{}.{}", Self::SERVICE, config.cluster_domain())
+    }
+
+    fn resolve(config: &Config, network: &Network) -> Fallible<()> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_read_timeout(Some(Self::TIMEOUT))?;
+        socket.send_to(
+            &Self::query(&Self::name(config)),
+            SocketAddr::new(network.dns()?.into(), 53),
+        )?;
+
+        let mut buf = [0; 512];
+        let len = socket.recv(&mut buf)?;
+        Self::verify(&buf[..len])
+    }
+
+    /// Build a recursive query for the A record of the provided name
+    fn query(name: &str) -> Vec<u8> {
+        let mut query = vec![];
+        query.extend_from_slice(&Self::ID.to_be_bytes());
+        // Recursion desired, one question
+        query.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]);
+        for label in name.split('.') {
+            query.push(label.len() as u8);
+            query.extend_from_slice(label.as_bytes());
+        }
+        // Root label, type A, class IN
+        query.extend_from_slice(&[0, 0x00, 0x01, 0x00, 0x01]);
+        query
+    }
+
+    /// Verify that the response answers the query successfully
+    fn verify(response: &[u8]) -> Fallible<()> {
+        if response.len() < 12 {
+            bail!("DNS response too short")
+        }
+        if u16::from_be_bytes([response[0], response[1]]) != Self::ID {
+            bail!("DNS response ID does not match")
+        }
+        if response[2] & 0x80 == 0 {
+            bail!("DNS message is not a response")
+        }
+        let rcode = response[3] & 0x0f;
+        if rcode != 0 {
+            bail!("DNS response code {}", rcode)
+        }
+        if u16::from_be_bytes([response[6], response[7]]) == 0 {
+            bail!("DNS response contains no answer")
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_success() {
+        let query = DnsWatchdog::query("a.bc");
+        assert_eq!(query.len(), 12 + 6 + 4);
+        assert_eq!(&query[12..18], &[1, b'a', 2, b'b', b'c', 0]);
+    }
+
+    #[test]
+    fn verify_success() -> Fallible<()> {
+        let mut response = DnsWatchdog::query("a.bc");
+        response[2] |= 0x80;
+        response[7] = 1;
+        DnsWatchdog::verify(&response)
+    }
+
+    #[test]
+    fn verify_failure() {
+        let mut response = DnsWatchdog::query("a.bc");
+        assert!(DnsWatchdog::verify(&response).is_err());
+        response[2] |= 0x80;
+        response[3] = 3;
+        assert!(DnsWatchdog::verify(&response).is_err());
+        assert!(DnsWatchdog::verify(&[]).is_err());
+    }
+}
@@ -0,0 +1,398 @@
+use crate::{
+    config::{Doctor as DoctorArgs, OutputFormat},
+    kubeconfig::KubeConfig,
+    output::Output,
+    pki::Pki,
+    process::Process,
+    Config, Fallible,
+};
+use log::{info, warn};
+use nix::sys::statvfs::statvfs;
+use serde_json::{json, Value};
+use std::{
+    fs::read_dir,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A single problem of the running cluster
+struct Finding {
+    problem: String,
+    suggestion: String,
+}
+
+impl Finding {
+    fn new(problem: String, suggestion: &str) -> Self {
+        Self {
+            problem,
+            suggestion: suggestion.into(),
+        }
+    }
+}
+
+/// Diagnostics for common problems of a running cluster
+pub struct Doctor<'a> {
+    config: &'a Config,
+    admin: PathBuf,
+}
+
+impl<'a> Doctor<'a> {
+    /// Certificates expiring within this amount of seconds are reported
+    const CERT_EXPIRY: u64 = 30 * 24 * 60 * 60;
+
+    /// The maximum tolerated clock difference to the API Server in seconds
+    const MAX_TIME_SKEW: u64 = 5;
+
+    /// The minimum free space of the root directory in percent
+    const MIN_FREE_SPACE: u64 = 10;
+
+    /// Run all checks and print the found problems including a suggestion
+    pub fn run(config: &Config, args: &DoctorArgs) -> Fallible<()> {
+        let doctor = Doctor {
+            config,
+            admin: KubeConfig::admin_path(config),
+        };
+        let checks = vec![
+            ("nodes", doctor.nodes()),
+            ("system pods", doctor.system_pods()),
+            ("certificates", doctor.certificates()),
+            ("disk space", doctor.disk_space()),
+            ("time skew", doctor.time_skew()),
+        ];
+
+        let problems = match args.output().format() {
+            OutputFormat::Text => Self::log(checks),
+            x => {
+                let value = Self::value(checks);
+                Output::print(*x, &value)?;
+                value["problems"].as_u64().unwrap_or_default()
+            }
+        };
+        if problems > 0 {
+            bail!("Found {} problem(s)", problems)
+        }
+        info!("No problems found");
+        Ok(())
+    }
+
+    /// Log the results of the checks for humans and count the problems
+    fn log(checks: Vec<(&str, Fallible<Vec<Finding>>)>) -> u64 {
+        let mut problems = 0;
+        for (name, result) in checks {
+            match result {
+                Ok(findings) if findings.is_empty() => info!("Checked {}: OK", name),
+                Ok(findings) => {
+                    for finding in findings {
+                        warn!("Checked {}: {}", name, finding.problem);
+                        warn!("  Suggestion: {}", finding.suggestion);
+                        problems += 1;
+                    }
+                }
+                Err(e) => {
+                    warn!("Unable to check {}: {}", name, e);
+                    problems += 1;
+                }
+            }
+        }
+        problems
+    }
+
+    /// Convert the results of the checks into their machine readable
+    /// representation, whereas failed checks count as a single problem
+    fn value(checks: Vec<(&str, Fallible<Vec<Finding>>)>) -> Value {
+        let mut problems = 0;
+        let mut results = vec![];
+        for (name, result) in checks {
+            let (findings, error) = match result {
+                Ok(findings) => (findings, None),
+                Err(e) => (vec![], Some(e.to_string())),
+            };
+            problems += findings.len() + error.iter().count();
+            results.push(json!({
+                "name": name,
+                "findings": findings
+                    .iter()
+                    .map(|x| json!({ "problem": x.problem, "suggestion": x.suggestion }))
+                    .collect::<Vec<_>>(),
+                "error": error,
+            }));
+        }
+        json!({ "checks": results, "problems": problems })
+    }
+
+    /// Find nodes which are not ready or under resource pressure
+    fn nodes(&self) -> Fallible<Vec<Finding>> {
+        Ok(Self::node_findings(&self.kubectl(&["get", "nodes"])?))
+    }
+
+    /// Find crash looping pods within the kube-system namespace
+    fn system_pods(&self) -> Fallible<Vec<Finding>> {
+        Ok(Self::pod_findings(&self.kubectl(&[
+            "get",
+            "pods",
+            "--namespace=kube-system",
+        ])?))
+    }
+
+    /// Find certificates of the PKI which expire soon
+    fn certificates(&self) -> Fallible<Vec<Finding>> {
+        let mut findings = vec![];
+        let dir = self.config.root().join(Pki::DIR);
+        for entry in read_dir(&dir)? {
+            let path = entry?.path();
+            let name = path
+                .file_name()
+                .and_then(|x| x.to_str())
+                .unwrap_or_default();
+            if !name.ends_with(".pem") || name.ends_with("-key.pem") {
+                continue;
+            }
+            let output = Process::run_to_completion("openssl")
+                .arg("x509")
+                .arg("-noout")
+                .arg("-checkend")
+                .arg(Self::CERT_EXPIRY.to_string())
+                .arg("-in")
+                .arg(&path)
+                .output()?;
+            if !output.success() {
+                findings.push(Finding::new(
+                    format!("Certificate '{}' expires within 30 days", path.display()),
+                    "Recreate the cluster in a new root directory to get a fresh PKI",
+                ));
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Find a nearly full file system of the root directory
+    fn disk_space(&self) -> Fallible<Vec<Finding>> {
+        let stat = statvfs(self.config.root())?;
+        let free = stat.blocks_available() as u64 * 100 / (stat.blocks() as u64).max(1);
+        if free < Self::MIN_FREE_SPACE {
+            return Ok(vec![Finding::new(
+                format!(
+                    "Only {}% free space left for '{}'",
+                    free,
+                    self.config.root().display()
+                ),
+                "Remove unused images via `crictl rmi --prune` or free up space on the host",
+            )]);
+        }
+        Ok(vec![])
+    }
+
+    /// Compare the local clock with the one of the API Server
+    fn time_skew(&self) -> Fallible<Vec<Finding>> {
+        let dir = self.config.root().join(Pki::DIR);
+        let output = Process::run_to_completion("curl")
+            .arg("--silent")
+            .arg("--show-error")
+            .arg("--output=/dev/null")
+            .arg("--dump-header=-")
+            .arg(format!("--cacert={}", dir.join("ca.pem").display()))
+            .arg(format!("--cert={}", dir.join("admin.pem").display()))
+            .arg(format!("--key={}", dir.join("admin-key.pem").display()))
+            .arg(format!(
+                "https://127.0.0.1:{}/healthz",
+                self.config.ports().apiserver()
+            ))
+            .output()?;
+        if !output.success() {
+            bail!("curl command failed: {}", output.stderr().trim())
+        }
+        let date = output
+            .stdout()
+            .lines()
+            .find_map(|x| {
+                let mut split = x.splitn(2, ':');
+                match (split.next(), split.next()) {
+                    (Some(k), Some(v)) if k.eq_ignore_ascii_case("date") => Some(v.trim()),
+                    _ => None,
+                }
+            })
+            .ok_or_else(|| format_err!("API Server response contains no date"))?;
+        let remote = Self::epoch(date)?;
+        let local = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or_default();
+
+        let skew = if remote > local {
+            remote - local
+        } else {
+            local - remote
+        };
+        if skew > Self::MAX_TIME_SKEW {
+            return Ok(vec![Finding::new(
+                format!("The API Server clock differs by {}s", skew),
+                "Synchronize the host clock via NTP, or check if --fake-time is intended",
+            )]);
+        }
+        Ok(vec![])
+    }
+
+    /// Convert the provided date into seconds since the epoch
+    fn epoch(date: &str) -> Fallible<u64> {
+        let output = Process::run_to_completion("date")
+            .arg("--date")
+            .arg(date)
+            .arg("+%s")
+            .output()?;
+        if !output.success() {
+            bail!("Unable to parse date '{}'", date)
+        }
+        output
+            .stdout()
+            .trim()
+            .parse()
+            .map_err(|_| format_err!("Invalid date '{}'", date))
+    }
+
+    fn node_findings(nodes: &Value) -> Vec<Finding> {
+        let mut findings = vec![];
+        for node in Self::items(nodes) {
+            let name = node["metadata"]["name"].as_str().unwrap_or_default();
+            for condition in node["status"]["conditions"]
+                .as_array()
+                .into_iter()
+                .flatten()
+            {
+                let status = condition["status"].as_str().unwrap_or_default();
+                match (condition["type"].as_str().unwrap_or_default(), status) {
+                    ("Ready", "True") => {}
+                    ("Ready", _) => findings.push(Finding::new(
+                        format!("Node '{}' is not ready", name),
+                        "Check the kubelet and CRI-O logs within the log directory of the root",
+                    )),
+                    ("DiskPressure", "True") => findings.push(Finding::new(
+                        format!("Node '{}' has disk pressure", name),
+                        "Remove unused images via `crictl rmi --prune` or free up space on the host",
+                    )),
+                    ("MemoryPressure", "True") | ("PIDPressure", "True") => {
+                        findings.push(Finding::new(
+                            format!(
+                                "Node '{}' has {}",
+                                name,
+                                condition["type"].as_str().unwrap_or_default()
+                            ),
+                            "Reduce the workload or raise the resources of the host",
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        findings
+    }
+
+    fn pod_findings(pods: &Value) -> Vec<Finding> {
+        let mut findings = vec![];
+        for pod in Self::items(pods) {
+            let name = pod["metadata"]["name"].as_str().unwrap_or_default();
+            for status in pod["status"]["containerStatuses"]
+                .as_array()
+                .into_iter()
+                .flatten()
+            {
+                if status["state"]["waiting"]["reason"].as_str() == Some("CrashLoopBackOff") {
+                    findings.push(Finding::new(
+                        format!(
+                            "Container '{}' of pod '{}' is in CrashLoopBackOff",
+                            status["name"].as_str().unwrap_or_default(),
+                            name
+                        ),
+                        &format!(
+                            "Inspect the container via `kubectl -n kube-system logs --previous {}`",
+                            name
+                        ),
+                    ));
+                }
+            }
+        }
+        findings
+    }
+
+    fn items(list: &Value) -> impl Iterator<Item = &Value> {
+        list["items"].as_array().into_iter().flatten()
+    }
+
+    fn kubectl(&self, args: &[&str]) -> Fallible<Value> {
+        let output = Process::run_to_completion("kubectl")
+            .arg(format!("--kubeconfig={}", self.admin.display()))
+            .args(args)
+            .arg("--output=json")
+            .output()?;
+        if !output.success() {
+            bail!(
+                "kubectl {} command failed: {}",
+                args.join(" "),
+                output.stderr().trim()
+            );
+        }
+        Ok(serde_json::from_str(output.stdout())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_success() {
+        let checks = vec![
+            ("nodes", Ok(vec![])),
+            (
+                "disk space",
+                Ok(vec![Finding::new("Disk full".into(), "Free space")]),
+            ),
+            ("time skew", Err(format_err!("No API Server"))),
+        ];
+        assert_eq!(
+            Doctor::value(checks),
+            json!({
+                "checks": [
+                    { "name": "nodes", "findings": [], "error": null },
+                    {
+                        "name": "disk space",
+                        "findings": [{ "problem": "Disk full", "suggestion": "Free space" }],
+                        "error": null,
+                    },
+                    { "name": "time skew", "findings": [], "error": "No API Server" },
+                ],
+                "problems": 2,
+            })
+        );
+    }
+
+    #[test]
+    fn node_findings_success() {
+        let nodes = json!({"items": [{
+            "metadata": {"name": "node"},
+            "status": {"conditions": [
+                {"type": "Ready", "status": "False"},
+                {"type": "DiskPressure", "status": "True"},
+                {"type": "MemoryPressure", "status": "False"},
+            ]},
+        }]});
+        let findings = Doctor::node_findings(&nodes);
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].problem, "Node 'node' is not ready");
+        assert_eq!(findings[1].problem, "Node 'node' has disk pressure");
+    }
+
+    #[test]
+    fn pod_findings_success() {
+        let pods = json!({"items": [{
+            "metadata": {"name": "coredns"},
+            "status": {"containerStatuses": [
+                {"name": "coredns", "state": {"waiting": {"reason": "CrashLoopBackOff"}}},
+                {"name": "sidecar", "state": {"running": {}}},
+            ]},
+        }]});
+        let findings = Doctor::pod_findings(&pods);
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].problem.contains("'coredns'"));
+        assert!(Doctor::pod_findings(&json!({})).is_empty());
+    }
+}
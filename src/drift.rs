@@ -0,0 +1,104 @@
+use crate::{readiness::Readiness, runinfo::RunInfo, Config, Fallible};
+use log::debug;
+use std::{
+    fs::{self, create_dir_all, read_dir},
+    path::PathBuf,
+    process::Command,
+};
+
+/// The detection of configuration changes since the cluster got started
+pub struct Drift;
+
+impl Drift {
+    /// The directory within the root
+    const DIR: &'static str = "drift";
+
+    /// The file name of the configuration the cluster runs with
+    const FILENAME: &'static str = "running.toml";
+
+    /// Remember the configuration the running cluster uses
+    pub fn record(config: &Config) -> Fallible<()> {
+        let dir = config.root().join(Self::DIR);
+        create_dir_all(&dir)?;
+        fs::write(dir.join(Self::FILENAME), toml::to_string(config)?)?;
+        Ok(())
+    }
+
+    /// Compare the running configuration with the current one, which
+    /// returns a unified diff if they differ
+    pub fn diff(config: &Config) -> Fallible<Option<String>> {
+        let running = Self::file(config);
+        if !running.exists() {
+            bail!("No running configuration found, please start the cluster first")
+        }
+
+        // Serialize the current configuration the same way as the recorded
+        // one to avoid differences in formatting
+        let current = config.root().join(Self::DIR).join("current.toml");
+        fs::write(&current, toml::to_string(config)?)?;
+
+        let output = Command::new("diff")
+            .arg("--unified")
+            .arg("--label=running")
+            .arg("--label=kubernix.toml")
+            .arg(&running)
+            .arg(&current)
+            .output()?;
+        match output.status.code() {
+            Some(0) => Ok(None),
+            Some(1) => Ok(Some(String::from_utf8(output.stdout)?)),
+            _ => {
+                debug!("diff stderr: {}", String::from_utf8(output.stderr)?);
+                bail!("diff command failed with {}", output.status)
+            }
+        }
+    }
+
+    /// Retrieve the running components together with their referenced files
+    /// which changed after the components got started
+    pub fn changed_files(config: &Config) -> Fallible<Vec<(String, Vec<PathBuf>)>> {
+        let mut changed = vec![];
+        for entry in read_dir(config.root())? {
+            let dir = entry?.path();
+            if !dir.join(RunInfo::FILE).exists() || !Readiness::is_marked(&dir) {
+                continue;
+            }
+            let files = RunInfo::load(&dir)?.changed_files();
+            if !files.is_empty() {
+                let name = dir
+                    .file_name()
+                    .map(|x| x.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                changed.push((name, files));
+            }
+        }
+        changed.sort();
+        Ok(changed)
+    }
+
+    fn file(config: &Config) -> PathBuf {
+        config.root().join(Self::DIR).join(Self::FILENAME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+
+    #[test]
+    fn diff_success() -> Fallible<()> {
+        let c = test_config()?;
+        assert!(Drift::diff(&c).is_err());
+        Drift::record(&c)?;
+        assert!(Drift::diff(&c)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn changed_files_success() -> Fallible<()> {
+        let c = test_config()?;
+        assert!(Drift::changed_files(&c)?.is_empty());
+        Ok(())
+    }
+}
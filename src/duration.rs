@@ -0,0 +1,80 @@
+use crate::{Error, Fallible};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+    time::Duration,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+/// A human readable duration like `90s`, `2m` or `1h`
+pub struct HumanDuration(Duration);
+
+impl HumanDuration {
+    /// Retrieve the inner duration
+    pub fn duration(&self) -> Duration {
+        self.0
+    }
+}
+
+impl Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.0.as_secs();
+        if secs != 0 && secs % 3600 == 0 {
+            write!(f, "{}h", secs / 3600)
+        } else if secs != 0 && secs % 60 == 0 {
+            write!(f, "{}m", secs / 60)
+        } else {
+            write!(f, "{}s", secs)
+        }
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let s = s.trim();
+        let (value, factor) = match s.chars().last() {
+            Some('s') => (&s[..s.len() - 1], 1),
+            Some('m') => (&s[..s.len() - 1], 60),
+            Some('h') => (&s[..s.len() - 1], 3600),
+            Some('d') => (&s[..s.len() - 1], 86400),
+            _ => (s, 1),
+        };
+        let value: u64 = value
+            .parse()
+            .map_err(|_| format_err!("Invalid duration '{}'", s))?;
+        Ok(HumanDuration(Duration::from_secs(value * factor)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        assert_eq!("120".parse::<HumanDuration>()?.duration().as_secs(), 120);
+        assert_eq!("90s".parse::<HumanDuration>()?.duration().as_secs(), 90);
+        assert_eq!("2m".parse::<HumanDuration>()?.duration().as_secs(), 120);
+        assert_eq!("2h".parse::<HumanDuration>()?.duration().as_secs(), 7200);
+        assert_eq!("1d".parse::<HumanDuration>()?.duration().as_secs(), 86400);
+        Ok(())
+    }
+
+    #[test]
+    fn from_str_failure() {
+        assert!("".parse::<HumanDuration>().is_err());
+        assert!("2x".parse::<HumanDuration>().is_err());
+        assert!("m".parse::<HumanDuration>().is_err());
+    }
+
+    #[test]
+    fn display() -> Fallible<()> {
+        assert_eq!("90s".parse::<HumanDuration>()?.to_string(), "90s");
+        assert_eq!("120s".parse::<HumanDuration>()?.to_string(), "2m");
+        assert_eq!("7200".parse::<HumanDuration>()?.to_string(), "2h");
+        Ok(())
+    }
+}
@@ -1,35 +1,49 @@
-use crate::Config;
+use crate::{seed::Seed, Config, Fallible};
 use base64::encode;
-use failure::Fallible;
 use getset::Getters;
 use log::info;
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use std::{
     fs::{self, create_dir_all},
     path::PathBuf,
 };
 
-#[derive(Getters)]
+#[derive(Clone, Getters)]
 pub struct EncryptionConfig {
     #[get = "pub"]
     path: PathBuf,
 }
 
 impl EncryptionConfig {
+    /// The target directory of the encryption config
+    pub const DIR: &'static str = "encryptionconfig";
+
     pub fn new(config: &Config) -> Fallible<EncryptionConfig> {
         info!("Creating encryption config");
 
-        let rnd = thread_rng().gen::<[u8; 32]>();
+        let rnd = Seed::rng(config, "encryption-key").gen::<[u8; 32]>();
         let b64 = encode(&rnd);
         let yml = format!(include_str!("assets/encryptionconfig.yml"), b64);
 
-        let dir = &config.root().join("encryptionconfig");
-        create_dir_all(dir)?;
-
-        let path = dir.join("config.yml");
+        let path = Self::file(config);
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
         fs::write(&path, yml)?;
         Ok(EncryptionConfig { path })
     }
+
+    /// Load the already created encryption config of a previous run
+    pub fn load(config: &Config) -> EncryptionConfig {
+        EncryptionConfig {
+            path: Self::file(config),
+        }
+    }
+
+    /// Retrieve the path to the encryption config file
+    pub fn file(config: &Config) -> PathBuf {
+        config.root().join(Self::DIR).join("config.yml")
+    }
 }
 
 #[cfg(test)]
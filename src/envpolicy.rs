@@ -0,0 +1,126 @@
+use crate::{faketime::FakeTime, Config, Fallible};
+use std::{
+    collections::BTreeMap,
+    env::{var, vars},
+    fs,
+    path::Path,
+    process::Command,
+};
+
+/// The environment handling of spawned processes
+#[derive(Clone)]
+pub struct EnvPolicy {
+    /// Inherit the whole environment of kubernix
+    inherit: bool,
+
+    /// The resulting environment variables
+    vars: BTreeMap<String, String>,
+}
+
+impl EnvPolicy {
+    /// The file name of the environment log within the component directory
+    pub const FILE: &'static str = "environment";
+
+    /// Variables which are always passed to pure processes
+    const ALLOWLIST: &'static [&'static str] = &[
+        "HOME",
+        "LANG",
+        "LC_ALL",
+        "LC_CTYPE",
+        "LC_MESSAGES",
+        "LOCALE_ARCHIVE",
+        "NIX_SSL_CERT_FILE",
+        "PATH",
+        "SSL_CERT_FILE",
+        "TMPDIR",
+        "TZ",
+    ];
+
+    /// Create a new policy, which inherits the whole environment only for
+    /// impure configurations
+    pub fn new(config: &Config) -> Fallible<EnvPolicy> {
+        let inherit = *config.impure();
+        let mut vars: BTreeMap<String, String> = if inherit {
+            vars().collect()
+        } else {
+            Self::ALLOWLIST
+                .iter()
+                .filter_map(|&k| var(k).ok().map(|v| (k.to_owned(), v)))
+                .collect()
+        };
+        for (k, v) in FakeTime::env(config)? {
+            vars.insert(k.to_owned(), v);
+        }
+        Ok(EnvPolicy { inherit, vars })
+    }
+
+    /// Pass the provided variable of the current environment, if set
+    pub fn inherit(&mut self, key: &str) {
+        if let Ok(value) = var(key) {
+            self.vars.insert(key.to_owned(), value);
+        }
+    }
+
+    /// Set the provided variable
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.vars.insert(key.to_owned(), value.to_owned());
+    }
+
+    /// Retrieve the resulting environment variables
+    pub fn vars(&self) -> &BTreeMap<String, String> {
+        &self.vars
+    }
+
+    /// Apply the environment to the provided command
+    pub fn apply(&self, command: &mut Command) {
+        if !self.inherit {
+            command.env_clear();
+        }
+        command.envs(&self.vars);
+    }
+
+    /// Write the resulting environment into the provided directory
+    pub fn write(&self, dir: &Path) -> Fallible<()> {
+        let file = dir.join(Self::FILE);
+        let content: String = self
+            .vars
+            .iter()
+            .map(|(k, v)| format!("{}={}\n", k, v))
+            .collect();
+        fs::write(&file, content)
+            .map_err(|e| format_err!("Unable to create '{}': {}", file.display(), e))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+    use tempfile::tempdir;
+
+    #[test]
+    fn new_success_pure() -> Fallible<()> {
+        let c = test_config()?;
+        let mut p = EnvPolicy::new(&c)?;
+        assert!(!p.inherit);
+        assert!(p
+            .vars
+            .keys()
+            .all(|k| EnvPolicy::ALLOWLIST.contains(&k.as_str())));
+
+        p.inherit("CARGO");
+        assert!(p.vars.contains_key("CARGO"));
+        Ok(())
+    }
+
+    #[test]
+    fn write_success() -> Fallible<()> {
+        let c = test_config()?;
+        let d = tempdir()?;
+        let p = EnvPolicy::new(&c)?;
+        p.write(d.path())?;
+        assert!(d.path().join(EnvPolicy::FILE).exists());
+        Ok(())
+    }
+}
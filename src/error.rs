@@ -0,0 +1,148 @@
+//! Error handling
+use ipnetwork::IpNetworkError;
+use std::{env::JoinPathsError, io, string::FromUtf8Error};
+use thiserror::Error as ThisError;
+
+/// Return early with an error built from the provided format string
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err(format_err!($($arg)*))
+    };
+}
+
+/// Build an error from the provided format string
+macro_rules! format_err {
+    ($($arg:tt)*) => {
+        $crate::Error::Other(format!($($arg)*))
+    };
+}
+
+/// The result type used by kubernix
+pub type Fallible<T> = Result<T, Error>;
+
+#[derive(Debug, ThisError)]
+/// All errors which can occur within kubernix
+pub enum Error {
+    #[error("Preflight check failed: {0}")]
+    /// The host system is not able to run the cluster
+    Preflight(String),
+
+    #[error("Nix environment failed: {0}")]
+    /// Building or entering the Nix environment failed
+    Nix(String),
+
+    #[error("Unable to setup PKI: {0}")]
+    /// Creating the certificates failed
+    Pki(String),
+
+    #[error("Command inside the Nix environment exited with {0}")]
+    /// A command inside the Nix environment failed with the exit code
+    Shell(i32),
+
+    #[error("Unable to start {0}: {1}")]
+    /// A component was not able to start
+    ComponentStart(String, String),
+
+    #[error("Timed out {0}")]
+    /// Waiting for a condition exceeded its timeout
+    Timeout(String),
+
+    #[error("Interrupted by signal {0}")]
+    /// A termination signal has been received
+    Interrupted(i32),
+
+    #[error("{0}")]
+    /// Any other error
+    Other(String),
+
+    #[error(transparent)]
+    /// An I/O error
+    Io(#[from] io::Error),
+
+    #[error(transparent)]
+    /// A system call error
+    Sys(#[from] nix::Error),
+
+    #[error(transparent)]
+    /// A network address error
+    Network(#[from] IpNetworkError),
+
+    #[error(transparent)]
+    /// A path joining error
+    JoinPaths(#[from] JoinPathsError),
+
+    #[error(transparent)]
+    /// An UTF-8 conversion error
+    Utf8(#[from] FromUtf8Error),
+
+    #[error(transparent)]
+    /// A JSON error
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    /// A TOML deserialization error
+    TomlDe(#[from] toml::de::Error),
+
+    #[error(transparent)]
+    /// A TOML serialization error
+    TomlSer(#[from] toml::ser::Error),
+
+    #[error(transparent)]
+    /// A YAML error
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error(transparent)]
+    /// A logger initialization error
+    Logger(#[from] log::SetLoggerError),
+
+    #[error(transparent)]
+    /// A terminal error
+    Terminal(#[from] crossterm::ErrorKind),
+}
+
+impl Error {
+    /// Create a component start error, which keeps timeouts and interrupts
+    /// as they are
+    pub fn component_start(name: &str, error: Error) -> Self {
+        match error {
+            Error::Timeout(_) | Error::Interrupted(_) => error,
+            e => Error::ComponentStart(name.into(), e.to_string()),
+        }
+    }
+
+    /// The process exit code for the error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Preflight(_) => 10,
+            Error::Nix(_) => 11,
+            Error::Pki(_) => 12,
+            Error::ComponentStart(..) => 13,
+            Error::Timeout(_) => 14,
+            Error::Shell(code) => *code,
+            Error::Interrupted(signal) => 128 + signal,
+            _ => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn component_start_success() {
+        let e = Error::component_start("etcd", format_err!("failed"));
+        assert_eq!(e.to_string(), "Unable to start etcd: failed");
+        assert_eq!(e.exit_code(), 13);
+
+        let e = Error::component_start("etcd", Error::Timeout("waiting".into()));
+        assert_eq!(e.exit_code(), 14);
+    }
+
+    #[test]
+    fn exit_code_success() {
+        assert_eq!(Error::Preflight("".into()).exit_code(), 10);
+        assert_eq!(format_err!("other").exit_code(), 1);
+        assert_eq!(Error::Interrupted(2).exit_code(), 130);
+    }
+}
@@ -1,13 +1,20 @@
 use crate::{
+    component::ComponentKind,
     config::Config,
     pki::Pki,
     process::{Process, Startable, Stoppable},
+    profile::Preset,
+    verbosity::Verbosity,
+    Fallible,
 };
-use failure::Fallible;
 use log::info;
 use std::{
-    fs::{create_dir_all, remove_dir_all},
-    net::Ipv4Addr,
+    fs::{
+        create_dir_all, read_dir, remove_dir_all, set_permissions, symlink_metadata, DirBuilder,
+        Permissions,
+    },
+    os::unix::fs::{DirBuilderExt, PermissionsExt},
+    path::{Path, PathBuf},
 };
 
 pub struct Etcd {
@@ -15,36 +22,157 @@ pub struct Etcd {
 }
 
 impl Etcd {
-    pub fn start(config: &Config, pki: &Pki) -> Fallible<Startable> {
-        info!("Starting etcd");
+    const DIR: &'static str = "etcd";
+    const DATA_DIR: &'static str = "run";
 
-        let localhost = Ipv4Addr::LOCALHOST.to_string();
-        let etcd_localhost = format!("https://{}:2379", localhost);
-        let etcd_localhost_peer = format!("https://{}:2380", localhost);
+    /// The unix sockets within the etcd directory
+    const CLIENT_SOCKET: &'static str = "client.sock";
+    const PEER_SOCKET: &'static str = "peer.sock";
 
-        // Remove the etcd data dir if already exists (configuration re-use)
-        let dir = config.root().join("etcd");
-        create_dir_all(&dir)?;
+    /// The maximum path length of a unix socket
+    const MAX_SOCKET_PATH: usize = 107;
+
+    /// Retrieve the URL clients use to connect to etcd
+    pub fn client_url(config: &Config) -> String {
+        Self::url(config, Self::CLIENT_SOCKET, *config.ports().etcd_client())
+    }
+
+    /// Retrieve the URL of the etcd peer
+    fn peer_url(config: &Config) -> String {
+        Self::url(config, Self::PEER_SOCKET, *config.ports().etcd_peer())
+    }
+
+    /// Build an URL of etcd, whereas unix sockets are protected by their file
+    /// permissions instead of TLS
+    fn url(config: &Config, socket: &str, port: u16) -> String {
+        if *config.etcd_unix_socket() {
+            format!("unix://{}", Self::socket(config, socket).display())
+        } else {
+            format!("https://{}:{}", config.etcd_address(), port)
+        }
+    }
+
+    fn socket(config: &Config, name: &str) -> PathBuf {
+        config.root().join(Self::DIR).join(name)
+    }
 
-        let data_dir = dir.join("run");
+    /// Verify that etcd is only reachable from the host itself
+    fn validate(config: &Config) -> Fallible<()> {
+        if *config.etcd_unix_socket() {
+            let socket = Self::socket(config, Self::CLIENT_SOCKET);
+            if socket.as_os_str().len() > Self::MAX_SOCKET_PATH {
+                bail!(
+                    "etcd socket path '{}' exceeds {} characters, please use a shorter root",
+                    socket.display(),
+                    Self::MAX_SOCKET_PATH
+                )
+            }
+        } else if !config.etcd_address().is_loopback() {
+            bail!(
+                "etcd address {} is not a loopback address",
+                config.etcd_address()
+            )
+        }
+        Ok(())
+    }
+
+    /// Remove the etcd data dir if already exists (configuration re-use)
+    pub fn reset(config: &Config) -> Fallible<()> {
+        let data_dir = config.root().join(Self::DIR).join(Self::DATA_DIR);
         if data_dir.exists() {
             remove_dir_all(&data_dir)?;
         }
+        Ok(())
+    }
+
+    /// Retrieve the size of the data directory in bytes
+    pub fn data_size(config: &Config) -> Fallible<u64> {
+        fn size(path: &Path) -> Fallible<u64> {
+            let metadata = symlink_metadata(path)?;
+            if !metadata.is_dir() {
+                return Ok(metadata.len());
+            }
+            let mut total = 0;
+            for entry in read_dir(path)? {
+                total += size(&entry?.path())?;
+            }
+            Ok(total)
+        }
+        size(&config.root().join(Self::DIR).join(Self::DATA_DIR))
+    }
+
+    /// Save a snapshot of the running etcd into the target file
+    pub fn snapshot(config: &Config, pki: &Pki, target: &Path) -> Fallible<()> {
+        info!("Saving etcd snapshot to '{}'", target.display());
+        if let Some(parent) = target.parent() {
+            create_dir_all(parent)?;
+        }
+        Process::run_to_completion("etcdctl")
+            .env("ETCDCTL_API", "3")
+            .arg(format!("--endpoints={}", Self::client_url(config)))
+            .arg(format!("--cacert={}", pki.ca().cert().display()))
+            .arg(format!("--cert={}", pki.apiserver().cert().display()))
+            .arg(format!("--key={}", pki.apiserver().key().display()))
+            .arg("snapshot")
+            .arg("save")
+            .arg(target)
+            .current_dir(config.root())
+            .run()?;
+        Ok(())
+    }
+
+    /// Restore the data dir of the stopped etcd from the provided snapshot
+    pub fn restore(config: &Config, snapshot: &Path) -> Fallible<()> {
+        info!("Restoring etcd snapshot '{}'", snapshot.display());
+        Self::reset(config)?;
+        let peer = Self::peer_url(config);
+        Process::run_to_completion("etcdctl")
+            .env("ETCDCTL_API", "3")
+            .arg("snapshot")
+            .arg("restore")
+            .arg(snapshot)
+            .arg("--name=etcd")
+            .arg(format!("--initial-cluster=etcd={}", peer))
+            .arg("--initial-cluster-token=etcd-cluster")
+            .arg(format!("--initial-advertise-peer-urls={}", peer))
+            .arg(format!(
+                "--data-dir={}",
+                config.root().join(Self::DIR).join(Self::DATA_DIR).display()
+            ))
+            .current_dir(config.root())
+            .run()?;
+        Ok(())
+    }
+
+    pub fn start(config: &Config, pki: &Pki) -> Fallible<Startable> {
+        info!("Starting etcd");
+
+        Self::validate(config)?;
+        let client = Self::client_url(config);
+        let peer = Self::peer_url(config);
+
+        let dir = config.root().join(Self::DIR);
+        create_dir_all(&dir)?;
+
+        // etcd refuses to start if the data dir is accessible by others
+        let data_dir = dir.join(Self::DATA_DIR);
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&data_dir)?;
+        set_permissions(&data_dir, Permissions::from_mode(0o700))?;
 
-        let mut process = Process::start(
-            config,
-            &dir,
-            "etcd",
-            &[
-                &format!("--advertise-client-urls={}", etcd_localhost),
+        let process = Process::builder(config, &dir, "etcd")
+            .args(&[
+                &format!("--advertise-client-urls={}", client),
                 "--client-cert-auth",
                 &format!("--data-dir={}", data_dir.display()),
-                &format!("--initial-advertise-peer-urls={}", etcd_localhost_peer),
+                &format!("--initial-advertise-peer-urls={}", peer),
                 "--initial-cluster-state=new",
                 "--initial-cluster-token=etcd-cluster",
-                &format!("--initial-cluster=etcd={}", etcd_localhost_peer),
-                &format!("--listen-client-urls={}", etcd_localhost),
-                &format!("--listen-peer-urls={}", etcd_localhost_peer),
+                &format!("--initial-cluster=etcd={}", peer),
+                &format!("--listen-client-urls={}", client),
+                &format!("--listen-peer-urls={}", peer),
                 "--name=etcd",
                 "--peer-client-cert-auth",
                 &format!("--cert-file={}", pki.apiserver().cert().display()),
@@ -53,10 +181,17 @@ impl Etcd {
                 &format!("--peer-key-file={}", pki.apiserver().key().display()),
                 &format!("--peer-trusted-ca-file={}", pki.ca().cert().display()),
                 &format!("--trusted-ca-file={}", pki.ca().cert().display()),
-            ],
-        )?;
+            ])
+            .args(&Verbosity::args(config, ComponentKind::Etcd))
+            .args(&Preset::for_config(config).args(ComponentKind::Etcd))
+            // Keep the database files private
+            .cwd(&data_dir)
+            .umask(0o077)
+            // Required to run etcd on non amd64 architectures
+            .inherit_env("ETCD_UNSUPPORTED_ARCH")
+            .ready_on("ready to serve client requests")
+            .spawn()?;
 
-        process.wait_ready("ready to serve client requests")?;
         info!("etcd is ready");
         Ok(Box::new(Etcd { process }))
     }
@@ -80,6 +215,9 @@ mod tests {
         let p = Pki::new(&c, &n, "", "")?;
 
         let mut etcd = Etcd::start(&c, &p)?;
+        let data_dir = c.root().join(Etcd::DIR).join(Etcd::DATA_DIR);
+        let mode = symlink_metadata(&data_dir)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
         etcd.stop()
     }
 }
@@ -0,0 +1,296 @@
+use crate::{
+    config::{Events, OutputFormat},
+    output::Output,
+    Config, Fallible,
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fmt::{self, Display},
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The kinds of significant events during the lifetime of a cluster
+pub enum EventKind {
+    /// The cluster has been bootstrapped
+    ClusterStarted,
+
+    /// The cluster got destroyed
+    ClusterStopped,
+
+    /// A process has been spawned
+    Started,
+
+    /// A process became ready
+    Ready,
+
+    /// A process died unexpectedly
+    Crashed,
+
+    /// A crashed process has been spawned again
+    Restarted,
+
+    /// A still running process of a crashed previous run has been taken over
+    Adopted,
+
+    /// A running process repeatedly failed its liveness probes
+    Unhealthy,
+
+    /// An unhealthy process passed its liveness probe again
+    Recovered,
+
+    /// A process has been stopped intentionally
+    Stopped,
+
+    /// The configuration of the running cluster has been applied
+    ConfigChanged,
+
+    /// The Kubernetes version of the running cluster changed
+    Upgraded,
+}
+
+impl Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            EventKind::ClusterStarted => "cluster-started",
+            EventKind::ClusterStopped => "cluster-stopped",
+            EventKind::Started => "started",
+            EventKind::Ready => "ready",
+            EventKind::Crashed => "crashed",
+            EventKind::Restarted => "restarted",
+            EventKind::Adopted => "adopted",
+            EventKind::Unhealthy => "unhealthy",
+            EventKind::Recovered => "recovered",
+            EventKind::Stopped => "stopped",
+            EventKind::ConfigChanged => "config-changed",
+            EventKind::Upgraded => "upgraded",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+/// A single entry of the event timeline
+struct Event {
+    time: u64,
+    kind: EventKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    component: Option<String>,
+    message: String,
+}
+
+/// The append-only event timeline of a cluster, which survives the cluster
+/// for post-mortem debugging
+pub struct EventLog;
+
+impl EventLog {
+    /// The file name of the timeline within the root directory
+    const FILENAME: &'static str = "events.log";
+
+    /// The interval of checking the timeline for new events when following it
+    const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// The path of the timeline within the provided root directory
+    pub fn file(root: &Path) -> PathBuf {
+        root.join(Self::FILENAME)
+    }
+
+    /// Append an event to the timeline of the provided root directory,
+    /// whereas failures are not fatal for the cluster
+    pub fn record(root: &Path, kind: EventKind, component: Option<&str>, message: &str) {
+        if let Err(e) = Self::append(root, kind, component, message) {
+            debug!("Unable to record event: {}", e)
+        }
+    }
+
+    /// Print the timeline of the cluster and optionally wait for new events
+    pub fn run(config: &Config, args: &Events) -> Fallible<()> {
+        let file = Self::file(config.root());
+        if !file.exists() && !*args.follow() {
+            bail!("No events found in '{}'", file.display())
+        }
+        // The timeline may not exist yet if a cluster is just starting
+        while !file.exists() {
+            sleep(Self::FOLLOW_INTERVAL);
+        }
+
+        let mut reader = BufReader::new(File::open(&file)?);
+        let mut line = String::new();
+        loop {
+            if reader.read_line(&mut line)? == 0 {
+                if !*args.follow() {
+                    return Ok(());
+                }
+                sleep(Self::FOLLOW_INTERVAL);
+                continue;
+            }
+            // Incomplete lines get continued on the next read
+            if !line.ends_with('\n') {
+                continue;
+            }
+            match serde_json::from_str::<Event>(&line) {
+                Ok(event) => Self::print(&event, *args.output().format())?,
+                Err(e) => debug!("Skipping invalid event '{}': {}", line.trim(), e),
+            }
+            line.clear();
+        }
+    }
+
+    fn append(
+        root: &Path,
+        kind: EventKind,
+        component: Option<&str>,
+        message: &str,
+    ) -> Fallible<()> {
+        let event = Event {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_secs())
+                .unwrap_or_default(),
+            kind,
+            component: component.map(ToOwned::to_owned),
+            message: message.into(),
+        };
+        // A single write keeps concurrently appended events intact
+        let line = format!("{}\n", serde_json::to_string(&event)?);
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Self::file(root))?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Print a single event in the provided format, whereas structured
+    /// events are written one per line to stay consumable while following
+    fn print(event: &Event, format: OutputFormat) -> Fallible<()> {
+        match format {
+            OutputFormat::Text => println!("{}", Self::format(event)),
+            x => Output::print_item(x, &Self::value(event))?,
+        }
+        Ok(())
+    }
+
+    /// Convert a single event into its machine readable representation
+    fn value(event: &Event) -> Value {
+        json!({
+            "time": Self::timestamp(event.time),
+            "timestamp": event.time,
+            "kind": event.kind.to_string(),
+            "component": event.component,
+            "message": event.message,
+        })
+    }
+
+    /// Format a single event for humans
+    fn format(event: &Event) -> String {
+        let mut result = format!(
+            "{} {:<15}",
+            Self::timestamp(event.time),
+            event.kind.to_string()
+        );
+        if let Some(component) = &event.component {
+            result.push_str(&format!(" {}:", component));
+        }
+        result.push_str(&format!(" {}", event.message));
+        result
+    }
+
+    /// Convert the provided UNIX time into an RFC 3339 UTC timestamp
+    fn timestamp(time: u64) -> String {
+        // Civil date from days since the epoch, see
+        // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let days = (time / 86400) as i64 + 719_468;
+        let era = days / 146_097;
+        let doe = days - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        let secs = time % 86400;
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read_to_string;
+    use tempfile::tempdir;
+
+    #[test]
+    fn record_success() -> Fallible<()> {
+        let d = tempdir()?;
+        EventLog::record(d.path(), EventKind::Started, Some("etcd"), "PID 42");
+        EventLog::record(d.path(), EventKind::ConfigChanged, None, "Applied");
+        let timeline = read_to_string(EventLog::file(d.path()))?;
+        let events: Vec<Event> = timeline
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, EventKind::Started);
+        assert_eq!(events[0].component.as_deref(), Some("etcd"));
+        assert!(timeline.contains(r#""kind":"config-changed","message":"Applied""#));
+        Ok(())
+    }
+
+    #[test]
+    fn format_success() {
+        let event = Event {
+            time: 1_584_019_932,
+            kind: EventKind::Crashed,
+            component: Some("kubelet".into()),
+            message: "exit status: 1".into(),
+        };
+        assert_eq!(
+            EventLog::format(&event),
+            "2020-03-12T13:32:12Z crashed         kubelet: exit status: 1"
+        );
+    }
+
+    #[test]
+    fn value_success() {
+        let event = Event {
+            time: 1_584_019_932,
+            kind: EventKind::ConfigChanged,
+            component: None,
+            message: "Applied".into(),
+        };
+        assert_eq!(
+            EventLog::value(&event),
+            json!({
+                "time": "2020-03-12T13:32:12Z",
+                "timestamp": 1_584_019_932,
+                "kind": "config-changed",
+                "component": null,
+                "message": "Applied",
+            })
+        );
+    }
+
+    #[test]
+    fn timestamp_success() {
+        assert_eq!(EventLog::timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(EventLog::timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(EventLog::timestamp(1_609_459_199), "2020-12-31T23:59:59Z");
+    }
+}
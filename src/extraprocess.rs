@@ -0,0 +1,143 @@
+use crate::{
+    activation::Activation,
+    component::{Component, Context},
+    process::{Process, Startable},
+    Fallible, KUBECONFIG_ENV,
+};
+use getset::Getters;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+
+#[derive(Clone, Debug, Deserialize, Getters, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// A user defined process, which is supervised alongside the cluster
+pub struct ExtraProcess {
+    #[get = "pub"]
+    /// The unique name of the process
+    name: String,
+
+    #[get = "pub"]
+    /// The command to be executed
+    command: String,
+
+    #[get = "pub"]
+    #[serde(default)]
+    /// The arguments of the command
+    args: Vec<String>,
+
+    #[get = "pub"]
+    #[serde(default)]
+    /// Additional environment variables
+    env: BTreeMap<String, String>,
+
+    #[get = "pub"]
+    /// The working directory, which defaults to the process directory
+    cwd: Option<PathBuf>,
+
+    #[get = "pub"]
+    /// The output pattern indicating that the process is ready
+    ready_on: Option<String>,
+
+    #[get = "pub"]
+    /// The regular expression of an output line indicating that the process
+    /// is ready
+    ready_on_regex: Option<String>,
+
+    #[get = "pub"]
+    #[serde(default)]
+    /// The components or processes which have to be ready before
+    depends_on: Vec<String>,
+
+    #[get = "pub"]
+    /// Start the process lazily on the first connection to a port
+    activation: Option<Activation>,
+}
+
+impl Component for ExtraProcess {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn dependencies(&self) -> Vec<String> {
+        self.depends_on.clone()
+    }
+
+    fn activation(&self) -> Option<Activation> {
+        self.activation
+    }
+
+    fn start(&self, context: &Context) -> Fallible<Startable> {
+        // Provide cluster access per default
+        let dir = context.dir(&self.name);
+        let kubeconfig = context.admin_kubeconfig().display().to_string();
+        let mut builder = Process::builder(context.config(), &dir, &self.command)
+            .name(&self.name)
+            .args(&self.args)
+            .env(KUBECONFIG_ENV, &kubeconfig);
+        for (key, value) in &self.env {
+            builder = builder.env(key, value);
+        }
+        if let Some(cwd) = &self.cwd {
+            builder = builder.cwd(cwd);
+        }
+        if let Some(pattern) = &self.ready_on {
+            builder = builder.ready_on(pattern);
+        }
+        if let Some(pattern) = &self.ready_on_regex {
+            let regex = Regex::new(pattern).map_err(|e| {
+                format_err!("Invalid ready-on-regex of process '{}': {}", self.name, e)
+            })?;
+            builder = builder.ready_on_regex(regex);
+        }
+        Ok(Box::new(builder.spawn()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_success() -> Fallible<()> {
+        let p: ExtraProcess = toml::from_str(
+            r#"
+name = "operator"
+command = "operator"
+args = ["--verbose"]
+ready-on = "Starting workers"
+ready-on-regex = "listening on :\\d+"
+depends-on = ["apiserver"]
+
+[env]
+WATCH_NAMESPACE = "default"
+"#,
+        )?;
+        assert_eq!(p.name(), "operator");
+        assert_eq!(p.args().len(), 1);
+        assert_eq!(p.env().get("WATCH_NAMESPACE"), Some(&"default".to_owned()));
+        assert_eq!(p.dependencies(), vec!["apiserver".to_owned()]);
+        assert_eq!(p.ready_on_regex().as_deref(), Some(r"listening on :\d+"));
+        assert!(p.cwd().is_none());
+        assert!(p.activation().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_activation_success() -> Fallible<()> {
+        let p: ExtraProcess = toml::from_str(
+            r#"
+name = "dashboard"
+command = "dashboard"
+
+[activation]
+listen-port = 9090
+target-port = 19090
+"#,
+        )?;
+        let activation = p.activation().ok_or_else(|| format_err!("no activation"))?;
+        assert_eq!(*activation.listen_port(), 9090);
+        assert_eq!(*activation.target_port(), 19090);
+        Ok(())
+    }
+}
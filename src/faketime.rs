@@ -0,0 +1,102 @@
+use crate::{Config, Fallible, Kubernix};
+use log::{debug, info};
+use std::{
+    fs::{self, create_dir_all},
+    path::{Path, PathBuf},
+};
+
+pub struct FakeTime;
+
+impl FakeTime {
+    /// The Nix package providing libfaketime
+    pub const PACKAGE: &'static str = "libfaketime";
+
+    /// Write the initial time offset into the root, which can be changed
+    /// during runtime to travel in time
+    pub fn setup(config: &Config) -> Fallible<()> {
+        if let Some(offset) = config.faketime() {
+            let file = Self::file(config);
+            info!(
+                "Running components with fake time '{}', change '{}' to adjust it",
+                offset,
+                file.display()
+            );
+            create_dir_all(config.root().join("faketime"))?;
+            fs::write(file, offset)?;
+        }
+        Ok(())
+    }
+
+    /// Retrieve the environment variables for running a process under libfaketime
+    pub fn env(config: &Config) -> Fallible<Vec<(&'static str, String)>> {
+        if config.faketime().is_none() {
+            return Ok(vec![]);
+        }
+
+        let lib = Self::library(&Kubernix::find_executable("faketime")?)?;
+        debug!("Using libfaketime library {}", lib.display());
+
+        Ok(vec![
+            ("LD_PRELOAD", lib.display().to_string()),
+            (
+                "FAKETIME_TIMESTAMP_FILE",
+                Self::file(config).display().to_string(),
+            ),
+            ("FAKETIME_NO_CACHE", "1".into()),
+            ("FAKETIME_DONT_FAKE_MONOTONIC", "1".into()),
+        ])
+    }
+
+    /// Resolve the preload library of the package containing the provided
+    /// `faketime` executable
+    fn library(executable: &Path) -> Fallible<PathBuf> {
+        let lib = executable
+            .parent()
+            .and_then(|x| x.parent())
+            .map(|x| x.join("lib").join("faketime").join("libfaketime.so.1"))
+            .ok_or_else(|| format_err!("Unable to find libfaketime library path"))?;
+        if !lib.exists() {
+            bail!("libfaketime library '{}' does not exist", lib.display());
+        }
+        Ok(lib)
+    }
+
+    fn file(config: &Config) -> PathBuf {
+        config.root().join("faketime").join("faketimerc")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+    use tempfile::tempdir;
+
+    #[test]
+    fn env_success_disabled() -> Fallible<()> {
+        let c = test_config()?;
+        assert!(FakeTime::env(&c)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn library_success() -> Fallible<()> {
+        let d = tempdir()?;
+        let lib = d.path().join("lib").join("faketime");
+        create_dir_all(&lib)?;
+        create_dir_all(d.path().join("bin"))?;
+        fs::write(lib.join("libfaketime.so.1"), "")?;
+        assert_eq!(
+            FakeTime::library(&d.path().join("bin").join("faketime"))?,
+            lib.join("libfaketime.so.1")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn library_failure_missing() -> Fallible<()> {
+        let d = tempdir()?;
+        assert!(FakeTime::library(&d.path().join("bin").join("faketime")).is_err());
+        Ok(())
+    }
+}
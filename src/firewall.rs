@@ -0,0 +1,352 @@
+use crate::{network::Network, Config, Fallible};
+use ipnetwork::Ipv4Network;
+use log::{debug, info, warn};
+use serde_json::Value;
+use std::{
+    fmt::{self, Display},
+    fs::read_to_string,
+    path::Path,
+    process::Command,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The host firewalls kubernix is able to coexist with
+pub enum FirewallBackend {
+    /// firewalld, which owns the whole ruleset of the host
+    Firewalld,
+
+    /// A plain nftables ruleset dropping packets by default
+    Nftables,
+}
+
+impl Display for FirewallBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FirewallBackend::Firewalld => "firewalld",
+            FirewallBackend::Nftables => "nftables",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+/// An nftables chain or rule location
+struct Location {
+    family: String,
+    table: String,
+    chain: String,
+}
+
+pub struct Firewall;
+
+impl Firewall {
+    /// The Nix package providing the nft command line
+    pub const PACKAGE: &'static str = "nftables";
+
+    /// The comment identifying the nftables rules of kubernix
+    const COMMENT: &'static str = "kubernix";
+
+    /// The PID file of a running firewalld
+    const FIREWALLD_PID: &'static str = "/var/run/firewalld.pid";
+
+    /// The locations of the firewalld command line, which is taken from the
+    /// host since it has to talk to the running daemon
+    const FIREWALL_CMD: [&'static str; 2] = ["/usr/bin/firewall-cmd", "/usr/sbin/firewall-cmd"];
+
+    /// The firewalld zone accepting all traffic of the cluster networks
+    const TRUSTED_ZONE: &'static str = "trusted";
+
+    /// Detect the firewall of the host, if it filters packets at all
+    pub fn detect() -> Option<FirewallBackend> {
+        if Self::firewalld_running() {
+            return Some(FirewallBackend::Firewalld);
+        }
+        match Self::drop_chains() {
+            Ok(x) if !x.is_empty() => Some(FirewallBackend::Nftables),
+            Ok(_) => None,
+            Err(e) => {
+                debug!("Unable to list nftables chains: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Verify that the detected firewall can be configured
+    pub fn preflight(config: &Config) -> Fallible<()> {
+        let backend = match Self::detect() {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        if *config.skip_firewall() {
+            warn!(
+                "The host firewall {} is active but --skip-firewall is set, which may result in \
+                 filtered pod and service traffic",
+                backend
+            );
+        } else if backend == FirewallBackend::Firewalld && Self::firewall_cmd().is_none() {
+            bail!(
+                "firewalld is running but firewall-cmd is not available, please use \
+                 --skip-firewall to ignore the host firewall"
+            )
+        }
+        Ok(())
+    }
+
+    /// Accept the traffic of the cluster networks in the host firewall
+    pub fn setup(config: &Config, network: &Network) -> Fallible<()> {
+        if *config.skip_firewall() {
+            return Ok(());
+        }
+        match Self::detect() {
+            Some(FirewallBackend::Firewalld) => {
+                info!("Adding the cluster networks to the trusted firewalld zone");
+                for cidr in Self::cidrs(network) {
+                    Self::firewalld(&format!("--add-source={}", cidr))?;
+                }
+            }
+            Some(FirewallBackend::Nftables) => {
+                // Rules of a previous run are replaced
+                Self::remove_rules()?;
+                info!("Accepting the cluster networks in the nftables ruleset");
+                let comment = format!("\"{}\"", Self::COMMENT);
+                for chain in Self::drop_chains()? {
+                    for cidr in Self::cidrs(network) {
+                        for direction in &["saddr", "daddr"] {
+                            Self::nft(&[
+                                "insert",
+                                "rule",
+                                &chain.family,
+                                &chain.table,
+                                &chain.chain,
+                                "ip",
+                                direction,
+                                &cidr.to_string(),
+                                "accept",
+                                "comment",
+                                &comment,
+                            ])?;
+                        }
+                    }
+                }
+            }
+            None => debug!("No packet filtering host firewall found"),
+        }
+        Ok(())
+    }
+
+    /// Remove all rules added to the host firewall
+    pub fn cleanup(config: &Config, network: &Network) -> Fallible<()> {
+        if *config.skip_firewall() {
+            return Ok(());
+        }
+        match Self::detect() {
+            Some(FirewallBackend::Firewalld) => {
+                for cidr in Self::cidrs(network) {
+                    Self::firewalld(&format!("--remove-source={}", cidr))?;
+                }
+            }
+            Some(FirewallBackend::Nftables) => Self::remove_rules()?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Retrieve the cluster networks, whereas networks contained in
+    /// another one are omitted
+    fn cidrs(network: &Network) -> Vec<Ipv4Network> {
+        Self::distinct(&[*network.cluster(), *network.crio(), *network.service()])
+    }
+
+    /// Filter the networks which are not part of another one
+    fn distinct(cidrs: &[Ipv4Network]) -> Vec<Ipv4Network> {
+        let mut result: Vec<Ipv4Network> = vec![];
+        for cidr in cidrs {
+            let contained = cidrs
+                .iter()
+                .any(|x| x != cidr && x.prefix() < cidr.prefix() && x.contains(cidr.network()));
+            if !contained && !result.contains(cidr) {
+                result.push(*cidr)
+            }
+        }
+        result
+    }
+
+    /// Check if firewalld is running on the host
+    fn firewalld_running() -> bool {
+        read_to_string(Self::FIREWALLD_PID)
+            .map(|x| Path::new("/proc").join(x.trim()).exists())
+            .unwrap_or(false)
+    }
+
+    /// Find the firewalld command line of the host
+    fn firewall_cmd() -> Option<&'static str> {
+        Self::FIREWALL_CMD
+            .iter()
+            .find(|x| Path::new(x).is_file())
+            .cloned()
+    }
+
+    /// Change the runtime configuration of the trusted firewalld zone, which
+    /// gets lost on a reload of firewalld
+    fn firewalld(arg: &str) -> Fallible<()> {
+        let cmd = Self::firewall_cmd().ok_or_else(|| format_err!("firewall-cmd not found"))?;
+        debug!("Running firewall-cmd {}", arg);
+        let output = Command::new(cmd)
+            .arg(format!("--zone={}", Self::TRUSTED_ZONE))
+            .arg(arg)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "Unable to run `firewall-cmd {}`: {}",
+                arg,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        }
+        Ok(())
+    }
+
+    /// Retrieve all IPv4 input and forward chains dropping packets by default
+    fn drop_chains() -> Fallible<Vec<Location>> {
+        Self::parse_drop_chains(&Self::nft(&["--json", "list", "chains"])?)
+    }
+
+    /// Remove all rules of kubernix from the ruleset
+    fn remove_rules() -> Fallible<()> {
+        let ruleset = Self::nft(&["--json", "--handle", "list", "ruleset"])?;
+        for (location, handle) in Self::parse_rules(&ruleset)? {
+            Self::nft(&[
+                "delete",
+                "rule",
+                &location.family,
+                &location.table,
+                &location.chain,
+                "handle",
+                &handle.to_string(),
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Parse the base chains which drop IPv4 packets by default
+    fn parse_drop_chains(json: &str) -> Fallible<Vec<Location>> {
+        Ok(Self::items(json, "chain")?
+            .into_iter()
+            .filter(|x| {
+                ["ip", "inet"].contains(&x["family"].as_str().unwrap_or_default())
+                    && ["input", "forward"].contains(&x["hook"].as_str().unwrap_or_default())
+                    && x["policy"] == "drop"
+            })
+            .map(|x| Location {
+                family: x["family"].as_str().unwrap_or_default().into(),
+                table: x["table"].as_str().unwrap_or_default().into(),
+                chain: x["name"].as_str().unwrap_or_default().into(),
+            })
+            .collect())
+    }
+
+    /// Parse the rules of kubernix and their handles
+    fn parse_rules(json: &str) -> Fallible<Vec<(Location, u64)>> {
+        Ok(Self::items(json, "rule")?
+            .into_iter()
+            .filter(|x| x["comment"] == Self::COMMENT)
+            .filter_map(|x| {
+                let location = Location {
+                    family: x["family"].as_str()?.into(),
+                    table: x["table"].as_str()?.into(),
+                    chain: x["chain"].as_str()?.into(),
+                };
+                Some((location, x["handle"].as_u64()?))
+            })
+            .collect())
+    }
+
+    /// Retrieve all objects of the provided kind from the nft JSON output
+    fn items(json: &str, kind: &str) -> Fallible<Vec<Value>> {
+        let value: Value = serde_json::from_str(json)?;
+        Ok(value["nftables"]
+            .as_array()
+            .ok_or_else(|| format_err!("Unexpected nft output"))?
+            .iter()
+            .filter_map(|x| x.get(kind).cloned())
+            .collect())
+    }
+
+    fn nft(args: &[&str]) -> Fallible<String> {
+        let output = Command::new("nft").args(args).output()?;
+        if !output.status.success() {
+            bail!(
+                "Unable to run `nft {}`: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distinct_success() -> Fallible<()> {
+        let cluster = "10.10.0.0/16".parse()?;
+        let crio = "10.10.0.0/17".parse()?;
+        let service = "10.11.0.0/24".parse()?;
+        assert_eq!(
+            Firewall::distinct(&[cluster, crio, service]),
+            vec![cluster, service]
+        );
+        assert_eq!(Firewall::distinct(&[cluster, cluster]), vec![cluster]);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_drop_chains_success() -> Fallible<()> {
+        let chains = Firewall::parse_drop_chains(
+            r#"{"nftables": [
+                {"metainfo": {"json_schema_version": 1}},
+                {"chain": {"family": "inet", "table": "filter", "name": "input",
+                           "handle": 1, "type": "filter", "hook": "input",
+                           "prio": 0, "policy": "drop"}},
+                {"chain": {"family": "inet", "table": "filter", "name": "output",
+                           "handle": 2, "type": "filter", "hook": "output",
+                           "prio": 0, "policy": "drop"}},
+                {"chain": {"family": "ip6", "table": "filter", "name": "forward",
+                           "handle": 3, "type": "filter", "hook": "forward",
+                           "prio": 0, "policy": "drop"}},
+                {"chain": {"family": "ip", "table": "nat", "name": "postrouting",
+                           "handle": 4, "type": "nat", "hook": "postrouting",
+                           "prio": 100, "policy": "accept"}},
+                {"chain": {"family": "inet", "table": "filter", "name": "allowed",
+                           "handle": 5}}
+            ]}"#,
+        )?;
+        assert_eq!(
+            chains,
+            vec![Location {
+                family: "inet".into(),
+                table: "filter".into(),
+                chain: "input".into(),
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_rules_success() -> Fallible<()> {
+        let rules = Firewall::parse_rules(
+            r#"{"nftables": [
+                {"rule": {"family": "inet", "table": "filter", "chain": "input",
+                          "handle": 7, "comment": "kubernix", "expr": []}},
+                {"rule": {"family": "inet", "table": "filter", "chain": "input",
+                          "handle": 8, "expr": []}}
+            ]}"#,
+        )?;
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].0.chain, "input");
+        assert_eq!(rules[0].1, 7);
+        assert!(Firewall::parse_rules("{}").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{apirecord::ApiRecord, pki::Pki, process::Process, Config, Fallible};
+use log::info;
+use std::{
+    fs::{self, create_dir_all},
+    path::Path,
+};
+
+pub struct FrontProxy;
+
+impl FrontProxy {
+    /// The Nix package providing the load balancer
+    pub const PACKAGE: &'static str = "haproxy";
+
+    /// The name of the client certificate the proxy uses to forward the
+    /// identity of the users
+    pub const CLIENT_NAME: &'static str = "kubernetes";
+
+    /// Check if the front proxy is required for the provided configuration
+    pub fn enabled(config: &Config) -> bool {
+        *config.apiserver_load_balancer()
+            || *config.apiserver_replicas() > 1
+            || *config.api_record()
+    }
+
+    /// Start the load balancer in front of the API Servers listening on the
+    /// provided local ports
+    pub fn start(config: &Config, dir: &Path, pki: &Pki, backends: &[u16]) -> Fallible<Process> {
+        info!("Starting API Server front proxy");
+        let dir = dir.join("frontproxy");
+        create_dir_all(&dir)?;
+
+        let cfg = if *config.api_record() {
+            ApiRecord::validate(config)?;
+            let socket = dir.join("record.sock");
+            ApiRecord::listen(config, &socket)?;
+
+            // HAProxy expects the certificate and the key within one file
+            let pem = dir.join("kubernetes.pem");
+            fs::write(
+                &pem,
+                [
+                    fs::read_to_string(pki.apiserver().cert())?,
+                    fs::read_to_string(pki.apiserver().key())?,
+                ]
+                .concat(),
+            )?;
+            Self::haproxy_http_cfg(
+                *config.ports().apiserver(),
+                backends,
+                &pem,
+                pki.ca().cert(),
+                &socket,
+                *config.api_record_sample(),
+            )
+        } else {
+            Self::haproxy_cfg(*config.ports().apiserver(), backends)
+        };
+        let cfg_file = dir.join("haproxy.cfg");
+        fs::write(&cfg_file, cfg)?;
+
+        let process = Process::builder(config, &dir, "haproxy")
+            .args(&["-db", "-f", &cfg_file.display().to_string()])
+            .spawn()?;
+
+        info!("API Server front proxy is ready");
+        Ok(process)
+    }
+
+    /// Render the HAProxy configuration for the provided public and backend
+    /// ports
+    fn haproxy_cfg(port: u16, backends: &[u16]) -> String {
+        format!(
+            "defaults\n  mode tcp\n  timeout connect 5s\n  timeout client 1h\n  \
+             timeout server 1h\n\n\
+             frontend apiserver\n  bind 0.0.0.0:{}\n  default_backend apiservers\n\n\
+             backend apiservers\n  balance roundrobin\n  option redispatch\n  retries 3\n{}",
+            port,
+            Self::servers(backends, "check")
+        )
+    }
+
+    /// Render the HAProxy configuration which terminates TLS to log every
+    /// request. Clients using certificates get forwarded via the proxy
+    /// certificate and the request header authentication, whereas token based
+    /// requests are passed through unchanged.
+    fn haproxy_http_cfg(
+        port: u16,
+        backends: &[u16],
+        pem: &Path,
+        ca: &Path,
+        socket: &Path,
+        sample: u8,
+    ) -> String {
+        let ssl = format!("check ssl verify required ca-file {}", ca.display());
+        format!(
+            "global\n  log {socket} format raw local0\n\n\
+             defaults\n  mode http\n  log global\n  timeout connect 5s\n  \
+             timeout client 1h\n  timeout server 1h\n\n\
+             frontend apiserver\n  bind 0.0.0.0:{port} ssl crt {pem} ca-file {ca} verify optional\n  \
+             log-format '{format}'\n  \
+             http-request set-log-level silent if {{ rand(100) ge {sample} }}\n  \
+             http-request del-header X-Remote-User\n  \
+             http-request del-header X-Remote-Group\n  \
+             http-request set-header X-Remote-User %[ssl_c_s_dn(CN)] if {{ ssl_c_used }}\n  \
+             http-request set-header X-Remote-Group %[ssl_c_s_dn(O)] if {{ ssl_c_used }}\n  \
+             use_backend apiservers-identity if {{ ssl_c_used }}\n  \
+             default_backend apiservers\n\n\
+             backend apiservers\n  balance roundrobin\n  option redispatch\n  retries 3\n{servers}\n\
+             backend apiservers-identity\n  balance roundrobin\n  option redispatch\n  \
+             retries 3\n{identity}",
+            socket = socket.display(),
+            port = port,
+            pem = pem.display(),
+            ca = ca.display(),
+            format = ApiRecord::LOG_FORMAT,
+            sample = sample,
+            servers = Self::servers(backends, &ssl),
+            identity = Self::servers(backends, &format!("{} crt {}", ssl, pem.display())),
+        )
+    }
+
+    fn servers(backends: &[u16], options: &str) -> String {
+        backends
+            .iter()
+            .enumerate()
+            .map(|(i, port)| format!("  server apiserver-{} 127.0.0.1:{} {}\n", i, port, options))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn haproxy_cfg_success() {
+        let cfg = FrontProxy::haproxy_cfg(6443, &[6444, 6445]);
+        assert!(cfg.contains("bind 0.0.0.0:6443\n"));
+        assert!(cfg.contains("server apiserver-0 127.0.0.1:6444 check\n"));
+        assert!(cfg.contains("server apiserver-1 127.0.0.1:6445 check\n"));
+    }
+
+    #[test]
+    fn haproxy_http_cfg_success() {
+        let cfg = FrontProxy::haproxy_http_cfg(
+            6443,
+            &[6444],
+            Path::new("/pem"),
+            Path::new("/ca"),
+            Path::new("/sock"),
+            10,
+        );
+        assert!(cfg.contains("log /sock format raw local0\n"));
+        assert!(cfg.contains("bind 0.0.0.0:6443 ssl crt /pem ca-file /ca verify optional\n"));
+        assert!(cfg.contains("if { rand(100) ge 10 }\n"));
+        assert!(cfg
+            .contains("server apiserver-0 127.0.0.1:6444 check ssl verify required ca-file /ca\n"));
+        assert!(cfg.contains(
+            "server apiserver-0 127.0.0.1:6444 check ssl verify required ca-file /ca crt /pem\n"
+        ));
+    }
+}
@@ -0,0 +1,349 @@
+use crate::{
+    config::Gc, lock::RootLock, upgrade::UpgradeRecord, Config, Fallible, CRIO_DIR, NIX_DIR,
+    RUNTIME_ENV,
+};
+use log::{debug, info, warn};
+use proc_mounts::MountIter;
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    fs::{canonicalize, read_dir, read_link, remove_dir_all, remove_file, symlink_metadata},
+    path::{Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
+
+/// Garbage collection of run roots and the leftovers of running clusters
+pub struct GarbageCollector<'a> {
+    config: &'a Config,
+    args: &'a Gc,
+    mounts: Vec<PathBuf>,
+    reclaimed: u64,
+}
+
+impl<'a> GarbageCollector<'a> {
+    /// The timestamp layout of rotated logs, where every `0` is a digit
+    const ROTATED_TIMESTAMP: &'static str = "0000-00-00T00-00-00.000";
+
+    /// The prefix of the Nix GC roots created by upgrades
+    const NIX_ROOT_PREFIX: &'static str = "kubernetes-";
+
+    /// Collect all garbage matching the provided arguments
+    pub fn run(config: &Config, args: &Gc) -> Fallible<()> {
+        let mut gc = GarbageCollector {
+            config,
+            args,
+            mounts: MountIter::new()?
+                .filter_map(|x| x.ok())
+                .map(|x| x.dest)
+                .collect(),
+            reclaimed: 0,
+        };
+
+        for root in gc.roots()? {
+            if gc.is_mounted(&root) || RootLock::is_held(&root)? {
+                debug!("Keeping run root '{}' since it is in use", root.display());
+            } else if gc.is_old(Self::last_used(&root)) {
+                gc.remove(&root, "run root")?;
+                continue;
+            }
+            gc.logs(&root)?;
+            gc.nix_roots(&root)?;
+        }
+
+        // Images can only be listed via the runtime of a running cluster
+        if RootLock::is_held(config.root())? {
+            gc.images()?;
+        }
+
+        info!(
+            "{} {:.1} MiB in total",
+            if *args.dry_run() {
+                "Would reclaim"
+            } else {
+                "Reclaimed"
+            },
+            gc.reclaimed as f64 / 1024. / 1024.
+        );
+        Ok(())
+    }
+
+    /// Find all run roots inside the search directory, including the
+    /// configured one
+    fn roots(&self) -> Fallible<Vec<PathBuf>> {
+        let dir = match self.args.dir() {
+            Some(dir) => dir.clone(),
+            None => self
+                .config
+                .root()
+                .parent()
+                .filter(|x| !x.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."))
+                .to_path_buf(),
+        };
+        debug!("Searching for run roots in '{}'", dir.display());
+
+        let mut roots = read_dir(dir)?
+            .filter_map(|x| x.ok())
+            .map(|x| x.path())
+            .chain(Some(self.config.root().clone()))
+            .filter(|x| x.join(Config::FILENAME).is_file())
+            .filter_map(|x| canonicalize(x).ok())
+            .collect::<Vec<_>>();
+        roots.sort();
+        roots.dedup();
+        Ok(roots)
+    }
+
+    /// Remove rotated logs below the provided root
+    fn logs(&mut self, root: &Path) -> Fallible<()> {
+        let mut dirs = vec![root.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            for entry in read_dir(&dir)?.filter_map(|x| x.ok()) {
+                let path = entry.path();
+                let file_type = entry.file_type()?;
+                if file_type.is_dir() {
+                    // The container storage and the Nix environment never
+                    // contain kubernix logs
+                    let skipped = dir == root
+                        && (entry.file_name() == CRIO_DIR || entry.file_name() == NIX_DIR);
+                    if !skipped && !self.is_mounted(&path) {
+                        dirs.push(path);
+                    }
+                } else if file_type.is_file()
+                    && Self::is_rotated(&entry.file_name().to_string_lossy())
+                    && self.is_old(entry.metadata()?.modified().ok())
+                {
+                    self.remove(&path, "rotated log")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the Nix GC roots of former upgrades, whereas the latest one and
+    /// the one required for a rollback are kept
+    fn nix_roots(&mut self, root: &Path) -> Fallible<()> {
+        let dir = root.join(NIX_DIR);
+        if !dir.exists() {
+            return Ok(());
+        }
+
+        let mut links = read_dir(dir)?
+            .filter_map(|x| x.ok())
+            .filter(|x| {
+                x.file_name()
+                    .to_string_lossy()
+                    .starts_with(Self::NIX_ROOT_PREFIX)
+            })
+            .filter_map(|x| {
+                let metadata = symlink_metadata(x.path()).ok()?;
+                if metadata.file_type().is_symlink() {
+                    Some((metadata.modified().ok(), x.path()))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        links.sort();
+        let latest = links.pop();
+        let rollback = UpgradeRecord::load_from(root)
+            .ok()
+            .map(|x| x.closure().clone());
+
+        for (modified, link) in links {
+            let target = read_link(&link)?;
+            if !target.exists() || (self.is_old(modified) && Some(&target) != rollback.as_ref()) {
+                self.remove(&link, "Nix GC root")?;
+            }
+        }
+        if let Some((_, link)) = latest {
+            if !link.exists() {
+                self.remove(&link, "Nix GC root")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove all images which are not used by any container of the running
+    /// cluster
+    fn images(&mut self) -> Fallible<()> {
+        let containers = self.crictl(&["ps", "-a", "-o", "json"])?;
+        let used = containers["containers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|x| vec![&x["imageRef"], &x["image"]["image"]])
+            .filter_map(|x| x.as_str())
+            .map(|x| x.trim_start_matches("sha256:").to_owned())
+            .collect::<HashSet<_>>();
+
+        let images = self.crictl(&["images", "-o", "json"])?;
+        for image in images["images"].as_array().into_iter().flatten() {
+            let id = match image["id"].as_str() {
+                Some(id) => id.trim_start_matches("sha256:"),
+                None => continue,
+            };
+            let names = image["repoTags"]
+                .as_array()
+                .into_iter()
+                .chain(image["repoDigests"].as_array())
+                .flatten()
+                .filter_map(|x| x.as_str())
+                .collect::<Vec<_>>();
+
+            // Pinned images like the pause image are required by the runtime
+            if image["pinned"].as_bool().unwrap_or_default()
+                || used.contains(id)
+                || names.iter().any(|x| used.contains(*x))
+            {
+                continue;
+            }
+
+            let name = names.first().copied().unwrap_or(id);
+            let size: u64 = image["size"]
+                .as_str()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or_default();
+            if *self.args.dry_run() {
+                info!("Would remove unused image {}", name);
+            } else {
+                info!("Removing unused image {}", name);
+                if let Err(e) = self.crictl(&["rmi", id]) {
+                    warn!("Unable to remove image {}: {}", name, e);
+                    continue;
+                }
+            }
+            self.reclaimed += size;
+        }
+        Ok(())
+    }
+
+    /// Run crictl against the runtime of the configured root and parse its
+    /// JSON output
+    fn crictl(&self, args: &[&str]) -> Fallible<Value> {
+        let socket = self.config.root().join(CRIO_DIR).join("crio.sock");
+        let output = Command::new("crictl")
+            .env(RUNTIME_ENV, format!("unix://{}", socket.display()))
+            .args(args)
+            .output()?;
+        if !output.status.success() {
+            debug!("crictl stdout: {}", String::from_utf8(output.stdout)?);
+            debug!("crictl stderr: {}", String::from_utf8(output.stderr)?);
+            bail!("crictl {} command failed", args[0]);
+        }
+        if output.stdout.is_empty() {
+            return Ok(Value::Null);
+        }
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    /// Remove the provided path, or only report it on dry runs
+    fn remove(&mut self, path: &Path, kind: &str) -> Fallible<()> {
+        let size = Self::size(path);
+        if *self.args.dry_run() {
+            info!("Would remove {} '{}'", kind, path.display());
+        } else {
+            info!("Removing {} '{}'", kind, path.display());
+            if symlink_metadata(path)?.is_dir() {
+                remove_dir_all(path)?;
+            } else {
+                remove_file(path)?;
+            }
+        }
+        self.reclaimed += size;
+        Ok(())
+    }
+
+    /// Check if the provided path or anything below it is mounted
+    fn is_mounted(&self, path: &Path) -> bool {
+        self.mounts.iter().any(|x| x.starts_with(path))
+    }
+
+    /// Check if the provided modification time exceeds the age filter
+    fn is_old(&self, modified: Option<SystemTime>) -> bool {
+        modified
+            .and_then(|x| SystemTime::now().duration_since(x).ok())
+            .map_or(false, |x| x >= self.args.older_than().duration())
+    }
+
+    /// The last time the provided root was used, which is the most recent
+    /// modification of its configuration, lock or logs
+    fn last_used(root: &Path) -> Option<SystemTime> {
+        let logs = read_dir(root.join("log"))
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.ok())
+            .map(|x| x.path());
+        vec![
+            root.to_path_buf(),
+            root.join(Config::FILENAME),
+            root.join(RootLock::FILENAME),
+        ]
+        .into_iter()
+        .chain(logs)
+        .filter_map(|x| symlink_metadata(x).and_then(|x| x.modified()).ok())
+        .max()
+    }
+
+    /// Retrieve the disk usage of the provided path without following
+    /// symlinks
+    fn size(path: &Path) -> u64 {
+        let metadata = match symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return 0,
+        };
+        if !metadata.is_dir() {
+            return metadata.len();
+        }
+        read_dir(path)
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.ok())
+            .map(|x| Self::size(&x.path()))
+            .sum()
+    }
+
+    /// Check if the provided file name belongs to a rotated log, like
+    /// `crio.log.1` or `audit-2019-10-24T08-41-07.012.log`
+    fn is_rotated(name: &str) -> bool {
+        if name.contains(".log.") {
+            return true;
+        }
+        let len = Self::ROTATED_TIMESTAMP.len();
+        if !name.is_ascii() || !name.ends_with(".log") || name.len() < len + 6 {
+            return false;
+        }
+        let stem = &name[..name.len() - 4];
+        let (prefix, timestamp) = stem.split_at(stem.len() - len);
+        prefix.ends_with('-')
+            && timestamp
+                .chars()
+                .zip(Self::ROTATED_TIMESTAMP.chars())
+                .all(|(c, t)| if t == '0' { c.is_ascii_digit() } else { c == t })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_rotated_success() {
+        assert!(GarbageCollector::is_rotated("crio.log.1"));
+        assert!(GarbageCollector::is_rotated("kubelet.log.2.gz"));
+        assert!(GarbageCollector::is_rotated(
+            "audit-2019-10-24T08-41-07.012.log"
+        ));
+    }
+
+    #[test]
+    fn is_rotated_failure() {
+        assert!(!GarbageCollector::is_rotated("crio.log"));
+        assert!(!GarbageCollector::is_rotated("audit.log"));
+        assert!(!GarbageCollector::is_rotated("2019-10-24T08-41-07.012.log"));
+        assert!(!GarbageCollector::is_rotated(
+            "audit-2019-10-24T08-41-07.01x.log"
+        ));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{Config, Error, Fallible};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+    process::Command,
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The points in the cluster lifecycle where hooks can be executed
+pub enum HookPoint {
+    /// Before the cluster components get started
+    PreUp,
+
+    /// After the cluster is up and running
+    PostUp,
+
+    /// Before the cluster gets torn down
+    PreDown,
+
+    /// After the cluster has been torn down
+    PostDown,
+}
+
+impl Display for HookPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            HookPoint::PreUp => "pre-up",
+            HookPoint::PostUp => "post-up",
+            HookPoint::PreDown => "pre-down",
+            HookPoint::PostDown => "post-down",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for HookPoint {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "pre-up" => Ok(HookPoint::PreUp),
+            "post-up" => Ok(HookPoint::PostUp),
+            "pre-down" => Ok(HookPoint::PreDown),
+            "post-down" => Ok(HookPoint::PostDown),
+            _ => Err(format_err!("Unknown hook point '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The behavior if a hook fails
+pub enum HookFailure {
+    /// Abort the current operation
+    Fatal,
+
+    /// Log a warning and continue
+    Warn,
+}
+
+impl FromStr for HookFailure {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "fatal" => Ok(HookFailure::Fatal),
+            "warn" => Ok(HookFailure::Warn),
+            _ => Err(format_err!("Unknown hook failure behavior '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+/// A script executed at a certain point of the cluster lifecycle
+pub struct Hook {
+    point: HookPoint,
+    script: PathBuf,
+}
+
+impl Hook {
+    /// Run all hooks of the provided configuration for the lifecycle point
+    /// with the provided cluster environment
+    pub fn run_all(config: &Config, point: HookPoint, env: &[(&str, String)]) -> Fallible<()> {
+        for hook in config.hooks().iter().filter(|x| x.point == point) {
+            if let Err(e) = hook.run(config, env) {
+                match config.hook_failure() {
+                    HookFailure::Fatal => return Err(e),
+                    HookFailure::Warn => warn!("{}", e),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn run(&self, config: &Config, env: &[(&str, String)]) -> Fallible<()> {
+        info!("Running {} hook '{}'", self.point, self.script.display());
+        let status = Command::new(&self.script)
+            .envs(env.iter().map(|(k, v)| (k, v)))
+            .env("KUBERNIX_HOOK", self.point.to_string())
+            .env("KUBERNIX_ROOT", config.root())
+            .status()
+            .map_err(|e| {
+                format_err!(
+                    "Unable to run {} hook '{}': {}",
+                    self.point,
+                    self.script.display(),
+                    e
+                )
+            })?;
+        if !status.success() {
+            bail!(
+                "{} hook '{}' failed with {}",
+                self.point,
+                self.script.display(),
+                status
+            )
+        }
+        Ok(())
+    }
+}
+
+impl Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.point, self.script.display())
+    }
+}
+
+impl FromStr for Hook {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let mut split = s.splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some(point), Some(script)) if !script.is_empty() => Ok(Hook {
+                point: point.parse()?,
+                script: script.into(),
+            }),
+            _ => Err(format_err!(
+                "Invalid hook '{}', expected '<point>=<script>'",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        let h: Hook = "post-up=seed.sh".parse()?;
+        assert_eq!(h.point, HookPoint::PostUp);
+        assert_eq!(h.script, PathBuf::from("seed.sh"));
+        assert_eq!(h.to_string(), "post-up=seed.sh");
+        Ok(())
+    }
+
+    #[test]
+    fn from_str_failure() {
+        assert!("post-up".parse::<Hook>().is_err());
+        assert!("post-up=".parse::<Hook>().is_err());
+        assert!("invalid=seed.sh".parse::<Hook>().is_err());
+    }
+
+    #[test]
+    fn run_success() -> Fallible<()> {
+        let c = test_config()?;
+        let h: Hook = "post-up=true".parse()?;
+        h.run(&c, &[])
+    }
+
+    #[test]
+    fn run_failure() -> Fallible<()> {
+        let c = test_config()?;
+        let h: Hook = "post-up=false".parse()?;
+        assert!(h.run(&c, &[]).is_err());
+        let h: Hook = "post-up=invalid_command".parse()?;
+        assert!(h.run(&c, &[]).is_err());
+        Ok(())
+    }
+}
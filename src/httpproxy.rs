@@ -0,0 +1,63 @@
+use crate::Config;
+
+pub struct HttpProxy;
+
+impl HttpProxy {
+    /// Addresses which are never proxied
+    const LOCAL: &'static [&'static str] = &["localhost", "127.0.0.1"];
+
+    /// Retrieve the proxy environment variables, whereas the cluster network
+    /// and the provided local addresses are always excluded from proxying
+    pub fn env(config: &Config, local: &[&str]) -> Vec<(&'static str, String)> {
+        let mut env = vec![];
+        if let Some(x) = config.http_proxy() {
+            env.push(("HTTP_PROXY", x.clone()));
+        }
+        if let Some(x) = config.https_proxy() {
+            env.push(("HTTPS_PROXY", x.clone()));
+        }
+        if env.is_empty() {
+            return env;
+        }
+
+        let mut no_proxy = config.no_proxy().clone();
+        no_proxy.push(config.cidr().to_string());
+        no_proxy.extend(
+            config
+                .pod_cidr()
+                .iter()
+                .chain(config.service_cidr())
+                .map(|x| x.to_string()),
+        );
+        no_proxy.extend(Self::LOCAL.iter().chain(local).map(|x| x.to_string()));
+        env.push(("NO_PROXY", no_proxy.join(",")));
+
+        // Not all tools respect the upper case variants
+        let lower: Vec<(&'static str, String)> = env
+            .iter()
+            .map(|(k, v)| {
+                let k = match *k {
+                    "HTTP_PROXY" => "http_proxy",
+                    "HTTPS_PROXY" => "https_proxy",
+                    _ => "no_proxy",
+                };
+                (k, v.clone())
+            })
+            .collect();
+        env.extend(lower);
+        env
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::tests::test_config, Fallible};
+
+    #[test]
+    fn env_success_disabled() -> Fallible<()> {
+        let c = test_config()?;
+        assert!(HttpProxy::env(&c, &[]).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{config::BuildAndLoad, containerized::Containerized, Config, Fallible, CRIO_DIR};
+use log::{debug, info};
+use std::process::Command;
+
+pub struct ImageBuild;
+
+impl ImageBuild {
+    /// Build the image directly into the storage of the container runtime,
+    /// which makes it available to the cluster without pushing it to a
+    /// registry
+    pub fn run(config: &Config, args: &BuildAndLoad) -> Fallible<()> {
+        let dir = config.root().join(CRIO_DIR);
+        let storage = dir.join("storage");
+        if !storage.exists() {
+            bail!(
+                "Container runtime storage '{}' does not exist, please bootstrap the cluster first",
+                storage.display()
+            )
+        }
+        info!("Building image '{}'", args.tag());
+
+        let mut cmd = Command::new("buildah");
+        cmd.arg(format!("--root={}", storage.display()))
+            .arg(format!("--runroot={}", dir.join("run").display()))
+            .arg(format!(
+                "--storage-driver={}",
+                Containerized::storage_driver(config)
+            ))
+            .arg("bud")
+            .arg("--isolation=chroot")
+            .arg(format!(
+                "--signature-policy={}",
+                dir.join("policy.json").display()
+            ))
+            .arg(format!("--tag={}", args.tag()))
+            .env("CONTAINERS_REGISTRIES_CONF", dir.join("registries.conf"));
+        if let Some(file) = args.file() {
+            cmd.arg(format!("--file={}", file.display()));
+        }
+        let status = cmd.arg(args.context()).status()?;
+        if !status.success() {
+            bail!("buildah command failed with {}", status)
+        }
+
+        debug!("Image stored in '{}'", storage.display());
+        info!(
+            "Image '{}' is available to the cluster, use `imagePullPolicy: Never` or \
+             `IfNotPresent` to run it",
+            args.tag()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{component::ComponentKind, pki::Pki, process::Process, Config, Fallible};
+use log::{info, warn};
+use std::{
+    fs::{create_dir_all, read_dir},
+    path::PathBuf,
+};
+
+/// The Go coverage and profiling support of the components, which allows
+/// Kubernetes developers to measure their changes
+pub struct Instrumentation;
+
+impl Instrumentation {
+    /// The directory containing the coverage data within the root
+    const COVERAGE_DIR: &'static str = "coverage";
+
+    /// The directory containing the collected profiles within the root
+    const PPROF_DIR: &'static str = "pprof";
+
+    /// The profiles collected before stopping a component and their file
+    /// names
+    const PROFILES: &'static [(&'static str, &'static str)] = &[
+        ("heap", "heap.pb.gz"),
+        ("goroutine?debug=2", "goroutine.txt"),
+    ];
+
+    /// Verify that all requested components support the instrumentation
+    pub fn preflight(config: &Config) -> Fallible<()> {
+        if config.pprof().contains(&ComponentKind::Crio) {
+            bail!("Profiling is not supported for {}", ComponentKind::Crio)
+        }
+        Ok(())
+    }
+
+    /// Check if the profiling endpoints of the provided component should be
+    /// served
+    pub fn profiling(config: &Config, kind: ComponentKind) -> bool {
+        *config.profiling() || config.pprof().contains(&kind)
+    }
+
+    /// Retrieve the environment variables of the provided component, which
+    /// let coverage enabled builds write their data into the root
+    pub fn env(config: &Config, kind: ComponentKind) -> Fallible<Vec<(&'static str, String)>> {
+        if !config.coverage().contains(&kind) {
+            return Ok(vec![]);
+        }
+        let dir = Self::coverage_dir(config, kind);
+        create_dir_all(&dir)?;
+        Ok(vec![("GOCOVERDIR", dir.display().to_string())])
+    }
+
+    /// Collect the profiles of all profiled components, which has to happen
+    /// while they are still running
+    pub fn collect_profiles(config: &Config) {
+        for kind in config.pprof() {
+            if let Err(e) = Self::collect_profile(config, *kind) {
+                warn!("Unable to collect profiles of {}: {}", kind, e)
+            }
+        }
+    }
+
+    /// Report the coverage data written by the stopped components
+    pub fn report_coverage(config: &Config) {
+        for kind in config.coverage() {
+            let dir = Self::coverage_dir(config, *kind);
+            let files = read_dir(&dir).map(|x| x.count()).unwrap_or_default();
+            if files == 0 {
+                warn!(
+                    "No coverage data of {} found, is its binary built with '-cover'?",
+                    kind
+                );
+            } else {
+                info!(
+                    "Coverage data of {} written to '{}' ({} files)",
+                    kind,
+                    dir.display(),
+                    files
+                );
+            }
+        }
+    }
+
+    fn coverage_dir(config: &Config, kind: ComponentKind) -> PathBuf {
+        config
+            .root()
+            .join(Self::COVERAGE_DIR)
+            .join(kind.to_string())
+    }
+
+    fn collect_profile(config: &Config, kind: ComponentKind) -> Fallible<()> {
+        let (url, verify) = Self::endpoint(config, kind)
+            .ok_or_else(|| format_err!("No profiling endpoint available"))?;
+        let dir = config.root().join(Self::PPROF_DIR).join(kind.to_string());
+        create_dir_all(&dir)?;
+
+        let pki = config.root().join(Pki::DIR);
+        for (profile, file) in Self::PROFILES {
+            let output = Process::run_to_completion("curl")
+                .arg("--silent")
+                .arg("--show-error")
+                .arg("--fail")
+                .arg(format!("--output={}", dir.join(file).display()))
+                .arg(if verify {
+                    format!("--cacert={}", pki.join("ca.pem").display())
+                } else {
+                    "--insecure".into()
+                })
+                .arg(format!("--cert={}", pki.join("admin.pem").display()))
+                .arg(format!("--key={}", pki.join("admin-key.pem").display()))
+                .arg(format!("{}/{}", url, profile))
+                .interruptible(false)
+                .output()?;
+            if !output.success() {
+                bail!("curl command failed: {}", output.stderr().trim())
+            }
+        }
+        info!("Profiles of {} written to '{}'", kind, dir.display());
+        Ok(())
+    }
+
+    /// The profiling endpoint of a component and whether its serving
+    /// certificate is signed by the cluster CA
+    fn endpoint(config: &Config, kind: ComponentKind) -> Option<(String, bool)> {
+        let ports = config.ports();
+        let (url, verify) = match kind {
+            ComponentKind::Crio => return None,
+            ComponentKind::Etcd => (format!("https://127.0.0.1:{}", ports.etcd_client()), true),
+            ComponentKind::ApiServer => (format!("https://127.0.0.1:{}", ports.apiserver()), true),
+            ComponentKind::ControllerManager => (
+                format!("https://127.0.0.1:{}", ports.controller_manager()),
+                false,
+            ),
+            ComponentKind::Scheduler => (format!("https://127.0.0.1:{}", ports.scheduler()), false),
+            ComponentKind::Kubelet => (format!("https://127.0.0.1:{}", ports.kubelet()), false),
+            ComponentKind::Proxy => (format!("http://127.0.0.1:{}", ports.proxy_metrics()), false),
+        };
+        Some((format!("{}/debug/pprof", url), verify))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+
+    #[test]
+    fn env_success() -> Fallible<()> {
+        let c = test_config()?;
+        assert!(Instrumentation::env(&c, ComponentKind::ApiServer)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn endpoint_success() -> Fallible<()> {
+        let c = test_config()?;
+        assert_eq!(
+            Instrumentation::endpoint(&c, ComponentKind::ApiServer),
+            Some(("https://127.0.0.1:6443/debug/pprof".into(), true))
+        );
+        assert_eq!(
+            Instrumentation::endpoint(&c, ComponentKind::Proxy),
+            Some(("http://127.0.0.1:10249/debug/pprof".into(), false))
+        );
+        assert!(Instrumentation::endpoint(&c, ComponentKind::Crio).is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{component::ComponentKind, system::System};
+use log::debug;
+use std::time::{Duration, Instant};
+
+/// Detects changes of the primary IP of the host, like after switching the
+/// network of a laptop
+pub struct IpWatch {
+    detected: String,
+    last_check: Instant,
+}
+
+impl IpWatch {
+    /// The interval between two IP checks
+    pub const INTERVAL: Duration = Duration::from_secs(15);
+
+    /// The components using the IP of the host, in their restart order
+    pub const AFFECTED: &'static [ComponentKind] = &[
+        ComponentKind::ApiServer,
+        ComponentKind::Kubelet,
+        ComponentKind::Proxy,
+    ];
+
+    /// Create a new watch for the provided IP of the host
+    pub fn new(ip: &str) -> Self {
+        Self {
+            detected: ip.into(),
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Retrieve the new IP of the host if the interval elapsed and the IP
+    /// changed since the last detection
+    pub fn poll(&mut self) -> Option<String> {
+        if self.last_check.elapsed() < Self::INTERVAL {
+            return None;
+        }
+        self.last_check = Instant::now();
+        match System::new().current_ip() {
+            Ok(ip) => self.update(ip),
+            Err(e) => {
+                // No default route, for example while being offline
+                debug!("Unable to detect host IP: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Store the detected IP and retrieve it if it differs from the last one
+    fn update(&mut self, ip: String) -> Option<String> {
+        if ip == self.detected {
+            return None;
+        }
+        self.detected = ip.clone();
+        Some(ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_success() {
+        let mut watch = IpWatch::new("10.0.0.1");
+        assert_eq!(watch.update("10.0.0.1".into()), None);
+        assert_eq!(
+            watch.update("192.168.1.2".into()),
+            Some("192.168.1.2".into())
+        );
+        assert_eq!(watch.update("192.168.1.2".into()), None);
+        assert_eq!(watch.update("10.0.0.1".into()), Some("10.0.0.1".into()));
+    }
+}
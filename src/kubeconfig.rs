@@ -1,15 +1,13 @@
-use crate::{pki::Pki, Config};
-use failure::{bail, Fallible};
+use crate::{pki::Pki, process::Process, seed::Seed, Config, Fallible};
 use getset::Getters;
 use log::{debug, info};
 use std::{
     fs::create_dir_all,
     net::Ipv4Addr,
     path::{Path, PathBuf},
-    process::Command,
 };
 
-#[derive(Default, Getters)]
+#[derive(Clone, Default, Getters)]
 pub struct KubeConfig {
     #[get = "pub"]
     kubelet: PathBuf,
@@ -28,26 +26,55 @@ pub struct KubeConfig {
 }
 
 impl KubeConfig {
+    /// The target directory of all kubeconfigs
+    pub const DIR: &'static str = "kubeconfig";
+
     pub fn new(config: &Config, pki: &Pki, ip: &str, hostname: &str) -> Fallible<KubeConfig> {
         info!("Creating kubeconfigs");
 
         // Create the target dir
-        let dir = config.root().join("kubeconfig");
+        let dir = config.root().join(Self::DIR);
         create_dir_all(&dir)?;
 
         let mut kube = KubeConfig::default();
-        kube.kubelet = Self::setup_kubelet(&dir, &pki, ip, hostname)?;
-        kube.proxy = Self::setup_proxy(&dir, &pki, ip)?;
-        kube.controller_manager = Self::setup_controller_manager(&dir, &pki)?;
-        kube.scheduler = Self::setup_scheduler(&dir, &pki)?;
-        kube.admin = Self::setup_admin(&dir, &pki)?;
+        kube.kubelet = Self::setup_kubelet(config, &pki, ip, hostname)?;
+        kube.proxy = Self::setup_proxy(config, &pki, ip)?;
+        kube.controller_manager = Self::setup_controller_manager(config, &pki)?;
+        kube.scheduler = Self::setup_scheduler(config, &pki)?;
+        kube.admin = Self::setup_admin(config, &pki)?;
 
         Ok(kube)
     }
 
-    fn setup_kubelet(dir: &Path, pki: &Pki, ip: &str, hostname: &str) -> Fallible<PathBuf> {
+    /// Recreate the kubeconfigs pointing to the IP of the host
+    pub fn refresh(&self, config: &Config, pki: &Pki, ip: &str, hostname: &str) -> Fallible<()> {
+        info!("Recreating kubeconfigs for IP {}", ip);
+        Self::setup_kubelet(config, pki, ip, hostname)?;
+        Self::setup_proxy(config, pki, ip)?;
+        Ok(())
+    }
+
+    /// Load the already created kubeconfigs of a previous run
+    pub fn load(config: &Config, hostname: &str) -> KubeConfig {
+        let dir = config.root().join(Self::DIR);
+        let path = |name: &str| dir.join(format!("{}.kubeconfig", name));
+        KubeConfig {
+            kubelet: path(hostname),
+            proxy: path("kube-proxy"),
+            controller_manager: path("kube-controller-manager"),
+            scheduler: path("kube-scheduler"),
+            admin: path("admin"),
+        }
+    }
+
+    /// Retrieve the path to the admin kubeconfig without regenerating it
+    pub fn admin_path(config: &Config) -> PathBuf {
+        config.root().join(Self::DIR).join("admin.kubeconfig")
+    }
+
+    fn setup_kubelet(config: &Config, pki: &Pki, ip: &str, hostname: &str) -> Fallible<PathBuf> {
         Ok(Self::setup_kubeconfig(
-            dir,
+            config,
             ip,
             hostname,
             &format!("system:node:{}", hostname),
@@ -57,10 +84,10 @@ impl KubeConfig {
         )?)
     }
 
-    fn setup_proxy(dir: &Path, pki: &Pki, ip: &str) -> Fallible<PathBuf> {
+    fn setup_proxy(config: &Config, pki: &Pki, ip: &str) -> Fallible<PathBuf> {
         const NAME: &str = "kube-proxy";
         Ok(Self::setup_kubeconfig(
-            dir,
+            config,
             ip,
             NAME,
             &format!("system:{}", NAME),
@@ -70,10 +97,10 @@ impl KubeConfig {
         )?)
     }
 
-    fn setup_controller_manager(dir: &Path, pki: &Pki) -> Fallible<PathBuf> {
+    fn setup_controller_manager(config: &Config, pki: &Pki) -> Fallible<PathBuf> {
         const NAME: &str = "kube-controller-manager";
         Ok(Self::setup_kubeconfig(
-            dir,
+            config,
             &Ipv4Addr::LOCALHOST.to_string(),
             NAME,
             &format!("system:{}", NAME),
@@ -83,10 +110,10 @@ impl KubeConfig {
         )?)
     }
 
-    fn setup_scheduler(dir: &Path, pki: &Pki) -> Fallible<PathBuf> {
+    fn setup_scheduler(config: &Config, pki: &Pki) -> Fallible<PathBuf> {
         const NAME: &str = "kube-scheduler";
         Ok(Self::setup_kubeconfig(
-            dir,
+            config,
             &Ipv4Addr::LOCALHOST.to_string(),
             NAME,
             &format!("system:{}", NAME),
@@ -96,10 +123,10 @@ impl KubeConfig {
         )?)
     }
 
-    fn setup_admin(dir: &Path, pki: &Pki) -> Fallible<PathBuf> {
+    fn setup_admin(config: &Config, pki: &Pki) -> Fallible<PathBuf> {
         const NAME: &str = "admin";
         Ok(Self::setup_kubeconfig(
-            dir,
+            config,
             &Ipv4Addr::LOCALHOST.to_string(),
             NAME,
             NAME,
@@ -110,7 +137,7 @@ impl KubeConfig {
     }
 
     fn setup_kubeconfig(
-        dir: &Path,
+        config: &Config,
         ip: &str,
         name: &str,
         user: &str,
@@ -119,88 +146,52 @@ impl KubeConfig {
         key: &Path,
     ) -> Fallible<PathBuf> {
         debug!("Creating kubeconfig for {}", name);
-        let target = dir.join(format!("{}.kubeconfig", name));
+        let target = config
+            .root()
+            .join(Self::DIR)
+            .join(format!("{}.kubeconfig", name));
         let kubeconfig_arg = format!("--kubeconfig={}", target.display());
+        let embed_certs_arg = format!("--embed-certs={}", Seed::embed_certs(config));
 
-        let output = Command::new("kubectl")
+        Process::run_to_completion("kubectl")
             .arg("config")
             .arg("set-cluster")
             .arg("kubernetes")
             .arg(format!("--certificate-authority={}", ca.display()))
-            .arg("--embed-certs=true")
-            .arg(format!("--server=https://{}:6443", ip))
+            .arg(&embed_certs_arg)
+            .arg(format!(
+                "--server=https://{}:{}",
+                ip,
+                config.ports().apiserver()
+            ))
             .arg(&kubeconfig_arg)
-            .output()?;
-        if !output.status.success() {
-            debug!(
-                "kubectl set-cluster stdout: {}",
-                String::from_utf8(output.stdout)?
-            );
-            debug!(
-                "kubectl set-cluster stderr: {}",
-                String::from_utf8(output.stderr)?
-            );
-            bail!("Kubectl set-cluster command failed");
-        }
+            .run()?;
 
-        let output = Command::new("kubectl")
+        Process::run_to_completion("kubectl")
             .arg("config")
             .arg("set-credentials")
             .arg(user)
             .arg(format!("--client-certificate={}", cert.display()))
             .arg(format!("--client-key={}", key.display()))
-            .arg("--embed-certs=true")
+            .arg(&embed_certs_arg)
             .arg(&kubeconfig_arg)
-            .output()?;
-        if !output.status.success() {
-            debug!(
-                "kubectl set-credentials stdout: {}",
-                String::from_utf8(output.stdout)?
-            );
-            debug!(
-                "kubectl set-credentials stderr: {}",
-                String::from_utf8(output.stderr)?
-            );
-            bail!("Kubectl set-credentials command failed");
-        }
+            .run()?;
 
-        let output = Command::new("kubectl")
+        Process::run_to_completion("kubectl")
             .arg("config")
             .arg("set-context")
             .arg("default")
             .arg("--cluster=kubernetes")
             .arg(format!("--user={}", user))
             .arg(&kubeconfig_arg)
-            .output()?;
-        if !output.status.success() {
-            debug!(
-                "kubectl set-context stdout: {}",
-                String::from_utf8(output.stdout)?
-            );
-            debug!(
-                "kubectl set-context stderr: {}",
-                String::from_utf8(output.stderr)?
-            );
-            bail!("Kubectl set-context command failed");
-        }
+            .run()?;
 
-        let output = Command::new("kubectl")
+        Process::run_to_completion("kubectl")
             .arg("config")
             .arg("use-context")
             .arg("default")
             .arg(&kubeconfig_arg)
-            .output()?;
-        if !output.status.success() {
-            debug!(
-                "kubectl use-context stdout: {}",
-                String::from_utf8(output.stdout)?
-            );
-            debug!(
-                "kubectl use-context stderr: {}",
-                String::from_utf8(output.stderr)?
-            );
-            bail!("Kubectl use-context command failed");
-        }
+            .run()?;
 
         debug!("Kubeconfig created for {}", name);
         Ok(target)
@@ -0,0 +1,225 @@
+use crate::{kubeconfig::KubeConfig, pki::Pki, process::Process, Config, Fallible};
+use log::{debug, info};
+use nix::unistd::{chown, Gid, Uid};
+use std::{
+    env::var,
+    fs::{self, create_dir_all, rename},
+    path::{Path, PathBuf},
+};
+
+/// The merge of the admin credentials into the kubeconfig of the user
+pub struct KubeConfigMerge;
+
+/// The user invoking kubernix
+struct User {
+    home: PathBuf,
+    uid: u32,
+    gid: u32,
+}
+
+impl KubeConfigMerge {
+    /// The environment variable containing the user invoking sudo
+    pub const SUDO_USER_ENV: &'static str = "SUDO_USER";
+
+    /// Add the cluster as a new context to the kubeconfig of the user and
+    /// switch to it
+    pub fn merge(config: &Config) -> Fallible<()> {
+        if !*config.kubeconfig_merge() {
+            return Ok(());
+        }
+        let user = Self::user()?;
+        let target = Self::target(&user);
+        let context = Self::context(config);
+        info!("Merging context '{}' into '{}'", context, target.display());
+
+        // The credentials get embedded to be independent from the root
+        let pki = config.root().join(Pki::DIR);
+        let file = config.root().join(KubeConfig::DIR).join("merge.kubeconfig");
+        let file_arg = format!("--kubeconfig={}", file.display());
+        Self::kubectl(&[
+            &file_arg,
+            "config",
+            "set-cluster",
+            &context,
+            &format!("--server=https://127.0.0.1:{}", config.ports().apiserver()),
+            &format!("--certificate-authority={}", pki.join("ca.pem").display()),
+            "--embed-certs=true",
+        ])?;
+        Self::kubectl(&[
+            &file_arg,
+            "config",
+            "set-credentials",
+            &context,
+            &format!("--client-certificate={}", pki.join("admin.pem").display()),
+            &format!("--client-key={}", pki.join("admin-key.pem").display()),
+            "--embed-certs=true",
+        ])?;
+        Self::kubectl(&[
+            &file_arg,
+            "config",
+            "set-context",
+            &context,
+            &format!("--cluster={}", context),
+            &format!("--user={}", context),
+        ])?;
+
+        if let Some(parent) = target.parent() {
+            if !parent.exists() {
+                create_dir_all(parent)?;
+                chown(
+                    parent,
+                    Some(Uid::from_raw(user.uid)),
+                    Some(Gid::from_raw(user.gid)),
+                )?;
+            }
+        }
+        let merged = if target.exists() {
+            let output = Process::run_to_completion("kubectl")
+                .arg("config")
+                .arg("view")
+                .arg("--flatten")
+                .env(
+                    "KUBECONFIG",
+                    format!("{}:{}", target.display(), file.display()),
+                )
+                .output()?;
+            if !output.success() {
+                bail!("Unable to merge kubeconfig: {}", output.stderr().trim())
+            }
+            output.stdout().as_bytes().to_vec()
+        } else {
+            fs::read(&file)?
+        };
+
+        // Replace the file atomically to not corrupt it on failures
+        let tmp = target.with_extension("kubernix");
+        fs::write(&tmp, merged)?;
+        chown(
+            &tmp,
+            Some(Uid::from_raw(user.uid)),
+            Some(Gid::from_raw(user.gid)),
+        )?;
+        rename(&tmp, &target)?;
+
+        Self::kubectl(&[
+            &format!("--kubeconfig={}", target.display()),
+            "config",
+            "use-context",
+            &context,
+        ])?;
+        info!("Context '{}' is ready to use", context);
+        Ok(())
+    }
+
+    /// Remove the context of the cluster from the kubeconfig of the user
+    pub fn unmerge(config: &Config) -> Fallible<()> {
+        if !*config.kubeconfig_merge() {
+            return Ok(());
+        }
+        let target = Self::target(&Self::user()?);
+        if !target.exists() {
+            return Ok(());
+        }
+        let context = Self::context(config);
+        debug!("Removing context '{}' from '{}'", context, target.display());
+
+        let target_arg = format!("--kubeconfig={}", target.display());
+        let current =
+            Self::kubectl(&[&target_arg, "config", "current-context"]).unwrap_or_default();
+        if current.trim() == context {
+            Self::kubectl(&[&target_arg, "config", "unset", "current-context"])?;
+        }
+        Self::kubectl(&[&target_arg, "config", "delete-context", &context])?;
+        Self::kubectl(&[&target_arg, "config", "delete-cluster", &context])?;
+        Self::kubectl(&[
+            &target_arg,
+            "config",
+            "unset",
+            &format!("users.{}", context),
+        ])?;
+        Ok(())
+    }
+
+    /// The name of the cluster, context and user within the kubeconfig,
+    /// which is derived from the root directory
+    fn context(config: &Config) -> String {
+        let name = config
+            .root()
+            .file_name()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if name.starts_with("kubernix-") {
+            name
+        } else {
+            format!("kubernix-{}", name)
+        }
+    }
+
+    /// The kubeconfig of the user
+    fn target(user: &User) -> PathBuf {
+        user.home.join(".kube").join("config")
+    }
+
+    /// Retrieve the user which invoked sudo, or the current one otherwise
+    fn user() -> Fallible<User> {
+        match var(Self::SUDO_USER_ENV) {
+            Ok(name) => Self::passwd_user(&fs::read_to_string("/etc/passwd")?, &name),
+            Err(_) => Ok(User {
+                home: PathBuf::from(
+                    var("HOME").map_err(|_| format_err!("No home directory found"))?,
+                ),
+                uid: Uid::current().as_raw(),
+                gid: Gid::current().as_raw(),
+            }),
+        }
+    }
+
+    /// Find the user within the provided passwd database
+    fn passwd_user(passwd: &str, name: &str) -> Fallible<User> {
+        passwd
+            .lines()
+            .map(|x| x.split(':').collect::<Vec<_>>())
+            .find(|x| x.len() >= 6 && x[0] == name)
+            .and_then(|x| {
+                Some(User {
+                    home: Path::new(x[5]).to_path_buf(),
+                    uid: x[2].parse().ok()?,
+                    gid: x[3].parse().ok()?,
+                })
+            })
+            .ok_or_else(|| format_err!("Unable to find user '{}'", name))
+    }
+
+    fn kubectl(args: &[&str]) -> Fallible<String> {
+        // The kubeconfig gets unmerged during teardown as well
+        let output = Process::run_to_completion("kubectl")
+            .args(args)
+            .interruptible(false)
+            .output()?;
+        if !output.success() {
+            bail!(
+                "kubectl {} command failed: {}",
+                args[1..].join(" "),
+                output.stderr().trim()
+            );
+        }
+        Ok(output.stdout().to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwd_user_success() -> Fallible<()> {
+        let passwd = "root:x:0:0:root:/root:/bin/bash\n\
+                      alice:x:1000:100:Alice:/home/alice:/bin/bash\n";
+        let user = KubeConfigMerge::passwd_user(passwd, "alice")?;
+        assert_eq!(user.home, Path::new("/home/alice"));
+        assert_eq!(user.uid, 1000);
+        assert_eq!(user.gid, 100);
+        assert!(KubeConfigMerge::passwd_user(passwd, "bob").is_err());
+        Ok(())
+    }
+}
@@ -1,15 +1,24 @@
 use crate::{
+    budget::Budget,
+    component::ComponentKind,
     config::Config,
+    credentialprovider::CredentialProvider,
+    httpproxy::HttpProxy,
     kubeconfig::KubeConfig,
     network::Network,
     pki::Pki,
     process::{Process, Startable, Stoppable},
+    profile::Preset,
+    registryauth::RegistryAuth,
+    securityprofile::SecurityProfile,
+    verbosity::Verbosity,
+    Fallible,
 };
-use failure::Fallible;
 use log::info;
 use std::{
     fs::{self, create_dir_all},
     path::Path,
+    time::Duration,
 };
 
 pub struct Kubelet {
@@ -20,6 +29,7 @@ impl Kubelet {
     pub fn start(
         config: &Config,
         network: &Network,
+        ip: &str,
         pki: &Pki,
         kubeconfig: &KubeConfig,
         socket: &Path,
@@ -29,38 +39,137 @@ impl Kubelet {
         let dir = config.root().join("kubelet");
         create_dir_all(&dir)?;
 
+        // Rotated serving certificates get requested from the API Server
+        // instead of using the static one of the PKI
+        let tls = if *config.kubelet_serving_rotation() {
+            "rotateCertificates: true\nserverTLSBootstrap: true\n".to_owned()
+        } else {
+            format!(
+                "tlsCertFile: \"{}\"\ntlsPrivateKeyFile: \"{}\"\n",
+                pki.kubelet().cert().display(),
+                pki.kubelet().key().display(),
+            )
+        };
+        // A standalone kubelet has no API Server to delegate the
+        // authorization to and runs static pods only
+        let standalone = *config.kubelet_only();
+        let manifests = dir.join("manifests");
+        let (webhook, authorization, static_pods) = if standalone {
+            create_dir_all(&manifests)?;
+            (
+                false,
+                "AlwaysAllow",
+                format!("staticPodPath: \"{}\"\n", manifests.display()),
+            )
+        } else {
+            (true, "Webhook", String::new())
+        };
         let yml = format!(
             include_str!("assets/kubelet.yml"),
+            config.anonymous_auth(),
+            webhook,
             pki.ca().cert().display(),
+            authorization,
+            config.cluster_domain(),
             network.dns()?,
             network.crio(),
-            pki.kubelet().cert().display(),
-            pki.kubelet().key().display(),
+            format!("{}{}{}", tls, static_pods, Self::lease(config)),
         );
         let yml_file = dir.join("config.yml");
         fs::write(&yml_file, yml)?;
 
-        let mut process = Process::start(
-            config,
-            &dir,
-            "kubelet",
-            &[
-                &format!("--config={}", yml_file.display()),
-                &format!("--root-dir={}", dir.join("run").display()),
-                "--container-runtime=remote",
-                &format!("--container-runtime-endpoint=unix://{}", socket.display()),
-                &format!("--kubeconfig={}", kubeconfig.kubelet().display()),
-                "--image-pull-progress-deadline=2m",
-                "--network-plugin=cni",
-                "--register-node=true",
-                "--v=2",
-            ],
-        )?;
+        // The kubelet looks up the credentials within its root directory
+        let root_dir = dir.join("run");
+        RegistryAuth::install(config, &root_dir)?;
+
+        let mut args = vec![
+            format!("--config={}", yml_file.display()),
+            format!("--root-dir={}", root_dir.display()),
+            format!("--cert-dir={}", dir.join("pki").display()),
+            "--container-runtime=remote".into(),
+            format!("--container-runtime-endpoint=unix://{}", socket.display()),
+            "--image-pull-progress-deadline=2m".into(),
+            "--network-plugin=cni".into(),
+            // The address has to match the serving certificate
+            format!("--node-ip={}", ip),
+            format!(
+                "--seccomp-profile-root={}",
+                SecurityProfile::seccomp_dir(config).display()
+            ),
+        ];
+        args.extend(Verbosity::args(config, ComponentKind::Kubelet));
+        if !standalone {
+            args.push(format!("--kubeconfig={}", kubeconfig.kubelet().display()));
+            args.push("--register-node=true".into());
+        }
+        if let Some(image) = config.pause_image() {
+            // Prevents the image from being garbage collected
+            args.push(format!("--pod-infra-container-image={}", image));
+        }
+        if *config.cloud_provider_external() {
+            // The node gets tainted as uninitialized until a cloud controller
+            // manager initializes it
+            args.push("--cloud-provider=external".into());
+        }
+        args.extend(CredentialProvider::kubelet_args(config, &dir)?);
+        args.extend(Budget::kubelet_args(config));
+        args.push(format!("--port={}", config.ports().kubelet()));
+        args.push(format!(
+            "--healthz-port={}",
+            config.ports().kubelet_healthz()
+        ));
+        // The legacy read-only port serves the pod and node status
+        args.push(format!(
+            "--read-only-port={}",
+            if *config.insecure_port() {
+                *config.ports().kubelet_read_only()
+            } else {
+                0
+            }
+        ));
+        args.extend(Preset::for_config(config).args(ComponentKind::Kubelet));
+        if let Some(x) = config.kube_api_qps() {
+            args.push(format!("--kube-api-qps={}", x));
+        }
+        if let Some(x) = config.kube_api_burst() {
+            args.push(format!("--kube-api-burst={}", x));
+        }
+
+        let mut builder = Process::builder(config, &dir, "kubelet")
+            .args(&args)
+            .envs(&HttpProxy::env(config, &[ip]))
+            .depends_on(ComponentKind::Crio)
+            .timeout(Duration::from_secs(60));
+        builder = if standalone {
+            builder.ready_on("Started kubelet")
+        } else {
+            // The node is already registered when restarting the kubelet
+            builder
+                .depends_on(ComponentKind::ApiServer)
+                .ready_on("Successfully registered node")
+                .ready_on("was previously registered")
+        };
+        let process = builder.spawn()?;
 
-        process.wait_ready("Successfully registered node")?;
         info!("Kubelet is ready");
         Ok(Box::new(Kubelet { process }))
     }
+
+    /// The node lease duration in laptop mode, which outlasts short
+    /// suspends of the host
+    const LAPTOP_LEASE_DURATION: u32 = 240;
+
+    /// Retrieve the node lease configuration
+    fn lease(config: &Config) -> String {
+        if *config.laptop_mode() {
+            format!(
+                "nodeLeaseDurationSeconds: {}\n",
+                Self::LAPTOP_LEASE_DURATION
+            )
+        } else {
+            String::new()
+        }
+    }
 }
 
 impl Stoppable for Kubelet {
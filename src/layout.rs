@@ -0,0 +1,150 @@
+use crate::{kubeconfig::KubeConfig, pki::Pki, Config, Error, Fallible};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs::{create_dir_all, remove_file, symlink_metadata},
+    os::unix::fs::symlink,
+    path::Path,
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The available layouts of the certificates and kubeconfigs
+pub enum Layout {
+    /// The native layout of kubernix
+    Kubernix,
+
+    /// The additional directory structure and file names of kubeadm
+    Kubeadm,
+}
+
+impl Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Layout::Kubernix => "kubernix",
+            Layout::Kubeadm => "kubeadm",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Layout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "kubernix" => Ok(Layout::Kubernix),
+            "kubeadm" => Ok(Layout::Kubeadm),
+            _ => Err(format_err!("Unknown layout '{}'", s)),
+        }
+    }
+}
+
+impl Layout {
+    /// The directory of the kubeadm layout inside the root, which corresponds
+    /// to `/etc/kubernetes`
+    pub const KUBEADM_DIR: &'static str = "kubernetes";
+
+    /// Provide the certificates and kubeconfigs in the configured layout
+    pub fn apply(config: &Config, pki: &Pki, kubeconfig: &KubeConfig) -> Fallible<()> {
+        if *config.layout() == Layout::Kubernix {
+            return Ok(());
+        }
+        let dir = config.root().join(Self::KUBEADM_DIR);
+        debug!("Linking kubeadm layout into '{}'", dir.display());
+        for (name, target) in Self::kubeadm_files(pki, kubeconfig) {
+            Self::link(&dir.join(name), target)?;
+        }
+        Ok(())
+    }
+
+    /// The kubeadm file names and their kubernix counterparts. The API
+    /// Server pair is used for etcd as well, whereas the front proxy shares
+    /// the CA of the cluster.
+    fn kubeadm_files<'a>(
+        pki: &'a Pki,
+        kubeconfig: &'a KubeConfig,
+    ) -> Vec<(&'static str, &'a Path)> {
+        vec![
+            ("pki/ca.crt", pki.ca().cert()),
+            ("pki/ca.key", pki.ca().key()),
+            ("pki/apiserver.crt", pki.apiserver().cert()),
+            ("pki/apiserver.key", pki.apiserver().key()),
+            ("pki/apiserver-kubelet-client.crt", pki.apiserver().cert()),
+            ("pki/apiserver-kubelet-client.key", pki.apiserver().key()),
+            ("pki/apiserver-etcd-client.crt", pki.apiserver().cert()),
+            ("pki/apiserver-etcd-client.key", pki.apiserver().key()),
+            ("pki/front-proxy-ca.crt", pki.ca().cert()),
+            ("pki/front-proxy-ca.key", pki.ca().key()),
+            (
+                "pki/front-proxy-client.crt",
+                pki.front_proxy_client().cert(),
+            ),
+            ("pki/front-proxy-client.key", pki.front_proxy_client().key()),
+            ("pki/sa.key", pki.service_account().key()),
+            ("pki/sa.pub", pki.service_account().cert()),
+            ("pki/etcd/ca.crt", pki.ca().cert()),
+            ("pki/etcd/ca.key", pki.ca().key()),
+            ("pki/etcd/server.crt", pki.apiserver().cert()),
+            ("pki/etcd/server.key", pki.apiserver().key()),
+            ("pki/etcd/peer.crt", pki.apiserver().cert()),
+            ("pki/etcd/peer.key", pki.apiserver().key()),
+            ("pki/etcd/healthcheck-client.crt", pki.apiserver().cert()),
+            ("pki/etcd/healthcheck-client.key", pki.apiserver().key()),
+            ("admin.conf", kubeconfig.admin()),
+            ("controller-manager.conf", kubeconfig.controller_manager()),
+            ("scheduler.conf", kubeconfig.scheduler()),
+            ("kubelet.conf", kubeconfig.kubelet()),
+        ]
+    }
+
+    /// Create a symlink to the target, whereas an existing one gets replaced
+    fn link(link: &Path, target: &Path) -> Fallible<()> {
+        if let Some(parent) = link.parent() {
+            create_dir_all(parent)?;
+        }
+        if symlink_metadata(link).is_ok() {
+            remove_file(link)?;
+        }
+        symlink(target, link).map_err(|e| {
+            format_err!(
+                "Unable to link '{}' to '{}': {}",
+                link.display(),
+                target.display(),
+                e
+            )
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, read_link};
+    use tempfile::tempdir;
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        for layout in &[Layout::Kubernix, Layout::Kubeadm] {
+            assert_eq!(&layout.to_string().parse::<Layout>()?, layout);
+        }
+        assert!("kops".parse::<Layout>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn link_success() -> Fallible<()> {
+        let d = tempdir()?;
+        let target = d.path().join("ca.pem");
+        fs::write(&target, "")?;
+        let link = d.path().join("kubernetes").join("pki").join("ca.crt");
+        Layout::link(&link, &target)?;
+        // Linking again replaces the existing link
+        Layout::link(&link, &target)?;
+        assert_eq!(read_link(&link)?, target);
+        Ok(())
+    }
+}
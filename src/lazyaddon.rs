@@ -0,0 +1,173 @@
+use crate::{
+    activation::Activation,
+    component::{Component, Context},
+    process::{Process, Startable},
+    Error, Fallible,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs::{self, create_dir_all},
+    str::FromStr,
+    time::Duration,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The built-in addons which get deployed on the first connection to their
+/// local port
+pub enum LazyAddon {
+    /// The Kubernetes dashboard, served via plain HTTP
+    Dashboard,
+
+    /// A container image registry
+    Registry,
+
+    /// The metrics server providing the resource metrics API
+    MetricsServer,
+}
+
+impl Display for LazyAddon {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            LazyAddon::Dashboard => "dashboard",
+            LazyAddon::Registry => "registry",
+            LazyAddon::MetricsServer => "metrics-server",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for LazyAddon {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "dashboard" => Ok(LazyAddon::Dashboard),
+            "registry" => Ok(LazyAddon::Registry),
+            "metrics-server" => Ok(LazyAddon::MetricsServer),
+            _ => Err(format_err!("Unknown lazy addon '{}'", s)),
+        }
+    }
+}
+
+impl LazyAddon {
+    /// The namespace of all addons
+    const NAMESPACE: &'static str = "kube-system";
+
+    /// The offset of the local port the addon gets forwarded to
+    const TARGET_PORT_OFFSET: u16 = 10000;
+
+    /// The maximum time to wait for the addon deployment, which includes
+    /// pulling its image
+    const ROLLOUT_TIMEOUT: Duration = Duration::from_secs(300);
+
+    /// The port of the addon container, which is the local port as well
+    fn port(self) -> u16 {
+        match self {
+            LazyAddon::Dashboard => 9090,
+            LazyAddon::Registry => 5000,
+            LazyAddon::MetricsServer => 4443,
+        }
+    }
+
+    /// The manifest deploying the addon
+    fn manifest(self) -> &'static str {
+        match self {
+            LazyAddon::Dashboard => include_str!("assets/dashboard.yml"),
+            LazyAddon::Registry => include_str!("assets/registry.yml"),
+            LazyAddon::MetricsServer => include_str!("assets/metrics-server.yml"),
+        }
+    }
+}
+
+impl Component for LazyAddon {
+    fn name(&self) -> String {
+        self.to_string()
+    }
+
+    fn activation(&self) -> Option<Activation> {
+        Some(Activation::new(
+            self.port(),
+            self.port() + Self::TARGET_PORT_OFFSET,
+        ))
+    }
+
+    /// Deploy the addon into the cluster and forward its port to the local
+    /// target port of the activation
+    fn start(&self, context: &Context) -> Fallible<Startable> {
+        let dir = context.dir(&self.name());
+        create_dir_all(&dir)?;
+        let yml_file = dir.join(format!("{}.yml", self));
+        fs::write(&yml_file, self.manifest())?;
+
+        info!("Deploying addon {}", self);
+        let kubeconfig = format!("--kubeconfig={}", context.admin_kubeconfig().display());
+        let namespace = format!("--namespace={}", Self::NAMESPACE);
+        let deployment = format!("deployment/{}", self);
+        Process::run_to_completion("kubectl")
+            .arg("apply")
+            .arg(&kubeconfig)
+            .arg("-f")
+            .arg(&yml_file)
+            .retries(3)
+            .run()?;
+        Process::run_to_completion("kubectl")
+            .args(&[
+                "rollout",
+                "status",
+                &kubeconfig,
+                &namespace,
+                &deployment,
+                &format!("--timeout={}s", Self::ROLLOUT_TIMEOUT.as_secs()),
+            ])
+            .timeout(Self::ROLLOUT_TIMEOUT + Duration::from_secs(30))
+            .run()?;
+
+        let target = self.port() + Self::TARGET_PORT_OFFSET;
+        Ok(Box::new(
+            Process::builder(context.config(), &dir, "kubectl")
+                .name(&self.name())
+                .args(&[
+                    "port-forward",
+                    &kubeconfig,
+                    &namespace,
+                    "--address=127.0.0.1",
+                    &deployment,
+                    &format!("{}:{}", target, self.port()),
+                ])
+                .ready_on("Forwarding from")
+                .spawn()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        for addon in &[
+            LazyAddon::Dashboard,
+            LazyAddon::Registry,
+            LazyAddon::MetricsServer,
+        ] {
+            assert_eq!(&addon.to_string().parse::<LazyAddon>()?, addon);
+        }
+        assert!("invalid".parse::<LazyAddon>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn activation_success() -> Fallible<()> {
+        let activation = LazyAddon::Registry
+            .activation()
+            .ok_or_else(|| format_err!("no activation"))?;
+        assert!(activation.listen_address().is_none());
+        assert_eq!(*activation.listen_port(), 5000);
+        assert_eq!(*activation.target_port(), 15000);
+        Ok(())
+    }
+}
@@ -1,67 +1,223 @@
 //! # kubernix
 #![deny(missing_docs)]
 
+#[macro_use]
+mod error;
+
+mod activation;
+mod aggregation;
+mod apirecord;
 mod apiserver;
+mod bench;
+mod binarywatch;
+mod budget;
+mod bundle;
+mod cloudcontrollermanager;
+mod cni;
+mod component;
 mod config;
+mod conformance;
+mod containerized;
+mod control;
 mod controllermanager;
 mod coredns;
+mod crds;
+mod credentialprovider;
+mod credentials;
 mod crio;
+mod csi;
+mod csrapprover;
+mod debugger;
+mod dnswatchdog;
+mod doctor;
+mod drift;
+mod duration;
 mod encryptionconfig;
+mod envpolicy;
 mod etcd;
+mod events;
+mod extraprocess;
+mod faketime;
+mod firewall;
+mod frontproxy;
+mod gc;
+mod hook;
+mod httpproxy;
+mod imagebuild;
+mod instrumentation;
+mod ipwatch;
 mod kubeconfig;
+mod kubeconfigmerge;
 mod kubelet;
+mod layout;
+mod lazyaddon;
+mod liveness;
+mod loadbalancer;
+mod localbinary;
+mod lock;
+mod logger;
+mod matrix;
+mod migrate;
+mod mirror;
+mod netem;
 mod network;
+mod networkpolicy;
+mod output;
+mod packageoverride;
 mod pki;
+mod plan;
+mod ports;
 mod process;
+mod profile;
 mod proxy;
+mod readiness;
+mod registry;
+mod registryauth;
+mod rlimit;
+mod runinfo;
+mod runtimeclass;
 mod scheduler;
+mod secrets;
+mod securityprofile;
+mod seed;
+mod selinux;
+mod signals;
+mod soak;
+mod state;
+mod supervisor;
+mod sync;
 mod system;
+mod telemetry;
+mod token;
+mod ttl;
+mod ui;
+mod upgrade;
+mod verbosity;
+mod verify;
+mod vm;
+mod volumes;
 
-pub use config::Config;
+pub use activation::Activation;
+pub use component::{Component, ComponentKind, Context, Health};
+pub use config::{Config, SubCommand};
+pub use error::{Error, Fallible};
+pub use extraprocess::ExtraProcess;
+pub use process::{
+    FakeBackend, Process, ProcessBackend, ProcessBuilder, RealBackend, RestartPolicy, SpawnMode,
+    Startable, Stoppable,
+};
+pub use registry::Registry;
+pub use runinfo::RunInfo;
 
-use apiserver::ApiServer;
-use controllermanager::ControllerManager;
+use activation::Stub;
+use aggregation::Aggregation;
+use bench::Bench;
+use binarywatch::BinaryWatch;
+use budget::Budget;
+use bundle::Bundle;
+use cloudcontrollermanager::CloudControllerManager;
+use component::StandaloneKubelet;
+use config::{OutputFormat, WaitCondition};
+use conformance::Conformance;
+use containerized::Containerized;
+use control::{Control, ControlRequest, Route};
 use coredns::CoreDNS;
-use crio::Crio;
+use crds::Crds;
+use credentials::Credentials;
+use csi::Csi;
+use csrapprover::CsrApprover;
+use debugger::Debugger;
+use dnswatchdog::DnsWatchdog;
+use doctor::Doctor;
+use drift::Drift;
 use encryptionconfig::EncryptionConfig;
 use etcd::Etcd;
+use events::{EventKind, EventLog};
+use faketime::FakeTime;
+use firewall::Firewall;
+use frontproxy::FrontProxy;
+use gc::GarbageCollector;
+use hook::{Hook, HookPoint};
+use httpproxy::HttpProxy;
+use imagebuild::ImageBuild;
+use instrumentation::Instrumentation;
+use ipwatch::IpWatch;
 use kubeconfig::KubeConfig;
-use kubelet::Kubelet;
+use kubeconfigmerge::KubeConfigMerge;
+use layout::Layout;
+use liveness::Liveness;
+use loadbalancer::LoadBalancer;
+use localbinary::LocalBinary;
+use lock::RootLock;
+use logger::Logger;
+use matrix::Matrix;
+use migrate::RootMigration;
+use netem::Netem;
 use network::Network;
+use networkpolicy::NetworkPolicy;
+use output::Output;
+use packageoverride::PackageOverride;
 use pki::Pki;
-use process::{Process, Startable};
+use plan::Plan;
+use ports::Ports;
 use proxy::Proxy;
-use scheduler::Scheduler;
+use readiness::Readiness;
+use rlimit::RLimit;
+use runtimeclass::RuntimeClass;
+use scheduler::SecondaryScheduler;
+use secrets::Secrets;
+use securityprofile::SecurityProfile;
+use selinux::Selinux;
+use signals::Signals;
+use soak::Soak;
+use state::{Phase, State};
+use sync::PodSync;
 use system::System;
+use telemetry::Telemetry;
+use token::TokenRequest;
+use ttl::Ttl;
+use ui::Ui;
+use upgrade::{UpgradeRecord, UpgradeRequest};
+use verify::Verify;
+use vm::Vm;
+use volumes::Volumes;
 
-use env_logger::Builder;
-use failure::{bail, format_err, Fallible};
-use log::{debug, error, info, LevelFilter};
+use log::{debug, error, info, warn, LevelFilter};
 use nix::{
     mount::{umount2, MntFlags},
     unistd::getuid,
 };
 use proc_mounts::MountIter;
 use rayon::scope;
+use serde_json::{json, Value};
 use std::{
-    env::{current_exe, split_paths, var, var_os},
+    env::{self, current_exe, join_paths, set_var, split_paths, var, var_os},
+    ffi::OsString,
     fmt::Display,
     fs::{self, create_dir_all},
+    mem::replace,
+    net::TcpStream,
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, ExitStatus},
+    sync::mpsc::{Receiver, TryRecvError},
     thread::sleep,
     time::{Duration, Instant},
 };
+use tracing::info_span;
 
 const CRIO_DIR: &str = "crio";
 const NIX_DIR: &str = "nix";
 const KUBERNIX_ENV: &str = "kubernix.env";
 
+const CSR_APPROVAL_INTERVAL: Duration = Duration::from_secs(10);
+
 const KUBECONFIG_ENV: &str = "KUBECONFIG";
 const NIX_SHELL_ENV: &str = "IN_NIX_SHELL";
+const ROOT_ENV: &str = "KUBERNIX_RUN";
 const RUNTIME_ENV: &str = "CONTAINER_RUNTIME_ENDPOINT";
 
-type Stoppables = Vec<Startable>;
+type Stoppables = Vec<(ComponentKind, Startable)>;
+type Activating = (Stub, TcpStream, Receiver<Fallible<Startable>>);
 
 /// The main entry point for the application
 pub struct Kubernix {
@@ -70,12 +226,72 @@ pub struct Kubernix {
     crio_socket: PathBuf,
     kubeconfig: KubeConfig,
     processes: Stoppables,
+    pki: Pki,
+    encryptionconfig: EncryptionConfig,
+    ip: String,
+    registry: Registry,
+    additional: Vec<(String, Startable)>,
+    stubs: Vec<Stub>,
+    activating: Vec<Activating>,
+    csr_approval: Instant,
+    dns_check: Instant,
+    node_init: Instant,
+    liveness: Liveness,
+    binary_watch: Option<BinaryWatch>,
+    ip_watch: IpWatch,
+    ttl: Ttl,
+    control: Option<Control>,
+    stop_requested: bool,
+    path: Option<OsString>,
 }
 
 impl Kubernix {
     /// Start kubernix by consuming the provided configuration
-    pub fn start(mut config: Config) -> Fallible<()> {
+    pub fn start(config: Config) -> Fallible<()> {
+        Self::start_with_registry(config, Registry::default())
+    }
+
+    /// Start kubernix by consuming the provided configuration, whereas all
+    /// components of the registry get started
+    pub fn start_with_registry(mut config: Config, mut registry: Registry) -> Fallible<()> {
         Self::prepare_env(&mut config)?;
+        Signals::install()?;
+
+        // Ensure that only one instance is running per root directory
+        let _lock = RootLock::acquire(&config)?;
+
+        // The cluster runs inside the VM, which brings its own nix environment
+        if *config.vm() {
+            if *config.dry_run() {
+                bail!("A dry run is not supported together with the VM")
+            }
+            return Vm::run(&config);
+        }
+
+        // The secondary scheduler runs as an additional component
+        if let Some(x) = config.secondary_scheduler() {
+            registry.register(Box::new(SecondaryScheduler::new(x)))?;
+        }
+
+        // Supervise the processes of the configuration file as well
+        for process in config.processes() {
+            registry.register(Box::new(process.clone()))?;
+        }
+        for addon in config.lazy_addons() {
+            registry.register(Box::new(*addon))?;
+        }
+
+        // Disabled built-in components are not supervised at all, whereas
+        // dependent components have to be removed first
+        if *config.kubelet_only() {
+            registry.unregister(&ComponentKind::Kubelet.to_string())?;
+            registry.register(Box::new(StandaloneKubelet))?;
+        }
+        for kind in ComponentKind::ALL.iter().rev() {
+            if !kind.enabled(&config) {
+                registry.unregister(&kind.to_string())?;
+            }
+        }
 
         // Bootstrap if we're not inside a nix shell
         if var(NIX_SHELL_ENV).is_err() {
@@ -83,7 +299,7 @@ impl Kubernix {
             Self::bootstrap_nix(config)
         } else {
             info!("Bootstrapping cluster inside nix environment");
-            Self::bootstrap_cluster(config)
+            Self::bootstrap_cluster(config, registry)
         }
     }
 
@@ -96,20 +312,540 @@ impl Kubernix {
             config.root().display()
         );
 
-        Self::nix_shell_run(
+        // The exit status of an interactive shell is not meaningful
+        Self::nix_shell_status(
             &config,
             &format!(
                 "bash --init-file {}",
                 config.root().join(KUBERNIX_ENV).display()
             ),
+        )?;
+        Ok(())
+    }
+
+    /// Export the credentials of the provided configuration environment
+    pub fn export_credentials(mut config: Config) -> Fallible<()> {
+        Self::prepare_env(&mut config)?;
+
+        match config.subcommand() {
+            Some(SubCommand::ExportCredentials(args)) => Credentials::export(&config, args),
+            _ => bail!("No credentials export arguments provided"),
+        }
+    }
+
+    /// Upgrade Kubernetes of the running cluster
+    pub fn upgrade(mut config: Config) -> Fallible<()> {
+        Self::prepare_env(&mut config)?;
+
+        match config.subcommand() {
+            Some(SubCommand::Upgrade(args)) => UpgradeRequest::send(&config, args),
+            _ => bail!("No upgrade arguments provided"),
+        }
+    }
+
+    /// Show the configuration drift of the running cluster and apply it if
+    /// requested
+    pub fn diff(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Diff(args)) => args,
+            _ => bail!("No diff arguments provided"),
+        };
+        let changed = Drift::changed_files(&config)?;
+        for (name, files) in &changed {
+            for file in files {
+                warn!(
+                    "Component '{}' uses '{}', which changed after its start",
+                    name,
+                    file.display()
+                );
+            }
+        }
+        match Drift::diff(&config)? {
+            None if changed.is_empty() => info!("No configuration drift found"),
+            None => {
+                if *args.apply() {
+                    for (name, _) in &changed {
+                        info!("Restarting component '{}'", name);
+                        Control::request(
+                            &config,
+                            "POST",
+                            &format!("/v1/components/{}/restart", name),
+                        )?;
+                    }
+                }
+            }
+            Some(diff) => {
+                print!("{}", diff);
+                if *args.apply() {
+                    UpgradeRequest::Reconfigure.submit(&config)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for the running cluster to fulfill the provided conditions
+    pub fn wait(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Wait(args)) => args,
+            _ => bail!("No wait arguments provided"),
+        };
+
+        Self::run_in_nix_shell(&config, &config.root().join(NIX_DIR), || {
+            Readiness::wait(&config, args)
+        })
+    }
+
+    /// Benchmark the control plane of the running cluster
+    pub fn bench(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Bench(args)) => args,
+            _ => bail!("No bench arguments provided"),
+        };
+
+        Self::run_in_nix_shell(&config, &config.root().join(NIX_DIR), || {
+            Bench::run(&config, args)
+        })
+    }
+
+    /// Verify the streaming paths between the API Server and the kubelet
+    pub fn verify(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Verify(args)) => args,
+            _ => bail!("No verify arguments provided"),
+        };
+
+        Self::run_in_nix_shell(&config, &config.root().join(NIX_DIR), || {
+            Verify::run(&config, args)
+        })
+    }
+
+    /// Diagnose common problems of the running cluster
+    pub fn doctor(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Doctor(args)) => args,
+            _ => bail!("No doctor arguments provided"),
+        };
+
+        Self::run_in_nix_shell(&config, &config.root().join(NIX_DIR), || {
+            Doctor::run(&config, args)
+        })
+    }
+
+    /// Remove old run roots and the garbage of the running cluster
+    pub fn gc(mut config: Config) -> Fallible<()> {
+        // Collecting garbage should not leave a new root behind
+        if config.root().exists() {
+            Self::prepare_env(&mut config)?;
+        } else {
+            Logger::init(&config)?;
+        }
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Gc(args)) => args,
+            _ => bail!("No gc arguments provided"),
+        };
+
+        // Pruning the images of a running cluster requires its tooling
+        if RootLock::is_held(config.root())? {
+            Self::run_in_nix_shell(&config, &config.root().join(NIX_DIR), || {
+                GarbageCollector::run(&config, args)
+            })
+        } else {
+            GarbageCollector::run(&config, args)
+        }
+    }
+
+    /// Build a container image into the storage of the container runtime
+    pub fn build_and_load(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::BuildAndLoad(args)) => args,
+            _ => bail!("No build-and-load arguments provided"),
+        };
+
+        Self::run_in_nix_shell(&config, &config.root().join(NIX_DIR), || {
+            ImageBuild::run(&config, args)
+        })
+    }
+
+    /// Synchronize a local directory into a pod of the running cluster
+    pub fn sync(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Sync(args)) => args,
+            _ => bail!("No sync arguments provided"),
+        };
+
+        Self::run_in_nix_shell(&config, &config.root().join(NIX_DIR), || {
+            PodSync::run(&config, args)
+        })
+    }
+
+    /// Apply CustomResourceDefinitions to the running cluster
+    pub fn apply_crds(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::ApplyCrds(args)) => args,
+            _ => bail!("No apply-crds arguments provided"),
+        };
+
+        Self::run_in_nix_shell(&config, &config.root().join(NIX_DIR), || {
+            Crds::run(&config, args)
+        })
+    }
+
+    /// Register an extension API server of the local host to the running
+    /// cluster
+    pub fn register_apiservice(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::RegisterApiService(args)) => args,
+            _ => bail!("No register-apiservice arguments provided"),
+        };
+
+        Self::run_in_nix_shell(&config, &config.root().join(NIX_DIR), || {
+            Aggregation::register(&config, args)
+        })
+    }
+
+    /// Shape the pod network of the running cluster
+    pub fn netem(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        match config.subcommand() {
+            Some(SubCommand::Netem(args)) => Netem::run(args),
+            _ => bail!("No netem arguments provided"),
+        }
+    }
+
+    /// Show the status of the running cluster and its components
+    pub fn status(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Status(args)) => args,
+            _ => bail!("No status arguments provided"),
+        };
+        let status = Control::request(&config, "GET", "/v1/status")?;
+        if *args.output().format() != OutputFormat::Text {
+            return Output::print(*args.output().format(), &status);
+        }
+        info!(
+            "Cluster in '{}' is running for {}s (PID {})",
+            config.root().display(),
+            status["uptime"],
+            status["pid"]
+        );
+        for component in status["components"].as_array().unwrap_or(&vec![]) {
+            let ports = component["ports"]
+                .as_array()
+                .map(|x| {
+                    x.iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .filter(|x| !x.is_empty())
+                .map(|x| format!(" on ports {}", x))
+                .unwrap_or_default();
+            info!(
+                "Component '{}' is {}{}",
+                component["name"].as_str().unwrap_or_default(),
+                component["health"].as_str().unwrap_or_default(),
+                ports
+            );
+        }
+        info!(
+            "Cluster DNS is {}",
+            status["dns"].as_str().unwrap_or_default()
+        );
+        info!(
+            "Network plugin is {}, NetworkPolicies are {}",
+            status["cni"].as_str().unwrap_or_default(),
+            status["network-policy"].as_str().unwrap_or_default()
+        );
+        Ok(())
+    }
+
+    /// List the components of the running cluster
+    pub fn list(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::List(args)) => args,
+            _ => bail!("No list arguments provided"),
+        };
+        let components = Control::request(&config, "GET", "/v1/components")?;
+        if *args.output().format() != OutputFormat::Text {
+            return Output::print(*args.output().format(), &components);
+        }
+        for component in components.as_array().unwrap_or(&vec![]) {
+            info!(
+                "{} ({}, PID {})",
+                component["name"].as_str().unwrap_or_default(),
+                component["health"].as_str().unwrap_or_default(),
+                component["pid"]
+            );
+        }
+        Ok(())
+    }
+
+    /// Check the readiness conditions of the running cluster once
+    pub fn check(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Check(args)) => args,
+            _ => bail!("No check arguments provided"),
+        };
+
+        Self::run_in_nix_shell(&config, &config.root().join(NIX_DIR), || {
+            Readiness::check(&config, args)
+        })
+    }
+
+    /// Restart a single component of the running cluster
+    pub fn restart(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Restart(args)) => args,
+            _ => bail!("No restart arguments provided"),
+        };
+        info!("Restarting component '{}'", args.component());
+        let restarted = Control::request(
+            &config,
+            "POST",
+            &format!("/v1/components/{}/restart", args.component()),
+        )?;
+        info!(
+            "Component '{}' restarted (PID {})",
+            args.component(),
+            restarted["pid"]
+        );
+        Ok(())
+    }
+
+    /// Show the logs of a single component of the running cluster
+    pub fn logs(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Logs(args)) => args,
+            _ => bail!("No logs arguments provided"),
+        };
+        Control::stream(
+            &config,
+            &format!(
+                "/v1/components/{}/logs?follow={}",
+                args.component(),
+                args.follow()
+            ),
         )
     }
 
+    /// Stop the running cluster
+    pub fn shutdown(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        Control::request(&config, "POST", "/v1/stop")?;
+        info!("Stopping cluster in '{}'", config.root().display());
+        Ok(())
+    }
+
+    /// Show an interactive dashboard of the running cluster
+    pub fn ui(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+        Ui::run(&config)
+    }
+
+    /// Show the event timeline of the cluster, which is also available
+    /// after the cluster got destroyed
+    pub fn events(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        match config.subcommand() {
+            Some(SubCommand::Events(args)) => EventLog::run(&config, args),
+            _ => bail!("No events arguments provided"),
+        }
+    }
+
+    /// Export the generated setup of the cluster as standalone bundle
+    pub fn export(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        match config.subcommand() {
+            Some(SubCommand::Export(args)) => Bundle::export(&config, args),
+            _ => bail!("No export arguments provided"),
+        }
+    }
+
+    /// Mint a service account token of the running cluster
+    pub fn token(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Token(args)) => args,
+            _ => bail!("No token arguments provided"),
+        };
+
+        Self::run_in_nix_shell(&config, &config.root().join(NIX_DIR), || {
+            TokenRequest::run(&config, args)
+        })
+    }
+
+    /// Run a single component of the running cluster under the debugger
+    pub fn debug(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Debug(args)) => args,
+            _ => bail!("No debug arguments provided"),
+        };
+
+        // The nix shell additionally provides delve
+        let nix_dir = config.root().join(NIX_DIR);
+        let nix_file = nix_dir.join("debug.nix");
+        if var(NIX_SHELL_ENV).is_err() {
+            if !nix_dir.exists() {
+                bail!("Nix environment not found, please bootstrap the cluster first")
+            }
+            fs::write(&nix_file, include_str!("../nix/debug.nix"))?;
+        }
+        Self::run_in_nix_shell(&config, &nix_file, || Debugger::run(&config, args))
+    }
+
+    /// Run a subset of the upstream conformance tests against the running
+    /// cluster
+    pub fn conformance(mut config: Config) -> Fallible<()> {
+        if !config.root().exists() {
+            bail!("No cluster found in '{}'", config.root().display())
+        }
+        Self::prepare_env(&mut config)?;
+
+        let args = match config.subcommand() {
+            Some(SubCommand::Conformance(args)) => args,
+            _ => bail!("No conformance arguments provided"),
+        };
+
+        Self::run_in_nix_shell(&config, &config.root().join(NIX_DIR), || {
+            Conformance::run(&config, args)
+        })
+    }
+
+    /// Boot a cluster for every combination of a matrix and run a command
+    /// against each of them
+    pub fn matrix(mut config: Config) -> Fallible<()> {
+        if !getuid().is_root() {
+            return Err(Error::Preflight("Please run kubernix as root".into()));
+        }
+        // The root only contains the roots of the single clusters
+        config.canonicalize_root()?;
+        Logger::init(&config)?;
+
+        match config.subcommand() {
+            Some(SubCommand::Matrix(args)) => Matrix::run(&config, args),
+            _ => bail!("No matrix arguments provided"),
+        }
+    }
+
+    /// Move the root of a stopped cluster to a new location
+    pub fn migrate_root(config: Config) -> Fallible<()> {
+        if !getuid().is_root() {
+            return Err(Error::Preflight("Please run kubernix as root".into()));
+        }
+        // The configured root is not involved and should not be created
+        Logger::init(&config)?;
+
+        match config.subcommand() {
+            Some(SubCommand::MigrateRoot(args)) => RootMigration::run(args),
+            _ => bail!("No migrate-root arguments provided"),
+        }
+    }
+
     /// Prepare the environment based on the provided config
     fn prepare_env(config: &mut Config) -> Fallible<()> {
-        // Rootless is currently not supported
-        if !getuid().is_root() {
-            bail!("Please run kubernix as root")
+        // Rootless is currently not supported, except for the VM backend
+        if !getuid().is_root() && !*config.vm() {
+            return Err(Error::Preflight("Please run kubernix as root".into()));
         }
 
         // Prepare the configuration
@@ -121,47 +857,131 @@ impl Kubernix {
         config.canonicalize_root()?;
 
         // Setup the logger
-        let mut builder = Builder::new();
-        builder
-            .format_timestamp(None)
-            .filter(None, *config.log_level())
-            .try_init()?;
+        Logger::init(config)?;
+        Telemetry::init(config)?;
 
         Ok(())
     }
 
     /// Stop kubernix by cleaning up all running processes
     fn stop(&mut self) {
-        for x in &mut self.processes {
+        Instrumentation::collect_profiles(&self.config);
+        for (_, _, receiver) in self.activating.drain(..) {
+            if let Ok(Ok(mut x)) = receiver.recv() {
+                if let Err(e) = x.stop() {
+                    debug!("{}", e)
+                }
+            }
+        }
+        for (_, x) in self.additional.iter_mut().rev() {
             if let Err(e) = x.stop() {
                 debug!("{}", e)
             }
         }
+        for (_, x) in &mut self.processes {
+            if let Err(e) = x.stop() {
+                debug!("{}", e)
+            }
+        }
+        Instrumentation::report_coverage(&self.config);
     }
 
     /// Bootstrap the whole cluster, which assumes to be inside a nix shell
-    fn bootstrap_cluster(config: Config) -> Fallible<()> {
+    fn bootstrap_cluster(mut config: Config, registry: Registry) -> Fallible<()> {
+        if *config.dry_run() {
+            return Self::dry_run(config);
+        }
+
+        // The root span gets exported as soon as the cluster is up
+        let bootstrap = info_span!("bootstrap");
+        let guard = bootstrap.enter();
+
         // Ensure that the system is prepared
-        let system = System::new();
-        system.prepare()?;
+        let mut system = System::new();
+        if Proxy::enabled(&config) {
+            system.add_modules(config.proxy_mode().modules());
+        }
+        if *config.containerized() {
+            system.relax();
+        }
+        info_span!("preflight")
+            .in_scope(|| {
+                Containerized::preflight(&config)
+                    .and_then(|_| system.prepare())
+                    .and_then(|_| RLimit::preflight(&config))
+                    .and_then(|_| Budget::preflight(&config))
+                    .and_then(|_| Secrets::preflight(&config))
+                    .and_then(|_| Selinux::preflight(&config))
+                    .and_then(|_| Firewall::preflight(&config))
+                    .and_then(|_| Ports::preflight(&config))
+                    .and_then(|_| LocalBinary::preflight(&config))
+                    .and_then(|_| Instrumentation::preflight(&config))
+            })
+            .map_err(|e| Error::Preflight(e.to_string()))?;
+
+        Containerized::setup(&config)?;
+
+        // All processes spawned from now on are part of the resource budget
+        Budget::apply(&config)?;
 
         // Retrieve the local IP
         let ip = system.ip()?;
         let hostname = system.hostname()?;
 
         // Setup the network
-        let network = Network::new(&config)?;
+        let network = Network::new(&mut config)?;
+        Firewall::setup(&config, &network)?;
+
+        // Completed phases of a previous run are skipped if it gets continued
+        let mut state = State::new(&config)?;
+
+        // Generated secrets are only available in plaintext inside a tmpfs
+        Secrets::unseal(&config)?;
 
         // Setup the PKI
-        let pki = Pki::new(&config, &network, &ip, &hostname)?;
+        let pki = state.run(
+            Phase::Pki,
+            &[config.root().join(Pki::DIR)],
+            || Pki::new(&config, &network, &ip, &hostname).map_err(|e| Error::Pki(e.to_string())),
+            || Ok(Pki::load(&config, &hostname)),
+        )?;
 
         // Setup the configs
-        let kubeconfig = KubeConfig::new(&config, &pki, &ip, &hostname)?;
-        let encryptionconfig = EncryptionConfig::new(&config)?;
+        let kubeconfig = state.run(
+            Phase::KubeConfig,
+            &[config.root().join(KubeConfig::DIR)],
+            || KubeConfig::new(&config, &pki, &ip, &hostname),
+            || Ok(KubeConfig::load(&config, &hostname)),
+        )?;
+        let encryptionconfig = state.run(
+            Phase::EncryptionConfig,
+            &[EncryptionConfig::file(&config)],
+            || EncryptionConfig::new(&config),
+            || Ok(EncryptionConfig::load(&config)),
+        )?;
+        Layout::apply(&config, &pki, &kubeconfig)?;
+        Secrets::seal(&config)?;
+        FakeTime::setup(&config)?;
+        SecurityProfile::setup(&config)?;
+        Selinux::setup(&config)?;
+        config.cni().prepare(&config)?;
+
+        // The etcd data is kept if the run gets continued, since it is
+        // encrypted with the previous encryption config
+        state.run(Phase::EtcdReset, &[], || Etcd::reset(&config), || Ok(()))?;
+
+        // Remove stale readiness markers of previous runs, since the
+        // components wait for their dependencies
+        for kind in &ComponentKind::ALL {
+            Readiness::unmark(&config.root().join(kind.to_string()))?;
+        }
 
         // Full path to the CRI socket
         let crio_socket = config.root().join(CRIO_DIR).join("crio.sock");
 
+        let env = Self::cluster_env(&crio_socket, &kubeconfig);
+        Hook::run_all(&config, HookPoint::PreUp, &env)?;
+
         // All processes
         let mut crio = Process::stopped();
         let mut etcd = Process::stopped();
@@ -172,61 +992,480 @@ impl Kubernix {
         let mut prox = Process::stopped();
 
         // Spawn the processes
+        Signals::check()?;
         info!("Starting processes");
+        let context = Context {
+            config: &config,
+            network: &network,
+            pki: &pki,
+            kubeconfig: &kubeconfig,
+            encryptionconfig: &encryptionconfig,
+            crio_socket: &crio_socket,
+            ip: &ip,
+        };
+        let components = info_span!("components");
+        // The worker threads do not know about the current span
+        let start = |kind: ComponentKind| {
+            info_span!(parent: &components, "start", component = %kind)
+                .in_scope(|| kind.start(&context))
+        };
         scope(|s| {
-            s.spawn(|_| crio = Crio::start(&config, &network, &crio_socket));
-            s.spawn(|_| {
-                etcd = Etcd::start(&config, &pki);
-                apis =
-                    ApiServer::start(&config, &network, &ip, &pki, &encryptionconfig, &kubeconfig)
-            });
-            s.spawn(|_| cont = ControllerManager::start(&config, &network, &pki, &kubeconfig));
-            s.spawn(|_| sche = Scheduler::start(&config, &kubeconfig));
-            s.spawn(|_| kube = Kubelet::start(&config, &network, &pki, &kubeconfig, &crio_socket));
-            s.spawn(|_| prox = Proxy::start(&config, &network, &kubeconfig));
+            s.spawn(|_| crio = start(ComponentKind::Crio));
+            if !*config.kubelet_only() {
+                s.spawn(|_| {
+                    etcd = start(ComponentKind::Etcd);
+                    apis = start(ComponentKind::ApiServer);
+                });
+                s.spawn(|_| cont = start(ComponentKind::ControllerManager));
+                s.spawn(|_| sche = start(ComponentKind::Scheduler));
+            }
+            s.spawn(|_| kube = start(ComponentKind::Kubelet));
+            if Proxy::enabled(&config) {
+                s.spawn(|_| prox = start(ComponentKind::Proxy));
+            }
         });
+        drop(components);
 
         let mut processes = vec![];
 
         // This order is important since we will shut down the processes in its reverse
-        let results = vec![kube, sche, prox, cont, apis, etcd, crio];
-        let all_ok = results.iter().all(|x| x.is_ok());
+        let results = vec![
+            (ComponentKind::Kubelet, kube),
+            (ComponentKind::Scheduler, sche),
+            (ComponentKind::Proxy, prox),
+            (ComponentKind::ControllerManager, cont),
+            (ComponentKind::ApiServer, apis),
+            (ComponentKind::Etcd, etcd),
+            (ComponentKind::Crio, crio),
+        ];
 
-        // Note: wait for `drain_filter()` to be stable and make it more straightforward
-        for process in results {
+        // Keep the failure of the earliest started component, which is
+        // likely the root cause for the others
+        let mut failure = None;
+        for (kind, process) in results {
+            if !kind.enabled(&config) {
+                continue;
+            }
             match process {
-                Ok(p) => processes.push(p),
-                Err(e) => error!("{}", e),
+                Ok(p) => processes.push((kind, p)),
+                Err(e) => {
+                    error!("{}", e);
+                    failure = Some(Error::component_start(&kind.to_string(), e));
+                }
             }
         }
 
         // Setup the main instance
+        let ttl = Ttl::new(&config);
+        let ip_watch = IpWatch::new(&ip);
+        let liveness = Liveness::new(&config);
+        let binary_watch = BinaryWatch::new(&config)?;
         let mut kubernix = Kubernix {
             config,
             network,
             crio_socket,
             kubeconfig,
             processes,
+            pki,
+            encryptionconfig,
+            ip,
+            registry,
+            additional: vec![],
+            stubs: vec![],
+            activating: vec![],
+            csr_approval: Instant::now(),
+            dns_check: Instant::now(),
+            node_init: Instant::now(),
+            liveness,
+            binary_watch,
+            ip_watch,
+            ttl,
+            control: None,
+            stop_requested: false,
+            path: var_os("PATH"),
         };
 
         // No dead processes
-        if all_ok {
-            kubernix.apply_addons()?;
+        if let Some(e) = failure {
+            error!("Unable to start all processes");
+            return Err(e);
+        }
+
+        // A standalone kubelet has no API Server to apply the addons to
+        if !*kubernix.config.kubelet_only() {
+            state.run(Phase::Addons, &[], || kubernix.apply_addons(), || Ok(()))?;
+        }
+        kubernix.start_additional()?;
+        KubeConfigMerge::merge(&kubernix.config)?;
+        Drift::record(&kubernix.config)?;
+
+        info!("Everything is up and running");
+        EventLog::record(
+            kubernix.config.root(),
+            EventKind::ClusterStarted,
+            None,
+            "Everything is up and running",
+        );
+        match Control::bind(&kubernix.config) {
+            Ok(x) => kubernix.control = Some(x),
+            Err(e) => warn!("Control API not available: {}", e),
+        }
+        kubernix.report_status()?;
+        drop(guard);
+        drop(bootstrap);
 
-            info!("Everything is up and running");
-            kubernix.spawn_shell()?;
+        Hook::run_all(&kubernix.config, HookPoint::PostUp, &env)?;
+        if *kubernix.config.soak() {
+            kubernix.soak()
         } else {
-            error!("Unable to start all processes")
+            kubernix.spawn_shell()
         }
+    }
+
+    /// Render the whole cluster into the root directory and print the plan
+    /// without modifying the system or starting anything
+    fn dry_run(mut config: Config) -> Fallible<()> {
+        info!("Rendering the cluster without starting anything");
+        let system = System::new();
+        let ip = system.ip()?;
+        let hostname = system.hostname()?;
+        let network = Network::new(&mut config)?;
+        let pki =
+            Pki::new(&config, &network, &ip, &hostname).map_err(|e| Error::Pki(e.to_string()))?;
+        let kubeconfig = KubeConfig::new(&config, &pki, &ip, &hostname)?;
+        let encryptionconfig = EncryptionConfig::new(&config)?;
+        Layout::apply(&config, &pki, &kubeconfig)?;
+        let crio_socket = config.root().join(CRIO_DIR).join("crio.sock");
 
+        // Additional components are not part of the plan, since they may
+        // require a running cluster
+        let context = Context {
+            config: &config,
+            network: &network,
+            pki: &pki,
+            kubeconfig: &kubeconfig,
+            encryptionconfig: &encryptionconfig,
+            crio_socket: &crio_socket,
+            ip: &ip,
+        };
+        for kind in ComponentKind::ALL.iter().filter(|x| x.enabled(&config)) {
+            kind.start(&context)
+                .map_err(|e| Error::component_start(&kind.to_string(), e))?;
+        }
+        Plan::write(&config, &pki)
+    }
+
+    /// Retrieve the context of the running cluster
+    fn context(&self) -> Context<'_> {
+        Context {
+            config: &self.config,
+            network: &self.network,
+            pki: &self.pki,
+            kubeconfig: &self.kubeconfig,
+            encryptionconfig: &self.encryptionconfig,
+            crio_socket: &self.crio_socket,
+            ip: &self.ip,
+        }
+    }
+
+    /// Start a single component of the already bootstrapped cluster
+    fn start_component(&self, kind: ComponentKind) -> Fallible<Startable> {
+        kind.start(&self.context())
+    }
+
+    /// Start all additionally registered components in their dependency order.
+    /// This method stops the cluster on any error.
+    fn start_additional(&mut self) -> Fallible<()> {
+        let mut started = vec![];
+        let mut stubs = vec![];
+        let mut result = Ok(());
+        {
+            let context = self.context();
+            for component in self.registry.additional()? {
+                if let Some(activation) = component.activation() {
+                    match Stub::bind(&component.name(), activation) {
+                        Ok(x) => stubs.push(x),
+                        Err(e) => {
+                            result = Err(e);
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                info!("Starting component '{}'", component.name());
+                match self.registry.start(component, &context) {
+                    Ok(x) => started.push((component.name(), x)),
+                    Err(e) => {
+                        result = Err(Error::component_start(&component.name(), e));
+                        break;
+                    }
+                }
+            }
+        }
+        self.additional = started;
+        self.stubs = stubs;
+        result
+    }
+
+    /// Start the lazily activated components which got their first
+    /// connection in the background and hand the connection over to them
+    /// as soon as they are started
+    fn activate(&mut self) -> Fallible<()> {
+        for (stub, stream, receiver) in self.activating.drain(..).collect::<Vec<_>>() {
+            match receiver.try_recv() {
+                Err(TryRecvError::Empty) => self.activating.push((stub, stream, receiver)),
+                Ok(Ok(x)) => {
+                    info!("Component '{}' started on first connection", stub.name());
+                    self.additional.push((stub.name().to_owned(), x));
+                    stub.serve(stream)?;
+                }
+                Ok(Err(e)) => {
+                    error!("Unable to start component '{}': {}", stub.name(), e);
+                    self.stubs.push(stub);
+                }
+                Err(TryRecvError::Disconnected) => {
+                    error!("Unable to start component '{}'", stub.name());
+                    self.stubs.push(stub);
+                }
+            }
+        }
+
+        for stub in self.stubs.drain(..).collect::<Vec<_>>() {
+            let stream = match stub.accept()? {
+                Some(x) => x,
+                None => {
+                    self.stubs.push(stub);
+                    continue;
+                }
+            };
+            info!("Starting component '{}' on first connection", stub.name());
+            let receiver = self
+                .registry
+                .start_background(stub.name(), &self.context())?;
+            self.activating.push((stub, stream, receiver));
+        }
+        Ok(())
+    }
+
+    /// Log the health of all components
+    fn report_status(&self) -> Fallible<()> {
+        for (name, health) in self.component_health()? {
+            info!("Component '{}' is {}", name, health);
+        }
+        info!(
+            "Cluster DNS is {}",
+            DnsWatchdog::health(&self.config, &self.network)
+        );
+        info!(
+            "Network plugin is {}, NetworkPolicies are {}",
+            self.config.cni(),
+            NetworkPolicy::mode(&self.config)
+        );
+        Ok(())
+    }
+
+    /// Restart a single running component
+    fn restart_component(&mut self, kind: ComponentKind) -> Fallible<()> {
+        info!("Restarting {}", kind);
+        self.stop_component(kind)?;
+        self.start_stopped_component(kind)
+    }
+
+    /// Stop a single running component
+    fn stop_component(&mut self, kind: ComponentKind) -> Fallible<()> {
+        let index = self.component_index(kind)?;
+        self.processes[index].1.stop()
+    }
+
+    /// Start a single component which has been stopped before
+    fn start_stopped_component(&mut self, kind: ComponentKind) -> Fallible<()> {
+        let index = self.component_index(kind)?;
+        self.processes[index].1 = self.start_component(kind)?;
+        Ok(())
+    }
+
+    /// Retrieve the index of the component within the processes
+    fn component_index(&self, kind: ComponentKind) -> Fallible<usize> {
+        self.processes
+            .iter()
+            .position(|(k, _)| *k == kind)
+            .ok_or_else(|| format_err!("Component {} is not running", kind))
+    }
+
+    /// Upgrade the running cluster by restarting all components in the
+    /// supported order with the requested closure
+    fn upgrade_cluster(&mut self, request: &UpgradeRequest) -> Fallible<()> {
+        let current = UpgradeRecord::new(&self.config)?;
+        let (closure, snapshot, event) = match request {
+            UpgradeRequest::Upgrade {
+                version,
+                closure,
+                allow_downgrade,
+            } => {
+                if version < current.version() && !allow_downgrade {
+                    bail!(
+                        "Downgrading from {} to {} is not allowed",
+                        current.version(),
+                        version
+                    )
+                }
+                info!(
+                    "Upgrading cluster from Kubernetes {} to {}",
+                    current.version(),
+                    version
+                );
+
+                // Record the current state to be able to roll back
+                Etcd::snapshot(&self.config, &self.pki, current.snapshot())?;
+                current.save(&self.config)?;
+                (
+                    closure.to_owned(),
+                    None,
+                    format!(
+                        "Upgraded from Kubernetes {} to {}",
+                        current.version(),
+                        version
+                    ),
+                )
+            }
+            UpgradeRequest::Rollback => {
+                let previous = UpgradeRecord::load(&self.config)?;
+                info!(
+                    "Rolling back cluster from Kubernetes {} to {}",
+                    current.version(),
+                    previous.version()
+                );
+                (
+                    previous.closure().to_owned(),
+                    Some(previous.snapshot().to_owned()),
+                    format!(
+                        "Rolled back from Kubernetes {} to {}",
+                        current.version(),
+                        previous.version()
+                    ),
+                )
+            }
+            UpgradeRequest::Reconfigure => bail!("A reconfiguration is no upgrade"),
+        };
+
+        if let Err(e) = self.roll_out(&closure, snapshot.as_deref()) {
+            // A failed rollback cannot be rolled back again
+            if let UpgradeRequest::Upgrade { .. } = request {
+                error!("Upgrade failed, rolling back: {}", e);
+                match self.upgrade_cluster(&UpgradeRequest::Rollback) {
+                    Ok(()) => bail!("Upgrade failed and got rolled back: {}", e),
+                    Err(r) => bail!("Upgrade failed: {}, rollback failed as well: {}", e, r),
+                }
+            }
+            return Err(e);
+        }
+
+        info!("Cluster upgrade done");
+        EventLog::record(self.config.root(), EventKind::Upgraded, None, &event);
+        Ok(())
+    }
+
+    /// Restart all components with the binaries of the provided closure,
+    /// whereas etcd gets restored from the snapshot if provided. The
+    /// restarted cluster has to become healthy within
+    /// `UpgradeRequest::HEALTH_TIMEOUT`.
+    fn roll_out(&mut self, closure: &Path, snapshot: Option<&Path>) -> Fallible<()> {
+        // Prefer the binaries of the closure for all restarted components,
+        // whereas the closures of former upgrades get dropped from the path
+        let mut paths = vec![closure.join("bin")];
+        if let Some(path) = &self.path {
+            paths.extend(split_paths(path));
+        }
+        set_var("PATH", join_paths(paths)?);
+
+        let kinds: Vec<ComponentKind> = ComponentKind::UPGRADE_ORDER
+            .iter()
+            .filter(|x| x.enabled(&self.config))
+            .cloned()
+            .collect();
+        for kind in &kinds {
+            match (kind, snapshot) {
+                (ComponentKind::Etcd, Some(snapshot)) => {
+                    self.stop_component(*kind)?;
+                    Etcd::restore(&self.config, snapshot)?;
+                    self.start_stopped_component(*kind)?;
+                }
+                _ => self.restart_component(*kind)?,
+            }
+        }
+
+        // The restarted cluster has to become healthy with the new binaries
+        let mut conditions: Vec<WaitCondition> =
+            kinds.iter().map(|&x| WaitCondition::Component(x)).collect();
+        if kinds.contains(&ComponentKind::Kubelet) {
+            conditions.push(WaitCondition::NodesReady);
+        }
+        Readiness::wait_for(
+            &self.config,
+            &conditions,
+            Duration::from_secs(UpgradeRequest::HEALTH_TIMEOUT),
+        )?;
+        Ok(())
+    }
+
+    /// Reload the configuration file and restart all components to apply it
+    fn reconfigure(&mut self) -> Fallible<()> {
+        info!("Reloading configuration");
+        self.config.update_from_file()?;
+        self.config.canonicalize_root()?;
+
+        let kinds: Vec<ComponentKind> = ComponentKind::UPGRADE_ORDER
+            .iter()
+            .filter(|x| x.enabled(&self.config))
+            .cloned()
+            .collect();
+        for kind in kinds {
+            self.restart_component(kind)?;
+        }
+        Drift::record(&self.config)?;
+        info!("Configuration applied");
+        EventLog::record(
+            self.config.root(),
+            EventKind::ConfigChanged,
+            None,
+            "Configuration applied",
+        );
         Ok(())
     }
 
     /// Apply needed workloads to the running cluster. This method stops the cluster on any error.
     fn apply_addons(&mut self) -> Fallible<()> {
+        if let Err(e) = Crds::apply(&self.config, &self.kubeconfig) {
+            bail!("Unable to apply CustomResourceDefinitions: {}", e);
+        }
+        if let Err(e) = CloudControllerManager::apply(&self.config, &self.kubeconfig) {
+            bail!("Unable to apply stub cloud controller manager: {}", e);
+        }
+        if let Err(e) =
+            self.config
+                .cni()
+                .apply(&self.config, &self.network, &self.kubeconfig, &self.ip)
+        {
+            bail!("Unable to apply {}: {}", self.config.cni(), e);
+        }
         if let Err(e) = CoreDNS::apply(&self.config, &self.network, &self.kubeconfig) {
             bail!("Unable to apply CoreDNS: {}", e);
         }
+        if let Err(e) = NetworkPolicy::apply(&self.config, &self.kubeconfig, &self.ip) {
+            bail!("Unable to apply NetworkPolicy enforcement: {}", e);
+        }
+        if let Err(e) = LoadBalancer::apply(&self.config, &self.network, &self.kubeconfig) {
+            bail!("Unable to apply LoadBalancer address pool: {}", e);
+        }
+        if let Err(e) = Csi::apply(&self.config, &self.kubeconfig) {
+            bail!("Unable to apply CSI driver development mode: {}", e);
+        }
+        if let Err(e) = RuntimeClass::apply(&self.config, &self.kubeconfig) {
+            bail!("Unable to apply runtime classes: {}", e);
+        }
+        if let Err(e) = Volumes::apply(&self.config, &self.kubeconfig) {
+            bail!("Unable to apply persistent volumes: {}", e);
+        }
         Ok(())
     }
 
@@ -250,7 +1489,43 @@ impl Kubernix {
             include_str!("../nix/default.nix"),
         )?;
 
-        let packages = &config.packages().join(" ");
+        let mut packages = config.packages().clone();
+        if config.faketime().is_some() {
+            packages.push(FakeTime::PACKAGE.into());
+        }
+        if !config.apparmor_profiles().is_empty() {
+            packages.push(SecurityProfile::APPARMOR_PACKAGE.into());
+        }
+        if Selinux::enabled(&config) {
+            packages.push(Selinux::PACKAGE.into());
+        }
+        if FrontProxy::enabled(&config) {
+            packages.push(FrontProxy::PACKAGE.into());
+        }
+        if !*config.skip_firewall() {
+            packages.push(Firewall::PACKAGE.into());
+        }
+        if let Some(x) = config
+            .proxy_mode()
+            .package()
+            .filter(|_| Proxy::enabled(&config))
+        {
+            packages.push(x.into());
+        }
+        if let Some(x) = config.cni().package() {
+            packages.push(x.into());
+        }
+        if let Some(x) = config.secret_backend().package() {
+            packages.push(x.into());
+        }
+        packages.extend(
+            config
+                .runtime_classes()
+                .iter()
+                .filter_map(|x| x.package())
+                .map(Into::into),
+        );
+        let packages = &packages.join(" ");
         debug!("Adding additional packages: {}", packages);
         fs::write(
             nix_dir.join("deps.nix"),
@@ -273,43 +1548,461 @@ impl Kubernix {
             }
         }
 
+        // Replace single packages, which are applied on top of the overlay
+        for x in config.package_overrides() {
+            info!("Overriding package '{}'", x);
+        }
+        fs::write(
+            nix_dir.join("overrides.nix"),
+            PackageOverride::overlay(config.package_overrides()),
+        )?;
+
         // Run the shell
         Self::nix_shell_run(
             &config,
             &format!(
-                "{} --root {}",
+                "{} --root {}{}{}",
                 current_exe()?.display(),
-                config.root().display()
+                config.root().display(),
+                if *config.resume() { " --continue" } else { "" },
+                if *config.dry_run() { " --dry-run" } else { "" },
             ),
         )
     }
 
     /// Spawn a new interactive nix shell
-    fn spawn_shell(&self) -> Fallible<()> {
+    fn spawn_shell(&mut self) -> Fallible<()> {
         info!("Spawning interactive shell");
         info!("Please be aware that the cluster gets destroyed if you exit the shell");
         let env_file = self.config.root().join(KUBERNIX_ENV);
-        fs::write(
-            &env_file,
-            format!(
-                "PS1='> '\nexport {}={}\nexport {}={}",
-                RUNTIME_ENV,
-                format!("unix://{}", self.crio_socket.display()),
-                KUBECONFIG_ENV,
-                self.kubeconfig.admin().display(),
-            ),
-        )?;
+        let exports: Vec<String> = Self::cluster_env(&self.crio_socket, &self.kubeconfig)
+            .iter()
+            .map(|(k, v)| format!("export {}={}", k, v))
+            .collect();
+        fs::write(&env_file, format!("PS1='> '\n{}", exports.join("\n")))?;
 
-        Command::new("bash")
+        let mut shell = Command::new("bash")
             .current_dir(self.config.root())
             .arg("--init-file")
             .arg(env_file)
-            .status()?;
+            .spawn()?;
+
+        // Supervise the cluster until the shell exits or its lifetime ends
+        while shell.try_wait()?.is_none() {
+            if let Err(e) = Signals::check() {
+                shell.kill()?;
+                shell.wait()?;
+                return Err(e);
+            }
+            if self.ttl.expired() || self.stop_requested {
+                shell.kill()?;
+                shell.wait()?;
+                return Ok(());
+            }
+            self.supervise()?;
+            sleep(Duration::from_secs(1));
+        }
+        Ok(())
+    }
+
+    /// Keep the cluster running without a shell, probe the components
+    /// periodically and restart the unhealthy ones until a signal arrives or
+    /// the lifetime of the cluster ends
+    fn soak(&mut self) -> Fallible<()> {
+        info!(
+            "Running in soak mode, checking components every {:?}",
+            Soak::INTERVAL
+        );
+        info!(
+            "Health history is written to '{}'",
+            Soak::file(&self.config).display()
+        );
+        let mut last_check = Instant::now();
+        loop {
+            Signals::check()?;
+            if self.ttl.expired() || self.stop_requested {
+                return Ok(());
+            }
+            self.supervise()?;
+            if last_check.elapsed() >= Soak::INTERVAL {
+                self.check_health()?;
+                last_check = Instant::now();
+            }
+            sleep(Duration::from_secs(1));
+        }
+    }
+
+    /// Probe all components, restart the unhealthy ones and record the
+    /// result in the health history
+    fn check_health(&mut self) -> Fallible<()> {
+        let status = self.component_health()?;
+        let mut restarted = vec![];
+        for (name, health) in &status {
+            if *health == Health::Ready {
+                continue;
+            }
+            match name.parse::<ComponentKind>() {
+                Ok(kind) => {
+                    warn!("Component '{}' is {}, restarting", name, health);
+                    match self.restart_component(kind) {
+                        Ok(()) => restarted.push(name.clone()),
+                        Err(e) => error!("Unable to restart {}: {}", name, e),
+                    }
+                }
+                Err(_) => warn!("Component '{}' is {}", name, health),
+            }
+        }
+        Soak::record(&self.config, &status, &restarted)
+    }
+
+    /// Run the periodic management tasks of the running cluster
+    fn supervise(&mut self) -> Fallible<()> {
+        self.handle_upgrade_request()?;
+        self.handle_control_requests()?;
+        self.activate()?;
+        if *self.config.kubelet_serving_rotation()
+            && self.csr_approval.elapsed() >= CSR_APPROVAL_INTERVAL
+        {
+            if let Err(e) = CsrApprover::approve(&self.kubeconfig) {
+                error!("Unable to approve certificate requests: {}", e);
+            }
+            self.csr_approval = Instant::now();
+        }
+        if *self.config.dns_watchdog() && self.dns_check.elapsed() >= DnsWatchdog::INTERVAL {
+            if let Err(e) = DnsWatchdog::check(&self.config, &self.network, &self.kubeconfig) {
+                error!("Unable to restart cluster DNS: {}", e);
+            }
+            self.dns_check = Instant::now();
+        }
+        if CloudControllerManager::enabled(&self.config)
+            && self.node_init.elapsed() >= CloudControllerManager::INTERVAL
+        {
+            if let Err(e) = CloudControllerManager::initialize(&self.kubeconfig) {
+                error!("Unable to initialize nodes: {}", e);
+            }
+            self.node_init = Instant::now();
+        }
+        if self.liveness.due() {
+            self.check_liveness();
+        }
+        let rebuilt = match self.binary_watch.as_mut().map(BinaryWatch::poll) {
+            Some(Ok(x)) => x,
+            Some(Err(e)) => {
+                error!("Unable to watch local binaries: {}", e);
+                vec![]
+            }
+            None => vec![],
+        };
+        for kind in rebuilt {
+            self.restart_rebuilt(kind);
+        }
+        if let Some(ip) = self.ip_watch.poll() {
+            if ip == self.ip {
+                info!("Host IP changed back to {}", ip);
+            } else if *self.config.auto_reconfigure_network() {
+                if let Err(e) = self.reconfigure_network(&ip) {
+                    error!("Unable to reconfigure the cluster for IP {}: {}", ip, e);
+                }
+            } else {
+                warn!(
+                    "Host IP changed from {} to {}, restart the cluster or use \
+                     --auto-reconfigure-network to apply it",
+                    self.ip, ip
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Probe the health endpoints of all running components and restart the
+    /// unhealthy ones if requested
+    fn check_liveness(&mut self) {
+        let kinds: Vec<ComponentKind> = self.processes.iter().map(|(x, _)| *x).collect();
+        for kind in self.liveness.check(&self.config, &kinds) {
+            if !*self.config.liveness_restart() {
+                continue;
+            }
+            match self.restart_component(kind) {
+                Ok(()) => self.liveness.reset(kind),
+                Err(e) => error!("Unable to restart unhealthy {}: {}", kind, e),
+            }
+        }
+    }
+
+    /// Restart a component after its local binary got rebuilt and report the
+    /// resulting downtime
+    fn restart_rebuilt(&mut self, kind: ComponentKind) {
+        info!("Binary of {} got rebuilt, restarting", kind);
+        let start = Instant::now();
+        if let Err(e) = self.stop_component(kind) {
+            error!("Unable to stop {}: {}", kind, e);
+            return;
+        }
+        let stopped = start.elapsed();
+        if let Err(e) = self.start_stopped_component(kind) {
+            error!("Unable to start {} with rebuilt binary: {}", kind, e);
+            return;
+        }
+        self.liveness.reset(kind);
+        info!(
+            "Component {} is ready again after {:.1}s of downtime \
+             (stopping {:.1}s, starting {:.1}s)",
+            kind,
+            start.elapsed().as_secs_f64(),
+            stopped.as_secs_f64(),
+            (start.elapsed() - stopped).as_secs_f64(),
+        );
+    }
+
+    /// Regenerate the certificates and kubeconfigs containing the IP of the
+    /// host and restart the components using it
+    fn reconfigure_network(&mut self, ip: &str) -> Fallible<()> {
+        info!("Host IP changed from {} to {}, reconfiguring", self.ip, ip);
+        let hostname = System::new().hostname()?;
+        self.pki
+            .refresh(&self.config, &self.network, ip, &hostname)
+            .map_err(|e| Error::Pki(e.to_string()))?;
+        self.kubeconfig
+            .refresh(&self.config, &self.pki, ip, &hostname)?;
+        Secrets::seal(&self.config)?;
+
+        let previous = replace(&mut self.ip, ip.into());
+        let kinds: Vec<ComponentKind> = IpWatch::AFFECTED
+            .iter()
+            .filter(|x| x.enabled(&self.config))
+            .cloned()
+            .collect();
+        for kind in kinds {
+            self.restart_component(kind)?;
+        }
+        info!("Network reconfigured");
+        EventLog::record(
+            self.config.root(),
+            EventKind::ConfigChanged,
+            None,
+            &format!("Host IP changed from {} to {}", previous, ip),
+        );
+        Ok(())
+    }
+
+    /// Run a pending upgrade request, if any
+    fn handle_upgrade_request(&mut self) -> Fallible<()> {
+        if let Some(request) = UpgradeRequest::pending(&self.config)? {
+            let result = match request {
+                UpgradeRequest::Reconfigure => self.reconfigure(),
+                request => self.upgrade_cluster(&request),
+            };
+            if let Err(e) = &result {
+                error!("Unable to upgrade cluster: {}", e);
+            }
+            UpgradeRequest::finish(&self.config, &result)?;
+        }
         Ok(())
     }
 
-    /// Run a pure nix shell command
+    /// Answer all pending requests of the control API
+    fn handle_control_requests(&mut self) -> Fallible<()> {
+        loop {
+            let request = match self.control.as_ref().map(Control::accept) {
+                Some(Ok(Some(x))) => x,
+                Some(Err(e)) => {
+                    debug!("Unable to accept control request: {}", e);
+                    return Ok(());
+                }
+                _ => return Ok(()),
+            };
+            if let Err(e) = self.handle_control_request(request) {
+                debug!("Unable to answer control request: {}", e);
+            }
+        }
+    }
+
+    /// Answer a single request of the control API
+    fn handle_control_request(&mut self, request: ControlRequest) -> Fallible<()> {
+        match request.route().clone() {
+            Route::Status => {
+                let uptime = self.control.as_ref().map_or(0, |x| x.uptime().as_secs());
+                request.respond(
+                    200,
+                    &json!({
+                        "root": self.config.root(),
+                        "pid": std::process::id(),
+                        "uptime": uptime,
+                        "components": self.components_json()?,
+                        "dns": DnsWatchdog::health(&self.config, &self.network).to_string(),
+                        "cni": self.config.cni().to_string(),
+                        "network-policy": NetworkPolicy::mode(&self.config),
+                    }),
+                )
+            }
+            Route::Components => request.respond(200, &self.components_json()?),
+            Route::Restart(name) => match self.restart_by_name(&name) {
+                Ok(()) => {
+                    let info = RunInfo::load(&self.config.root().join(&name)).ok();
+                    request.respond(
+                        200,
+                        &json!({
+                            "restarted": name,
+                            "pid": info.as_ref().and_then(|x| *x.pid()),
+                            "started": info.as_ref().and_then(|x| *x.started()),
+                        }),
+                    )
+                }
+                Err(e) => request.fail(400, &e.to_string()),
+            },
+            Route::StopComponent(name) => match self.stop_by_name(&name) {
+                Ok(()) => request.respond(200, &json!({ "stopped": name })),
+                Err(e) => request.fail(400, &e.to_string()),
+            },
+            Route::Logs(name, follow, tail) => {
+                let log_name = match name.parse::<ComponentKind>() {
+                    Ok(kind) => kind.command().to_owned(),
+                    Err(_) if self.registry.get(&name).is_some() => name,
+                    Err(e) => return request.fail(404, &e.to_string()),
+                };
+                let log_file = self
+                    .config
+                    .root()
+                    .join("log")
+                    .join(format!("{}.log", log_name));
+                request.stream_log(&log_file, follow, tail)
+            }
+            Route::Stop => {
+                info!("Stop requested via control API");
+                self.stop_requested = true;
+                request.respond(200, &json!({ "stopping": true }))
+            }
+            Route::NotFound => request.fail(404, "Unknown endpoint"),
+        }
+    }
+
+    /// Retrieve the health of all components including their liveness
+    fn component_health(&self) -> Fallible<Vec<(String, Health)>> {
+        Ok(self
+            .registry
+            .status(&self.context())?
+            .into_iter()
+            .map(|(name, health)| {
+                let health = self.liveness.health(&name, health);
+                (name, health)
+            })
+            .collect())
+    }
+
+    /// Retrieve the health and resource usage of all components as JSON
+    fn components_json(&self) -> Fallible<Value> {
+        Ok(self
+            .component_health()?
+            .into_iter()
+            .map(|(name, health)| {
+                let dir = self.config.root().join(&name);
+                let pid = Readiness::pid(&dir);
+                let info = RunInfo::load(&dir).ok();
+                json!({
+                    "name": name,
+                    "health": health.to_string(),
+                    "pid": pid,
+                    "started": info.as_ref().and_then(|x| *x.started()),
+                    "ports": info.as_ref().map(|x| x.ports().clone()).unwrap_or_default(),
+                    "usage": pid.map_or(Value::Null, Control::usage),
+                })
+            })
+            .collect())
+    }
+
+    /// Stop a built-in or additionally registered component by its name,
+    /// which can be started again by restarting it
+    fn stop_by_name(&mut self, name: &str) -> Fallible<()> {
+        info!("Stopping {}", name);
+        if let Ok(kind) = name.parse::<ComponentKind>() {
+            return self.stop_component(kind);
+        }
+        match self.additional.iter_mut().find(|(x, _)| x == name) {
+            Some((_, x)) => x.stop(),
+            None => bail!("Component '{}' is not running", name),
+        }
+    }
+
+    /// Restart a built-in or additionally registered component by its name
+    fn restart_by_name(&mut self, name: &str) -> Fallible<()> {
+        if let Ok(kind) = name.parse::<ComponentKind>() {
+            return self.restart_component(kind);
+        }
+        let index = self
+            .additional
+            .iter()
+            .position(|(x, _)| x == name)
+            .ok_or_else(|| format_err!("Component '{}' is not running", name))?;
+        let component = self
+            .registry
+            .get(name)
+            .ok_or_else(|| format_err!("Component '{}' not registered", name))?;
+        info!("Restarting {}", name);
+        self.additional[index].1.stop()?;
+        self.additional[index].1 = self.registry.start(component, &self.context())?;
+        Ok(())
+    }
+
+    /// Retrieve the environment variables to access the cluster
+    fn cluster_env(crio_socket: &Path, kubeconfig: &KubeConfig) -> Vec<(&'static str, String)> {
+        vec![
+            (RUNTIME_ENV, format!("unix://{}", crio_socket.display())),
+            (KUBECONFIG_ENV, kubeconfig.admin().display().to_string()),
+        ]
+    }
+
+    /// Run a pure nix shell command, whereas its exit code gets propagated
     fn nix_shell_run(config: &Config, arg: &str) -> Fallible<()> {
+        Self::nix_shell_run_in(config, &config.root().join(NIX_DIR), arg)
+    }
+
+    /// Run the provided function inside a nix shell of the provided
+    /// expression. The current command gets executed again within the shell
+    /// if it is not already running inside of it.
+    fn run_in_nix_shell<F>(config: &Config, shell: &Path, f: F) -> Fallible<()>
+    where
+        F: FnOnce() -> Fallible<()>,
+    {
+        if var(NIX_SHELL_ENV).is_ok() {
+            return f();
+        }
+        debug!("Nix environment not found, running inside nix shell");
+
+        // The root is passed via the environment, since it could originate
+        // from there and the pure shell does not keep it
+        let mut command = vec![current_exe()?.display().to_string()];
+        command.extend(env::args().skip(1));
+        Self::nix_shell_run_in(
+            config,
+            shell,
+            &format!(
+                "{}={} {}",
+                ROOT_ENV,
+                shell_words::quote(&config.root().display().to_string()),
+                shell_words::join(&command),
+            ),
+        )
+    }
+
+    /// Run a pure nix shell command within the provided shell expression,
+    /// whereas its exit code gets propagated
+    fn nix_shell_run_in(config: &Config, shell: &Path, arg: &str) -> Fallible<()> {
+        let status = Self::nix_shell_status_in(config, shell, arg)?;
+        match status.code() {
+            Some(0) => Ok(()),
+            Some(code) => Err(Error::Shell(code)),
+            None => Err(Error::Nix(format!("nix-shell terminated by {}", status))),
+        }
+    }
+
+    /// Run a pure nix shell command and return its exit status
+    fn nix_shell_status(config: &Config, arg: &str) -> Fallible<ExitStatus> {
+        Self::nix_shell_status_in(config, &config.root().join(NIX_DIR), arg)
+    }
+
+    /// Run a pure nix shell command within the provided shell expression and
+    /// return its exit status
+    fn nix_shell_status_in(config: &Config, shell: &Path, arg: &str) -> Fallible<ExitStatus> {
         let purity = if !*config.impure() {
             debug!("Runnig pure nix-shell");
             "--pure"
@@ -323,15 +2016,28 @@ impl Kubernix {
             LevelFilter::Info => "-Q", // just no build output
             _ => "--quiet",
         };
-        Command::new(Self::find_executable("nix-shell")?)
-            .arg(config.root().join(NIX_DIR))
+        let nix_shell =
+            Self::find_executable("nix-shell").map_err(|e| Error::Nix(e.to_string()))?;
+        let mut cmd = Command::new(nix_shell);
+        if *config.kubeconfig_merge() {
+            // Required to find the kubeconfig of the user invoking sudo
+            cmd.arg("--keep").arg(KubeConfigMerge::SUDO_USER_ENV);
+        }
+        if !config.package_overrides().is_empty() {
+            // Package overrides are resolved via flakes
+            cmd.arg("--option")
+                .arg("extra-experimental-features")
+                .arg("flakes");
+        }
+        Ok(cmd
+            .arg(shell)
             .arg(purity)
             .arg(verbosity)
             .arg(format!("-j{}", num_cpus::get()))
             .arg("--run")
             .arg(arg)
-            .status()?;
-        Ok(())
+            .envs(HttpProxy::env(config, &[]))
+            .status()?)
     }
 
     /// Find an executable inside the current $PATH environment
@@ -388,8 +2094,38 @@ impl Kubernix {
 
 impl Drop for Kubernix {
     fn drop(&mut self) {
+        let env = Self::cluster_env(&self.crio_socket, &self.kubeconfig);
+        if let Err(e) = Hook::run_all(&self.config, HookPoint::PreDown, &env) {
+            error!("{}", e)
+        }
+
         info!("Cleaning up");
-        self.stop();
-        self.umount();
+        info_span!("teardown").in_scope(|| {
+            self.stop();
+            self.umount();
+            if let Err(e) = LoadBalancer::cleanup(&self.config) {
+                debug!("{}", e)
+            }
+            if let Err(e) = Firewall::cleanup(&self.config, &self.network) {
+                error!("Unable to remove firewall rules: {}", e)
+            }
+            if let Err(e) = Budget::cleanup(&self.config) {
+                debug!("{}", e)
+            }
+            if let Err(e) = KubeConfigMerge::unmerge(&self.config) {
+                error!("Unable to remove kubeconfig context: {}", e)
+            }
+        });
+
+        if let Err(e) = Hook::run_all(&self.config, HookPoint::PostDown, &env) {
+            error!("{}", e)
+        }
+        EventLog::record(
+            self.config.root(),
+            EventKind::ClusterStopped,
+            None,
+            "Cluster destroyed",
+        );
+        Telemetry::flush();
     }
 }
@@ -0,0 +1,253 @@
+use crate::{
+    component::{ComponentKind, Health},
+    events::{EventKind, EventLog},
+    pki::Pki,
+    process::Process,
+    readiness::Readiness,
+    Config, Fallible,
+};
+use log::{debug, info, warn};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// The periodic health polling of all running components after their
+/// readiness, which detects hung processes
+pub struct Liveness {
+    interval: Duration,
+    threshold: u32,
+    last_check: Instant,
+    failures: HashMap<ComponentKind, u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The change of the liveness of a component caused by a single probe
+pub enum Transition {
+    /// The component is healthy and was before
+    Healthy,
+
+    /// The probe failed, but the threshold is not reached yet
+    Failing(u32),
+
+    /// The threshold of consecutive failures got reached by this probe
+    Unhealthy,
+
+    /// The component is still unhealthy
+    StillUnhealthy,
+
+    /// The probe succeeded after the component was unhealthy
+    Recovered,
+}
+
+impl Liveness {
+    /// The maximum time a single probe may take
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub fn new(config: &Config) -> Self {
+        Self {
+            interval: Duration::from_secs(*config.liveness_interval()),
+            threshold: (*config.liveness_threshold()).max(1),
+            last_check: Instant::now(),
+            failures: HashMap::new(),
+        }
+    }
+
+    /// Check if the next probes are due
+    pub fn due(&self) -> bool {
+        self.interval != Duration::from_secs(0) && self.last_check.elapsed() >= self.interval
+    }
+
+    /// Probe the provided ready components and return the ones which became
+    /// unhealthy
+    pub fn check(&mut self, config: &Config, kinds: &[ComponentKind]) -> Vec<ComponentKind> {
+        let mut unhealthy = vec![];
+        for &kind in kinds {
+            let (url, verify) = match Self::endpoint(config, kind) {
+                Some(x) => x,
+                None => continue,
+            };
+            // Crashed processes get handled by their restart policy
+            if !Readiness::is_ready(config, kind) {
+                self.failures.remove(&kind);
+                continue;
+            }
+            let result = Self::probe(config, &url, verify);
+            if let Err(e) = &result {
+                debug!("Liveness probe of {} failed: {}", kind, e);
+            }
+            let name = kind.to_string();
+            match self.record(kind, result.is_ok()) {
+                Transition::Unhealthy => {
+                    warn!(
+                        "Component '{}' is unhealthy after {} failed probes",
+                        kind, self.threshold
+                    );
+                    EventLog::record(
+                        config.root(),
+                        EventKind::Unhealthy,
+                        Some(&name),
+                        &format!("{} failed probes of {}", self.threshold, url),
+                    );
+                    unhealthy.push(kind);
+                }
+                Transition::Recovered => {
+                    info!("Component '{}' is healthy again", kind);
+                    EventLog::record(config.root(), EventKind::Recovered, Some(&name), &url);
+                }
+                Transition::Failing(x) => debug!("Component '{}' failed {} probes", kind, x),
+                Transition::Healthy | Transition::StillUnhealthy => {}
+            }
+        }
+        self.last_check = Instant::now();
+        unhealthy
+    }
+
+    /// Overlay the liveness onto the provided health of a component
+    pub fn health(&self, name: &str, health: Health) -> Health {
+        let unhealthy = name
+            .parse::<ComponentKind>()
+            .ok()
+            .and_then(|x| self.failures.get(&x))
+            .map(|x| *x >= self.threshold)
+            .unwrap_or(false);
+        if health == Health::Ready && unhealthy {
+            Health::Unhealthy
+        } else {
+            health
+        }
+    }
+
+    /// Forget the failures of a component, for example after restarting it
+    pub fn reset(&mut self, kind: ComponentKind) {
+        self.failures.remove(&kind);
+    }
+
+    /// Record the result of a single probe
+    fn record(&mut self, kind: ComponentKind, success: bool) -> Transition {
+        let previous = self.failures.get(&kind).cloned().unwrap_or_default();
+        if success {
+            self.failures.remove(&kind);
+            return if previous >= self.threshold {
+                Transition::Recovered
+            } else {
+                Transition::Healthy
+            };
+        }
+        let failures = previous + 1;
+        self.failures.insert(kind, failures);
+        if failures == self.threshold {
+            Transition::Unhealthy
+        } else if failures > self.threshold {
+            Transition::StillUnhealthy
+        } else {
+            Transition::Failing(failures)
+        }
+    }
+
+    /// The health endpoint of a component and whether its serving
+    /// certificate is signed by the cluster CA
+    fn endpoint(config: &Config, kind: ComponentKind) -> Option<(String, bool)> {
+        let ports = config.ports();
+        match kind {
+            // The container runtime serves no health endpoint
+            ComponentKind::Crio => None,
+            ComponentKind::Etcd => Some((
+                format!("https://127.0.0.1:{}/health", ports.etcd_client()),
+                true,
+            )),
+            ComponentKind::ApiServer => Some((
+                format!("https://127.0.0.1:{}/healthz", ports.apiserver()),
+                true,
+            )),
+            ComponentKind::ControllerManager => Some((
+                format!("https://127.0.0.1:{}/healthz", ports.controller_manager()),
+                false,
+            )),
+            ComponentKind::Scheduler => Some((
+                format!("https://127.0.0.1:{}/healthz", ports.scheduler()),
+                false,
+            )),
+            ComponentKind::Kubelet => Some((
+                format!("http://127.0.0.1:{}/healthz", ports.kubelet_healthz()),
+                false,
+            )),
+            ComponentKind::Proxy => Some((
+                format!("http://127.0.0.1:{}/healthz", ports.proxy_healthz()),
+                false,
+            )),
+        }
+    }
+
+    /// Request the provided URL with the admin credentials
+    fn probe(config: &Config, url: &str, verify: bool) -> Fallible<()> {
+        let dir = config.root().join(Pki::DIR);
+        let output = Process::run_to_completion("curl")
+            .arg("--silent")
+            .arg("--show-error")
+            .arg("--fail")
+            .arg("--output=/dev/null")
+            .arg(format!("--max-time={}", Self::TIMEOUT.as_secs()))
+            .arg(if verify {
+                format!("--cacert={}", dir.join("ca.pem").display())
+            } else {
+                "--insecure".into()
+            })
+            .arg(format!("--cert={}", dir.join("admin.pem").display()))
+            .arg(format!("--key={}", dir.join("admin-key.pem").display()))
+            .arg(url)
+            .output()?;
+        if !output.success() {
+            bail!("curl command failed: {}", output.stderr().trim())
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn liveness(threshold: u32) -> Liveness {
+        Liveness {
+            interval: Duration::from_secs(10),
+            threshold,
+            last_check: Instant::now(),
+            failures: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn record_success() {
+        let mut l = liveness(2);
+        let kind = ComponentKind::ApiServer;
+        assert_eq!(l.record(kind, true), Transition::Healthy);
+        assert_eq!(l.record(kind, false), Transition::Failing(1));
+        assert_eq!(l.health("apiserver", Health::Ready), Health::Ready);
+        assert_eq!(l.record(kind, false), Transition::Unhealthy);
+        assert_eq!(l.record(kind, false), Transition::StillUnhealthy);
+        assert_eq!(l.health("apiserver", Health::Ready), Health::Unhealthy);
+        assert_eq!(l.health("apiserver", Health::NotReady), Health::NotReady);
+        assert_eq!(l.health("etcd", Health::Ready), Health::Ready);
+        assert_eq!(l.record(kind, true), Transition::Recovered);
+        assert_eq!(l.health("apiserver", Health::Ready), Health::Ready);
+    }
+
+    #[test]
+    fn reset_success() {
+        let mut l = liveness(1);
+        let kind = ComponentKind::Scheduler;
+        assert_eq!(l.record(kind, false), Transition::Unhealthy);
+        l.reset(kind);
+        assert_eq!(l.health("scheduler", Health::Ready), Health::Ready);
+        assert_eq!(l.record(kind, true), Transition::Healthy);
+    }
+
+    #[test]
+    fn due_success() {
+        let mut l = liveness(1);
+        assert!(!l.due());
+        l.interval = Duration::from_secs(0);
+        assert!(!l.due());
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{
+    cni::Cni, config::Config, kubeconfig::KubeConfig, network::Network, process::Process, Fallible,
+};
+use ipnetwork::Ipv4Network;
+use log::info;
+use std::{
+    fs::{self, create_dir_all},
+    process::Command,
+};
+
+pub struct LoadBalancer;
+
+impl LoadBalancer {
+    /// Deploy the layer 2 announcement controller for the configured address
+    /// pool and route the pool to the cluster bridge
+    pub fn apply(config: &Config, network: &Network, kubeconfig: &KubeConfig) -> Fallible<()> {
+        let pool = match config.lb_pool() {
+            Some(x) => *x,
+            None => return Ok(()),
+        };
+        if *config.cni() != Cni::Bridge {
+            bail!(
+                "The LoadBalancer address pool requires the bridge network plugin, not {}",
+                config.cni()
+            )
+        }
+        Self::validate(pool, network)?;
+        info!("Deploying LoadBalancer address pool {}", pool);
+
+        let dir = config.root().join("loadbalancer");
+        create_dir_all(&dir)?;
+
+        let yml = format!(include_str!("assets/metallb.yml"), pool);
+        let yml_file = dir.join("metallb.yml");
+        fs::write(&yml_file, yml)?;
+
+        Process::run_to_completion("kubectl")
+            .arg("apply")
+            .arg(format!("--kubeconfig={}", kubeconfig.admin().display()))
+            .arg("-f")
+            .arg(yml_file)
+            .retries(3)
+            .run()?;
+
+        // The bridge gets created by the first pod, but the route requires
+        // it earlier
+        if Self::ip(&["link", "show", Network::BRIDGE]).is_err() {
+            Self::ip(&["link", "add", Network::BRIDGE, "type", "bridge"])?;
+        }
+        Self::ip(&["link", "set", Network::BRIDGE, "up"])?;
+        Self::ip(&[
+            "route",
+            "replace",
+            &pool.to_string(),
+            "dev",
+            Network::BRIDGE,
+        ])?;
+
+        info!("LoadBalancer address pool deployed");
+        Ok(())
+    }
+
+    /// Remove the route of the address pool
+    pub fn cleanup(config: &Config) -> Fallible<()> {
+        if let Some(pool) = config.lb_pool() {
+            Self::ip(&["route", "del", &pool.to_string(), "dev", Network::BRIDGE])?;
+        }
+        Ok(())
+    }
+
+    /// Verify that the pool does not overlap with the cluster networks
+    fn validate(pool: Ipv4Network, network: &Network) -> Fallible<()> {
+        for (name, cidr) in &[
+            ("pod", network.crio()),
+            ("cluster", network.cluster()),
+            ("service", network.service()),
+        ] {
+            if pool.overlaps(**cidr) {
+                bail!(
+                    "LoadBalancer address pool {} overlaps with the {} IP network {}",
+                    pool,
+                    name,
+                    cidr
+                )
+            }
+        }
+        Ok(())
+    }
+
+    fn ip(args: &[&str]) -> Fallible<()> {
+        let output = Command::new("ip").args(args).output()?;
+        if !output.status.success() {
+            bail!(
+                "Unable to run `ip {}`: {}",
+                args.join(" "),
+                String::from_utf8(output.stderr)?.trim()
+            )
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::tests::test_network;
+
+    #[test]
+    fn validate_success() -> Fallible<()> {
+        let n = test_network()?;
+        assert!(LoadBalancer::validate("10.10.0.0/24".parse()?, &n).is_err());
+        assert!(LoadBalancer::validate("10.20.0.0/24".parse()?, &n).is_ok());
+        Ok(())
+    }
+}
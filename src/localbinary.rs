@@ -0,0 +1,137 @@
+use crate::{component::ComponentKind, Config, Error, Fallible};
+use getset::Getters;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    env::current_dir,
+    fmt::{self, Display},
+    fs::metadata,
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    str::FromStr,
+};
+
+#[derive(Clone, Debug, Deserialize, Eq, Getters, PartialEq, Serialize)]
+/// A locally built binary which replaces the packaged one of a single
+/// component
+pub struct LocalBinary {
+    #[get = "pub"]
+    /// The component running the binary
+    component: ComponentKind,
+
+    #[get = "pub"]
+    /// The absolute path of the binary
+    path: PathBuf,
+}
+
+impl Display for LocalBinary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.component, self.path.display())
+    }
+}
+
+impl FromStr for LocalBinary {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let mut split = s.splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some(component), Some(path)) if !path.is_empty() => Ok(LocalBinary {
+                component: component.parse()?,
+                // Processes run within their own directory
+                path: current_dir()?.join(path),
+            }),
+            _ => Err(format_err!(
+                "Invalid local binary '{}', expected 'COMPONENT=PATH'",
+                s
+            )),
+        }
+    }
+}
+
+impl LocalBinary {
+    /// Verify that all local binaries are executable files
+    pub fn preflight(config: &Config) -> Fallible<()> {
+        if *config.watch_binaries() && config.local_binaries().is_empty() {
+            bail!("Watching binaries requires at least one local binary")
+        }
+        for x in config.local_binaries() {
+            let mode = metadata(&x.path)
+                .map_err(|e| format_err!("Local binary of {} not usable: {}", x.component, e))?
+                .permissions()
+                .mode();
+            if !x.path.is_file() || mode & 0o111 == 0 {
+                bail!(
+                    "Local binary '{}' of {} is no executable file",
+                    x.path.display(),
+                    x.component
+                )
+            }
+            info!(
+                "Using local binary '{}' for {}",
+                x.path.display(),
+                x.component
+            );
+        }
+        Ok(())
+    }
+
+    /// Retrieve the command to be executed, which is the local binary if the
+    /// command belongs to a component having one
+    pub fn resolve(config: &Config, command: &str) -> String {
+        Self::find(config.local_binaries(), command)
+            .unwrap_or(command)
+            .to_owned()
+    }
+
+    fn find<'a>(binaries: &'a [LocalBinary], command: &str) -> Option<&'a str> {
+        let kind = ComponentKind::from_command(command)?;
+        binaries
+            .iter()
+            .rev()
+            .find(|x| x.component == kind)
+            .and_then(|x| x.path.to_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        let b: LocalBinary = "apiserver=/go/bin/kube-apiserver".parse()?;
+        assert_eq!(b.component, ComponentKind::ApiServer);
+        assert_eq!(b.path, PathBuf::from("/go/bin/kube-apiserver"));
+        assert_eq!(b.to_string(), "apiserver=/go/bin/kube-apiserver");
+
+        let b: LocalBinary = "kubelet=_output/bin/kubelet".parse()?;
+        assert!(b.path.is_absolute());
+        assert!(b.path.ends_with("_output/bin/kubelet"));
+        Ok(())
+    }
+
+    #[test]
+    fn from_str_failure() {
+        assert!("apiserver".parse::<LocalBinary>().is_err());
+        assert!("apiserver=".parse::<LocalBinary>().is_err());
+        assert!("invalid=/go/bin/kube-apiserver"
+            .parse::<LocalBinary>()
+            .is_err());
+    }
+
+    #[test]
+    fn find_success() -> Fallible<()> {
+        let binaries = vec![
+            "scheduler=/a/kube-scheduler".parse()?,
+            "scheduler=/b/kube-scheduler".parse()?,
+        ];
+        assert_eq!(
+            LocalBinary::find(&binaries, "kube-scheduler"),
+            Some("/b/kube-scheduler")
+        );
+        assert_eq!(LocalBinary::find(&binaries, "kube-apiserver"), None);
+        assert_eq!(LocalBinary::find(&binaries, "my-controller"), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{Config, Error, Fallible};
+use log::debug;
+use nix::{
+    errno::Errno,
+    fcntl::{flock, FlockArg},
+    unistd::{getpid, getppid},
+    Error::Sys,
+};
+use psutil::process::Process;
+use std::{
+    fs::{read_to_string, File, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::io::AsRawFd,
+    path::Path,
+};
+
+/// An exclusive lock on the root directory, which gets released on drop
+pub struct RootLock {
+    _file: File,
+}
+
+impl RootLock {
+    /// The file name of the lock within the root directory
+    pub const FILENAME: &'static str = "kubernix.lock";
+
+    /// Acquire the lock for the root of the provided configuration
+    pub fn acquire(config: &Config) -> Fallible<RootLock> {
+        let path = config.root().join(Self::FILENAME);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => {
+                file.set_len(0)?;
+                write!(file, "{}", getpid())?;
+                debug!("Acquired lock '{}'", path.display());
+                Ok(RootLock { _file: file })
+            }
+            Err(Sys(Errno::EAGAIN)) => {
+                let owner = read_to_string(&path)?.trim().parse::<i32>().ok();
+                match owner {
+                    // The lock is held by the kubernix which spawned the nix
+                    // environment we are running in
+                    Some(pid) if Self::is_ancestor(pid) => {
+                        debug!("Lock '{}' held by parent PID {}", path.display(), pid);
+                        Ok(RootLock { _file: file })
+                    }
+                    _ => Err(Error::Preflight(format!(
+                        "kubernix is already running in '{}' (PID {}), please start another \
+                         cluster via --name or stop the running one via `kubernix stop`",
+                        config.root().display(),
+                        owner.map_or_else(|| "unknown".into(), |x| x.to_string()),
+                    ))),
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Check if the lock of the provided root is currently held by any
+    /// kubernix instance
+    pub fn is_held(root: &Path) -> Fallible<bool> {
+        let file = match File::open(root.join(Self::FILENAME)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        match flock(file.as_raw_fd(), FlockArg::LockSharedNonblock) {
+            // The shared lock gets released again by closing the file
+            Ok(()) => Ok(false),
+            Err(Sys(Errno::EAGAIN)) => Ok(true),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Check if the provided PID is an ancestor of the current process
+    fn is_ancestor(pid: i32) -> bool {
+        let mut current = getppid().as_raw();
+        while current > 1 {
+            if current == pid {
+                return true;
+            }
+            current = match Process::new(current) {
+                Ok(p) => p.ppid,
+                Err(_) => return false,
+            };
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+
+    #[test]
+    fn acquire_success() -> Fallible<()> {
+        let c = test_config()?;
+        let lock = RootLock::acquire(&c)?;
+        drop(lock);
+        RootLock::acquire(&c)?;
+        Ok(())
+    }
+
+    #[test]
+    fn acquire_failure_locked() -> Fallible<()> {
+        let c = test_config()?;
+        let _lock = RootLock::acquire(&c)?;
+        assert!(RootLock::acquire(&c).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn is_held_success() -> Fallible<()> {
+        let c = test_config()?;
+        assert!(!RootLock::is_held(c.root())?);
+        let lock = RootLock::acquire(&c)?;
+        assert!(RootLock::is_held(c.root())?);
+        drop(lock);
+        assert!(!RootLock::is_held(c.root())?);
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{Config, Error, Fallible};
+use env_logger::{
+    filter::{self, Filter},
+    Builder, WriteStyle,
+};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use std::{
+    env::var_os,
+    fmt::{self, Display},
+    os::unix::net::UnixDatagram,
+    path::Path,
+    process,
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The color behavior of the console output
+pub enum LogColor {
+    /// Colorize if the output is a terminal
+    Auto,
+
+    /// Always colorize
+    Always,
+
+    /// Never colorize
+    Never,
+}
+
+impl FromStr for LogColor {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "auto" => Ok(LogColor::Auto),
+            "always" => Ok(LogColor::Always),
+            "never" => Ok(LogColor::Never),
+            _ => Err(format_err!("Unknown color behavior '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+/// A log level override for a single module
+pub struct ModuleLevel {
+    module: String,
+    level: LevelFilter,
+}
+
+impl Display for ModuleLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.module, self.level)
+    }
+}
+
+impl FromStr for ModuleLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let mut split = s.splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some(module), Some(level)) if !module.is_empty() => Ok(ModuleLevel {
+                module: module.into(),
+                level: level
+                    .parse()
+                    .map_err(|_| format_err!("Invalid log level '{}'", level))?,
+            }),
+            _ => Err(format_err!(
+                "Invalid module log level '{}', expected '<module>=<level>'",
+                s
+            )),
+        }
+    }
+}
+
+/// The logging subsystem of kubernix
+pub struct Logger;
+
+impl Logger {
+    /// The native protocol socket of the systemd journal
+    const JOURNALD_SOCKET: &'static str = "/run/systemd/journal/socket";
+
+    /// Initialize the global logger for the provided configuration
+    pub fn init(config: &Config) -> Fallible<()> {
+        if *config.journald() {
+            return Journald::init(config);
+        }
+
+        let style = match config.log_color() {
+            LogColor::Auto => WriteStyle::Auto,
+            LogColor::Always => WriteStyle::Always,
+            LogColor::Never => WriteStyle::Never,
+        };
+        let mut builder = Builder::new();
+        builder
+            .format_timestamp(None)
+            .write_style(style)
+            .filter(None, *config.log_level());
+        for x in config.log_modules() {
+            builder.filter(Some(&x.module), x.level);
+        }
+        builder.try_init()?;
+        Ok(())
+    }
+
+    /// Create the level filter for the provided configuration
+    fn filter(config: &Config) -> Filter {
+        let mut builder = filter::Builder::new();
+        builder.filter(None, *config.log_level());
+        for x in config.log_modules() {
+            builder.filter(Some(&x.module), x.level);
+        }
+        builder.build()
+    }
+}
+
+/// A logger which writes directly into the systemd journal
+struct Journald {
+    filter: Filter,
+    socket: UnixDatagram,
+}
+
+impl Journald {
+    fn init(config: &Config) -> Fallible<()> {
+        if var_os("JOURNAL_STREAM").is_none() {
+            bail!("Logging to journald requires running under systemd")
+        }
+        let socket = UnixDatagram::unbound()?;
+        socket
+            .connect(Path::new(Logger::JOURNALD_SOCKET))
+            .map_err(|e| {
+                format_err!(
+                    "Unable to connect to journald socket '{}': {}",
+                    Logger::JOURNALD_SOCKET,
+                    e
+                )
+            })?;
+        let filter = Logger::filter(config);
+        log::set_max_level(filter.filter());
+        log::set_boxed_logger(Box::new(Journald { filter, socket }))?;
+        Ok(())
+    }
+
+    /// Encode a single field of the journald native protocol
+    fn field(buffer: &mut Vec<u8>, key: &str, value: &str) {
+        buffer.extend_from_slice(key.as_bytes());
+        if value.contains('\n') {
+            buffer.push(b'\n');
+            buffer.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            buffer.push(b'=');
+        }
+        buffer.extend_from_slice(value.as_bytes());
+        buffer.push(b'\n');
+    }
+
+    /// Map the log level to the syslog priority
+    fn priority(level: Level) -> &'static str {
+        match level {
+            Level::Error => "3",
+            Level::Warn => "4",
+            Level::Info => "6",
+            Level::Debug | Level::Trace => "7",
+        }
+    }
+}
+
+impl Log for Journald {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.filter.matches(record) {
+            return;
+        }
+        let mut buffer = vec![];
+        Self::field(&mut buffer, "MESSAGE", &record.args().to_string());
+        Self::field(&mut buffer, "PRIORITY", Self::priority(record.level()));
+        Self::field(&mut buffer, "SYSLOG_IDENTIFIER", "kubernix");
+        Self::field(&mut buffer, "SYSLOG_PID", &process::id().to_string());
+        Self::field(&mut buffer, "TARGET", record.target());
+        // Nothing we can do if the journal is not available any more
+        let _ = self.socket.send(&buffer);
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_level_from_str_success() -> Fallible<()> {
+        let m: ModuleLevel = "kubernix::process=debug".parse()?;
+        assert_eq!(m.module, "kubernix::process");
+        assert_eq!(m.level, LevelFilter::Debug);
+        assert_eq!(m.to_string(), "kubernix::process=DEBUG");
+        Ok(())
+    }
+
+    #[test]
+    fn module_level_from_str_failure() {
+        assert!("kubernix".parse::<ModuleLevel>().is_err());
+        assert!("=debug".parse::<ModuleLevel>().is_err());
+        assert!("kubernix=invalid".parse::<ModuleLevel>().is_err());
+    }
+
+    #[test]
+    fn field_success() {
+        let mut b = vec![];
+        Journald::field(&mut b, "MESSAGE", "hello");
+        assert_eq!(b, b"MESSAGE=hello\n");
+
+        let mut b = vec![];
+        Journald::field(&mut b, "MESSAGE", "a\nb");
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(b, expected);
+    }
+}
@@ -1,11 +1,11 @@
-use failure::Fallible;
-use kubernix::{Config, Kubernix};
+use kubernix::{Config, Fallible, Kubernix, SubCommand};
 use std::process::exit;
 
 pub fn main() {
     if let Err(e) = run() {
-        println!("Error: {}", e);
-        exit(1);
+        // Keep the machine readable output of stdout intact
+        eprintln!("Error: {}", e);
+        exit(e.exit_code());
     }
 }
 
@@ -13,11 +13,92 @@ fn run() -> Fallible<()> {
     // Parse CLI arguments
     let config = Config::default();
 
-    if config.subcommand().is_some() {
+    match config.subcommand() {
         // Spawn only a new shell
-        Kubernix::new_shell(config)
-    } else {
+        Some(SubCommand::Shell) => Kubernix::new_shell(config),
+
+        // Export the cluster credentials
+        Some(SubCommand::ExportCredentials(_)) => Kubernix::export_credentials(config),
+
+        // Upgrade the running cluster
+        Some(SubCommand::Upgrade(_)) => Kubernix::upgrade(config),
+
+        // Benchmark the running cluster
+        Some(SubCommand::Bench(_)) => Kubernix::bench(config),
+
+        // Verify the running cluster
+        Some(SubCommand::Verify(_)) => Kubernix::verify(config),
+
+        // Show the configuration drift of the running cluster
+        Some(SubCommand::Diff(_)) => Kubernix::diff(config),
+
+        // Diagnose the running cluster
+        Some(SubCommand::Doctor(_)) => Kubernix::doctor(config),
+
+        // Collect the garbage of old and running clusters
+        Some(SubCommand::Gc(_)) => Kubernix::gc(config),
+
+        // Build an image for the running cluster
+        Some(SubCommand::BuildAndLoad(_)) => Kubernix::build_and_load(config),
+
+        // Synchronize a directory into the running cluster
+        Some(SubCommand::Sync(_)) => Kubernix::sync(config),
+
+        // Shape the network of the running cluster
+        Some(SubCommand::Netem(_)) => Kubernix::netem(config),
+
+        // Apply CustomResourceDefinitions to the running cluster
+        Some(SubCommand::ApplyCrds(_)) => Kubernix::apply_crds(config),
+
+        // Register an extension API server to the running cluster
+        Some(SubCommand::RegisterApiService(_)) => Kubernix::register_apiservice(config),
+
+        // Wait for the running cluster
+        Some(SubCommand::Wait(_)) => Kubernix::wait(config),
+
+        // Check the running cluster once
+        Some(SubCommand::Check(_)) => Kubernix::check(config),
+
+        // Show the status of the running cluster
+        Some(SubCommand::Status(_)) => Kubernix::status(config),
+
+        // List the components of the running cluster
+        Some(SubCommand::List(_)) => Kubernix::list(config),
+
+        // Restart a component of the running cluster
+        Some(SubCommand::Restart(_)) => Kubernix::restart(config),
+
+        // Show the logs of a component of the running cluster
+        Some(SubCommand::Logs(_)) => Kubernix::logs(config),
+
+        // Stop the running cluster
+        Some(SubCommand::Stop) => Kubernix::shutdown(config),
+
+        // Show a dashboard of the running cluster
+        Some(SubCommand::Ui) => Kubernix::ui(config),
+
+        // Show the event timeline of the cluster
+        Some(SubCommand::Events(_)) => Kubernix::events(config),
+
+        // Export the generated setup of the cluster
+        Some(SubCommand::Export(_)) => Kubernix::export(config),
+
+        // Mint a service account token of the running cluster
+        Some(SubCommand::Token(_)) => Kubernix::token(config),
+
+        // Run a component of the running cluster under a debugger
+        Some(SubCommand::Debug(_)) => Kubernix::debug(config),
+
+        // Run the conformance tests against the running cluster
+        Some(SubCommand::Conformance(_)) => Kubernix::conformance(config),
+
+        // Run a command against every cluster of a matrix
+        Some(SubCommand::Matrix(_)) => Kubernix::matrix(config),
+
+        // Move the root of a stopped cluster
+        Some(SubCommand::MigrateRoot(_)) => Kubernix::migrate_root(config),
+
         // Run kubernix
-        Kubernix::start(config)
+        None => Kubernix::start(config),
     }
 }
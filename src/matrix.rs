@@ -0,0 +1,473 @@
+use crate::{
+    config::Matrix as MatrixArgs, control::Control, kubeconfig::KubeConfig, Config, Fallible,
+    Kubernix, CRIO_DIR, KUBECONFIG_ENV, RUNTIME_ENV,
+};
+use clap::Clap;
+use ipnetwork::Ipv4Network;
+use log::{debug, error, info};
+use nix::{
+    errno::Errno,
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+    Error::Sys,
+};
+use rayon::{prelude::*, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    env::current_exe,
+    fs::{self, read_to_string, remove_dir_all, File},
+    net::Ipv4Addr,
+    path::Path,
+    process::{Child, Command, Stdio},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Boots a cluster for every combination of a matrix specification and runs
+/// a command against each of them
+pub struct Matrix<'a> {
+    config: &'a Config,
+    args: &'a MatrixArgs,
+    spec: Spec,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// The matrix specification file
+struct Spec {
+    /// The shell command to be run against every cluster
+    command: String,
+
+    #[serde(default)]
+    /// The kubernix arguments shared by all clusters
+    args: Vec<String>,
+
+    /// The axes of the matrix and their values
+    axis: BTreeMap<String, Vec<AxisValue>>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+/// A single value of a matrix axis
+struct AxisValue {
+    /// The name of the value, which is part of the cluster name
+    name: String,
+
+    #[serde(default)]
+    /// The kubernix arguments selecting the value
+    args: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A single combination of axis values
+struct Combination {
+    values: Vec<(String, String)>,
+    args: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+/// The result of a single combination
+struct Outcome {
+    name: String,
+    values: BTreeMap<String, String>,
+    exit_code: Option<i32>,
+    error: Option<String>,
+    duration: u64,
+}
+
+impl<'a> Matrix<'a> {
+    /// The file containing the outcomes of all combinations within the root
+    const RESULTS: &'static str = "matrix.json";
+
+    /// The log file of kubernix within the root of a single cluster
+    const LOG: &'static str = "kubernix.log";
+
+    /// The log file of the command within the root of a single cluster,
+    /// which is only written for parallel runs
+    const COMMAND_LOG: &'static str = "command.log";
+
+    /// The port offset between clusters running at the same time
+    const PORT_OFFSET: u16 = 1000;
+
+    /// The interval of checking whether a cluster is up
+    const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+    /// Run all combinations of the provided specification
+    pub fn run(config: &Config, args: &MatrixArgs) -> Fallible<()> {
+        if *args.parallel() == 0 {
+            bail!("At least one cluster has to run at a time")
+        }
+        let content = read_to_string(args.spec())
+            .map_err(|e| format_err!("Unable to read matrix '{}': {}", args.spec().display(), e))?;
+        let spec: Spec = toml::from_str(&content)
+            .map_err(|e| format_err!("Unable to load matrix '{}': {}", args.spec().display(), e))?;
+        let combinations = Self::combinations(&spec)?;
+        let matrix = Matrix { config, args, spec };
+
+        info!(
+            "Running {} combinations, {} at a time",
+            combinations.len(),
+            args.parallel()
+        );
+        let outcomes: Vec<Outcome> = if *args.parallel() == 1 {
+            combinations
+                .iter()
+                .enumerate()
+                .map(|(i, x)| matrix.run_one(i, x))
+                .collect()
+        } else {
+            ThreadPoolBuilder::new()
+                .num_threads(*args.parallel())
+                .build()
+                .map_err(|e| format_err!("Unable to create thread pool: {}", e))?
+                .install(|| {
+                    combinations
+                        .par_iter()
+                        .enumerate()
+                        .map(|(i, x)| matrix.run_one(i, x))
+                        .collect()
+                })
+        };
+
+        let results = config.root().join(Self::RESULTS);
+        fs::write(&results, serde_json::to_string_pretty(&outcomes)?)?;
+        let mut failed = 0;
+        for outcome in &outcomes {
+            match (&outcome.error, outcome.exit_code) {
+                (None, Some(0)) => info!("{}: passed after {}s", outcome.name, outcome.duration),
+                (Some(e), _) => {
+                    failed += 1;
+                    error!("{}: {}", outcome.name, e)
+                }
+                (None, Some(code)) => {
+                    failed += 1;
+                    error!("{}: command failed with exit code {}", outcome.name, code)
+                }
+                (None, None) => {
+                    failed += 1;
+                    error!("{}: command terminated by signal", outcome.name)
+                }
+            }
+        }
+        info!("Matrix results written to '{}'", results.display());
+        if failed > 0 {
+            bail!("{} of {} combinations failed", failed, outcomes.len())
+        }
+        Ok(())
+    }
+
+    /// Build all combinations of the axis values, whereas the shared
+    /// arguments come first
+    fn combinations(spec: &Spec) -> Fallible<Vec<Combination>> {
+        // Every cluster needs a name of its own
+        if spec.axis.is_empty() {
+            bail!("The matrix contains no axes")
+        }
+        let mut combinations = vec![Combination {
+            values: vec![],
+            args: vec![],
+        }];
+        for (axis, values) in &spec.axis {
+            if !Self::is_name(axis) {
+                bail!("Invalid matrix axis '{}'", axis)
+            }
+            if values.is_empty() {
+                bail!("Matrix axis '{}' contains no values", axis)
+            }
+            for (i, value) in values.iter().enumerate() {
+                if !Self::is_name(&value.name) {
+                    bail!("Invalid value '{}' of matrix axis '{}'", value.name, axis)
+                }
+                if values[..i].iter().any(|x| x.name == value.name) {
+                    bail!("Duplicate value '{}' of matrix axis '{}'", value.name, axis)
+                }
+            }
+            combinations = combinations
+                .iter()
+                .flat_map(|c| {
+                    values.iter().map(move |v| {
+                        let mut c = c.clone();
+                        c.values.push((axis.clone(), v.name.clone()));
+                        c.args.extend(v.args.iter().cloned());
+                        c
+                    })
+                })
+                .collect();
+        }
+        Ok(combinations)
+    }
+
+    /// Check if the provided name is usable as part of a directory and
+    /// environment variable name
+    fn is_name(name: &str) -> bool {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_')
+    }
+
+    /// Run a single combination and record its outcome
+    fn run_one(&self, index: usize, combination: &Combination) -> Outcome {
+        let start = Instant::now();
+        let name = combination.name();
+        info!("Starting cluster '{}'", name);
+        let (exit_code, error) = match self.cluster(index, combination) {
+            Ok(x) => (x, None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        info!("Cluster '{}' finished", name);
+        Outcome {
+            name,
+            values: combination.values.iter().cloned().collect(),
+            exit_code,
+            error,
+            duration: start.elapsed().as_secs(),
+        }
+    }
+
+    /// Boot the cluster of a single combination, run the command against it
+    /// and tear it down again
+    fn cluster(&self, index: usize, combination: &Combination) -> Fallible<Option<i32>> {
+        let root = self.config.root().join(combination.name());
+        if root.exists() {
+            remove_dir_all(&root)?;
+        }
+        let config = self.cluster_config(index, combination, &root)?;
+
+        // The cluster has no interactive shell, which would exit immediately
+        let log = File::create(root.join(Self::LOG))?;
+        let mut child = Command::new(current_exe()?)
+            .arg("--root")
+            .arg(&root)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()?;
+        let result = self
+            .wait(&config, &mut child)
+            .and_then(|_| self.execute(&config, combination));
+
+        debug!("Stopping cluster '{}'", combination.name());
+        match kill(Pid::from_raw(child.id() as i32), Signal::SIGTERM) {
+            Err(Sys(Errno::ESRCH)) => {}
+            x => x?,
+        }
+        child.wait()?;
+        result
+    }
+
+    /// Create the configuration of a single cluster, whereas clusters running
+    /// at the same time get distinct ports and CIDRs
+    fn cluster_config(
+        &self,
+        index: usize,
+        combination: &Combination,
+        root: &Path,
+    ) -> Fallible<Config> {
+        let mut args = vec![
+            "kubernix".to_owned(),
+            "--root".into(),
+            root.display().to_string(),
+            "--soak".into(),
+        ];
+        args.extend(self.spec.args.iter().cloned());
+        args.extend(combination.args.iter().cloned());
+        let mut config = Config::try_parse_from(args).map_err(|e| {
+            format_err!(
+                "Invalid arguments of combination '{}': {}",
+                combination.name(),
+                e
+            )
+        })?;
+
+        if *self.args.parallel() > 1 {
+            let offset = u16::try_from(index)
+                .ok()
+                .and_then(|x| x.checked_mul(Self::PORT_OFFSET))
+                .ok_or_else(|| format_err!("Too many combinations to run in parallel"))?;
+            let ports = config.ports().offset(offset)?;
+            config.update_ports(ports)?;
+            let cidr = Self::shift(*config.cidr(), index)?;
+            config.update_cidr(cidr)?;
+        } else {
+            config.to_file()?;
+        }
+        Ok(config)
+    }
+
+    /// Shift the provided CIDR by its own size, so that the networks of
+    /// clusters running at the same time do not overlap
+    fn shift(cidr: Ipv4Network, index: usize) -> Fallible<Ipv4Network> {
+        let size = 1u64 << (32 - u32::from(cidr.prefix()));
+        let network = u64::from(u32::from(cidr.network())) + size * index as u64;
+        let network = u32::try_from(network)
+            .map_err(|_| format_err!("CIDR {} cannot be shifted {} times", cidr, index))?;
+        Ok(Ipv4Network::new(Ipv4Addr::from(network), cidr.prefix())?)
+    }
+
+    /// Wait until the cluster serves its control API, which happens once
+    /// everything is up and running
+    fn wait(&self, config: &Config, child: &mut Child) -> Fallible<()> {
+        let start = Instant::now();
+        loop {
+            if let Some(status) = child.try_wait()? {
+                bail!(
+                    "kubernix exited with {}, see '{}'",
+                    status,
+                    config.root().join(Self::LOG).display()
+                )
+            }
+            if Control::request(config, "GET", "/v1/status").is_ok() {
+                return Ok(());
+            }
+            if start.elapsed() >= self.args.timeout().duration() {
+                bail!("Cluster not up within {}", self.args.timeout())
+            }
+            sleep(Self::POLL_INTERVAL);
+        }
+    }
+
+    /// Run the command inside the nix shell of the cluster and retrieve its
+    /// exit code
+    fn execute(&self, config: &Config, combination: &Combination) -> Fallible<Option<i32>> {
+        let mut env = vec![
+            (
+                RUNTIME_ENV.to_owned(),
+                format!(
+                    "unix://{}",
+                    config.root().join(CRIO_DIR).join("crio.sock").display()
+                ),
+            ),
+            (
+                KUBECONFIG_ENV.to_owned(),
+                KubeConfig::admin_path(config).display().to_string(),
+            ),
+        ];
+        env.extend(combination.env());
+        let exports: Vec<String> = env
+            .iter()
+            .map(|(k, v)| format!("export {}={}", k, Self::quote(v)))
+            .collect();
+        let mut script = format!("{} && {}", exports.join(" && "), self.spec.command);
+        if *self.args.parallel() > 1 {
+            // The output of clusters running at the same time would interleave
+            script = format!(
+                "( {} ) >{} 2>&1",
+                script,
+                Self::quote(&config.root().join(Self::COMMAND_LOG).display().to_string())
+            );
+        }
+
+        info!("Running command against cluster '{}'", combination.name());
+        Ok(Kubernix::nix_shell_status(config, &script)?.code())
+    }
+
+    /// Quote the provided value for the shell
+    fn quote(value: &str) -> String {
+        format!("'{}'", value.replace('\'', r#"'\''"#))
+    }
+}
+
+impl Combination {
+    /// The name of the combination, which is also the directory of its root
+    fn name(&self) -> String {
+        self.values
+            .iter()
+            .map(|(_, x)| x.as_str())
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    /// The environment variables exposing the axis values to the command
+    fn env(&self) -> Vec<(String, String)> {
+        self.values
+            .iter()
+            .map(|(axis, value)| {
+                (
+                    format!(
+                        "KUBERNIX_MATRIX_{}",
+                        axis.to_uppercase().replace(&['-', '.'][..], "_")
+                    ),
+                    value.clone(),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(content: &str) -> Fallible<Spec> {
+        Ok(toml::from_str(content)?)
+    }
+
+    #[test]
+    fn combinations_success() -> Fallible<()> {
+        let s = spec(
+            r#"
+            command = "kubectl get nodes"
+            args = ["--skip-firewall"]
+
+            [[axis.runtime]]
+            name = "runc"
+
+            [[axis.runtime]]
+            name = "crun"
+            args = ["--runtime-class=crun"]
+
+            [[axis.cni]]
+            name = "bridge"
+            args = ["--cni=bridge"]
+
+            [[axis.cni]]
+            name = "cilium"
+            args = ["--cni=cilium"]
+            "#,
+        )?;
+        let c = Matrix::combinations(&s)?;
+        assert_eq!(
+            c.iter().map(Combination::name).collect::<Vec<_>>(),
+            vec!["bridge-runc", "bridge-crun", "cilium-runc", "cilium-crun"]
+        );
+        assert_eq!(c[3].args, vec!["--cni=cilium", "--runtime-class=crun"]);
+        assert_eq!(
+            c[3].env(),
+            vec![
+                ("KUBERNIX_MATRIX_CNI".to_owned(), "cilium".to_owned()),
+                ("KUBERNIX_MATRIX_RUNTIME".to_owned(), "crun".to_owned()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn combinations_failure() -> Fallible<()> {
+        let empty = spec("command = \"true\"\n[axis]\ncni = []")?;
+        assert!(Matrix::combinations(&empty).is_err());
+
+        let invalid = spec("command = \"true\"\n[[axis.cni]]\nname = \"a/b\"")?;
+        assert!(Matrix::combinations(&invalid).is_err());
+
+        let duplicate =
+            spec("command = \"true\"\n[[axis.cni]]\nname = \"a\"\n[[axis.cni]]\nname = \"a\"")?;
+        assert!(Matrix::combinations(&duplicate).is_err());
+
+        assert!(Matrix::combinations(&spec("command = \"true\"\n[axis]")?).is_err());
+        assert!(spec("command = \"true\"\nunknown = 1\n[axis]").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn shift_success() -> Fallible<()> {
+        let cidr: Ipv4Network = "10.10.0.0/16".parse()?;
+        assert_eq!(Matrix::shift(cidr, 0)?, cidr);
+        assert_eq!(Matrix::shift(cidr, 2)?.to_string(), "10.12.0.0/16");
+        let cidr: Ipv4Network = "255.255.255.0/24".parse()?;
+        assert!(Matrix::shift(cidr, 1).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{config::MigrateRoot, lock::RootLock, Config, Fallible};
+use log::{debug, info};
+use nix::errno::Errno;
+use proc_mounts::MountIter;
+use std::{
+    env::current_dir,
+    fs::{self, canonicalize, read_dir, read_link, remove_dir_all, remove_file, rename},
+    os::unix::fs::symlink,
+    path::Path,
+    process::Command,
+};
+
+/// The relocation of the root of a stopped cluster
+pub struct RootMigration;
+
+impl RootMigration {
+    /// Directories with runtime data only, which is never rewritten
+    const SKIP_DIRS: &'static [&'static str] =
+        &["crio/run", "crio/storage", "etcd/run", "kubelet/run"];
+
+    /// Files larger than this are considered to be data instead of configuration
+    const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+    /// Move the root and rewrite all references to the old location
+    pub fn run(args: &MigrateRoot) -> Fallible<()> {
+        let old = canonicalize(args.old_root()).map_err(|e| {
+            format_err!("Unable to find root '{}': {}", args.old_root().display(), e)
+        })?;
+        if !old.join(Config::FILENAME).is_file() {
+            bail!("No cluster found in '{}'", old.display())
+        }
+        if RootLock::is_held(&old)? || Self::mounted(&old)? {
+            bail!(
+                "The cluster in '{}' is still running, please stop it first",
+                old.display()
+            )
+        }
+
+        let new = if args.new_root().is_absolute() {
+            args.new_root().to_owned()
+        } else {
+            current_dir()?.join(args.new_root())
+        };
+        if new.exists() {
+            bail!("Target root '{}' already exists", new.display())
+        }
+        if new.starts_with(&old) {
+            bail!(
+                "Target root '{}' is inside of '{}'",
+                new.display(),
+                old.display()
+            )
+        }
+
+        info!("Moving '{}' to '{}'", old.display(), new.display());
+        Self::relocate(&old, &new)?;
+        let new = canonicalize(&new)?;
+
+        let mut rewritten = 0;
+        Self::rewrite_dir(&old, &new, &new, &mut rewritten)?;
+        info!(
+            "Rewrote {} files, the cluster can be started via --root {}",
+            rewritten,
+            new.display()
+        );
+        Ok(())
+    }
+
+    /// Check if anything is still mounted inside the provided root
+    fn mounted(root: &Path) -> Fallible<bool> {
+        Ok(MountIter::new()?
+            .filter_map(|x| x.ok())
+            .any(|x| x.dest.starts_with(root)))
+    }
+
+    /// Move the directory, whereas a copy is used across file systems
+    fn relocate(old: &Path, new: &Path) -> Fallible<()> {
+        match rename(old, new) {
+            Ok(()) => Ok(()),
+            Err(e) if e.raw_os_error() == Some(Errno::EXDEV as i32) => {
+                debug!("Copying root across file systems");
+                let status = Command::new("cp")
+                    .arg("--archive")
+                    .arg(old)
+                    .arg(new)
+                    .status()?;
+                if !status.success() {
+                    bail!("Unable to copy '{}' to '{}'", old.display(), new.display())
+                }
+                remove_dir_all(old)?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Rewrite all files and symlinks below the provided directory
+    fn rewrite_dir(old: &Path, new: &Path, dir: &Path, rewritten: &mut usize) -> Fallible<()> {
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            let metadata = fs::symlink_metadata(&path)?;
+            if metadata.file_type().is_symlink() {
+                let target = read_link(&path)?;
+                if let Ok(relative) = target.strip_prefix(old) {
+                    debug!("Relinking '{}'", path.display());
+                    remove_file(&path)?;
+                    symlink(new.join(relative), &path)?;
+                    *rewritten += 1;
+                }
+            } else if metadata.is_dir() {
+                let skip = Self::SKIP_DIRS.iter().any(|x| path == new.join(x));
+                if !skip {
+                    Self::rewrite_dir(old, new, &path, rewritten)?;
+                }
+            } else if metadata.is_file() && metadata.len() <= Self::MAX_FILE_SIZE {
+                // Binary files do not contain any paths to be rewritten
+                let content = match String::from_utf8(fs::read(&path)?) {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                };
+                if let Some(x) = Self::rewrite(
+                    &content,
+                    &old.display().to_string(),
+                    &new.display().to_string(),
+                ) {
+                    debug!("Rewriting '{}'", path.display());
+                    fs::write(&path, x)?;
+                    *rewritten += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace all occurrences of the old path by the new one, whereas
+    /// longer paths only sharing the prefix stay untouched
+    fn rewrite(content: &str, old: &str, new: &str) -> Option<String> {
+        let is_path_char = |c: char| c.is_alphanumeric() || "-_.".contains(c);
+        let mut result = String::with_capacity(content.len());
+        let mut rest = content;
+        let mut changed = false;
+        while let Some(start) = rest.find(old) {
+            let end = start + old.len();
+            // The path must neither continue nor be the suffix of another one
+            let before = rest[..start].chars().next_back();
+            let after = rest[end..].chars().next();
+            let bounded = !before.into_iter().chain(after).any(is_path_char);
+            result.push_str(&rest[..start]);
+            result.push_str(if bounded { new } else { old });
+            changed |= bounded;
+            rest = &rest[end..];
+        }
+        result.push_str(rest);
+        if changed {
+            Some(result)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::create_dir_all;
+    use tempfile::tempdir;
+
+    #[test]
+    fn rewrite_success() {
+        assert_eq!(
+            RootMigration::rewrite(
+                "--config=/old/root/kubelet/config.yml\nroot = \"/old/root\"\n",
+                "/old/root",
+                "/new",
+            ),
+            Some("--config=/new/kubelet/config.yml\nroot = \"/new\"\n".into())
+        );
+    }
+
+    #[test]
+    fn rewrite_prefix_untouched() {
+        assert_eq!(
+            RootMigration::rewrite("/old/root-2/x /x/old/root", "/old/root", "/new"),
+            None
+        );
+        assert_eq!(RootMigration::rewrite("", "/old/root", "/new"), None);
+    }
+
+    #[test]
+    fn rewrite_dir_success() -> Fallible<()> {
+        let d = tempdir()?;
+        let old = d.path().join("old");
+        let new = d.path().join("new");
+        create_dir_all(new.join("etcd").join("run"))?;
+        create_dir_all(new.join("pki"))?;
+        fs::write(new.join("pki").join("ca.pem"), "")?;
+        fs::write(
+            new.join("run.sh"),
+            format!("--ca={}/pki/ca.pem", old.display()),
+        )?;
+        fs::write(
+            new.join("etcd").join("run").join("db"),
+            old.display().to_string(),
+        )?;
+        symlink(old.join("pki").join("ca.pem"), new.join("ca.crt"))?;
+
+        let mut rewritten = 0;
+        RootMigration::rewrite_dir(&old, &new, &new, &mut rewritten)?;
+        assert_eq!(rewritten, 2);
+        assert_eq!(
+            fs::read_to_string(new.join("run.sh"))?,
+            format!("--ca={}/pki/ca.pem", new.display())
+        );
+        assert_eq!(
+            read_link(new.join("ca.crt"))?,
+            new.join("pki").join("ca.pem")
+        );
+        assert_eq!(
+            fs::read_to_string(new.join("etcd").join("run").join("db"))?,
+            old.display().to_string()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{Error, Fallible};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+/// A mirror to be used when pulling images from a registry
+pub struct RegistryMirror {
+    registry: String,
+    mirror: String,
+}
+
+impl Display for RegistryMirror {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.registry, self.mirror)
+    }
+}
+
+impl FromStr for RegistryMirror {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let mut split = s.splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some(registry), Some(mirror)) if !registry.is_empty() && !mirror.is_empty() => {
+                Ok(RegistryMirror {
+                    registry: registry.into(),
+                    mirror: mirror.into(),
+                })
+            }
+            _ => Err(format_err!(
+                "Invalid registry mirror '{}', expected 'REGISTRY=MIRROR'",
+                s
+            )),
+        }
+    }
+}
+
+impl RegistryMirror {
+    /// Render the provided mirrors as containers-registries.conf(5) version 2
+    pub fn registries_conf(mirrors: &[RegistryMirror]) -> String {
+        let mut registries: Vec<&str> = vec![];
+        for x in mirrors {
+            if !registries.contains(&x.registry.as_str()) {
+                registries.push(&x.registry);
+            }
+        }
+
+        let mut conf = String::new();
+        for registry in registries {
+            conf.push_str(&format!(
+                "[[registry]]\nprefix = \"{0}\"\nlocation = \"{0}\"\n\n",
+                registry
+            ));
+            for x in mirrors.iter().filter(|x| x.registry == registry) {
+                conf.push_str(&format!(
+                    "[[registry.mirror]]\nlocation = \"{}\"\n\n",
+                    x.mirror
+                ));
+            }
+        }
+        conf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        let m: RegistryMirror = "docker.io=mirror.local:5000".parse()?;
+        assert_eq!(m.registry, "docker.io");
+        assert_eq!(m.mirror, "mirror.local:5000");
+        assert_eq!(m.to_string(), "docker.io=mirror.local:5000");
+        Ok(())
+    }
+
+    #[test]
+    fn from_str_failure() {
+        assert!("docker.io".parse::<RegistryMirror>().is_err());
+        assert!("=mirror".parse::<RegistryMirror>().is_err());
+    }
+
+    #[test]
+    fn registries_conf_success() -> Fallible<()> {
+        let conf = RegistryMirror::registries_conf(&[
+            "docker.io=a".parse()?,
+            "docker.io=b".parse()?,
+            "quay.io=c".parse()?,
+        ]);
+        assert_eq!(conf.matches("[[registry]]").count(), 2);
+        assert_eq!(conf.matches("[[registry.mirror]]").count(), 3);
+        assert!(conf.contains("prefix = \"quay.io\""));
+        Ok(())
+    }
+}
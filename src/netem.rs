@@ -0,0 +1,86 @@
+use crate::{config::Netem as NetemArgs, network::Network, Fallible};
+use log::{debug, info};
+use std::process::Command;
+
+pub struct Netem;
+
+impl Netem {
+    /// The default interface inside a pod network namespace
+    const POD_INTERFACE: &'static str = "eth0";
+
+    /// Apply, clear or show the traffic control rules of the interface
+    pub fn run(args: &NetemArgs) -> Fallible<()> {
+        let interface = Self::interface(args);
+        let rules = Self::rules(args);
+        let tc = if *args.clear() {
+            info!("Clearing network emulation rules of {}", interface);
+            vec!["qdisc", "del", "dev", interface, "root"]
+        } else if rules.is_empty() {
+            vec!["qdisc", "show", "dev", interface]
+        } else {
+            info!(
+                "Applying network emulation rules to {}: {}",
+                interface,
+                rules.join(" ")
+            );
+            let mut tc = vec!["qdisc", "replace", "dev", interface, "root", "netem"];
+            tc.extend(rules.iter().map(String::as_str));
+            tc
+        };
+
+        let mut cmd = match args.netns() {
+            Some(netns) => {
+                let mut cmd = Command::new("ip");
+                cmd.arg("netns").arg("exec").arg(netns).arg("tc");
+                cmd
+            }
+            None => Command::new("tc"),
+        };
+        let output = cmd.args(&tc).output()?;
+        let stdout = String::from_utf8(output.stdout)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8(output.stderr)?;
+            debug!("tc stderr: {}", stderr);
+            // Removing non existing rules is fine
+            if *args.clear() && stderr.contains("No such file or directory") {
+                info!("No network emulation rules found");
+                return Ok(());
+            }
+            bail!("tc command failed: {}", stderr.trim());
+        }
+        if !stdout.is_empty() {
+            println!("{}", stdout.trim_end());
+        }
+        Ok(())
+    }
+
+    /// Retrieve the interface to be shaped
+    fn interface(args: &NetemArgs) -> &str {
+        match (args.interface(), args.netns()) {
+            (Some(x), _) => x,
+            (None, Some(_)) => Self::POD_INTERFACE,
+            (None, None) => Network::BRIDGE,
+        }
+    }
+
+    /// Retrieve the netem parameters of the provided arguments
+    fn rules(args: &NetemArgs) -> Vec<String> {
+        let mut rules = vec![];
+        if let Some(delay) = args.delay() {
+            rules.push("delay".into());
+            rules.push(format!("{}ms", delay));
+            if let Some(jitter) = args.jitter() {
+                rules.push(format!("{}ms", jitter));
+            }
+        }
+        if let Some(loss) = args.loss() {
+            rules.push("loss".into());
+            rules.push(format!("{}%", loss));
+        }
+        if let Some(rate) = args.rate() {
+            rules.push("rate".into());
+            rules.push(rate.clone());
+        }
+        rules
+    }
+}
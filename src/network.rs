@@ -1,11 +1,10 @@
-use crate::Config;
-use failure::{bail, format_err, Fallible};
+use crate::{Config, Fallible};
 use getset::Getters;
 use ipnetwork::Ipv4Network;
 use log::{debug, warn};
 use std::{net::Ipv4Addr, process::Command};
 
-#[derive(Getters)]
+#[derive(Clone, Getters)]
 pub struct Network {
     #[get = "pub"]
     crio: Ipv4Network,
@@ -21,64 +20,212 @@ impl Network {
     /// The global name for the bridged interface
     pub const BRIDGE: &'static str = "kubernix1";
 
+    /// The default cluster CIDR, which gets replaced automatically if it
+    /// conflicts with the host routes
+    pub const DEFAULT_CIDR: &'static str = "10.10.0.0/16";
+
+    /// The private IP ranges as defined in RFC1918
+    const PRIVATE_RANGES: [(Ipv4Addr, u8); 3] = [
+        (Ipv4Addr::new(10, 0, 0, 0), 8),
+        (Ipv4Addr::new(172, 16, 0, 0), 12),
+        (Ipv4Addr::new(192, 168, 0, 0), 16),
+    ];
+
+    /// The largest service network supported by the API Server
+    const MIN_SERVICE_PREFIX: u8 = 12;
+
+    /// The smallest network which still provides enough addresses
+    const MAX_PREFIX: u8 = 28;
+
     /// Create a new network from the provided config
-    pub fn new(config: &Config) -> Fallible<Self> {
-        if config.cidr().prefix() > 24 {
+    pub fn new(config: &mut Config) -> Fallible<Self> {
+        let routes = Self::host_routes()?;
+
+        // The cluster CIDR is only used if not all networks are specified
+        if config.pod_cidr().is_none() || config.service_cidr().is_none() {
+            if config.cidr().prefix() > 24 {
+                bail!(
+                    "Specified IP network {} is too small, please use at least a /24 subnet",
+                    config.cidr()
+                )
+            }
+            Self::avoid_conflicts(config, &routes)?;
+        }
+        let (crio, cluster, service) = Self::split(*config.cidr())?;
+
+        let (crio, cluster) = match config.pod_cidr() {
+            Some(x) => {
+                Self::validate("pod", *x, 24, &routes)?;
+                (*x, *x)
+            }
+            None => (crio, cluster),
+        };
+        debug!("Using crio CIDR {}", crio);
+        debug!("Using cluster CIDR {}", cluster);
+
+        let service = match config.service_cidr() {
+            Some(x) => {
+                if x.prefix() < Self::MIN_SERVICE_PREFIX {
+                    bail!(
+                        "Specified service IP network {} is too large, please use at most a /{} subnet",
+                        x,
+                        Self::MIN_SERVICE_PREFIX
+                    )
+                }
+                Self::validate("service", *x, Self::MAX_PREFIX, &routes)?;
+                *x
+            }
+            None => service,
+        };
+        debug!("Using service CIDR {}", service);
+
+        if crio.overlaps(service) || cluster.overlaps(service) {
             bail!(
-                "Specified IP network {} is too small, please use at least a /24 subnet",
-                config.cidr()
+                "The pod IP network {} overlaps with the service IP network {}",
+                cluster,
+                service
             )
         }
 
-        Self::warn_overlapping_route(*config.cidr())?;
+        if let Some(size) = config.node_cidr_mask_size() {
+            if *size < cluster.prefix() || *size > Self::MAX_PREFIX {
+                bail!(
+                    "Node CIDR mask size {} has to be between the pod IP network prefix {} and {}",
+                    size,
+                    cluster.prefix(),
+                    Self::MAX_PREFIX
+                )
+            }
+        }
 
-        let crio = Ipv4Network::new(config.cidr().ip(), config.cidr().prefix() + 1)?;
-        debug!("Using crio CIDR {}", crio);
+        Ok(Self {
+            crio,
+            cluster,
+            service,
+        })
+    }
 
+    /// Split the provided cluster CIDR into the crio, cluster and service
+    /// networks
+    fn split(cidr: Ipv4Network) -> Fallible<(Ipv4Network, Ipv4Network, Ipv4Network)> {
+        let crio = Ipv4Network::new(cidr.ip(), cidr.prefix() + 1)?;
         let cluster = Ipv4Network::new(
-            config
-                .cidr()
-                .nth(config.cidr().size() / 2)
+            cidr.nth(cidr.size() / 2)
                 .ok_or_else(|| format_err!("Unable to retrieve cluster CIDR start IP"))?,
-            config.cidr().prefix() + 2,
+            cidr.prefix() + 2,
         )?;
-        debug!("Using cluster CIDR {}", cluster);
-
         let service = Ipv4Network::new(
-            config
-                .cidr()
-                .nth(config.cidr().size() / 2 + cluster.size())
+            cidr.nth(cidr.size() / 2 + cluster.size())
                 .ok_or_else(|| format_err!("Unable to retrieve service CIDR start IP"))?,
-            config.cidr().prefix() + 3,
+            cidr.prefix() + 3,
         )?;
-        debug!("Using service CIDR {}", service);
+        Ok((crio, cluster, service))
+    }
 
-        Ok(Self {
-            crio,
-            cluster,
-            service,
-        })
+    /// Validate an explicitly specified network
+    fn validate(
+        name: &str,
+        cidr: Ipv4Network,
+        max_prefix: u8,
+        routes: &[Ipv4Network],
+    ) -> Fallible<()> {
+        if cidr.prefix() > max_prefix {
+            bail!(
+                "Specified {} IP network {} is too small, please use at least a /{} subnet",
+                name,
+                cidr,
+                max_prefix
+            )
+        }
+        if let Some(route) = Self::conflict(cidr, routes) {
+            bail!(
+                "Specified {} IP network {} overlaps with the host route {}",
+                name,
+                cidr,
+                route
+            )
+        }
+        Ok(())
     }
 
-    /// Check if there are overlapping routes and warn
-    fn warn_overlapping_route(cidr: Ipv4Network) -> Fallible<()> {
+    /// Check if the cluster CIDR overlaps with any host route. The default
+    /// CIDR gets replaced by a free private range, whereas a conflicting
+    /// user provided CIDR results in an error
+    fn avoid_conflicts(config: &mut Config, routes: &[Ipv4Network]) -> Fallible<()> {
+        let route = match Self::conflict(*config.cidr(), routes) {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let free = Self::free_cidr(config.cidr().prefix(), routes)?;
+
+        if config.cidr().to_string() != Self::DEFAULT_CIDR {
+            bail!(
+                "Specified IP network {} overlaps with the host route {}, {}",
+                config.cidr(),
+                route,
+                match free {
+                    Some(x) => format!("please choose another one like `--cidr {}`", x),
+                    None => "please choose another one via `--cidr`".into(),
+                }
+            )
+        }
+        match free {
+            Some(x) => {
+                warn!(
+                    "Default IP network {} overlaps with the host route {}, using {} instead",
+                    config.cidr(),
+                    route,
+                    x
+                );
+                config.update_cidr(x)
+            }
+            None => bail!(
+                "Default IP network {} overlaps with the host route {} and no free \
+                 private network found, please specify one via `--cidr`",
+                config.cidr(),
+                route
+            ),
+        }
+    }
+
+    /// Retrieve all host routes which are not managed by kubernix
+    fn host_routes() -> Fallible<Vec<Ipv4Network>> {
         let cmd = Command::new("ip").arg("route").output()?;
         if !cmd.status.success() {
             bail!("Unable to obtain `ip` routes")
         }
-        String::from_utf8(cmd.stdout)?
+        Ok(String::from_utf8(cmd.stdout)?
             .lines()
             .filter(|x| !x.contains(Self::BRIDGE))
-            .filter_map(|x| x.split_whitespace().nth(0))
+            .filter_map(|x| x.split_whitespace().next())
             .filter_map(|x| x.parse::<Ipv4Network>().ok())
-            .filter(|x| x.is_supernet_of(cidr))
-            .for_each(|x| {
-                warn!(
-                    "There seems to be an overlapping IP route {}. {}",
-                    x, "the cluster may not work as expected",
-                );
-            });
-        Ok(())
+            .collect())
+    }
+
+    /// Retrieve the first route overlapping with the provided CIDR
+    fn conflict(cidr: Ipv4Network, routes: &[Ipv4Network]) -> Option<Ipv4Network> {
+        routes.iter().find(|x| x.overlaps(cidr)).cloned()
+    }
+
+    /// Find the first private network with the provided prefix which does
+    /// not overlap with any route
+    fn free_cidr(prefix: u8, routes: &[Ipv4Network]) -> Fallible<Option<Ipv4Network>> {
+        for (ip, range_prefix) in Self::PRIVATE_RANGES.iter() {
+            if prefix < *range_prefix {
+                continue;
+            }
+            let start = u32::from(*ip);
+            let step = 1u64 << (32 - prefix);
+            let count = 1u64 << (prefix - range_prefix);
+            for i in 0..count {
+                let candidate =
+                    Ipv4Network::new(Ipv4Addr::from(start + (i * step) as u32), prefix)?;
+                if Self::conflict(candidate, routes).is_none() {
+                    return Ok(Some(candidate));
+                }
+            }
+        }
+        Ok(None)
     }
 
     /// Retrieve the DNS address from the service CIDR
@@ -108,29 +255,80 @@ pub mod tests {
     use crate::config::tests::{test_config, test_config_wrong_cidr};
 
     pub fn test_network() -> Fallible<Network> {
-        let c = test_config()?;
-        Network::new(&c)
+        let mut c = test_config()?;
+        Network::new(&mut c)
     }
 
     #[test]
     fn new_success() -> Fallible<()> {
-        let c = test_config()?;
-        Network::new(&c)?;
+        let mut c = test_config()?;
+        Network::new(&mut c)?;
         Ok(())
     }
 
     #[test]
     fn new_failure() -> Fallible<()> {
-        let c = test_config_wrong_cidr()?;
-        assert!(Network::new(&c).is_err());
+        let mut c = test_config_wrong_cidr()?;
+        assert!(Network::new(&mut c).is_err());
         Ok(())
     }
 
     #[test]
     fn dns_success() -> Fallible<()> {
-        let c = test_config()?;
-        let n = Network::new(&c)?;
+        let mut c = test_config()?;
+        let n = Network::new(&mut c)?;
         assert_eq!(n.dns()?, Ipv4Addr::new(10, 10, 192, 2));
         Ok(())
     }
+
+    #[test]
+    fn split_success() -> Fallible<()> {
+        let (crio, cluster, service) = Network::split("10.10.0.0/16".parse()?)?;
+        assert_eq!(crio, "10.10.0.0/17".parse()?);
+        assert_eq!(cluster, "10.10.128.0/18".parse()?);
+        assert_eq!(service, "10.10.192.0/19".parse()?);
+        Ok(())
+    }
+
+    #[test]
+    fn validate_failure() -> Fallible<()> {
+        let routes = vec!["10.10.5.0/24".parse()?];
+        assert!(Network::validate("pod", "10.20.0.0/25".parse()?, 24, &routes).is_err());
+        assert!(Network::validate("pod", "10.10.0.0/16".parse()?, 24, &routes).is_err());
+        assert!(Network::validate("pod", "10.20.0.0/16".parse()?, 24, &routes).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn conflict_success() -> Fallible<()> {
+        let routes = vec!["10.10.5.0/24".parse()?, "192.168.1.0/24".parse()?];
+        assert_eq!(
+            Network::conflict("10.10.0.0/16".parse()?, &routes),
+            Some("10.10.5.0/24".parse()?)
+        );
+        assert!(Network::conflict("10.11.0.0/16".parse()?, &routes).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn free_cidr_success() -> Fallible<()> {
+        let routes = vec!["10.0.0.0/15".parse()?];
+        assert_eq!(
+            Network::free_cidr(16, &routes)?,
+            Some("10.2.0.0/16".parse()?)
+        );
+        let routes = vec!["10.0.0.0/8".parse()?];
+        assert_eq!(
+            Network::free_cidr(12, &routes)?,
+            Some("172.16.0.0/12".parse()?)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn free_cidr_failure() -> Fallible<()> {
+        let routes = vec!["0.0.0.0/1".parse()?, "128.0.0.0/1".parse()?];
+        assert!(Network::free_cidr(16, &routes)?.is_none());
+        Ok(())
+    }
 }
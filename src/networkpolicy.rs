@@ -0,0 +1,68 @@
+use crate::{cni::Cni, config::Config, kubeconfig::KubeConfig, process::Process, Fallible};
+use log::{info, warn};
+use std::fs::{self, create_dir_all};
+
+pub struct NetworkPolicy;
+
+impl NetworkPolicy {
+    /// Deploy kube-router as firewall to enforce NetworkPolicies on top of the
+    /// bridge network plugin
+    pub fn apply(config: &Config, kubeconfig: &KubeConfig, ip: &str) -> Fallible<()> {
+        if !*config.network_policy() {
+            return Ok(());
+        }
+        if *config.cni() != Cni::Bridge {
+            warn!(
+                "NetworkPolicies are already enforced by {}, skipping kube-router",
+                config.cni()
+            );
+            return Ok(());
+        }
+        info!("Deploying NetworkPolicy enforcement");
+
+        let dir = config.root().join("networkpolicy");
+        create_dir_all(&dir)?;
+
+        let yml = format!(
+            include_str!("assets/networkpolicy.yml"),
+            ip,
+            config.ports().apiserver()
+        );
+        let yml_file = dir.join("kube-router.yml");
+        fs::write(&yml_file, yml)?;
+
+        Process::run_to_completion("kubectl")
+            .arg("apply")
+            .arg(format!("--kubeconfig={}", kubeconfig.admin().display()))
+            .arg("-f")
+            .arg(yml_file)
+            .retries(3)
+            .run()?;
+
+        info!("NetworkPolicy enforcement deployed");
+        Ok(())
+    }
+
+    /// Retrieve a human readable description of how NetworkPolicies are
+    /// handled by the cluster
+    pub fn mode(config: &Config) -> String {
+        match (config.cni(), config.network_policy()) {
+            (Cni::Bridge, true) => "enforced by kube-router".into(),
+            (Cni::Bridge, false) => "not enforced".into(),
+            (x, _) => format!("enforced by {}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+
+    #[test]
+    fn mode_success_disabled() -> Fallible<()> {
+        let c = test_config()?;
+        assert_eq!(NetworkPolicy::mode(&c), "not enforced");
+        Ok(())
+    }
+}
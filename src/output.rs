@@ -0,0 +1,75 @@
+use crate::{config::OutputFormat, Fallible};
+use serde_json::Value;
+
+/// The machine readable output of the informational subcommands, which keeps
+/// the human readable logging out of stdout
+pub struct Output;
+
+impl Output {
+    /// Print a single result in the provided format
+    pub fn print(format: OutputFormat, value: &Value) -> Fallible<()> {
+        match format {
+            OutputFormat::Text => bail!("Text output has to be written by the subcommand"),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+            OutputFormat::Yaml => print!("{}", Self::yaml(value)?),
+        }
+        Ok(())
+    }
+
+    /// Print a single item of a stream in the provided format, which keeps
+    /// the concatenated output valid
+    pub fn print_item(format: OutputFormat, value: &Value) -> Fallible<()> {
+        match format {
+            OutputFormat::Text => bail!("Text output has to be written by the subcommand"),
+            OutputFormat::Json => println!("{}", serde_json::to_string(value)?),
+            OutputFormat::Yaml => print!("{}", Self::yaml(&Value::Array(vec![value.clone()]))?),
+        }
+        Ok(())
+    }
+
+    /// Render the provided value as YAML block without the document start
+    /// marker, which would split streamed items into separate documents
+    fn yaml(value: &Value) -> Fallible<String> {
+        let yaml = serde_yaml::to_string(value)?;
+        Ok(format!("{}\n", yaml.trim_start_matches("---").trim()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn yaml_success() -> Fallible<()> {
+        let value = json!({
+            "root": "/kubernix",
+            "uptime": 42,
+            "components": [
+                { "name": "etcd", "health": "ready" },
+                { "name": "kubelet", "health": "not ready" },
+            ],
+            "empty": {},
+            "with space": null,
+        });
+        let yaml = Output::yaml(&value)?;
+        assert!(!yaml.starts_with("---"));
+        assert_eq!(serde_yaml::from_str::<Value>(&yaml)?, value);
+        assert_eq!(
+            Output::yaml(&Value::Array(vec![json!({ "time": 1 })]))?,
+            "- time: 1\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn yaml_success_stream() -> Fallible<()> {
+        let items = vec![json!({ "time": 1 }), json!({ "time": 2 })];
+        let mut stream = String::new();
+        for item in &items {
+            stream.push_str(&Output::yaml(&Value::Array(vec![item.clone()]))?);
+        }
+        assert_eq!(serde_yaml::from_str::<Value>(&stream)?, Value::Array(items));
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{Error, Fallible};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+/// A single package of the environment which gets replaced by the one of
+/// another flake, like a local Kubernetes checkout
+pub struct PackageOverride {
+    package: String,
+    reference: String,
+    attribute: String,
+}
+
+impl Display for PackageOverride {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}#{}", self.package, self.reference, self.attribute)
+    }
+}
+
+impl FromStr for PackageOverride {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let mut split = s.splitn(2, '=');
+        let (package, target) = match (split.next(), split.next()) {
+            (Some(package), Some(target)) if !target.is_empty() => (package, target),
+            _ => bail!(
+                "Invalid package override '{}', expected 'PACKAGE=FLAKE[#ATTRIBUTE]'",
+                s
+            ),
+        };
+        let mut split = target.rsplitn(2, '#');
+        let (reference, attribute) = match (split.next(), split.next()) {
+            (Some(attribute), Some(reference)) => (reference, attribute),
+            _ => (target, package),
+        };
+        if reference.is_empty() {
+            bail!("Package override '{}' contains no flake reference", s)
+        }
+        for name in &[package, attribute] {
+            if !Self::is_identifier(name) {
+                bail!("Invalid package name '{}' of override '{}'", name, s)
+            }
+        }
+        Ok(PackageOverride {
+            package: package.into(),
+            reference: reference.into(),
+            attribute: attribute.into(),
+        })
+    }
+}
+
+impl PackageOverride {
+    /// Render the overlay replacing the packages of the provided overrides
+    pub fn overlay(overrides: &[PackageOverride]) -> String {
+        let attributes: Vec<String> = overrides
+            .iter()
+            .map(|x| {
+                format!(
+                    "{} = package {} {};",
+                    x.package,
+                    Self::nix_string(&x.reference),
+                    Self::nix_string(&x.attribute)
+                )
+            })
+            .collect();
+        include_str!("../nix/overrides.nix").replace("/* OVERRIDES */", &attributes.join("\n  "))
+    }
+
+    /// Check if the provided name is a plain Nix attribute name
+    fn is_identifier(name: &str) -> bool {
+        let mut chars = name.chars();
+        match chars.next() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+            _ => return false,
+        }
+        chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '\'')
+    }
+
+    /// Quote the provided value as Nix string
+    fn nix_string(value: &str) -> String {
+        format!(
+            "\"{}\"",
+            value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace("${", "\\${")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        let o: PackageOverride = "kubernetes=github:me/nixpkgs#kubernetes".parse()?;
+        assert_eq!(o.package, "kubernetes");
+        assert_eq!(o.reference, "github:me/nixpkgs");
+        assert_eq!(o.attribute, "kubernetes");
+        assert_eq!(o.to_string(), "kubernetes=github:me/nixpkgs#kubernetes");
+
+        let o: PackageOverride = "cri-o=path:/home/me/cri-o".parse()?;
+        assert_eq!(o.reference, "path:/home/me/cri-o");
+        assert_eq!(o.attribute, "cri-o");
+
+        let o: PackageOverride = "etcd=github:me/nixpkgs#etcd_3_4".parse()?;
+        assert_eq!(o.attribute, "etcd_3_4");
+        Ok(())
+    }
+
+    #[test]
+    fn from_str_failure() {
+        assert!("kubernetes".parse::<PackageOverride>().is_err());
+        assert!("kubernetes=".parse::<PackageOverride>().is_err());
+        assert!("kubernetes=#kubernetes".parse::<PackageOverride>().is_err());
+        assert!("=github:me/nixpkgs".parse::<PackageOverride>().is_err());
+        assert!("kube rnetes=github:me/nixpkgs"
+            .parse::<PackageOverride>()
+            .is_err());
+        assert!("kubernetes=github:me/nixpkgs#a.b"
+            .parse::<PackageOverride>()
+            .is_err());
+    }
+
+    #[test]
+    fn overlay_success() -> Fallible<()> {
+        let overlay = PackageOverride::overlay(&[
+            "kubernetes=github:me/nixpkgs#kubernetes".parse()?,
+            "etcd=path:/src/\"${x}\"".parse()?,
+        ]);
+        assert!(overlay.starts_with("self: super:"));
+        assert!(overlay.contains(
+            "kubernetes = package \"github:me/nixpkgs\" \"kubernetes\";\n  etcd = package"
+        ));
+        assert!(overlay.contains(r#""path:/src/\"\${x}\"" "etcd";"#));
+        assert!(!overlay.contains("/* OVERRIDES */"));
+        assert!(PackageOverride::overlay(&[]).contains("{\n  \n}"));
+        Ok(())
+    }
+}
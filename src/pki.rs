@@ -1,16 +1,15 @@
-use crate::{network::Network, Config};
-use failure::{bail, format_err, Fallible};
+use crate::{network::Network, process::Process, seed::Seed, Config, Fallible};
 use getset::Getters;
 use log::{debug, info};
 use serde_json::{json, to_string_pretty};
 use std::{
-    fs::{self, create_dir_all},
+    fs::{self, create_dir_all, rename},
     net::Ipv4Addr,
     path::{Path, PathBuf},
     process::{Command, Stdio},
 };
 
-#[derive(Getters)]
+#[derive(Clone, Getters)]
 pub struct Pki {
     #[get = "pub"]
     admin: Pair,
@@ -24,6 +23,9 @@ pub struct Pki {
     #[get = "pub"]
     controller_manager: Pair,
 
+    #[get = "pub"]
+    front_proxy_client: Pair,
+
     #[get = "pub"]
     kubelet: Pair,
 
@@ -37,7 +39,7 @@ pub struct Pki {
     service_account: Pair,
 }
 
-#[derive(Getters)]
+#[derive(Clone, Getters)]
 pub struct Pair {
     #[get = "pub"]
     cert: PathBuf,
@@ -56,6 +58,9 @@ impl Pair {
 
 #[derive(Getters)]
 struct PkiConfig<'a> {
+    #[get = "pub"]
+    config: &'a Config,
+
     #[get = "pub"]
     ca: &'a Pair,
 
@@ -70,47 +75,116 @@ struct PkiConfig<'a> {
 }
 
 impl Pki {
+    /// The directory name of the PKI within the root
+    pub const DIR: &'static str = "pki";
+
+    /// The common name of the client certificate the API Server uses to
+    /// forward requests to extension API servers
+    pub const FRONT_PROXY_CLIENT: &'static str = "front-proxy-client";
+
+    /// The validity of the CA in days, which is the default of cfssl
+    const CA_DAYS: u32 = 1825;
+
+    /// The validity of all other certificates in days
+    const DAYS: u32 = 365;
+
     pub fn new(config: &Config, network: &Network, ip: &str, hostname: &str) -> Fallible<Pki> {
         info!("Generating certificates");
 
         // Create the target dir
-        let pki_dir = &config.root().join("pki");
+        let pki_dir = &config.root().join(Self::DIR);
         create_dir_all(pki_dir)?;
 
-        // Set the hostnames
-        let hostnames = &[
-            ip,
-            &network.api()?.to_string(),
-            &Ipv4Addr::LOCALHOST.to_string(),
-            hostname,
-            "kubernetes",
-            "kubernetes.default",
-            "kubernetes.default.svc",
-            "kubernetes.default.svc.cluster",
-            "kubernetes.svc.cluster.local",
-        ];
-
-        let ca = Self::setup_ca(pki_dir)?;
+        let ca = Self::setup_ca(config, pki_dir)?;
         let pki_config = PkiConfig {
+            config,
             dir: pki_dir,
             ca: &ca,
             ca_config: Self::write_ca_config(pki_dir)?,
-            hostnames: &hostnames.join(","),
+            hostnames: &Self::hostnames(config, network, ip, hostname)?,
         };
 
         Ok(Pki {
             admin: Self::setup_admin(&pki_config)?,
             apiserver: Self::setup_apiserver(&pki_config)?,
             controller_manager: Self::setup_controller_manager(&pki_config)?,
+            front_proxy_client: Self::setup_front_proxy_client(&pki_config)?,
             kubelet: Self::setup_kubelet(&pki_config, hostname)?,
             proxy: Self::setup_proxy(&pki_config)?,
             scheduler: Self::setup_scheduler(&pki_config)?,
-            service_account: Self::setup_service_account(&pki_config)?,
+            service_account: Self::setup_service_account(config, &pki_config)?,
             ca,
         })
     }
 
-    fn setup_ca(dir: &Path) -> Fallible<Pair> {
+    /// Regenerate the certificates containing the IP of the host, whereas
+    /// the CA and all other certificates stay untouched
+    pub fn refresh(
+        &self,
+        config: &Config,
+        network: &Network,
+        ip: &str,
+        hostname: &str,
+    ) -> Fallible<()> {
+        info!("Regenerating certificates for IP {}", ip);
+        let pki_dir = &config.root().join(Self::DIR);
+        let pki_config = PkiConfig {
+            config,
+            dir: pki_dir,
+            ca: &self.ca,
+            ca_config: Self::write_ca_config(pki_dir)?,
+            hostnames: &Self::hostnames(config, network, ip, hostname)?,
+        };
+        Self::setup_apiserver(&pki_config)?;
+        Self::setup_kubelet(&pki_config, hostname)?;
+        Ok(())
+    }
+
+    /// The hostnames of the API Server and kubelet certificates
+    fn hostnames(config: &Config, network: &Network, ip: &str, hostname: &str) -> Fallible<String> {
+        let mut hostnames = vec![
+            ip.to_owned(),
+            network.api()?.to_string(),
+            Ipv4Addr::LOCALHOST.to_string(),
+            hostname.to_owned(),
+            "kubernetes".into(),
+            "kubernetes.default".into(),
+            "kubernetes.default.svc".into(),
+            "kubernetes.default.svc.cluster".into(),
+            "kubernetes.svc.cluster.local".into(),
+            format!("kubernetes.default.svc.{}", config.cluster_domain()),
+        ];
+        // The API Server pair is used by etcd as well
+        if *config.etcd_address() != Ipv4Addr::LOCALHOST {
+            hostnames.push(config.etcd_address().to_string());
+        }
+        Ok(hostnames.join(","))
+    }
+
+    /// Load the already generated certificates of a previous run
+    pub fn load(config: &Config, hostname: &str) -> Pki {
+        let dir = &config.root().join(Self::DIR);
+        let service_account = match (config.service_account_cert(), config.service_account_key()) {
+            (Some(cert), Some(key)) => Pair {
+                cert: cert.to_path_buf(),
+                key: key.to_path_buf(),
+            },
+            _ => Pair::new(dir, "service-account"),
+        };
+        Pki {
+            admin: Pair::new(dir, "admin"),
+            apiserver: Pair::new(dir, "kubernetes"),
+            ca: Pair::new(dir, "ca"),
+            controller_manager: Pair::new(dir, "kube-controller-manager"),
+            front_proxy_client: Pair::new(dir, Self::FRONT_PROXY_CLIENT),
+            kubelet: Pair::new(dir, hostname),
+            proxy: Pair::new(dir, "kube-proxy"),
+            scheduler: Pair::new(dir, "kube-scheduler"),
+            service_account,
+        }
+    }
+
+    fn setup_ca(config: &Config, dir: &Path) -> Fallible<Pair> {
         const NAME: &str = "ca";
         debug!("Creating CA certificates");
         const CN: &str = "Kubernetes";
@@ -139,8 +213,10 @@ impl Pki {
             debug!("cfssl/json stderr: {}", String::from_utf8(output.stderr)?);
             bail!("CA certificate generation failed");
         }
+        let pair = Pair::new(dir, NAME);
+        Self::seed_serial(config, &pair, None, "serial-ca", Self::CA_DAYS)?;
         debug!("CA certificates created");
-        Ok(Pair::new(dir, NAME))
+        Ok(pair)
     }
 
     fn setup_kubelet(pki_config: &PkiConfig, hostname: &str) -> Fallible<Pair> {
@@ -165,6 +241,13 @@ impl Pki {
         Ok(Self::generate(pki_config, NAME, &csr_file)?)
     }
 
+    fn setup_front_proxy_client(pki_config: &PkiConfig) -> Fallible<Pair> {
+        const NAME: &str = Pki::FRONT_PROXY_CLIENT;
+        let csr_file = pki_config.dir.join("front-proxy-client-csr.json");
+        Self::write_csr(NAME, NAME, &csr_file)?;
+        Ok(Self::generate(pki_config, NAME, &csr_file)?)
+    }
+
     fn setup_proxy(pki_config: &PkiConfig) -> Fallible<Pair> {
         const NAME: &str = "kube-proxy";
         let csr_file = pki_config.dir.join("admin-csr.json");
@@ -187,7 +270,25 @@ impl Pki {
         Ok(Self::generate(pki_config, NAME, &csr_file)?)
     }
 
-    fn setup_service_account(pki_config: &PkiConfig) -> Fallible<Pair> {
+    fn setup_service_account(config: &Config, pki_config: &PkiConfig) -> Fallible<Pair> {
+        // Use the user provided key pair if available
+        match (config.service_account_cert(), config.service_account_key()) {
+            (Some(cert), Some(key)) => {
+                debug!("Using provided service account key pair");
+                for file in &[cert, key] {
+                    if !file.exists() {
+                        bail!("Service account file '{}' does not exist", file.display())
+                    }
+                }
+                return Ok(Pair {
+                    cert: cert.to_path_buf(),
+                    key: key.to_path_buf(),
+                });
+            }
+            (None, None) => {}
+            _ => bail!("Both service account certificate and key have to be provided"),
+        }
+
         const NAME: &str = "service-account";
         let csr_file = pki_config.dir.join("service-account-csr.json");
         Self::write_csr("service-accounts", "Kubernetes", &csr_file)?;
@@ -223,9 +324,58 @@ impl Pki {
             debug!("cfssl/json stderr: {}", String::from_utf8(output.stderr)?);
             bail!("cfssl command failed");
         }
+        let pair = Pair::new(&pki_config.dir, name);
+
+        // A refreshed certificate has different hostnames, which results in
+        // a different serial than the one of its predecessor
+        Self::seed_serial(
+            pki_config.config,
+            &pair,
+            Some(pki_config.ca),
+            &format!("serial-{}-{}", name, pki_config.hostnames),
+            Self::DAYS,
+        )?;
         debug!("Certificate created for {}", name);
+        Ok(pair)
+    }
 
-        Ok(Pair::new(&pki_config.dir, name))
+    /// Re-sign the certificate with a serial number derived from the seed if
+    /// configured, because cfssl does not support choosing the serial. The
+    /// certificate signs itself if no issuer is provided.
+    fn seed_serial(
+        config: &Config,
+        pair: &Pair,
+        issuer: Option<&Pair>,
+        purpose: &str,
+        days: u32,
+    ) -> Fallible<()> {
+        let serial = match Seed::serial(config, purpose) {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        debug!("Using serial {} for '{}'", serial, pair.cert.display());
+        let signed = pair.cert.with_extension("seeded");
+        let openssl = Process::run_to_completion("openssl")
+            .arg("x509")
+            .arg("-in")
+            .arg(&pair.cert)
+            .arg("-set_serial")
+            .arg(&serial)
+            .arg("-days")
+            .arg(days.to_string())
+            .arg("-out")
+            .arg(&signed);
+        match issuer {
+            Some(ca) => openssl
+                .arg("-CA")
+                .arg(&ca.cert)
+                .arg("-CAkey")
+                .arg(&ca.key)
+                .run()?,
+            None => openssl.arg("-signkey").arg(&pair.key).run()?,
+        };
+        rename(signed, &pair.cert)?;
+        Ok(())
     }
 
     fn write_csr(cn: &str, o: &str, dest: &Path) -> Fallible<()> {
@@ -278,7 +428,7 @@ impl Pki {
 mod tests {
     use super::*;
     use crate::{
-        config::tests::{test_config, test_config_wrong_root},
+        config::tests::{test_config, test_config_seed, test_config_wrong_root},
         network::tests::test_network,
     };
 
@@ -290,6 +440,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn new_success_seed() -> Fallible<()> {
+        let c = test_config_seed()?;
+        let n = test_network()?;
+        let p = Pki::new(&c, &n, "", "")?;
+        let serial = |pair: &Pair| -> Fallible<String> {
+            Process::run_to_completion("openssl")
+                .arg("x509")
+                .arg("-noout")
+                .arg("-serial")
+                .arg("-in")
+                .arg(pair.cert())
+                .run()
+        };
+        let expected = Seed::serial(&c, "serial-ca").ok_or_else(|| format_err!("no serial"))?;
+        assert_eq!(
+            serial(p.ca())?.trim().to_lowercase(),
+            format!("serial={}", &expected[2..])
+        );
+        assert_ne!(serial(p.ca())?, serial(p.admin())?);
+        Ok(())
+    }
+
     #[test]
     fn new_failure() -> Fallible<()> {
         let c = test_config_wrong_root()?;
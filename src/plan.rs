@@ -0,0 +1,151 @@
+use crate::{
+    pki::{Pair, Pki},
+    process::Process,
+    Config, Fallible,
+};
+use log::info;
+use std::{
+    fs::{self, read_dir, read_to_string, symlink_metadata},
+    path::{Path, PathBuf},
+};
+
+/// The plan of a dry run, which describes what kubernix would do
+pub struct Plan;
+
+impl Plan {
+    /// The file name of the plan within the root directory
+    const FILENAME: &'static str = "plan.txt";
+
+    /// The name of the files containing the full command lines
+    const RUN_FILE: &'static str = "run.sh";
+
+    /// The path of the plan within the provided root directory
+    pub fn file(root: &Path) -> PathBuf {
+        root.join(Self::FILENAME)
+    }
+
+    /// Write the plan of the rendered root directory and print it
+    pub fn write(config: &Config, pki: &Pki) -> Fallible<()> {
+        let certificates = [
+            pki.ca(),
+            pki.apiserver(),
+            pki.admin(),
+            pki.controller_manager(),
+            pki.scheduler(),
+            pki.kubelet(),
+            pki.proxy(),
+            pki.front_proxy_client(),
+            pki.service_account(),
+        ]
+        .iter()
+        .map(|x| Self::certificate(x))
+        .collect::<Fallible<Vec<_>>>()?;
+
+        let plan = Self::render(config.root(), &certificates)?;
+        let file = Self::file(config.root());
+        fs::write(&file, &plan)?;
+        print!("{}", plan);
+        info!("Plan written to '{}'", file.display());
+        Ok(())
+    }
+
+    /// Retrieve the metadata of the certificate, whereas the key is never
+    /// part of the plan
+    fn certificate(pair: &Pair) -> Fallible<(PathBuf, String)> {
+        let metadata = Process::run_to_completion("openssl")
+            .arg("x509")
+            .arg("-noout")
+            .arg("-subject")
+            .arg("-issuer")
+            .arg("-in")
+            .arg(pair.cert())
+            .run()?;
+        Ok((pair.cert().to_owned(), metadata))
+    }
+
+    /// Render the plan of the provided root directory. Paths are relative to
+    /// the root to keep plans comparable.
+    fn render(root: &Path, certificates: &[(PathBuf, String)]) -> Fallible<String> {
+        let relative = |x: &Path| x.strip_prefix(root).unwrap_or(x).display().to_string();
+
+        let mut plan = format!("Plan for '{}'\n", root.display());
+        plan.push_str("\nCertificates:\n");
+        for (cert, metadata) in certificates {
+            plan.push_str(&format!("  {}\n", relative(cert)));
+            for line in metadata.lines().filter(|x| !x.trim().is_empty()) {
+                plan.push_str(&format!("    {}\n", line.trim()));
+            }
+        }
+
+        let mut files = vec![];
+        Self::files(root, &mut files)?;
+        files.retain(|x| x != &Self::file(root) && !x.starts_with(root.join("log")));
+        files.sort();
+
+        plan.push_str("\nFiles:\n");
+        for file in &files {
+            plan.push_str(&format!("  {}\n", relative(file)));
+        }
+
+        plan.push_str("\nProcesses:\n");
+        for file in files.iter().filter(|x| x.ends_with(Self::RUN_FILE)) {
+            let dir = file.parent().unwrap_or(root);
+            plan.push_str(&format!("  {}:\n", relative(dir)));
+            // Skip the shebang and shell options of the run file
+            for line in read_to_string(file)?
+                .lines()
+                .skip_while(|x| x.starts_with("#!") || x.starts_with("set ") || x.is_empty())
+            {
+                plan.push_str(&format!("    {}\n", line));
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Collect all regular files below the provided directory
+    fn files(dir: &Path, files: &mut Vec<PathBuf>) -> Fallible<()> {
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            let metadata = symlink_metadata(&path)?;
+            if metadata.is_dir() {
+                Self::files(&path, files)?;
+            } else if metadata.is_file() {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::create_dir_all;
+    use tempfile::tempdir;
+
+    #[test]
+    fn render_success() -> Fallible<()> {
+        let d = tempdir()?;
+        let root = d.path();
+        create_dir_all(root.join("etcd"))?;
+        create_dir_all(root.join("log"))?;
+        fs::write(root.join("etcd").join("env"), "")?;
+        fs::write(
+            root.join("etcd").join(Plan::RUN_FILE),
+            "#!/usr/bin/env bash\nset -euo pipefail\n\ncd /\netcd \\\n    --name=etcd\n",
+        )?;
+        fs::write(root.join("log").join("etcd.log"), "")?;
+        fs::write(Plan::file(root), "")?;
+
+        let certificates = vec![(
+            root.join("pki").join("ca.pem"),
+            "subject=CN = Kubernetes\n".to_owned(),
+        )];
+        let plan = Plan::render(root, &certificates)?;
+        assert!(plan.contains("  pki/ca.pem\n    subject=CN = Kubernetes\n"));
+        assert!(plan.contains("Files:\n  etcd/env\n  etcd/run.sh\n\n"));
+        assert!(plan.ends_with("Processes:\n  etcd:\n    cd /\n    etcd \\\n        --name=etcd\n"));
+        assert!(!plan.contains("etcd.log"));
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{Config, Fallible};
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Deserialize, Getters, PartialEq, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+/// The ports of all components listening on the host
+pub struct Ports {
+    #[get = "pub"]
+    /// The secure port of the API Server, which is served by the front proxy
+    /// if it is enabled
+    apiserver: u16,
+
+    #[get = "pub"]
+    /// The secure port of the first API Server behind the front proxy,
+    /// whereas additional replicas use the subsequent ports
+    apiserver_backend: u16,
+
+    #[get = "pub"]
+    /// The legacy insecure port of the API Server, bound to localhost only
+    apiserver_insecure: u16,
+
+    #[get = "pub"]
+    /// The client port of etcd
+    etcd_client: u16,
+
+    #[get = "pub"]
+    /// The peer port of etcd
+    etcd_peer: u16,
+
+    #[get = "pub"]
+    /// The port of the kubelet API
+    kubelet: u16,
+
+    #[get = "pub"]
+    /// The legacy read-only port of the kubelet
+    kubelet_read_only: u16,
+
+    #[get = "pub"]
+    /// The health check port of the kubelet, bound to localhost only
+    kubelet_healthz: u16,
+
+    #[get = "pub"]
+    /// The health check port of the network proxy
+    proxy_healthz: u16,
+
+    #[get = "pub"]
+    /// The metrics and profiling port of the network proxy, bound to
+    /// localhost only
+    proxy_metrics: u16,
+
+    #[get = "pub"]
+    /// The secure port of the first Controller Manager replica
+    controller_manager: u16,
+
+    #[get = "pub"]
+    /// The secure port of the first Scheduler replica
+    scheduler: u16,
+
+    #[get = "pub"]
+    /// The legacy insecure port of the first Scheduler replica
+    scheduler_insecure: u16,
+}
+
+impl Default for Ports {
+    fn default() -> Self {
+        Self {
+            apiserver: 6443,
+            apiserver_backend: 6444,
+            apiserver_insecure: 8080,
+            etcd_client: 2379,
+            etcd_peer: 2380,
+            kubelet: 10250,
+            kubelet_read_only: 10255,
+            kubelet_healthz: 10248,
+            proxy_healthz: 10256,
+            proxy_metrics: 10249,
+            controller_manager: 10257,
+            scheduler: 10259,
+            scheduler_insecure: 10251,
+        }
+    }
+}
+
+impl Ports {
+    /// Verify that no port gets used twice, including the ones of all
+    /// replicas
+    pub fn preflight(config: &Config) -> Fallible<()> {
+        Self::validate(
+            config.ports(),
+            *config.apiserver_replicas(),
+            *config.control_plane_replicas(),
+        )
+    }
+
+    /// Shift all ports by the provided offset, for example to run multiple
+    /// clusters side by side
+    pub fn offset(&self, offset: u16) -> Fallible<Ports> {
+        let shift = |port: u16| {
+            port.checked_add(offset)
+                .ok_or_else(|| format_err!("Port {} cannot be shifted by {}", port, offset))
+        };
+        Ok(Ports {
+            apiserver: shift(self.apiserver)?,
+            apiserver_backend: shift(self.apiserver_backend)?,
+            apiserver_insecure: shift(self.apiserver_insecure)?,
+            etcd_client: shift(self.etcd_client)?,
+            etcd_peer: shift(self.etcd_peer)?,
+            kubelet: shift(self.kubelet)?,
+            kubelet_read_only: shift(self.kubelet_read_only)?,
+            kubelet_healthz: shift(self.kubelet_healthz)?,
+            proxy_healthz: shift(self.proxy_healthz)?,
+            proxy_metrics: shift(self.proxy_metrics)?,
+            controller_manager: shift(self.controller_manager)?,
+            scheduler: shift(self.scheduler)?,
+            scheduler_insecure: shift(self.scheduler_insecure)?,
+        })
+    }
+
+    fn validate(&self, apiserver_replicas: u8, control_plane_replicas: u8) -> Fallible<()> {
+        let mut used = vec![
+            ("apiserver", self.apiserver),
+            ("apiserver-insecure", self.apiserver_insecure),
+            ("etcd-client", self.etcd_client),
+            ("etcd-peer", self.etcd_peer),
+            ("kubelet", self.kubelet),
+            ("kubelet-read-only", self.kubelet_read_only),
+            ("kubelet-healthz", self.kubelet_healthz),
+            ("proxy-healthz", self.proxy_healthz),
+            ("proxy-metrics", self.proxy_metrics),
+        ];
+        for replica in 0..u16::from(apiserver_replicas) {
+            used.push(("apiserver-backend", self.apiserver_backend + replica));
+        }
+        for replica in 0..u16::from(control_plane_replicas) {
+            let offset = replica * Config::REPLICA_PORT_OFFSET;
+            used.push(("controller-manager", self.controller_manager + offset));
+            used.push(("scheduler", self.scheduler + offset));
+            used.push(("scheduler-insecure", self.scheduler_insecure + offset));
+        }
+
+        let mut seen = HashMap::new();
+        for (name, port) in used {
+            if port == 0 {
+                bail!("Port '{}' must not be 0", name)
+            }
+            if let Some(other) = seen.insert(port, name) {
+                bail!("Port {} is used by '{}' and '{}'", port, other, name)
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_success() -> Fallible<()> {
+        Ports::default().validate(3, 3)
+    }
+
+    #[test]
+    fn validate_failure() {
+        let ports = Ports {
+            kubelet: 2379,
+            ..Default::default()
+        };
+        assert!(ports.validate(1, 1).is_err());
+
+        // The second API Server replica collides with the insecure port
+        let ports = Ports {
+            apiserver_backend: 8079,
+            ..Default::default()
+        };
+        assert!(ports.validate(1, 1).is_ok());
+        assert!(ports.validate(2, 1).is_err());
+
+        let ports = Ports {
+            scheduler: 0,
+            ..Default::default()
+        };
+        assert!(ports.validate(1, 1).is_err());
+    }
+
+    #[test]
+    fn offset_success() -> Fallible<()> {
+        let ports = Ports::default().offset(1000)?;
+        assert_eq!(ports.apiserver, 7443);
+        assert_eq!(ports.scheduler_insecure, 11251);
+        assert!(Ports::default().offset(60000).is_err());
+        Ok(())
+    }
+}
@@ -1,29 +1,66 @@
-use crate::Config;
-use failure::{bail, format_err, Fallible};
-use log::{debug, error, info};
+use crate::{
+    component::ComponentKind,
+    envpolicy::EnvPolicy,
+    events::{EventKind, EventLog},
+    instrumentation::Instrumentation,
+    localbinary::LocalBinary,
+    readiness::Readiness,
+    rlimit::RLimit,
+    runinfo::RunInfo,
+    signals::Signals,
+    supervisor::Supervisor,
+    Config, Error, Fallible,
+};
+use getset::Getters;
+use log::{debug, error, info, warn};
 use nix::{
-    sys::signal::{kill, Signal},
+    errno::Errno,
+    sys::{
+        signal::{kill, Signal},
+        stat::{umask, Mode},
+    },
     unistd::Pid,
+    Error::Sys,
 };
+use regex::Regex;
 use std::{
-    fs::{self, create_dir_all, metadata, set_permissions, File},
-    io::{BufRead, BufReader},
-    os::unix::fs::PermissionsExt,
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    fmt::{self, Display},
+    fs::{
+        self, create_dir_all, metadata, read_to_string, remove_file, set_permissions, File,
+        OpenOptions,
+    },
+    io::{self, Read, Seek, SeekFrom, Write},
+    os::unix::{
+        fs::{MetadataExt, PermissionsExt},
+        process::CommandExt,
+    },
     path::{Path, PathBuf},
-    process::{Command, Stdio},
-    sync::mpsc::{channel, Sender},
-    thread::{spawn, JoinHandle},
-    time::Instant,
+    process::{Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, sleep, spawn},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::{self as async_process, Child},
+    task::JoinHandle,
+    time::{delay_for, timeout as with_timeout},
 };
+use tracing::info_span;
 
 /// A general process abstraction
 pub struct Process {
-    command: String,
-    kill: Sender<()>,
-    log_file: PathBuf,
-    pid: u32,
+    name: String,
+    dir: PathBuf,
+    root: PathBuf,
+    stopping: Arc<AtomicBool>,
+    pid: Arc<AtomicU32>,
     watch: Option<JoinHandle<Fallible<()>>>,
-    readyness_timeout: u64,
 }
 
 /// The trait to stop something
@@ -35,58 +72,881 @@ pub trait Stoppable {
 /// Startable process type
 pub type Startable = Box<dyn Stoppable + Send>;
 
-impl Process {
-    /// Creates a new `Process` instance by spawning the provided command `cmd`.
-    /// If the process creation fails, an `Error` will be returned.
-    pub fn start(
-        config: &Config,
-        dir: &Path,
-        command: &'static str,
-        args: &[&str],
-    ) -> Fallible<Process> {
-        // Prepare the commands
-        if command.is_empty() {
-            bail!("No valid command provided")
+/// The behavior if a process dies unexpectedly
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RestartPolicy {
+    /// Never restart the process
+    Never,
+
+    /// Restart the process up to the provided amount of times if it exits
+    /// unexpectedly
+    OnFailure(u32),
+}
+
+/// How a `ProcessBackend` handles a process to be spawned
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpawnMode {
+    /// Spawn the real binary
+    Real,
+
+    /// Pretend a ready process without spawning anything
+    Fake,
+}
+
+/// The backend deciding how supervised processes get spawned, which allows
+/// running components without their binaries
+pub trait ProcessBackend: Send + Sync {
+    /// Decide how to spawn the process, whereas an error simulates a process
+    /// which failed to become ready
+    fn spawn(&self, name: &str, command: &str, args: &[String]) -> Fallible<SpawnMode>;
+}
+
+/// The default backend spawning all binaries
+pub struct RealBackend;
+
+impl ProcessBackend for RealBackend {
+    fn spawn(&self, _: &str, _: &str, _: &[String]) -> Fallible<SpawnMode> {
+        Ok(SpawnMode::Real)
+    }
+}
+
+#[derive(Default)]
+/// A backend faking all processes and recording their command lines, whereas
+/// single processes can be configured to fail
+pub struct FakeBackend {
+    failures: HashMap<String, String>,
+    spawned: Mutex<Vec<(String, Vec<String>)>>,
+}
+
+impl FakeBackend {
+    /// Let the process with the provided name fail with the message
+    pub fn fail(mut self, name: &str, message: &str) -> Self {
+        self.failures.insert(name.to_owned(), message.to_owned());
+        self
+    }
+
+    /// Retrieve the names and arguments of all faked processes in their
+    /// spawn order
+    pub fn spawned(&self) -> Vec<(String, Vec<String>)> {
+        self.spawned.lock().map(|x| x.clone()).unwrap_or_default()
+    }
+}
+
+impl ProcessBackend for FakeBackend {
+    fn spawn(&self, name: &str, _: &str, args: &[String]) -> Fallible<SpawnMode> {
+        if let Some(message) = self.failures.get(name) {
+            bail!("{}", message)
         }
+        if let Ok(mut x) = self.spawned.lock() {
+            x.push((name.to_owned(), args.to_vec()));
+        }
+        Ok(SpawnMode::Fake)
+    }
+}
+
+/// A pattern in the output of a process indicating that it is ready
+#[derive(Clone, Debug)]
+pub enum ReadyPattern {
+    /// A plain substring of a line
+    Literal(String),
+
+    /// A regular expression matching a line
+    Regex(Regex),
+}
+
+impl ReadyPattern {
+    /// Check if the pattern occurs in the provided line
+    fn matches(&self, line: &str) -> bool {
+        match self {
+            ReadyPattern::Literal(x) => line.contains(x.as_str()),
+            ReadyPattern::Regex(x) => x.is_match(line),
+        }
+    }
+}
+
+impl Display for ReadyPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadyPattern::Literal(x) => write!(f, "{}", x),
+            ReadyPattern::Regex(x) => write!(f, "/{}/", x),
+        }
+    }
+}
+
+/// A builder for spawning a `Process`
+pub struct ProcessBuilder<'a> {
+    config: &'a Config,
+    dir: PathBuf,
+    command: String,
+    name: Option<String>,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    inherit_env: Vec<String>,
+    cwd: Option<PathBuf>,
+    umask: u32,
+    stdin: Option<PathBuf>,
+    readiness: Vec<ReadyPattern>,
+    timeout: Duration,
+    restart: RestartPolicy,
+    dependencies: Vec<ComponentKind>,
+    dependency_timeout: Duration,
+    mark_ready: bool,
+}
+
+impl<'a> ProcessBuilder<'a> {
+    /// Set the name of the process, which is used for its log file and
+    /// defaults to the file name of the command
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Add a single argument
+    pub fn arg<S: Into<String>>(mut self, arg: S) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Add multiple arguments
+    pub fn args<S: AsRef<str>>(mut self, args: &[S]) -> Self {
+        self.args.extend(args.iter().map(|x| x.as_ref().to_owned()));
+        self
+    }
+
+    /// Set an additional environment variable
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.env.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Set multiple additional environment variables
+    pub fn envs(mut self, vars: &[(&str, String)]) -> Self {
+        self.env
+            .extend(vars.iter().map(|(k, v)| ((*k).to_owned(), v.clone())));
+        self
+    }
+
+    /// Pass a variable of the current environment to the process, even if the
+    /// configuration is pure
+    pub fn inherit_env(mut self, key: &str) -> Self {
+        self.inherit_env.push(key.to_owned());
+        self
+    }
+
+    /// Set the working directory, which defaults to the process directory
+    pub fn cwd(mut self, cwd: &Path) -> Self {
+        self.cwd = Some(cwd.to_owned());
+        self
+    }
+
+    /// Set the file mode creation mask, which defaults to `0022`
+    pub fn umask(mut self, umask: u32) -> Self {
+        self.umask = umask;
+        self
+    }
+
+    /// Read the standard input from the provided file, which defaults to
+    /// `/dev/null`
+    pub fn stdin(mut self, file: &Path) -> Self {
+        self.stdin = Some(file.to_owned());
+        self
+    }
+
+    /// Consider the process ready if the provided pattern occurs in its
+    /// output. Multiple patterns can be provided, whereas any of them is
+    /// sufficient.
+    pub fn ready_on(mut self, pattern: &str) -> Self {
+        self.readiness
+            .push(ReadyPattern::Literal(pattern.to_owned()));
+        self
+    }
+
+    /// Consider the process ready if a line of its output matches the
+    /// provided regular expression, which can be combined with `ready_on`
+    pub fn ready_on_regex(mut self, regex: Regex) -> Self {
+        self.readiness.push(ReadyPattern::Regex(regex));
+        self
+    }
+
+    /// Set the readiness timeout, which defaults to 30 seconds
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the restart policy, which defaults to `RestartPolicy::Never`
+    pub fn restart(mut self, restart: RestartPolicy) -> Self {
+        self.restart = restart;
+        self
+    }
+
+    /// Wait for the provided component to be ready before spawning
+    pub fn depends_on(mut self, kind: ComponentKind) -> Self {
+        self.dependencies.push(kind);
+        self
+    }
+
+    /// Set the maximum time to wait for all dependencies, which defaults to
+    /// `Process::DEPENDENCY_TIMEOUT`
+    pub fn dependency_timeout(mut self, timeout: Duration) -> Self {
+        self.dependency_timeout = timeout;
+        self
+    }
+
+    /// Do not mark the process as ready after spawning, which lets dependent
+    /// processes wait until `Process::mark_ready` gets called
+    pub fn defer_ready(mut self) -> Self {
+        self.mark_ready = false;
+        self
+    }
+
+    /// Spawn the process and wait for it to become ready
+    pub fn spawn(self) -> Fallible<Process> {
+        // Prepare the commands
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => Path::new(&self.command)
+                .file_name()
+                .and_then(|x| x.to_str())
+                .map(ToOwned::to_owned)
+                .ok_or_else(|| format_err!("No valid command provided"))?,
+        };
+        let span = info_span!("spawn", process = name.as_str());
+        let _enter = span.enter();
 
         // Prepare the log dir and file
-        let log_dir = config.root().join("log");
+        let log_dir = self.config.root().join("log");
         create_dir_all(&log_dir)?;
-        let mut log_file = log_dir.join(command);
+        let mut log_file = log_dir.join(&name);
         log_file.set_extension("log");
 
-        let out_file = File::create(&log_file)?;
-        let err_file = out_file.try_clone()?;
+        // Remove any stale readiness marker
+        create_dir_all(&self.dir)?;
+        Readiness::unmark(&self.dir)?;
 
-        // Spawn the process child
-        let mut child = Command::new(command)
-            .args(args)
-            .stderr(Stdio::from(err_file))
-            .stdout(Stdio::from(out_file))
-            .spawn()?;
+        // Wait for all dependencies, which never get ready during a dry run.
+        // Faked processes do not wait either, to allow faking single ones.
+        let mode = self
+            .config
+            .backend()
+            .spawn(&name, &self.command, &self.args)?;
+        if !*self.config.dry_run() && mode == SpawnMode::Real {
+            self.wait_dependencies()?;
+        }
+
+        // Apply the resource limits of known components
+        let kind = ComponentKind::from_command(&self.command);
+        let limits = kind
+            .map(|x| RLimit::for_component(self.config, x))
+            .unwrap_or_default();
+
+        // Prepare the environment and keep it for debugging purposes
+        let mut env = EnvPolicy::new(self.config)?;
+        for key in &self.inherit_env {
+            env.inherit(key);
+        }
+        for (key, value) in &self.env {
+            env.set(key, value);
+        }
+        if let Some(kind) = kind {
+            for (key, value) in Instrumentation::env(self.config, kind)? {
+                env.set(key, &value);
+            }
+        }
+        env.write(&self.dir)?;
+
+        // Run inside the process directory per default
+        let cwd = self.cwd.clone().unwrap_or_else(|| self.dir.clone());
+        create_dir_all(&cwd)?;
 
-        let (kill_tx, kill_rx) = channel();
-        let c = command.to_owned();
-        let pid = child.id();
-        let watch = spawn(move || {
-            // Wait for the process to exit
-            let status = child.wait()?;
+        let spawner = Spawner {
+            command: LocalBinary::resolve(self.config, &self.command),
+            name: name.clone(),
+            args: self.args.clone(),
+            env,
+            cwd,
+            umask: self.umask,
+            stdin: self.stdin.clone(),
+            limits,
+            log_file: log_file.clone(),
+        };
+        spawner.write_run_file(&self.dir)?;
+        spawner.write_run_info(&self.dir, None)?;
 
-            // No kill send, we assume that the process died
-            if kill_rx.try_recv().is_err() {
-                error!("Process '{}' died unexpectedly", c);
+        // The run file is everything a dry run needs
+        let root = self.config.root().clone();
+        if *self.config.dry_run() {
+            debug!("Not spawning process '{}' during dry run", name);
+            return Ok(Process::unspawned(name, &self.dir, root));
+        }
+
+        // The PID of kubernix keeps the readiness of faked processes valid
+        if mode == SpawnMode::Fake {
+            debug!("Faking process '{}'", name);
+            Readiness::mark(&self.dir, std::process::id())?;
+            EventLog::record(&root, EventKind::Ready, Some(&name), "fake");
+            return Ok(Process::unspawned(name, &self.dir, root));
+        }
+
+        // Processes of a crashed previous run get adopted if the run is
+        // continued and they have been ready, otherwise they get replaced
+        let mut adopted = None;
+        if let Some(orphan) = Process::orphan(&self.dir, &spawner.command_line()) {
+            if *self.config.resume() && Process::was_ready(&log_file, &self.readiness) {
+                adopted = Some(orphan);
             } else {
-                info!("Process '{}' exited", c);
+                info!("Stopping process '{}' of a previous run", name);
+                Process::terminate(orphan)?;
+            }
+        }
+
+        let running = match adopted {
+            Some(orphan) => {
+                info!("Adopting running process '{}' with PID {}", name, orphan);
+                EventLog::record(
+                    &root,
+                    EventKind::Adopted,
+                    Some(&name),
+                    &format!("PID {}", orphan),
+                );
+                Running::Adopted(orphan)
+            }
+            None => {
+                let child = Supervisor::enter(|| spawner.spawn(false))??;
+                spawner.write_run_info(&self.dir, Some(child.id()))?;
+                EventLog::record(
+                    &root,
+                    EventKind::Started,
+                    Some(&name),
+                    &format!("PID {}", child.id()),
+                );
+                Running::Child(Box::new(child))
             }
-            debug!("{} {}", c, status);
-            Ok(())
-        });
+        };
+        let pid = Arc::new(AtomicU32::new(running.id()));
+        let stopping = Arc::new(AtomicBool::new(false));
+        let mut process = Process {
+            name: name.clone(),
+            dir: self.dir.clone(),
+            root: root.clone(),
+            stopping: stopping.clone(),
+            pid: pid.clone(),
+            watch: None,
+        };
+
+        let dir = self.dir.clone();
+        let readiness = self.readiness.clone();
+        let timeout = self.timeout;
+        let restart = self.restart;
+        process.watch = Some(Supervisor::spawn(async move {
+            let mut running = running;
+            let mut restarts = 0;
+            loop {
+                // Wait for the process to exit
+                let status = running.wait().await?;
+
+                // Stop requested, otherwise we assume that the process died
+                if stopping.load(Ordering::SeqCst) {
+                    info!("Process '{}' exited", spawner.name);
+                    debug!("{} {}", spawner.name, status);
+                    return Ok(());
+                }
+                error!("Process '{}' died unexpectedly", spawner.name);
+                debug!("{} {}", spawner.name, status);
+                EventLog::record(&root, EventKind::Crashed, Some(&spawner.name), &status);
+                Readiness::unmark(&dir)?;
+
+                let max = match restart {
+                    RestartPolicy::OnFailure(max) => max,
+                    RestartPolicy::Never => return Ok(()),
+                };
+                if restarts >= max {
+                    error!("Giving up restarting process '{}'", spawner.name);
+                    return Ok(());
+                }
+                restarts += 1;
+                info!(
+                    "Restarting process '{}' ({}/{})",
+                    spawner.name, restarts, max
+                );
+                delay_for(Duration::from_secs(restarts.into())).await;
+                if stopping.load(Ordering::SeqCst) {
+                    return Ok(());
+                }
+
+                let offset = metadata(&spawner.log_file)?.len();
+                let mut child = spawner.spawn(true)?;
+                spawner.write_run_info(&dir, Some(child.id()))?;
+                pid.store(child.id(), Ordering::SeqCst);
+                EventLog::record(
+                    &root,
+                    EventKind::Restarted,
+                    Some(&spawner.name),
+                    &format!("PID {} ({}/{})", child.id(), restarts, max),
+                );
 
-        // Write the executed command into the dir
-        create_dir_all(dir)?;
+                // Stop requested while respawning
+                if stopping.load(Ordering::SeqCst) {
+                    child.kill()?;
+                    (&mut child).await?;
+                    return Ok(());
+                }
+
+                match scan_ready(&spawner.log_file, offset, &readiness, timeout).await {
+                    Ok(Some(_)) => {
+                        Readiness::mark(&dir, child.id())?;
+                        EventLog::record(
+                            &root,
+                            EventKind::Ready,
+                            Some(&spawner.name),
+                            &format!("PID {}", child.id()),
+                        );
+                    }
+                    Ok(None) => warn!("Restarted process '{}' did not become ready", spawner.name),
+                    // The process dies anyway on fatal errors
+                    Err(e @ Error::Other(_)) => {
+                        warn!("Restarted process '{}' failed: {}", spawner.name, e)
+                    }
+                    Err(e) => return Err(e),
+                }
+                running = Running::Child(Box::new(child));
+            }
+        })?);
+
+        // Wait for the process to become ready, which adopted ones already are
+        if adopted.is_none() && !self.readiness.is_empty() {
+            debug!(
+                "Waiting for process '{}' to become ready with patterns: {:?}",
+                name, self.readiness
+            );
+            match Supervisor::block_on(scan_ready(&log_file, 0, &self.readiness, timeout))? {
+                Ok(Some(pattern)) => debug!("Found pattern '{}'", pattern),
+                Ok(None) => {
+                    // Cleanup since process is not ready
+                    process.stop()?;
+                    return Err(Error::Timeout("waiting for process to become ready".into()));
+                }
+                Err(e) => {
+                    // Cleanup since process failed
+                    process.stop()?;
+                    return Err(e);
+                }
+            }
+        }
+        if self.mark_ready {
+            process.mark_ready()?;
+        }
+        Ok(process)
+    }
+
+    /// Wait for all dependencies to become ready
+    fn wait_dependencies(&self) -> Fallible<()> {
+        let now = Instant::now();
+        for dependency in &self.dependencies {
+            debug!(
+                "Process '{}' waiting for dependency '{}'",
+                self.command, dependency
+            );
+            while !Readiness::is_ready(self.config, *dependency) {
+                Signals::check()?;
+                if now.elapsed() >= self.dependency_timeout {
+                    return Err(Error::Timeout(format!(
+                        "waiting for dependency '{}' of process '{}'",
+                        dependency, self.command
+                    )));
+                }
+                sleep(Duration::from_millis(500));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A builder for running a one-shot command to completion
+pub struct OneShot {
+    command: String,
+    args: Vec<OsString>,
+    env: Vec<(String, OsString)>,
+    dir: Option<PathBuf>,
+    input: Option<String>,
+    stdin: Option<PathBuf>,
+    interruptible: bool,
+    retries: u32,
+    timeout: Duration,
+}
+
+impl OneShot {
+    /// Add a single argument
+    pub fn arg<S: AsRef<OsStr>>(mut self, arg: S) -> Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Add multiple arguments
+    pub fn args<S: AsRef<OsStr>>(mut self, args: &[S]) -> Self {
+        self.args.extend(args.iter().map(|x| x.as_ref().to_owned()));
+        self
+    }
+
+    /// Set an additional environment variable
+    pub fn env<V: AsRef<OsStr>>(mut self, key: &str, value: V) -> Self {
+        self.env.push((key.to_owned(), value.as_ref().to_owned()));
+        self
+    }
+
+    /// Set the working directory, which defaults to the current one
+    pub fn current_dir(mut self, dir: &Path) -> Self {
+        self.dir = Some(dir.to_owned());
+        self
+    }
+
+    /// Provide the standard input of the command
+    pub fn input(mut self, input: &str) -> Self {
+        self.input = Some(input.to_owned());
+        self
+    }
+
+    /// Read the standard input of the command from the provided file, which
+    /// allows passing binary data
+    pub fn stdin(mut self, file: &Path) -> Self {
+        self.stdin = Some(file.to_owned());
+        self
+    }
+
+    /// Set if a received termination signal aborts the command, which
+    /// defaults to true and should be disabled for cleanup commands
+    pub fn interruptible(mut self, interruptible: bool) -> Self {
+        self.interruptible = interruptible;
+        self
+    }
+
+    /// Set the number of retries if the command fails, which defaults to none
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Set the timeout of a single attempt, which defaults to 60 seconds
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Run the command until it succeeds or all retries are exhausted and
+    /// retrieve its standard output
+    pub fn run(self) -> Fallible<String> {
+        let name = self.name();
+        let mut attempt = 0;
+        loop {
+            let result = self.attempt(&name).and_then(|output| {
+                if output.success() {
+                    Ok(output.stdout)
+                } else {
+                    debug!("{} stdout: {}", name, output.stdout);
+                    debug!("{} stderr: {}", name, output.stderr);
+                    bail!("{} command failed", name)
+                }
+            });
+            match result {
+                Err(Error::Interrupted(x)) => return Err(Error::Interrupted(x)),
+                Err(e) if attempt < self.retries => {
+                    attempt += 1;
+                    warn!(
+                        "{}, retrying ({}/{})",
+                        e.to_string().trim_end_matches('.'),
+                        attempt,
+                        self.retries
+                    );
+                    sleep(Duration::from_secs(attempt.into()));
+                }
+                x => return x,
+            }
+        }
+    }
+
+    /// Run the command a single time and capture its output, whereas a
+    /// failing exit status is not considered as an error
+    pub fn output(self) -> Fallible<CommandOutput> {
+        self.attempt(&self.name())
+    }
+
+    /// Name the command like `kubectl apply` within logs and errors
+    fn name(&self) -> String {
+        match self
+            .args
+            .iter()
+            .map(|x| x.to_string_lossy())
+            .find(|x| !x.starts_with('-'))
+        {
+            Some(x) if !x.contains('/') => format!("{} {}", self.command, x),
+            _ => self.command.clone(),
+        }
+    }
+
+    /// Run the command a single time
+    fn attempt(&self, name: &str) -> Fallible<CommandOutput> {
+        debug!("Running {}", name);
+        let mut command = Command::new(&self.command);
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+        let stdin = match (&self.input, &self.stdin) {
+            (Some(_), _) => Stdio::piped(),
+            (None, Some(x)) => Stdio::from(
+                File::open(x)
+                    .map_err(|e| format_err!("Unable to open '{}': {}", x.display(), e))?,
+            ),
+            (None, None) => Stdio::null(),
+        };
+        let mut child = command
+            .args(&self.args)
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Read and write concurrently to not block on full pipes
+        let input = match (child.stdin.take(), self.input.clone()) {
+            (Some(mut stdin), Some(input)) => Some(spawn(move || {
+                stdin.write_all(input.as_bytes()).ok();
+            })),
+            _ => None,
+        };
+        let combined = Arc::new(Mutex::new(vec![]));
+        let stdout = Self::read(child.stdout.take(), combined.clone());
+        let stderr = Self::read(child.stderr.take(), combined.clone());
+
+        let now = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if self.interruptible {
+                if let Err(e) = Signals::check() {
+                    child.kill()?;
+                    child.wait()?;
+                    return Err(e);
+                }
+            }
+            if now.elapsed() >= self.timeout {
+                child.kill()?;
+                child.wait()?;
+                return Err(Error::Timeout(format!("running {}", name)));
+            }
+            sleep(Duration::from_millis(100));
+        };
+        if let Some(x) = input {
+            x.join().ok();
+        }
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        let combined = combined
+            .lock()
+            .map(|x| String::from_utf8_lossy(&x).into_owned())
+            .unwrap_or_default();
+        Ok(CommandOutput {
+            status,
+            stdout,
+            stderr,
+            combined,
+        })
+    }
+
+    /// Read the provided pipe in the background, whereas every chunk gets
+    /// appended to the combined buffer as well
+    fn read<R: Read + Send + 'static>(
+        pipe: Option<R>,
+        combined: Arc<Mutex<Vec<u8>>>,
+    ) -> thread::JoinHandle<String> {
+        spawn(move || {
+            let mut output = vec![];
+            if let Some(mut pipe) = pipe {
+                let mut buf = [0; 8192];
+                loop {
+                    match pipe.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => {
+                            output.extend_from_slice(&buf[..n]);
+                            if let Ok(mut x) = combined.lock() {
+                                x.extend_from_slice(&buf[..n]);
+                            }
+                        }
+                    }
+                }
+            }
+            String::from_utf8_lossy(&output).into_owned()
+        })
+    }
+}
+
+#[derive(Debug, Getters)]
+/// The captured output of a command which ran to completion
+pub struct CommandOutput {
+    #[get = "pub"]
+    /// The exit status of the command
+    status: ExitStatus,
+
+    #[get = "pub"]
+    /// The standard output of the command
+    stdout: String,
+
+    #[get = "pub"]
+    /// The standard error of the command
+    stderr: String,
+
+    #[get = "pub"]
+    /// The standard output and error in the order they got written
+    combined: String,
+}
+
+impl CommandOutput {
+    /// Check if the command exited successfully
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+}
+
+/// A supervised process, which is either a child of kubernix or adopted from
+/// a crashed previous run
+enum Running {
+    Child(Box<Child>),
+    Adopted(u32),
+}
+
+impl Running {
+    /// The PID of the process
+    fn id(&self) -> u32 {
+        match self {
+            Running::Child(x) => x.id(),
+            Running::Adopted(x) => *x,
+        }
+    }
+
+    /// Wait for the process to exit and describe its exit status. Adopted
+    /// processes can only be polled, since they are no children of kubernix.
+    async fn wait(&mut self) -> Fallible<String> {
+        match self {
+            Running::Child(x) => Ok((&mut **x).await?.to_string()),
+            Running::Adopted(pid) => {
+                while alive(*pid) {
+                    delay_for(Process::POLL_INTERVAL).await;
+                }
+                Ok("adopted process exited".into())
+            }
+        }
+    }
+}
+
+/// Everything needed to (re)spawn a process
+struct Spawner {
+    command: String,
+    name: String,
+    args: Vec<String>,
+    env: EnvPolicy,
+    cwd: PathBuf,
+    umask: u32,
+    stdin: Option<PathBuf>,
+    limits: Vec<RLimit>,
+    log_file: PathBuf,
+}
+
+impl Spawner {
+    /// Spawn the process child, whereas the log file gets truncated if
+    /// `append` is not set. This has to happen within the context of the
+    /// supervisor.
+    fn spawn(&self, append: bool) -> Fallible<Child> {
+        let out_file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&self.log_file)?;
+        let err_file = out_file.try_clone()?;
+        let in_file = match &self.stdin {
+            Some(x) => Stdio::from(
+                File::open(x)
+                    .map_err(|e| format_err!("Unable to open '{}': {}", x.display(), e))?,
+            ),
+            None => Stdio::null(),
+        };
+
+        let mut cmd = Command::new(&self.command);
+        self.env.apply(&mut cmd);
+        // Never share the terminal with the interactive shell
+        cmd.args(&self.args)
+            .current_dir(&self.cwd)
+            .stdin(in_file)
+            .stderr(Stdio::from(err_file))
+            .stdout(Stdio::from(out_file));
+
+        let mode = Mode::from_bits_truncate(self.umask);
+        let limits = self.limits.clone();
+        unsafe {
+            cmd.pre_exec(move || {
+                // Use a dedicated process group to not receive the terminal
+                // signals, which are handled by kubernix
+                if libc::setpgid(0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                umask(mode);
+                for limit in &limits {
+                    limit.apply()?;
+                }
+                Ok(())
+            });
+        }
+        Ok(async_process::Command::from(cmd).spawn()?)
+    }
+
+    /// The full command line of the process
+    fn command_line(&self) -> Vec<String> {
+        let mut command_line = vec![self.command.clone()];
+        command_line.extend(self.args.iter().cloned());
+        command_line
+    }
+
+    /// Write the metadata of the process into the provided directory, as well
+    /// as the PID file of the latest spawn if available
+    fn write_run_info(&self, dir: &Path, pid: Option<u32>) -> Fallible<()> {
+        let mut info = RunInfo::new(
+            &self.name,
+            &self.command,
+            &self.args,
+            self.env.vars().clone(),
+            &self.cwd,
+            self.umask,
+        );
+        if let Some(pid) = pid {
+            info.spawned(pid);
+            fs::write(dir.join(Process::PID_FILE), pid.to_string())?;
+        }
+        info.write(dir)
+    }
+
+    /// Write the executed command into the provided directory
+    fn write_run_file(&self, dir: &Path) -> Fallible<()> {
         let run_file = dir.join("run.sh");
         let sep = format!(" \\\n{}", " ".repeat(4));
-        let full_command = format!(r#"{}{}{}"#, command, sep, args.join(&sep));
+        let ulimits: String = self
+            .limits
+            .iter()
+            .map(|x| format!("{}\n", x.ulimit_command()))
+            .collect();
+        let stdin = match &self.stdin {
+            Some(x) => format!(" < {}", x.display()),
+            None => String::new(),
+        };
+        let full_command = format!(
+            "cd {}\numask {:04o}\n{}{}{}{}{}",
+            self.cwd.display(),
+            self.umask,
+            ulimits,
+            self.command,
+            sep,
+            self.args.join(&sep),
+            stdin
+        );
         fs::write(
             &run_file,
             format!(include_str!("assets/run.sh"), full_command),
@@ -95,69 +955,351 @@ impl Process {
         let mut perms = metadata(&run_file)?.permissions();
         perms.set_mode(0o755);
         set_permissions(run_file, perms)?;
+        Ok(())
+    }
+}
 
-        Ok(Process {
-            command: command.to_owned(),
-            kill: kill_tx,
-            log_file,
-            pid,
-            watch: Some(watch),
-            readyness_timeout: 30,
-        })
+/// Check if the process with the provided PID is still running
+fn alive(pid: u32) -> bool {
+    kill(Pid::from_raw(pid as i32), None).is_ok()
+}
+
+/// Split the content of a `/proc/<pid>/cmdline` file into its arguments
+fn parse_cmdline(cmdline: &[u8]) -> Vec<String> {
+    cmdline
+        .split(|x| *x == 0)
+        .map(|x| String::from_utf8_lossy(x).into_owned())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// Known fatal errors of the components, which get reported by a fatal log
+/// line containing the pattern, and their explanation
+const FATAL_ERRORS: [(&str, &str); 4] = [
+    (
+        "address already in use",
+        "A required port is already in use, probably by another Kubernetes installation",
+    ),
+    (
+        "permission denied",
+        "Permission denied, please make sure that no security module like SELinux \
+         or AppArmor blocks the access",
+    ),
+    (
+        "unknown flag",
+        "An unknown flag got provided, probably because the component version does \
+         not match the one expected by kubernix",
+    ),
+    (
+        "flag provided but not defined",
+        "An unknown flag got provided, probably because the component version does \
+         not match the one expected by kubernix",
+    ),
+];
+
+/// Retrieve the explanation of the known fatal error reported by the
+/// provided log line, if any
+fn fatal_error(line: &str) -> Option<&'static str> {
+    let line = line.trim_start();
+    // klog writes fatal lines like `F1017 08:15:42.123456 ...`
+    let bytes = line.as_bytes();
+    let klog = bytes.len() > 5
+        && bytes[0] == b'F'
+        && bytes[1..5].iter().all(u8::is_ascii_digit)
+        && bytes[5] == b' ';
+    let fatal = klog
+        || line.starts_with("Error: ")
+        || line.starts_with("flag provided but not defined")
+        || line.contains("level=fatal")
+        || line.contains(r#""level":"fatal""#);
+    if !fatal {
+        return None;
     }
+    let lowercase = line.to_lowercase();
+    FATAL_ERRORS
+        .iter()
+        .find(|(pattern, _)| lowercase.contains(pattern))
+        .map(|(_, explanation)| *explanation)
+}
 
-    // Wait for the process to become ready, by searching for the pattern in
-    // every line of its output.
-    pub fn wait_ready(&mut self, pattern: &str) -> Fallible<()> {
-        debug!(
-            "Waiting for process '{}' to become ready with pattern: '{}'",
-            self.command, pattern
-        );
-        let now = Instant::now();
-        let file = File::open(&self.log_file)?;
-        let mut reader = BufReader::new(file);
+/// Search for any of the patterns in every line of the log file, starting at
+/// the provided offset. Returns the found pattern or `None` on timeout, and
+/// fails early if the log reports a known fatal error. Invalid UTF-8 gets
+/// replaced and a truncated or rotated log file gets reopened from its start.
+async fn scan_ready(
+    log_file: &Path,
+    offset: u64,
+    patterns: &[ReadyPattern],
+    timeout: Duration,
+) -> Fallible<Option<String>> {
+    let (mut reader, mut inode) = open_log(log_file, offset)?;
+    let mut position = offset;
 
-        while now.elapsed().as_secs() < self.readyness_timeout {
-            let mut line = String::new();
-            reader.read_line(&mut line)?;
+    let scan = async {
+        let mut line = vec![];
+        loop {
+            Signals::check()?;
+            // Wait for the process to write more output at the end of the file
+            let read = reader.read_until(b'\n', &mut line).await?;
+            if read == 0 {
+                if rotated(log_file, position, inode) {
+                    debug!("Reopening rotated log file '{}'", log_file.display());
+                    let (x, y) = open_log(log_file, 0)?;
+                    reader = x;
+                    inode = y;
+                    position = 0;
+                    line.clear();
+                    continue;
+                }
+                delay_for(Process::POLL_INTERVAL).await;
+                continue;
+            }
+            position += read as u64;
+            // Incomplete lines get continued on the next read
+            if !line.ends_with(b"\n") {
+                continue;
+            }
 
-            if line.contains(pattern) {
-                debug!("Found pattern '{}' in line '{}'", pattern, line.trim());
-                return Ok(());
+            let text = decode_line(&line);
+            if let Some(pattern) = patterns.iter().find(|p| p.matches(&text)) {
+                debug!("Found pattern '{}' in line '{}'", pattern, text.trim());
+                return Ok(pattern.to_string());
+            }
+            if let Some(explanation) = fatal_error(&text) {
+                error!("Fatal error in '{}': {}", log_file.display(), text.trim());
+                bail!("{}", explanation);
             }
+            line.clear();
         }
+    };
+    match with_timeout(timeout, scan).await {
+        Ok(x) => x.map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Open the log file at the provided offset and retrieve its inode
+fn open_log(log_file: &Path, offset: u64) -> Fallible<(BufReader<tokio::fs::File>, u64)> {
+    let mut file = File::open(log_file)?;
+    let inode = file.metadata()?.ino();
+    file.seek(SeekFrom::Start(offset))?;
+    Ok((BufReader::new(tokio::fs::File::from_std(file)), inode))
+}
 
-        // Cleanup since process is not ready
-        self.stop()?;
-        bail!("Timed out waiting for process to become ready")
+/// Check if the log file got truncated below the read position or replaced
+/// by another file
+fn rotated(log_file: &Path, position: u64, inode: u64) -> bool {
+    match metadata(log_file) {
+        Ok(x) => x.len() < position || x.ino() != inode,
+        // The new file may not have been created yet
+        Err(_) => false,
+    }
+}
+
+/// Decode a line of a log file, whereas invalid UTF-8 gets replaced and
+/// Windows line endings get removed
+fn decode_line(line: &[u8]) -> String {
+    String::from_utf8_lossy(line)
+        .trim_end_matches(&['\n', '\r'][..])
+        .to_owned()
+}
+
+impl Process {
+    /// The maximum time to wait for the dependencies of a process
+    pub const DEPENDENCY_TIMEOUT: Duration = Duration::from_secs(120);
+
+    /// The file name of the PID file within the process directory
+    pub const PID_FILE: &'static str = "pid";
+
+    /// The interval of checking the log file for new output
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// The time to wait for a process of a previous run to terminate
+    const TERMINATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// The time to search for the readiness of an adopted process, whose log
+    /// file is already complete
+    const ADOPTION_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Create a new `ProcessBuilder` for the provided command, which stores its
+    /// runtime data inside `dir`.
+    pub fn builder<'a>(config: &'a Config, dir: &Path, command: &str) -> ProcessBuilder<'a> {
+        ProcessBuilder {
+            config,
+            dir: dir.to_owned(),
+            command: command.to_owned(),
+            name: None,
+            args: vec![],
+            env: vec![],
+            inherit_env: vec![],
+            cwd: None,
+            umask: 0o022,
+            stdin: None,
+            readiness: vec![],
+            timeout: Duration::from_secs(30),
+            restart: RestartPolicy::Never,
+            dependencies: vec![],
+            dependency_timeout: Self::DEPENDENCY_TIMEOUT,
+            mark_ready: true,
+        }
+    }
+
+    /// Create a new `OneShot` for the provided command, which runs to
+    /// completion instead of being supervised
+    pub fn run_to_completion(command: &str) -> OneShot {
+        OneShot {
+            command: command.to_owned(),
+            args: vec![],
+            env: vec![],
+            dir: None,
+            input: None,
+            stdin: None,
+            interruptible: true,
+            retries: 0,
+            timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// Mark the spawned process as ready, which is only required if its
+    /// readiness got deferred. Unspawned processes are left untouched.
+    pub fn mark_ready(&self) -> Fallible<()> {
+        let pid = self.pid.load(Ordering::SeqCst);
+        if pid == 0 {
+            return Ok(());
+        }
+        Readiness::mark(&self.dir, pid)?;
+        EventLog::record(
+            &self.root,
+            EventKind::Ready,
+            Some(&self.name),
+            &format!("PID {}", pid),
+        );
+        Ok(())
+    }
+
+    /// Stop all provided processes in their order, whereas a failing one
+    /// does not prevent stopping the others. The errors of all failed ones
+    /// get returned combined.
+    pub fn stop_all<'a, I>(processes: I) -> Fallible<()>
+    where
+        I: IntoIterator<Item = &'a mut Process>,
+    {
+        let errors: Vec<String> = processes
+            .into_iter()
+            .filter_map(|x| x.stop().err())
+            .map(|e| e.to_string())
+            .collect();
+        if !errors.is_empty() {
+            bail!("{}", errors.join(", "))
+        }
+        Ok(())
     }
 
     /// Retrieve a pseudo state for stopped processes
     pub fn stopped() -> Fallible<Startable> {
         Err(format_err!("Stopped"))
     }
+
+    /// Find a still running process of a previous run within the provided
+    /// directory, which has to have exactly the provided command line to not
+    /// confuse it with a reused PID or a changed configuration
+    fn orphan(dir: &Path, command_line: &[String]) -> Option<u32> {
+        let pid: u32 = read_to_string(dir.join(Self::PID_FILE))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()?;
+        let cmdline = fs::read(Path::new("/proc").join(pid.to_string()).join("cmdline")).ok()?;
+        if alive(pid) && parse_cmdline(&cmdline) == command_line {
+            Some(pid)
+        } else {
+            None
+        }
+    }
+
+    /// Check if the log file of a process reports that it has been ready
+    fn was_ready(log_file: &Path, patterns: &[ReadyPattern]) -> bool {
+        patterns.is_empty()
+            || Supervisor::block_on(scan_ready(log_file, 0, patterns, Self::ADOPTION_TIMEOUT))
+                .ok()
+                .and_then(|x| x.ok())
+                .flatten()
+                .is_some()
+    }
+
+    /// Terminate a process which is not a child of kubernix
+    fn terminate(pid: u32) -> Fallible<()> {
+        let target = Pid::from_raw(pid as i32);
+        match kill(target, Signal::SIGTERM) {
+            Err(Sys(Errno::ESRCH)) => return Ok(()),
+            x => x?,
+        }
+        let now = Instant::now();
+        while alive(pid) {
+            if now.elapsed() >= Self::TERMINATE_TIMEOUT {
+                warn!("Killing process with PID {}", pid);
+                kill(target, Signal::SIGKILL).ok();
+                break;
+            }
+            sleep(Self::POLL_INTERVAL);
+        }
+        Ok(())
+    }
+
+    /// Create a process which has never been spawned
+    fn unspawned(name: String, dir: &Path, root: PathBuf) -> Process {
+        Process {
+            name,
+            dir: dir.to_owned(),
+            root,
+            stopping: Arc::new(AtomicBool::new(true)),
+            pid: Arc::new(AtomicU32::new(0)),
+            watch: None,
+        }
+    }
 }
 
 impl Stoppable for Process {
     /// Stopping the process by killing it
     fn stop(&mut self) -> Fallible<()> {
-        debug!("Stopping process '{}'", self.command);
+        let span = info_span!("stop", process = self.name.as_str());
+        let _enter = span.enter();
+        debug!("Stopping process '{}'", self.name);
+        Readiness::unmark(&self.dir)?;
+
+        // Processes of a dry run or fake backend have never been spawned
+        let pid = self.pid.load(Ordering::SeqCst);
+        if pid == 0 {
+            return Ok(());
+        }
 
         // Indicate that this shutdown is intended
-        self.kill
-            .send(())
-            .map_err(|e| format_err!("Unable to send kill signal to process: {}", e))?;
+        self.stopping.store(true, Ordering::SeqCst);
 
-        // Send SIGTERM to the process
-        kill(Pid::from_raw(self.pid as i32), Signal::SIGTERM)?;
+        // Send SIGTERM to the process, which may be already dead
+        let pid = Pid::from_raw(pid as i32);
+        match kill(pid, Signal::SIGTERM) {
+            Err(Sys(Errno::ESRCH)) => debug!("Process '{}' already exited", self.name),
+            x => x?,
+        }
 
-        // Join the waiting thread
+        // Wait for the supervising task
         if let Some(handle) = self.watch.take() {
-            if handle.join().is_err() {
-                bail!("Unable to stop process '{}'", self.command);
+            if Supervisor::block_on(handle)?.is_err() {
+                bail!("Unable to stop process '{}'", self.name);
             }
         }
-        debug!("Process '{}' stopped", self.command);
+        let pid_file = self.dir.join(Self::PID_FILE);
+        if pid_file.exists() {
+            remove_file(pid_file)?;
+        }
+        debug!("Process '{}' stopped", self.name);
+        EventLog::record(
+            &self.root,
+            EventKind::Stopped,
+            Some(&self.name),
+            &format!("PID {}", pid),
+        );
         Ok(())
     }
 }
@@ -174,61 +1316,394 @@ mod tests {
     }
 
     #[test]
-    fn start_success() -> Fallible<()> {
+    fn spawn_success() -> Fallible<()> {
         let c = test_config()?;
         let d = tempdir()?;
-        Process::start(&c, d.path(), "echo", &[])?;
+        Process::builder(&c, d.path(), "echo").spawn()?;
         Ok(())
     }
 
     #[test]
-    fn start_failure_wrong_root() -> Fallible<()> {
+    fn spawn_success_options() -> Fallible<()> {
+        let c = test_config()?;
+        let d = tempdir()?;
+        let cwd = d.path().join("cwd");
+        let mut p = Process::builder(&c, d.path(), "sh")
+            .args(&["-c", "touch file && echo done"])
+            .cwd(&cwd)
+            .umask(0o077)
+            .inherit_env("CARGO")
+            .ready_on("done")
+            .spawn()?;
+        let mode = metadata(cwd.join("file"))?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        p.stop()
+    }
+
+    #[test]
+    fn spawn_success_stdin() -> Fallible<()> {
+        let c = test_config()?;
+        let d = tempdir()?;
+        let input = d.path().join("input");
+        fs::write(&input, "input line\n")?;
+        let mut p = Process::builder(&c, d.path(), "cat")
+            .stdin(&input)
+            .ready_on("input line")
+            .spawn()?;
+        p.stop()
+    }
+
+    #[test]
+    fn spawn_failure_wrong_root() -> Fallible<()> {
         let c = test_config_wrong_root()?;
         let d = tempdir()?;
-        assert!(Process::start(&c, d.path(), "echo", &[]).is_err());
+        assert!(Process::builder(&c, d.path(), "echo").spawn().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_failure_no_command() -> Fallible<()> {
+        let c = test_config()?;
+        let d = tempdir()?;
+        assert!(Process::builder(&c, d.path(), "").spawn().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_failure_invalid_command() -> Fallible<()> {
+        let c = test_config()?;
+        let d = tempdir()?;
+        assert!(Process::builder(&c, d.path(), "invalid_command")
+            .spawn()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_failure_dependency() -> Fallible<()> {
+        let c = test_config()?;
+        let d = tempdir()?;
+        assert!(Process::builder(&c, d.path(), "echo")
+            .depends_on(ComponentKind::Etcd)
+            .dependency_timeout(Duration::from_secs(1))
+            .spawn()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_fake_success() -> Fallible<()> {
+        let mut c = test_config()?;
+        let d = tempdir()?;
+        let backend = Arc::new(FakeBackend::default());
+        c.set_backend(backend.clone());
+        let mut p = Process::builder(&c, d.path(), "invalid_command")
+            .arg("--flag")
+            .depends_on(ComponentKind::Etcd)
+            .ready_on("never")
+            .spawn()?;
+        assert!(Readiness::is_marked(d.path()));
+        assert_eq!(
+            backend.spawned(),
+            vec![("invalid_command".into(), vec!["--flag".into()])]
+        );
+        p.stop()?;
+        assert!(!Readiness::is_marked(d.path()));
         Ok(())
     }
 
     #[test]
-    fn start_failure_no_command() -> Fallible<()> {
+    fn spawn_fake_failure() -> Fallible<()> {
+        let mut c = test_config()?;
+        let d = tempdir()?;
+        c.set_backend(Arc::new(
+            FakeBackend::default().fail("echo", "simulated failure"),
+        ));
+        assert!(Process::builder(&c, d.path(), "echo").spawn().is_err());
+        assert!(!Readiness::is_marked(d.path()));
+        Ok(())
+    }
+
+    #[test]
+    fn ready_on_success() -> Fallible<()> {
         let c = test_config()?;
         let d = tempdir()?;
-        assert!(Process::start(&c, d.path(), "", &[]).is_err());
+        let mut p = Process::builder(&c, d.path(), "sh")
+            .args(&["-c", "echo test && sleep 500"])
+            .ready_on("test")
+            .spawn()?;
+        assert!(d.path().join(Readiness::MARKER).exists());
+        p.stop()?;
+        assert!(!d.path().join(Readiness::MARKER).exists());
         Ok(())
     }
 
     #[test]
-    fn start_failure_invalid_command() -> Fallible<()> {
+    fn ready_on_success_deferred() -> Fallible<()> {
         let c = test_config()?;
         let d = tempdir()?;
-        assert!(Process::start(&c, d.path(), "invalid_command", &[]).is_err());
+        let mut p = Process::builder(&c, d.path(), "sh")
+            .args(&["-c", "echo test && sleep 500"])
+            .ready_on("test")
+            .defer_ready()
+            .spawn()?;
+        assert!(!Readiness::is_marked(d.path()));
+        p.mark_ready()?;
+        assert!(Readiness::is_marked(d.path()));
+        p.stop()?;
+        Ok(())
+    }
+
+    #[test]
+    fn stop_all_success() -> Fallible<()> {
+        let c = test_config()?;
+        let dirs = vec![tempdir()?, tempdir()?];
+        let mut processes = dirs
+            .iter()
+            .map(|d| {
+                Process::builder(&c, d.path(), "sh")
+                    .args(&["-c", "echo test && sleep 500"])
+                    .ready_on("test")
+                    .spawn()
+            })
+            .collect::<Fallible<Vec<_>>>()?;
+        Process::stop_all(processes.iter_mut().rev())?;
+        assert!(dirs.iter().all(|d| !Readiness::is_marked(d.path())));
         Ok(())
     }
 
     #[test]
-    fn wait_ready_success() -> Fallible<()> {
+    fn ready_on_failure() -> Fallible<()> {
         let c = test_config()?;
         let d = tempdir()?;
-        let mut p = Process::start(&c, d.path(), "echo", &["test"])?;
-        p.wait_ready("test")?;
+        assert!(Process::builder(&c, d.path(), "echo")
+            .arg("test")
+            .ready_on("invalid")
+            .timeout(Duration::from_secs(1))
+            .spawn()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ready_on_failure_fatal() -> Fallible<()> {
+        let c = test_config()?;
+        let d = tempdir()?;
+        match Process::builder(&c, d.path(), "sh")
+            .args(&["-c", "echo 'Error: unknown flag: --invalid' && sleep 500"])
+            .ready_on("test")
+            .spawn()
+        {
+            Err(Error::Other(e)) => assert!(e.contains("unknown flag")),
+            _ => panic!("fatal error not detected"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fatal_error_success() {
+        assert!(fatal_error(
+            "F1017 08:15:42.123456   1234 server.go:123] listen tcp :10250: bind: \
+             address already in use"
+        )
+        .is_some());
+        assert!(fatal_error("Error: unknown flag: --invalid\n").is_some());
+        assert!(fatal_error("flag provided but not defined: -invalid\n").is_some());
+        assert!(fatal_error(
+            r#"time="2019-10-24T08:41:07Z" level=fatal msg="open /var/run: permission denied""#
+        )
+        .is_some());
+        assert!(fatal_error(
+            r#"{"level":"fatal","msg":"listen tcp 127.0.0.1:2379: bind: address already in use"}"#
+        )
+        .is_some());
+    }
+
+    #[test]
+    fn fatal_error_failure() {
+        assert!(fatal_error("I1017 08:15:42.123456   1234 open /sys: permission denied").is_none());
+        assert!(fatal_error("F1017 08:15:42.123456   1234 something else failed").is_none());
+        assert!(fatal_error("Error: something else failed").is_none());
+        assert!(fatal_error("").is_none());
+    }
+
+    #[test]
+    fn parse_cmdline_success() {
+        assert_eq!(
+            parse_cmdline(b"etcd\0--name=etcd\0"),
+            vec!["etcd".to_owned(), "--name=etcd".to_owned()]
+        );
+        assert!(parse_cmdline(b"").is_empty());
+    }
+
+    #[test]
+    fn orphan_success() -> Fallible<()> {
+        let d = tempdir()?;
+        let mut child = Command::new("sleep").arg("30").spawn()?;
+        fs::write(d.path().join(Process::PID_FILE), child.id().to_string())?;
+
+        let command_line = vec!["sleep".to_owned(), "30".to_owned()];
+        // The command line may not be available right after spawning
+        let mut found = None;
+        for _ in 0..50 {
+            found = Process::orphan(d.path(), &command_line);
+            if found.is_some() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(found, Some(child.id()));
+        assert_eq!(Process::orphan(d.path(), &command_line[..1]), None);
+
+        child.kill()?;
+        child.wait()?;
+        assert_eq!(Process::orphan(d.path(), &command_line), None);
+        Ok(())
+    }
+
+    #[test]
+    fn was_ready_success() -> Fallible<()> {
+        let d = tempdir()?;
+        let log = d.path().join("test.log");
+        let patterns = [ReadyPattern::Literal("ready".into())];
+        assert!(!Process::was_ready(&log, &patterns));
+        fs::write(&log, "starting\n")?;
+        assert!(!Process::was_ready(&log, &patterns));
+        assert!(Process::was_ready(&log, &[]));
+        fs::write(&log, "starting\nready\n")?;
+        assert!(Process::was_ready(&log, &patterns));
+        Ok(())
+    }
+
+    #[test]
+    fn decode_line_success() {
+        assert_eq!(decode_line(b"ready\r\n"), "ready");
+        assert_eq!(decode_line(b"\x1b[0m\xff ready\n"), "\x1b[0m\u{fffd} ready");
+        assert_eq!(decode_line(b""), "");
+    }
+
+    #[test]
+    fn scan_ready_success_invalid_utf8() -> Fallible<()> {
+        let d = tempdir()?;
+        let log = d.path().join("test.log");
+        fs::write(&log, b"\x00\xfe\xff garbage\r\nserving on :8443\r\n")?;
+        let regex = Regex::new(r"serving on :\d+$").map_err(|e| format_err!("{}", e))?;
+        let patterns = [ReadyPattern::Regex(regex)];
+        let found = Supervisor::block_on(scan_ready(&log, 0, &patterns, Duration::from_secs(5)))??;
+        assert_eq!(found, Some(r"/serving on :\d+$/".into()));
         Ok(())
     }
 
     #[test]
-    fn wait_ready_failure() -> Fallible<()> {
+    fn scan_ready_success_truncated() -> Fallible<()> {
+        let d = tempdir()?;
+        let log = d.path().join("test.log");
+        fs::write(&log, "ready\n")?;
+        let patterns = [ReadyPattern::Literal("ready".into())];
+        let found =
+            Supervisor::block_on(scan_ready(&log, 1000, &patterns, Duration::from_secs(5)))??;
+        assert_eq!(found, Some("ready".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn scan_ready_failure_timeout() -> Fallible<()> {
+        let d = tempdir()?;
+        let log = d.path().join("test.log");
+        fs::write(&log, "starting\npartial ready")?;
+        let patterns = [ReadyPattern::Literal("ready".into())];
+        let found = Supervisor::block_on(scan_ready(&log, 0, &patterns, Duration::from_secs(1)))??;
+        assert_eq!(found, None);
+        Ok(())
+    }
+
+    #[test]
+    fn restart_success() -> Fallible<()> {
         let c = test_config()?;
         let d = tempdir()?;
-        let mut p = Process::start(&c, d.path(), "echo", &["test"])?;
-        p.readyness_timeout = 1;
-        assert!(p.wait_ready("invalid").is_err());
+        let mut p = Process::builder(&c, d.path(), "echo")
+            .arg("test")
+            .ready_on("test")
+            .restart(RestartPolicy::OnFailure(1))
+            .spawn()?;
+        p.stop()?;
         Ok(())
     }
 
+    #[test]
+    fn run_to_completion_success() -> Fallible<()> {
+        assert_eq!(
+            Process::run_to_completion("echo").arg("test").run()?,
+            "test\n"
+        );
+        assert_eq!(
+            Process::run_to_completion("cat").input("test").run()?,
+            "test"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn run_to_completion_success_stdin_dir() -> Fallible<()> {
+        let d = tempdir()?;
+        let input = d.path().join("input");
+        fs::write(&input, [0u8, 1, 2])?;
+        assert_eq!(
+            Process::run_to_completion("wc")
+                .arg("-c")
+                .stdin(&input)
+                .run()?
+                .trim(),
+            "3"
+        );
+        assert_eq!(
+            Process::run_to_completion("pwd")
+                .current_dir(d.path())
+                .run()?
+                .trim(),
+            d.path().canonicalize()?.display().to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn run_to_completion_output() -> Fallible<()> {
+        let output = Process::run_to_completion("sh")
+            .args(&["-c", "echo out && sleep 0.1 && echo err >&2 && exit 3"])
+            .output()?;
+        assert!(!output.success());
+        assert_eq!(output.stdout(), "out\n");
+        assert_eq!(output.stderr(), "err\n");
+        assert_eq!(output.combined(), "out\nerr\n");
+        Ok(())
+    }
+
+    #[test]
+    fn run_to_completion_failure() {
+        assert!(Process::run_to_completion("false")
+            .retries(1)
+            .run()
+            .is_err());
+        assert!(Process::run_to_completion("invalid-command").run().is_err());
+    }
+
+    #[test]
+    fn run_to_completion_failure_timeout() {
+        match Process::run_to_completion("sleep")
+            .arg("500")
+            .timeout(Duration::from_secs(1))
+            .run()
+        {
+            Err(Error::Timeout(_)) => {}
+            _ => panic!("timeout not detected"),
+        }
+    }
+
     #[test]
     fn stop_success() -> Fallible<()> {
         let c = test_config()?;
         let d = tempdir()?;
-        let mut p = Process::start(&c, d.path(), "sleep", &["500"])?;
+        let mut p = Process::builder(&c, d.path(), "sleep").arg("500").spawn()?;
         p.stop()?;
         Ok(())
     }
@@ -0,0 +1,261 @@
+use crate::{component::ComponentKind, Config, Error, Fallible};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The available bundles of component arguments
+pub enum Profile {
+    /// No additional arguments
+    Default,
+
+    /// Auditing of all requests
+    Secure,
+
+    /// Higher request limits and client rates
+    Performance,
+
+    /// Lower resource usage
+    Minimal,
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Profile::Default => "default",
+            Profile::Secure => "secure",
+            Profile::Performance => "performance",
+            Profile::Minimal => "minimal",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Profile {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "default" => Ok(Profile::Default),
+            "secure" => Ok(Profile::Secure),
+            "performance" => Ok(Profile::Performance),
+            "minimal" => Ok(Profile::Minimal),
+            _ => Err(format_err!("Unknown profile '{}'", s)),
+        }
+    }
+}
+
+impl Profile {
+    /// Retrieve the preset of the profile
+    pub fn preset(self) -> Preset {
+        match self {
+            Profile::Default => Preset::default(),
+            Profile::Secure => Preset::audit(),
+            Profile::Performance => Preset::high_qps(),
+            Profile::Minimal => Preset::low_resources(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Additional arguments per component, which are appended to the built-in
+/// ones and therefore take precedence
+pub struct Preset {
+    args: HashMap<ComponentKind, Vec<String>>,
+}
+
+impl Preset {
+    /// The audit policy file name inside the API Server directories
+    pub const AUDIT_POLICY: &'static str = "audit-policy.yml";
+
+    /// Retrieve the preset of the configured profile including the optional
+    /// laptop mode
+    pub fn for_config(config: &Config) -> Self {
+        let mut preset = config.profile().preset();
+        if *config.laptop_mode() {
+            preset = preset.merge(Preset::laptop())
+        }
+        // Explicitly profiled components take precedence over the profile
+        preset.merge(Preset::pprof(config.pprof()))
+    }
+
+    /// Retrieve the arguments for the provided component
+    pub fn args(&self, kind: ComponentKind) -> Vec<String> {
+        self.args.get(&kind).cloned().unwrap_or_default()
+    }
+
+    /// Combine two presets, whereas the arguments of `other` come last
+    pub fn merge(mut self, other: Preset) -> Self {
+        for (kind, args) in other.args {
+            self.args.entry(kind).or_default().extend(args);
+        }
+        self
+    }
+
+    fn with(mut self, kind: ComponentKind, args: &[&str]) -> Self {
+        self.args
+            .entry(kind)
+            .or_default()
+            .extend(args.iter().map(|x| (*x).to_owned()));
+        self
+    }
+
+    /// Log all API requests and responses metadata
+    fn audit() -> Self {
+        Preset::default().with(
+            ComponentKind::ApiServer,
+            &[&format!("--audit-policy-file={}", Self::AUDIT_POLICY)],
+        )
+    }
+
+    /// Enable the profiling endpoints of the provided components, whereas
+    /// the ones using configuration files are handled separately
+    fn pprof(kinds: &[ComponentKind]) -> Self {
+        kinds
+            .iter()
+            .fold(Preset::default(), |preset, kind| match kind {
+                ComponentKind::ApiServer | ComponentKind::ControllerManager => {
+                    preset.with(*kind, &["--profiling=true"])
+                }
+                ComponentKind::Etcd => preset.with(*kind, &["--enable-pprof"]),
+                _ => preset,
+            })
+    }
+
+    /// Raise the request limits and client rates
+    fn high_qps() -> Self {
+        Preset::default()
+            .with(
+                ComponentKind::ApiServer,
+                &[
+                    "--max-requests-inflight=800",
+                    "--max-mutating-requests-inflight=400",
+                ],
+            )
+            .with(
+                ComponentKind::ControllerManager,
+                &["--kube-api-qps=100", "--kube-api-burst=200"],
+            )
+            .with(
+                ComponentKind::Kubelet,
+                &["--kube-api-qps=50", "--kube-api-burst=100"],
+            )
+    }
+
+    /// Tolerate suspending the host without marking the node as not ready
+    /// and evicting its pods after resuming
+    fn laptop() -> Self {
+        Preset::default()
+            .with(
+                ComponentKind::ApiServer,
+                &[
+                    "--default-not-ready-toleration-seconds=1800",
+                    "--default-unreachable-toleration-seconds=1800",
+                ],
+            )
+            .with(
+                ComponentKind::ControllerManager,
+                &[
+                    "--node-monitor-grace-period=10m",
+                    "--node-startup-grace-period=10m",
+                    "--pod-eviction-timeout=30m",
+                ],
+            )
+            .with(
+                ComponentKind::Kubelet,
+                &["--eviction-pressure-transition-period=5m"],
+            )
+    }
+
+    /// Reduce the memory footprint and the number of pods
+    fn low_resources() -> Self {
+        Preset::default()
+            .with(
+                ComponentKind::ApiServer,
+                &["--watch-cache=false", "--event-ttl=10m"],
+            )
+            .with(ComponentKind::Kubelet, &["--max-pods=30"])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        assert_eq!("secure".parse::<Profile>()?, Profile::Secure);
+        assert_eq!("minimal".parse::<Profile>()?, Profile::Minimal);
+        assert!("invalid".parse::<Profile>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn preset_success() {
+        assert!(Profile::Default
+            .preset()
+            .args(ComponentKind::ApiServer)
+            .is_empty());
+
+        let args = Profile::Secure.preset().args(ComponentKind::ApiServer);
+        assert_eq!(args.len(), 1);
+        assert!(args[0].starts_with("--audit-policy-file="));
+        assert!(Profile::Secure
+            .preset()
+            .args(ComponentKind::Kubelet)
+            .is_empty());
+        assert!(Profile::Secure
+            .preset()
+            .args(ComponentKind::Scheduler)
+            .is_empty());
+    }
+
+    #[test]
+    fn preset_success_no_explicit_flags() {
+        // These flags are always set from their explicit options, which
+        // must not be overridden by any profile
+        let explicit = ["--anonymous-auth", "--profiling", "--read-only-port"];
+        for profile in &[
+            Profile::Default,
+            Profile::Secure,
+            Profile::Performance,
+            Profile::Minimal,
+        ] {
+            let preset = profile.preset().merge(Preset::laptop());
+            for kind in &ComponentKind::ALL {
+                assert!(!preset
+                    .args(*kind)
+                    .iter()
+                    .any(|x| explicit.iter().any(|e| x.starts_with(e))));
+            }
+        }
+    }
+
+    #[test]
+    fn laptop_success() {
+        let preset = Profile::Minimal.preset().merge(Preset::laptop());
+        let args = preset.args(ComponentKind::ControllerManager);
+        assert!(args.contains(&"--node-monitor-grace-period=10m".to_owned()));
+        assert_eq!(preset.args(ComponentKind::Kubelet)[0], "--max-pods=30");
+    }
+
+    #[test]
+    fn pprof_success() {
+        let preset = Profile::Secure.preset().merge(Preset::pprof(&[
+            ComponentKind::ApiServer,
+            ComponentKind::Etcd,
+            ComponentKind::Kubelet,
+        ]));
+        let args = preset.args(ComponentKind::ApiServer);
+        assert_eq!(args.last().map(String::as_str), Some("--profiling=true"));
+        assert_eq!(preset.args(ComponentKind::Etcd), vec!["--enable-pprof"]);
+        assert!(!preset
+            .args(ComponentKind::Kubelet)
+            .iter()
+            .any(|x| x.contains("profiling")));
+    }
+}
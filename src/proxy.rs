@@ -1,18 +1,99 @@
 use crate::{
+    component::ComponentKind,
     config::Config,
+    containerized::Containerized,
+    instrumentation::Instrumentation,
     kubeconfig::KubeConfig,
     network::Network,
     process::{Process, Startable, Stoppable},
+    verbosity::Verbosity,
+    Error, Fallible,
 };
-use failure::Fallible;
 use log::info;
-use std::fs::{self, create_dir_all};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs::{self, create_dir_all},
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The available kube-proxy modes
+pub enum ProxyMode {
+    /// Services via iptables rules
+    Iptables,
+
+    /// Services via the IP Virtual Server of the kernel
+    Ipvs,
+
+    /// Services via nftables rules, requires Kubernetes 1.29 or later
+    Nftables,
+}
+
+impl Display for ProxyMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ProxyMode::Iptables => "iptables",
+            ProxyMode::Ipvs => "ipvs",
+            ProxyMode::Nftables => "nftables",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ProxyMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "iptables" => Ok(ProxyMode::Iptables),
+            "ipvs" => Ok(ProxyMode::Ipvs),
+            "nftables" => Ok(ProxyMode::Nftables),
+            _ => Err(format_err!("Unknown proxy mode '{}'", s)),
+        }
+    }
+}
+
+impl ProxyMode {
+    /// The kernel modules required by the mode
+    pub fn modules(self) -> &'static [&'static str] {
+        match self {
+            ProxyMode::Iptables => &[],
+            ProxyMode::Ipvs => &["ip_vs", "ip_vs_rr", "ip_vs_wrr", "ip_vs_sh"],
+            ProxyMode::Nftables => &["nf_tables"],
+        }
+    }
+
+    /// The Nix package providing the tooling of the mode, if any
+    pub fn package(self) -> Option<&'static str> {
+        match self {
+            ProxyMode::Iptables => None,
+            ProxyMode::Ipvs => Some("ipset"),
+            ProxyMode::Nftables => Some("nftables"),
+        }
+    }
+
+    /// Additional configuration of the mode
+    fn config(self) -> &'static str {
+        match self {
+            ProxyMode::Nftables => "featureGates:\n  NFTablesProxyMode: true\n",
+            _ => "",
+        }
+    }
+}
 
 pub struct Proxy {
     process: Process,
 }
 
 impl Proxy {
+    /// Check if kube-proxy should run at all, which is not the case if it
+    /// gets replaced by the network plugin
+    pub fn enabled(config: &Config) -> bool {
+        !*config.no_kube_proxy() && !*config.kubelet_only()
+    }
+
     pub fn start(
         config: &Config,
         network: &Network,
@@ -26,19 +107,27 @@ impl Proxy {
         let yml = format!(
             include_str!("assets/proxy.yml"),
             kubeconfig.proxy().display(),
+            config.proxy_mode(),
             network.cluster(),
+            config.ports().proxy_healthz(),
+            config.ports().proxy_metrics(),
+            Instrumentation::profiling(config, ComponentKind::Proxy),
+            format!(
+                "{}{}",
+                config.proxy_mode().config(),
+                Containerized::proxy_config(config)
+            ),
         );
         let yml_file = dir.join("config.yml");
         fs::write(&yml_file, yml)?;
 
-        let mut process = Process::start(
-            config,
-            &dir,
-            "kube-proxy",
-            &[&format!("--config={}", yml_file.display())],
-        )?;
+        let process = Process::builder(config, &dir, "kube-proxy")
+            .arg(format!("--config={}", yml_file.display()))
+            .args(&Verbosity::args(config, ComponentKind::Proxy))
+            .depends_on(ComponentKind::ApiServer)
+            .ready_on("Caches are synced")
+            .spawn()?;
 
-        process.wait_ready("Caches are synced")?;
         info!("Proxy is ready");
         Ok(Box::new(Proxy { process }))
     }
@@ -49,3 +138,35 @@ impl Stoppable for Proxy {
         self.process.stop()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::tests::test_config, network::tests::test_network, process::FakeBackend};
+    use std::sync::Arc;
+
+    #[test]
+    fn start_fake_success() -> Fallible<()> {
+        let mut c = test_config()?;
+        let n = test_network()?;
+        let k = KubeConfig::load(&c, "");
+        let backend = Arc::new(FakeBackend::default());
+        c.set_backend(backend.clone());
+
+        let mut proxy = Proxy::start(&c, &n, &k)?;
+        let spawned = backend.spawned();
+        assert_eq!(spawned.len(), 1);
+        assert_eq!(spawned[0].0, "kube-proxy");
+        assert!(c.root().join("proxy").join("config.yml").exists());
+        proxy.stop()?;
+        Ok(())
+    }
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        assert_eq!("ipvs".parse::<ProxyMode>()?, ProxyMode::Ipvs);
+        assert_eq!("nftables".parse::<ProxyMode>()?, ProxyMode::Nftables);
+        assert!("userspace".parse::<ProxyMode>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{
+    component::ComponentKind,
+    config::{Check, OutputFormat, Wait, WaitCondition},
+    kubeconfig::KubeConfig,
+    output::Output,
+    process::Process,
+    Config, Error, Fallible,
+};
+use log::{debug, info};
+use nix::{sys::signal::kill, unistd::Pid};
+use serde_json::json;
+use std::{
+    fs::{self, read_to_string},
+    path::Path,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+pub struct Readiness;
+
+impl Readiness {
+    /// The file name of the readiness marker within the component directory
+    pub const MARKER: &'static str = "ready";
+
+    /// Mark the process with the provided PID as ready
+    pub fn mark(dir: &Path, pid: u32) -> Fallible<()> {
+        fs::write(dir.join(Self::MARKER), pid.to_string())?;
+        Ok(())
+    }
+
+    /// Remove the readiness mark if existing
+    pub fn unmark(dir: &Path) -> Fallible<()> {
+        let marker = dir.join(Self::MARKER);
+        if marker.exists() {
+            fs::remove_file(marker)?;
+        }
+        Ok(())
+    }
+
+    /// Check if a component is marked as ready and still running
+    pub fn is_ready(config: &Config, kind: ComponentKind) -> bool {
+        Self::is_marked(&config.root().join(kind.to_string()))
+    }
+
+    /// Check if the process within the provided directory is marked as ready
+    /// and still running
+    pub fn is_marked(dir: &Path) -> bool {
+        Self::pid(dir).map_or(false, |pid| kill(Pid::from_raw(pid), None).is_ok())
+    }
+
+    /// Retrieve the PID of the process marked as ready within the provided
+    /// directory
+    pub fn pid(dir: &Path) -> Option<i32> {
+        read_to_string(dir.join(Self::MARKER))
+            .ok()
+            .and_then(|x| x.trim().parse::<i32>().ok())
+    }
+
+    /// Wait for the conditions provided by the arguments
+    pub fn wait(config: &Config, args: &Wait) -> Fallible<()> {
+        Self::wait_for(
+            config,
+            &Self::conditions(config, args.conditions()),
+            args.timeout().duration(),
+        )?;
+        info!("All conditions met");
+        Ok(())
+    }
+
+    /// Wait until all provided conditions are met or the timeout is reached
+    pub fn wait_for(
+        config: &Config,
+        conditions: &[WaitCondition],
+        timeout: Duration,
+    ) -> Fallible<()> {
+        let now = Instant::now();
+        for condition in conditions {
+            debug!("Waiting for {}", condition);
+            loop {
+                if Self::is_met(config, condition)? {
+                    debug!("Condition {} met", condition);
+                    break;
+                }
+                if now.elapsed() >= timeout {
+                    return Err(Error::Timeout(format!("waiting for {}", condition)));
+                }
+                sleep(Duration::from_secs(1));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the conditions provided by the arguments once and print the
+    /// results in the requested format
+    pub fn check(config: &Config, args: &Check) -> Fallible<()> {
+        let mut unmet = vec![];
+        let mut results = vec![];
+        for condition in Self::conditions(config, args.conditions()) {
+            let met = Self::is_met(config, &condition)?;
+            if *args.output().format() == OutputFormat::Text {
+                info!(
+                    "Condition {} {}",
+                    condition,
+                    if met { "met" } else { "not met" }
+                );
+            }
+            if !met {
+                unmet.push(condition.to_string());
+            }
+            results.push(json!({ "condition": condition.to_string(), "met": met }));
+        }
+        if *args.output().format() != OutputFormat::Text {
+            Output::print(
+                *args.output().format(),
+                &json!({ "conditions": results, "met": unmet.is_empty() }),
+            )?;
+        }
+        if !unmet.is_empty() {
+            bail!("Conditions not met: {}", unmet.join(", "))
+        }
+        Ok(())
+    }
+
+    /// The provided conditions, whereas all enabled components are the
+    /// default
+    fn conditions(config: &Config, conditions: &[WaitCondition]) -> Vec<WaitCondition> {
+        if conditions.is_empty() {
+            ComponentKind::ALL
+                .iter()
+                .filter(|x| x.enabled(config))
+                .map(|&x| WaitCondition::Component(x))
+                .collect()
+        } else {
+            conditions.to_vec()
+        }
+    }
+
+    fn is_met(config: &Config, condition: &WaitCondition) -> Fallible<bool> {
+        match condition {
+            WaitCondition::Component(kind) => Ok(Self::is_ready(config, *kind)),
+            WaitCondition::NodesReady => {
+                let output = Process::run_to_completion("kubectl")
+                    .arg(format!(
+                        "--kubeconfig={}",
+                        KubeConfig::admin_path(config).display()
+                    ))
+                    .arg("wait")
+                    .arg("--for=condition=Ready")
+                    .arg("--timeout=1s")
+                    .arg("--all")
+                    .arg("nodes")
+                    .output()?;
+                if !output.success() {
+                    debug!("kubectl wait stderr: {}", output.stderr());
+                }
+                Ok(output.success())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+    use std::{fs::create_dir_all, process};
+
+    #[test]
+    fn mark_success() -> Fallible<()> {
+        let c = test_config()?;
+        let dir = c.root().join(ComponentKind::Etcd.to_string());
+        create_dir_all(&dir)?;
+        assert!(!Readiness::is_ready(&c, ComponentKind::Etcd));
+
+        Readiness::mark(&dir, process::id())?;
+        assert!(Readiness::is_ready(&c, ComponentKind::Etcd));
+
+        Readiness::unmark(&dir)?;
+        assert!(!Readiness::is_ready(&c, ComponentKind::Etcd));
+        Ok(())
+    }
+}
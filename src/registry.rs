@@ -0,0 +1,317 @@
+use crate::{
+    component::{Component, ComponentKind, Context, Health, OwnedContext},
+    process::{Process, Startable},
+    signals::Signals,
+    Error, Fallible,
+};
+use log::debug;
+use std::{
+    collections::HashSet,
+    sync::{
+        mpsc::{channel, Receiver},
+        Arc,
+    },
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
+};
+use tracing::info_span;
+
+/// The registry of all components supervised by kubernix
+pub struct Registry {
+    components: Vec<Arc<dyn Component>>,
+}
+
+impl Default for Registry {
+    /// Create a new registry containing all built-in components
+    fn default() -> Self {
+        Self {
+            components: ComponentKind::ALL
+                .iter()
+                .map(|&x| Arc::new(x) as Arc<dyn Component>)
+                .collect(),
+        }
+    }
+}
+
+impl Registry {
+    /// Add an additional component, which gets started after all built-in
+    /// components are up and running
+    pub fn register(&mut self, component: Box<dyn Component>) -> Fallible<()> {
+        let name = component.name();
+        if name.is_empty() || name.contains('/') {
+            bail!("Invalid component name '{}'", name)
+        }
+        if self.components.iter().any(|x| x.name() == name) {
+            bail!("Component '{}' is already registered", name)
+        }
+        debug!("Registering component '{}'", name);
+        self.components.push(component.into());
+        Ok(())
+    }
+
+    /// Remove a component, which must not be a dependency of any other one
+    pub fn unregister(&mut self, name: &str) -> Fallible<()> {
+        if let Some(x) = self
+            .components
+            .iter()
+            .find(|x| x.dependencies().iter().any(|d| d == name))
+        {
+            bail!(
+                "Component '{}' is required by component '{}'",
+                name,
+                x.name()
+            )
+        }
+        debug!("Unregistering component '{}'", name);
+        self.components.retain(|x| x.name() != name);
+        Ok(())
+    }
+
+    /// Retrieve all components ordered by their dependencies
+    pub fn ordered(&self) -> Fallible<Vec<&dyn Component>> {
+        let names: HashSet<String> = self.components.iter().map(|x| x.name()).collect();
+        for component in &self.components {
+            for dependency in component.dependencies() {
+                if !names.contains(&dependency) {
+                    bail!(
+                        "Component '{}' depends on unknown component '{}'",
+                        component.name(),
+                        dependency
+                    )
+                }
+            }
+        }
+
+        let mut ordered: Vec<&dyn Component> = vec![];
+        let mut done = HashSet::new();
+        while ordered.len() < self.components.len() {
+            let ready: Vec<&dyn Component> = self
+                .components
+                .iter()
+                .map(AsRef::as_ref)
+                .filter(|x| !done.contains(&x.name()))
+                .filter(|x| x.dependencies().iter().all(|d| done.contains(d)))
+                .collect();
+            if ready.is_empty() {
+                bail!("Cyclic dependencies between components detected")
+            }
+            for component in ready {
+                done.insert(component.name());
+                ordered.push(component);
+            }
+        }
+        Ok(ordered)
+    }
+
+    /// Retrieve a registered component by its name
+    pub fn get(&self, name: &str) -> Option<&dyn Component> {
+        self.components
+            .iter()
+            .find(|x| x.name() == name)
+            .map(|x| x.as_ref())
+    }
+
+    /// Retrieve all additionally registered components ordered by their
+    /// dependencies
+    pub fn additional(&self) -> Fallible<Vec<&dyn Component>> {
+        Ok(self
+            .ordered()?
+            .into_iter()
+            .filter(|x| x.name().parse::<ComponentKind>().is_err())
+            .collect())
+    }
+
+    /// Start the provided component after its dependencies are ready
+    pub fn start(&self, component: &dyn Component, context: &Context) -> Fallible<Startable> {
+        Self::wait_dependencies(component, |x| self.health(x, context))?;
+        info_span!("start", component = component.name().as_str())
+            .in_scope(|| component.start(context))
+    }
+
+    /// Start the component with the provided name in the background after
+    /// its dependencies are ready, whereas the result gets sent to the
+    /// returned receiver
+    pub fn start_background(
+        &self,
+        name: &str,
+        context: &Context,
+    ) -> Fallible<Receiver<Fallible<Startable>>> {
+        let component = self
+            .shared(name)
+            .ok_or_else(|| format_err!("Component '{}' not registered", name))?;
+        let dependencies: Vec<Arc<dyn Component>> = component
+            .dependencies()
+            .iter()
+            .filter_map(|x| self.shared(x))
+            .collect();
+        let context = OwnedContext::new(context);
+        let (sender, receiver) = channel();
+        spawn(move || {
+            let context = context.context();
+            let result = Self::wait_dependencies(component.as_ref(), |x| {
+                dependencies
+                    .iter()
+                    .find(|d| d.name() == x)
+                    .map_or(Health::NotReady, |d| d.health(&context))
+            })
+            .and_then(|_| {
+                info_span!("start", component = component.name().as_str())
+                    .in_scope(|| component.start(&context))
+            });
+            sender.send(result).ok();
+        });
+        Ok(receiver)
+    }
+
+    /// Wait until all dependencies of the component are ready
+    fn wait_dependencies<F>(component: &dyn Component, health: F) -> Fallible<()>
+    where
+        F: Fn(&str) -> Health,
+    {
+        let now = Instant::now();
+        for dependency in component.dependencies() {
+            debug!(
+                "Component '{}' waiting for dependency '{}'",
+                component.name(),
+                dependency
+            );
+            while health(&dependency) != Health::Ready {
+                Signals::check()?;
+                if now.elapsed() >= Process::DEPENDENCY_TIMEOUT {
+                    return Err(Error::Timeout(format!(
+                        "waiting for dependency '{}' of component '{}'",
+                        dependency,
+                        component.name()
+                    )));
+                }
+                sleep(Duration::from_millis(500));
+            }
+        }
+        Ok(())
+    }
+
+    /// Retrieve the health of all components in their dependency order
+    pub fn status(&self, context: &Context) -> Fallible<Vec<(String, Health)>> {
+        Ok(self
+            .ordered()?
+            .into_iter()
+            .map(|x| (x.name(), x.health(context)))
+            .collect())
+    }
+
+    /// Retrieve a shared reference to a registered component by its name
+    fn shared(&self, name: &str) -> Option<Arc<dyn Component>> {
+        self.components.iter().find(|x| x.name() == name).cloned()
+    }
+
+    fn health(&self, name: &str, context: &Context) -> Health {
+        self.get(name)
+            .map_or(Health::NotReady, |x| x.health(context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::StandaloneKubelet;
+
+    struct Test {
+        name: &'static str,
+        dependencies: Vec<String>,
+    }
+
+    impl Component for Test {
+        fn name(&self) -> String {
+            self.name.into()
+        }
+
+        fn dependencies(&self) -> Vec<String> {
+            self.dependencies.clone()
+        }
+
+        fn start(&self, _: &Context) -> Fallible<Startable> {
+            bail!("Not implemented")
+        }
+    }
+
+    fn test(name: &'static str, dependencies: &[&str]) -> Box<dyn Component> {
+        Box::new(Test {
+            name,
+            dependencies: dependencies.iter().map(|&x| x.to_owned()).collect(),
+        })
+    }
+
+    #[test]
+    fn ordered_success() -> Fallible<()> {
+        let mut r = Registry::default();
+        r.register(test("b", &["a"]))?;
+        r.register(test("a", &["apiserver"]))?;
+        let names: Vec<String> = r.ordered()?.iter().map(|x| x.name()).collect();
+        let pos = |n: &str| names.iter().position(|x| x == n).unwrap();
+        assert!(pos("etcd") < pos("apiserver"));
+        assert!(pos("apiserver") < pos("a"));
+        assert!(pos("a") < pos("b"));
+
+        let additional: Vec<String> = r.additional()?.iter().map(|x| x.name()).collect();
+        assert_eq!(additional, vec!["a", "b"]);
+        Ok(())
+    }
+
+    #[test]
+    fn ordered_failure_unknown_dependency() -> Fallible<()> {
+        let mut r = Registry::default();
+        r.register(test("a", &["invalid"]))?;
+        assert!(r.ordered().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ordered_failure_cycle() -> Fallible<()> {
+        let mut r = Registry::default();
+        r.register(test("a", &["b"]))?;
+        r.register(test("b", &["a"]))?;
+        assert!(r.ordered().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn register_failure() {
+        let mut r = Registry::default();
+        assert!(r.register(test("etcd", &[])).is_err());
+        assert!(r.register(test("", &[])).is_err());
+        assert!(r.register(test("a/b", &[])).is_err());
+    }
+
+    #[test]
+    fn unregister_success() -> Fallible<()> {
+        let mut r = Registry::default();
+        r.unregister("proxy")?;
+        assert!(r.ordered()?.iter().all(|x| x.name() != "proxy"));
+        Ok(())
+    }
+
+    #[test]
+    fn unregister_success_standalone_kubelet() -> Fallible<()> {
+        let mut r = Registry::default();
+        r.unregister("kubelet")?;
+        r.register(Box::new(StandaloneKubelet))?;
+        for name in &[
+            "proxy",
+            "scheduler",
+            "controllermanager",
+            "apiserver",
+            "etcd",
+        ] {
+            r.unregister(name)?;
+        }
+        let names: Vec<String> = r.ordered()?.iter().map(|x| x.name()).collect();
+        assert_eq!(names, vec!["crio", "kubelet"]);
+        Ok(())
+    }
+
+    #[test]
+    fn unregister_failure() {
+        let mut r = Registry::default();
+        assert!(r.unregister("apiserver").is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{Config, Fallible};
+use log::debug;
+use serde_json::Value;
+use std::{
+    fs::{self, create_dir_all, set_permissions, Permissions},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+pub struct RegistryAuth;
+
+impl RegistryAuth {
+    /// The file name of the credentials, which is also looked up by the
+    /// kubelet within its root directory
+    const FILENAME: &'static str = "config.json";
+
+    /// Install the configured registry credentials into the provided
+    /// directory and return their path
+    pub fn install(config: &Config, dir: &Path) -> Fallible<Option<PathBuf>> {
+        let source = match config.registry_auth() {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let content = fs::read_to_string(source).map_err(|e| {
+            format_err!(
+                "Unable to read registry auth file '{}': {}",
+                source.display(),
+                e
+            )
+        })?;
+        let auth: Value = serde_json::from_str(&content).map_err(|e| {
+            format_err!(
+                "Unable to parse registry auth file '{}': {}",
+                source.display(),
+                e
+            )
+        })?;
+        if auth.get("auths").is_none() {
+            bail!(
+                "Registry auth file '{}' contains no 'auths' section",
+                source.display()
+            );
+        }
+
+        create_dir_all(dir)?;
+        let file = dir.join(Self::FILENAME);
+        fs::write(&file, content)?;
+        set_permissions(&file, Permissions::from_mode(0o600))?;
+        debug!("Installed registry auth file '{}'", file.display());
+        Ok(Some(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+
+    #[test]
+    fn install_success_disabled() -> Fallible<()> {
+        let c = test_config()?;
+        assert!(RegistryAuth::install(&c, c.root())?.is_none());
+        Ok(())
+    }
+}
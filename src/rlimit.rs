@@ -0,0 +1,279 @@
+use crate::{component::ComponentKind, Config, Error, Fallible};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    io,
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The supported resources to be limited
+pub enum Resource {
+    /// The maximum number of open file descriptors
+    Nofile,
+
+    /// The maximum number of processes
+    Nproc,
+
+    /// The maximum amount of locked memory in bytes
+    Memlock,
+}
+
+impl Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Resource::Nofile => "nofile",
+            Resource::Nproc => "nproc",
+            Resource::Memlock => "memlock",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Resource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "nofile" => Ok(Resource::Nofile),
+            "nproc" => Ok(Resource::Nproc),
+            "memlock" => Ok(Resource::Memlock),
+            _ => Err(format_err!("Unknown resource '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+/// A resource limit for a single component
+pub struct RLimit {
+    component: ComponentKind,
+    resource: Resource,
+    soft: u64,
+    hard: u64,
+}
+
+impl RLimit {
+    /// The default number of open file descriptors for components which need
+    /// many of them
+    const DEFAULT_NOFILE: u64 = 65536;
+
+    /// The built-in limits, which can be overwritten via the configuration
+    fn defaults() -> Vec<RLimit> {
+        [
+            ComponentKind::Crio,
+            ComponentKind::Etcd,
+            ComponentKind::ApiServer,
+            ComponentKind::Kubelet,
+        ]
+        .iter()
+        .map(|&component| RLimit {
+            component,
+            resource: Resource::Nofile,
+            soft: Self::DEFAULT_NOFILE,
+            hard: Self::DEFAULT_NOFILE,
+        })
+        .collect()
+    }
+
+    /// Retrieve the effective limits for the provided component
+    pub fn for_component(config: &Config, kind: ComponentKind) -> Vec<RLimit> {
+        let mut limits: Vec<RLimit> = Self::defaults()
+            .into_iter()
+            .filter(|x| x.component == kind)
+            .collect();
+        for limit in config.rlimits().iter().filter(|x| x.component == kind) {
+            limits.retain(|x| x.resource != limit.resource);
+            limits.push(limit.clone());
+        }
+        limits
+    }
+
+    /// Ensure that the hard limits of the current process are sufficient for
+    /// all components, whereas too low ones get raised
+    pub fn preflight(config: &Config) -> Fallible<()> {
+        for kind in ComponentKind::ALL.iter().filter(|x| x.enabled(config)) {
+            for limit in Self::for_component(config, *kind) {
+                let current = limit.current()?;
+                debug!(
+                    "Current hard limit for {} is {}, {} needs {}",
+                    limit.resource, current.rlim_max, kind, limit.hard
+                );
+                if (current.rlim_max as u64) < limit.hard {
+                    debug!("Raising hard {} limit to {}", limit.resource, limit.hard);
+                    let rlim = libc::rlimit {
+                        rlim_cur: current.rlim_cur,
+                        rlim_max: limit.hard as libc::rlim_t,
+                    };
+                    if let Err(e) = Self::set(limit.resource, &rlim) {
+                        bail!(
+                            "Hard {} limit of {} is too low for {}, which requires {}, \
+                             and raising it failed: {}",
+                            limit.resource,
+                            current.rlim_max,
+                            kind,
+                            limit.hard,
+                            e
+                        )
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the limit to the current process
+    pub fn apply(&self) -> io::Result<()> {
+        let rlim = libc::rlimit {
+            rlim_cur: self.soft as libc::rlim_t,
+            rlim_max: self.hard as libc::rlim_t,
+        };
+        Self::set(self.resource, &rlim)
+    }
+
+    /// Retrieve the limit of the current process
+    fn current(&self) -> Fallible<libc::rlimit> {
+        let mut rlim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(raw_resource(self.resource), &mut rlim) } != 0 {
+            bail!(
+                "Unable to retrieve {} limit: {}",
+                self.resource,
+                io::Error::last_os_error()
+            )
+        }
+        Ok(rlim)
+    }
+
+    /// Set the limit of the provided resource for the current process
+    fn set(resource: Resource, rlim: &libc::rlimit) -> io::Result<()> {
+        if unsafe { libc::setrlimit(raw_resource(resource), rlim) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Retrieve the `ulimit` commands to reproduce the limit in a shell
+    pub fn ulimit_command(&self) -> String {
+        let flag = match self.resource {
+            Resource::Nofile => "n",
+            Resource::Nproc => "u",
+            Resource::Memlock => "l",
+        };
+        // `ulimit -l` expects kilobytes
+        let (soft, hard) = match self.resource {
+            Resource::Memlock => (self.soft / 1024, self.hard / 1024),
+            _ => (self.soft, self.hard),
+        };
+        format!("ulimit -H{} {}\nulimit -S{} {}", flag, hard, flag, soft)
+    }
+}
+
+/// The resource type of `getrlimit` and `setrlimit`, which is unsigned for
+/// glibc only
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type RawResource = libc::__rlimit_resource_t;
+
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type RawResource = libc::c_int;
+
+fn raw_resource(resource: Resource) -> RawResource {
+    match resource {
+        Resource::Nofile => libc::RLIMIT_NOFILE as RawResource,
+        Resource::Nproc => libc::RLIMIT_NPROC as RawResource,
+        Resource::Memlock => libc::RLIMIT_MEMLOCK as RawResource,
+    }
+}
+
+impl Display for RLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}={}", self.component, self.resource, self.soft)?;
+        if self.hard != self.soft {
+            write!(f, ":{}", self.hard)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for RLimit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let err = || {
+            format_err!(
+                "Invalid resource limit '{}', expected '<component>:<resource>=<soft>[:<hard>]'",
+                s
+            )
+        };
+        let mut split = s.splitn(2, '=');
+        let (key, value) = match (split.next(), split.next()) {
+            (Some(k), Some(v)) => (k, v),
+            _ => return Err(err()),
+        };
+        let mut key_split = key.splitn(2, ':');
+        let (component, resource) = match (key_split.next(), key_split.next()) {
+            (Some(c), Some(r)) => (c.parse()?, r.parse()?),
+            _ => return Err(err()),
+        };
+        let mut value_split = value.splitn(2, ':');
+        let soft: u64 = value_split
+            .next()
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(err)?;
+        let hard = match value_split.next() {
+            Some(x) => x.parse().map_err(|_| err())?,
+            None => soft,
+        };
+        if soft > hard {
+            bail!("Soft limit {} exceeds hard limit {} in '{}'", soft, hard, s)
+        }
+        Ok(RLimit {
+            component,
+            resource,
+            soft,
+            hard,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        let l: RLimit = "etcd:nofile=1024".parse()?;
+        assert_eq!(l.component, ComponentKind::Etcd);
+        assert_eq!(l.resource, Resource::Nofile);
+        assert_eq!(l.soft, 1024);
+        assert_eq!(l.hard, 1024);
+        assert_eq!(l.to_string(), "etcd:nofile=1024");
+
+        let l: RLimit = "kubelet:nproc=1024:2048".parse()?;
+        assert_eq!(l.soft, 1024);
+        assert_eq!(l.hard, 2048);
+        assert_eq!(l.to_string(), "kubelet:nproc=1024:2048");
+        Ok(())
+    }
+
+    #[test]
+    fn from_str_failure() {
+        assert!("etcd:nofile".parse::<RLimit>().is_err());
+        assert!("etcd=1024".parse::<RLimit>().is_err());
+        assert!("invalid:nofile=1024".parse::<RLimit>().is_err());
+        assert!("etcd:invalid=1024".parse::<RLimit>().is_err());
+        assert!("etcd:nofile=2048:1024".parse::<RLimit>().is_err());
+    }
+
+    #[test]
+    fn for_component_defaults() -> Fallible<()> {
+        let c = test_config()?;
+        assert_eq!(RLimit::for_component(&c, ComponentKind::Etcd).len(), 1);
+        assert!(RLimit::for_component(&c, ComponentKind::Proxy).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+use crate::Fallible;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The machine readable metadata of a process, which gets written alongside
+/// its run file
+pub struct RunInfo {
+    #[get = "pub"]
+    /// The name of the process
+    name: String,
+
+    #[get = "pub"]
+    /// The executed command
+    command: String,
+
+    #[get = "pub"]
+    /// The arguments of the command
+    args: Vec<String>,
+
+    #[get = "pub"]
+    /// The environment of the process
+    env: BTreeMap<String, String>,
+
+    #[get = "pub"]
+    /// The working directory of the process
+    cwd: PathBuf,
+
+    #[get = "pub"]
+    /// The file mode creation mask of the process
+    umask: u32,
+
+    #[get = "pub"]
+    /// The ports the process got configured for via its arguments
+    ports: Vec<u16>,
+
+    #[get = "pub"]
+    /// The certificates and keys referenced by the arguments
+    certificates: Vec<PathBuf>,
+
+    #[get = "pub"]
+    /// The configuration files referenced by the arguments
+    config_files: Vec<PathBuf>,
+
+    #[get = "pub"]
+    /// The PID of the latest spawn, which is not set before spawning
+    pid: Option<u32>,
+
+    #[get = "pub"]
+    /// The UNIX time of the latest spawn
+    started: Option<u64>,
+}
+
+impl RunInfo {
+    /// The file name of the metadata within the process directory
+    pub const FILE: &'static str = "run.json";
+
+    /// The file extensions of certificates and keys
+    const CERTIFICATE_EXTENSIONS: &'static [&'static str] = &["crt", "key", "pem"];
+
+    /// The file extensions of configuration files
+    const CONFIG_EXTENSIONS: &'static [&'static str] =
+        &["cfg", "conf", "json", "kubeconfig", "toml", "yaml", "yml"];
+
+    /// Create the metadata of a process which has not been spawned yet
+    pub fn new(
+        name: &str,
+        command: &str,
+        args: &[String],
+        env: BTreeMap<String, String>,
+        cwd: &Path,
+        umask: u32,
+    ) -> Self {
+        let values: Vec<(&str, &str)> = args.iter().filter_map(|x| Self::split(x)).collect();
+        let mut ports: Vec<u16> = values
+            .iter()
+            .filter_map(|(key, value)| Self::port(key, value))
+            .collect();
+        ports.sort();
+        ports.dedup();
+        let files = |extensions: &[&str]| -> Vec<PathBuf> {
+            values
+                .iter()
+                .map(|(_, value)| Path::new(value))
+                .filter(|x| {
+                    x.is_absolute()
+                        && x.extension()
+                            .and_then(|x| x.to_str())
+                            .map(|x| extensions.contains(&x))
+                            .unwrap_or(false)
+                })
+                .map(ToOwned::to_owned)
+                .collect()
+        };
+        RunInfo {
+            name: name.into(),
+            command: command.into(),
+            args: args.to_vec(),
+            env,
+            cwd: cwd.to_owned(),
+            umask,
+            ports,
+            certificates: files(Self::CERTIFICATE_EXTENSIONS),
+            config_files: files(Self::CONFIG_EXTENSIONS),
+            pid: None,
+            started: None,
+        }
+    }
+
+    /// Record a spawn of the process with the provided PID
+    pub fn spawned(&mut self, pid: u32) {
+        self.pid = Some(pid);
+        self.started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .ok();
+    }
+
+    /// Retrieve the referenced certificates and configuration files which
+    /// changed after the latest spawn, which the process does not know about
+    pub fn changed_files(&self) -> Vec<PathBuf> {
+        let started = match self.started {
+            Some(x) => x,
+            None => return vec![],
+        };
+        self.certificates
+            .iter()
+            .chain(&self.config_files)
+            .filter(|x| {
+                fs::metadata(x)
+                    .and_then(|x| x.modified())
+                    .ok()
+                    .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+                    .map_or(false, |x| x.as_secs() > started)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Write the metadata into the provided process directory
+    pub fn write(&self, dir: &Path) -> Fallible<()> {
+        let file = dir.join(Self::FILE);
+        fs::write(&file, serde_json::to_string_pretty(self)?)
+            .map_err(|e| format_err!("Unable to create '{}': {}", file.display(), e))?;
+        Ok(())
+    }
+
+    /// Load the metadata of the provided process directory
+    pub fn load(dir: &Path) -> Fallible<RunInfo> {
+        let file = dir.join(Self::FILE);
+        let content = fs::read_to_string(&file)
+            .map_err(|e| format_err!("Unable to read '{}': {}", file.display(), e))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Split an argument like `--flag=value` into its key and value
+    fn split(arg: &str) -> Option<(&str, &str)> {
+        let mut split = arg.trim_start_matches('-').splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some(key), Some(value)) if !value.is_empty() => Some((key, value)),
+            _ => None,
+        }
+    }
+
+    /// Retrieve the port of an argument, which is either a port flag or a URL
+    /// with an explicit port
+    fn port(key: &str, value: &str) -> Option<u16> {
+        let port = if key.ends_with("port") {
+            value
+        } else if value.contains("://") {
+            value
+                .split("://")
+                .nth(1)?
+                .split('/')
+                .next()?
+                .rsplit(':')
+                .next()?
+        } else {
+            return None;
+        };
+        port.parse().ok().filter(|x| *x > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn new_success() {
+        let args: Vec<String> = [
+            "--secure-port=6443",
+            "--insecure-port=0",
+            "--listen-client-urls=https://127.0.0.1:2379",
+            "--listen-peer-urls=unix:///root/etcd/peer.sock",
+            "--cert-file=/root/pki/apiserver.pem",
+            "--key-file=/root/pki/apiserver-key.pem",
+            "--config=/root/kubelet/config.yml",
+            "--kubeconfig=/root/kubeconfig/admin.kubeconfig",
+            "--data-dir=/root/etcd/run",
+            "--v=2",
+            "-db",
+        ]
+        .iter()
+        .map(|x| (*x).to_owned())
+        .collect();
+        let info = RunInfo::new(
+            "apiserver",
+            "kube-apiserver",
+            &args,
+            BTreeMap::new(),
+            Path::new("/root/apiserver"),
+            0o022,
+        );
+        assert_eq!(info.ports(), &[2379, 6443]);
+        assert_eq!(
+            info.certificates(),
+            &[
+                PathBuf::from("/root/pki/apiserver.pem"),
+                PathBuf::from("/root/pki/apiserver-key.pem")
+            ]
+        );
+        assert_eq!(
+            info.config_files(),
+            &[
+                PathBuf::from("/root/kubelet/config.yml"),
+                PathBuf::from("/root/kubeconfig/admin.kubeconfig")
+            ]
+        );
+        assert!(info.pid().is_none());
+    }
+
+    #[test]
+    fn write_load_success() -> Fallible<()> {
+        let d = tempdir()?;
+        let mut info = RunInfo::new("echo", "echo", &[], BTreeMap::new(), d.path(), 0o077);
+        info.spawned(42);
+        info.write(d.path())?;
+        let loaded = RunInfo::load(d.path())?;
+        assert_eq!(loaded, info);
+        assert_eq!(*loaded.pid(), Some(42));
+        assert!(loaded.started().is_some());
+        assert!(RunInfo::load(&d.path().join("missing")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn changed_files_success() -> Fallible<()> {
+        let d = tempdir()?;
+        let config = d.path().join("config.yml");
+        fs::write(&config, "")?;
+        let args = vec![format!("--config={}", config.display())];
+        let mut info = RunInfo::new("echo", "echo", &args, BTreeMap::new(), d.path(), 0o022);
+        assert!(info.changed_files().is_empty());
+
+        info.spawned(42);
+        assert!(info.changed_files().is_empty());
+
+        info.started = Some(0);
+        assert_eq!(info.changed_files(), vec![config]);
+        Ok(())
+    }
+}
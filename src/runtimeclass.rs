@@ -0,0 +1,144 @@
+use crate::{kubeconfig::KubeConfig, process::Process, Config, Error, Fallible, Kubernix};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs::{self, create_dir_all},
+    path::Path,
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The available OCI runtimes, which are exposed as RuntimeClass objects
+pub enum RuntimeClass {
+    /// The default runtime
+    Runc,
+
+    /// Kata Containers, running every pod inside a lightweight VM
+    Kata,
+
+    /// gVisor, running every pod inside a user space kernel
+    Gvisor,
+}
+
+impl Display for RuntimeClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RuntimeClass::Runc => "runc",
+            RuntimeClass::Kata => "kata",
+            RuntimeClass::Gvisor => "gvisor",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for RuntimeClass {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "runc" => Ok(RuntimeClass::Runc),
+            "kata" => Ok(RuntimeClass::Kata),
+            "gvisor" => Ok(RuntimeClass::Gvisor),
+            _ => Err(format_err!("Unsupported runtime class '{}'", s)),
+        }
+    }
+}
+
+impl RuntimeClass {
+    /// The Nix package providing the runtime, if not part of the default
+    /// dependencies
+    pub fn package(self) -> Option<&'static str> {
+        match self {
+            RuntimeClass::Runc => None,
+            RuntimeClass::Kata => Some("kata-runtime"),
+            RuntimeClass::Gvisor => Some("gvisor"),
+        }
+    }
+
+    /// The CRI-O runtime handler, whereas runc is the already configured
+    /// default runtime
+    pub fn handler(self) -> String {
+        match self {
+            RuntimeClass::Runc => "local-runc".into(),
+            x => x.to_string(),
+        }
+    }
+
+    /// The CRI-O `--runtimes` argument value for the runtime
+    pub fn crio_runtime(self, dir: &Path) -> Fallible<String> {
+        let executable = match self {
+            RuntimeClass::Runc => "runc",
+            RuntimeClass::Kata => "kata-runtime",
+            RuntimeClass::Gvisor => "runsc",
+        };
+        Ok(format!(
+            "{}:{}:{}",
+            self.handler(),
+            Kubernix::find_executable(executable)?.display(),
+            dir.join(self.to_string()).display()
+        ))
+    }
+
+    /// Create the RuntimeClass objects for all configured runtimes
+    pub fn apply(config: &Config, kubeconfig: &KubeConfig) -> Fallible<()> {
+        if config.runtime_classes().is_empty() {
+            return Ok(());
+        }
+        info!("Creating runtime classes");
+
+        let dir = config.root().join("runtimeclass");
+        create_dir_all(&dir)?;
+        let yml_file = dir.join("runtimeclasses.yml");
+        fs::write(&yml_file, Self::manifest(config.runtime_classes()))?;
+
+        Process::run_to_completion("kubectl")
+            .arg("apply")
+            .arg(format!("--kubeconfig={}", kubeconfig.admin().display()))
+            .arg("-f")
+            .arg(yml_file)
+            .retries(3)
+            .run()?;
+        Ok(())
+    }
+
+    /// Render the RuntimeClass objects for the provided runtimes
+    fn manifest(classes: &[RuntimeClass]) -> String {
+        classes
+            .iter()
+            .map(|x| {
+                format!(
+                    "apiVersion: node.k8s.io/v1beta1\n\
+                     kind: RuntimeClass\n\
+                     metadata:\n  name: {}\n\
+                     handler: {}\n",
+                    x,
+                    x.handler()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("---\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        assert_eq!("kata".parse::<RuntimeClass>()?, RuntimeClass::Kata);
+        assert_eq!("gvisor".parse::<RuntimeClass>()?, RuntimeClass::Gvisor);
+        assert!("crun".parse::<RuntimeClass>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_success() {
+        let m = RuntimeClass::manifest(&[RuntimeClass::Runc, RuntimeClass::Gvisor]);
+        assert!(m.contains("name: runc\nhandler: local-runc\n"));
+        assert!(m.contains("---\n"));
+        assert!(m.contains("name: gvisor\nhandler: gvisor\n"));
+    }
+}
@@ -1,14 +1,21 @@
 use crate::{
+    component::{Component, ComponentKind, Context},
     config::Config,
+    instrumentation::Instrumentation,
     kubeconfig::KubeConfig,
-    process::{Process, Startable, Stoppable},
+    process::{Process, RestartPolicy, Startable, Stoppable},
+    verbosity::Verbosity,
+    Fallible, KUBECONFIG_ENV,
 };
-use failure::Fallible;
 use log::info;
-use std::fs::{self, create_dir_all};
+use serde_json::{json, to_string_pretty, Value};
+use std::{
+    fs::{self, create_dir_all},
+    path::{Path, PathBuf},
+};
 
 pub struct Scheduler {
-    process: Process,
+    replicas: Vec<Process>,
 }
 
 impl Scheduler {
@@ -18,28 +25,183 @@ impl Scheduler {
         let dir = config.root().join("scheduler");
         create_dir_all(&dir)?;
 
-        let yml = format!(
-            include_str!("assets/scheduler.yml"),
-            kubeconfig.scheduler().display()
-        );
-        let cfg = &dir.join("config.yml");
-        fs::write(cfg, yml)?;
+        if *config.control_plane_replicas() == 0 {
+            bail!("At least one control plane replica is required");
+        }
+
+        // The extenders are part of the scheduling policy
+        let algorithm_source = match config.scheduler_extender() {
+            Some(url) => {
+                let policy = dir.join("policy.json");
+                fs::write(&policy, to_string_pretty(&Self::policy(url)?)?)?;
+                format!(
+                    "algorithmSource:\n  policy:\n    file:\n      path: \"{}\"\n",
+                    policy.display()
+                )
+            }
+            None => String::new(),
+        };
+
+        let mut replicas = vec![];
+        for replica in 0..*config.control_plane_replicas() {
+            let (replica_dir, name) = if replica == 0 {
+                (dir.clone(), "kube-scheduler".to_owned())
+            } else {
+                (
+                    dir.join(format!("replica-{}", replica)),
+                    format!("kube-scheduler-{}", replica),
+                )
+            };
+            create_dir_all(&replica_dir)?;
 
-        let mut process = Process::start(
-            config,
-            &dir,
-            "kube-scheduler",
-            &[&format!("--config={}", cfg.display()), "--v=2"],
-        )?;
+            // Additional replicas serve on distinct ports
+            let offset = u16::from(replica) * Config::REPLICA_PORT_OFFSET;
+            let yml = format!(
+                include_str!("assets/scheduler.yml"),
+                kubeconfig.scheduler().display(),
+                config.leader_election(),
+                config.ports().scheduler_insecure() + offset,
+                algorithm_source,
+                Instrumentation::profiling(config, ComponentKind::Scheduler),
+            );
+            let cfg = &replica_dir.join("config.yml");
+            fs::write(cfg, yml)?;
+
+            // The Scheduler exits if it loses its leader election
+            match Process::builder(config, &replica_dir, "kube-scheduler")
+                .name(&name)
+                .args(&[
+                    &format!("--config={}", cfg.display()),
+                    &format!("--secure-port={}", config.ports().scheduler() + offset),
+                ])
+                .args(&Verbosity::args(config, ComponentKind::Scheduler))
+                .depends_on(ComponentKind::ApiServer)
+                .restart(RestartPolicy::OnFailure(3))
+                .ready_on("Serving securely")
+                .spawn()
+            {
+                Ok(x) => replicas.push(x),
+                Err(e) => {
+                    for mut x in replicas.into_iter().rev() {
+                        x.stop()?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
 
-        process.wait_ready("Serving securely")?;
         info!("Scheduler is ready");
-        Ok(Box::new(Scheduler { process }))
+        Ok(Box::new(Scheduler { replicas }))
+    }
+
+    /// Create the scheduling policy for the HTTP extender at the provided
+    /// URL, whereas the default predicates and priorities stay in place
+    fn policy(url: &str) -> Fallible<Value> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            bail!("Invalid scheduler extender URL '{}'", url)
+        }
+        Ok(json!({
+            "kind": "Policy",
+            "apiVersion": "v1",
+            "extenders": [{
+                "urlPrefix": url.trim_end_matches('/'),
+                "filterVerb": "filter",
+                "prioritizeVerb": "prioritize",
+                "weight": 1,
+                "enableHttps": url.starts_with("https://"),
+                "nodeCacheCapable": false,
+            }],
+        }))
     }
 }
 
 impl Stoppable for Scheduler {
     fn stop(&mut self) -> Fallible<()> {
-        self.process.stop()
+        Process::stop_all(self.replicas.iter_mut().rev())
+    }
+}
+
+/// A user provided scheduler binary, which runs alongside the built-in
+/// Scheduler and handles the pods requesting its scheduler name
+pub struct SecondaryScheduler {
+    binary: PathBuf,
+}
+
+impl SecondaryScheduler {
+    /// The name of the component and its directory
+    const NAME: &'static str = "secondary-scheduler";
+
+    /// The secure port, which does not collide with the replicas of the
+    /// built-in Scheduler
+    const SECURE_PORT: u16 = 10269;
+
+    /// The insecure healthz and metrics port
+    const INSECURE_PORT: u16 = 10261;
+
+    pub fn new(binary: &Path) -> Self {
+        Self {
+            binary: binary.to_owned(),
+        }
+    }
+}
+
+impl Component for SecondaryScheduler {
+    fn name(&self) -> String {
+        Self::NAME.into()
+    }
+
+    fn dependencies(&self) -> Vec<String> {
+        vec![ComponentKind::ApiServer.to_string()]
+    }
+
+    fn start(&self, context: &Context) -> Fallible<Startable> {
+        let config = context.config();
+        let dir = context.dir(Self::NAME);
+        create_dir_all(&dir)?;
+
+        // The own kubeconfig uses the identity of the built-in Scheduler,
+        // which already has the required permissions
+        let kubeconfig = dir.join("kubeconfig");
+        fs::copy(context.kubeconfig.scheduler(), &kubeconfig)?;
+
+        let yml = format!(
+            include_str!("assets/secondary-scheduler.yml"),
+            config.secondary_scheduler_name(),
+            kubeconfig.display(),
+            Self::INSECURE_PORT,
+        );
+        let cfg = dir.join("config.yml");
+        fs::write(&cfg, yml)?;
+
+        let process = Process::builder(config, &dir, &self.binary.display().to_string())
+            .name(Self::NAME)
+            .args(&[
+                format!("--config={}", cfg.display()),
+                format!("--secure-port={}", Self::SECURE_PORT),
+            ])
+            .env(KUBECONFIG_ENV, &kubeconfig.display().to_string())
+            .restart(RestartPolicy::OnFailure(3))
+            .spawn()?;
+        Ok(Box::new(process))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_success() -> Fallible<()> {
+        let policy = Scheduler::policy("https://localhost:8888/scheduler/")?;
+        let extender = &policy["extenders"][0];
+        assert_eq!(extender["urlPrefix"], "https://localhost:8888/scheduler");
+        assert_eq!(extender["enableHttps"], true);
+        assert_eq!(extender["filterVerb"], "filter");
+        Ok(())
+    }
+
+    #[test]
+    fn policy_failure() {
+        assert!(Scheduler::policy("localhost:8888").is_err());
     }
 }
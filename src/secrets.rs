@@ -0,0 +1,418 @@
+use crate::{
+    encryptionconfig::EncryptionConfig, kubeconfig::KubeConfig, pki::Pki, Config, Error, Fallible,
+};
+use base64::encode;
+use log::{debug, info};
+use nix::mount::{mount, MsFlags};
+use proc_mounts::MountIter;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs::{
+        create_dir_all, remove_dir_all, remove_file, rename, symlink_metadata, File, OpenOptions,
+    },
+    io::Write,
+    os::unix::fs::{symlink, OpenOptionsExt},
+    path::Path,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The available storage backends for generated secrets
+pub enum SecretBackend {
+    /// Plaintext files inside the root
+    Plain,
+
+    /// A file encrypted to an age identity
+    Age,
+
+    /// A file encrypted with a key of the kernel keyring
+    Keyring,
+}
+
+impl Display for SecretBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SecretBackend::Plain => "plain",
+            SecretBackend::Age => "age",
+            SecretBackend::Keyring => "keyring",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SecretBackend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "plain" => Ok(SecretBackend::Plain),
+            "age" => Ok(SecretBackend::Age),
+            "keyring" => Ok(SecretBackend::Keyring),
+            _ => Err(format_err!("Unknown secret backend '{}'", s)),
+        }
+    }
+}
+
+impl SecretBackend {
+    /// The Nix package providing the tooling of the backend, if any
+    pub fn package(self) -> Option<&'static str> {
+        match self {
+            SecretBackend::Plain => None,
+            SecretBackend::Age => Some("age"),
+            SecretBackend::Keyring => Some("keyutils"),
+        }
+    }
+}
+
+/// Generated secrets, which are only available in plaintext inside a tmpfs
+/// while the cluster is running
+pub struct Secrets;
+
+impl Secrets {
+    /// The tmpfs directory within the root
+    const DIR: &'static str = "secrets";
+
+    /// The encrypted archive of the secrets within the root
+    const SEALED: &'static str = "secrets.tar.enc";
+
+    /// The environment variable passing the keyring key to openssl
+    const KEY_ENV: &'static str = "KUBERNIX_SECRET_KEY";
+
+    /// The directories containing generated secrets
+    const DIRS: [&'static str; 3] = [Pki::DIR, KubeConfig::DIR, EncryptionConfig::DIR];
+
+    /// Verify that the configured backend is usable
+    pub fn preflight(config: &Config) -> Fallible<()> {
+        if *config.secret_backend() == SecretBackend::Age {
+            match config.secret_age_identity() {
+                Some(x) if x.is_file() => {}
+                Some(x) => bail!("age identity '{}' does not exist", x.display()),
+                None => bail!("The age secret backend requires --secret-age-identity"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Mount the tmpfs, decrypt the secrets of a previous run into it and
+    /// link the secret directories of the root to it
+    pub fn unseal(config: &Config) -> Fallible<()> {
+        if *config.secret_backend() == SecretBackend::Plain {
+            // Links of a previously used backend would point into an
+            // unmounted tmpfs
+            for name in &Self::DIRS {
+                let link = config.root().join(name);
+                if symlink_metadata(&link).map_or(false, |x| x.file_type().is_symlink()) {
+                    remove_file(&link)?;
+                }
+            }
+            return Ok(());
+        }
+
+        let dir = config.root().join(Self::DIR);
+        if !Self::is_mounted(&dir)? {
+            debug!("Mounting secrets tmpfs to '{}'", dir.display());
+            create_dir_all(&dir)?;
+            mount(
+                Some("tmpfs"),
+                &dir,
+                Some("tmpfs"),
+                MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+                Some("mode=0700"),
+            )
+            .map_err(|e| format_err!("Unable to mount secrets tmpfs: {}", e))?;
+
+            let sealed = config.root().join(Self::SEALED);
+            if sealed.exists() {
+                info!("Unsealing secrets via {} backend", config.secret_backend());
+                Self::decrypt(Self::cipher(config, true)?, &sealed, &dir)?;
+            }
+        }
+
+        for name in &Self::DIRS {
+            let link = config.root().join(name);
+            let target = dir.join(name);
+            match symlink_metadata(&link) {
+                Ok(x) if x.file_type().is_symlink() => continue,
+                Ok(_) => {
+                    // Plaintext secrets of a previous run get moved into the
+                    // tmpfs
+                    debug!("Moving plaintext secrets '{}'", link.display());
+                    let status = Command::new("cp")
+                        .arg("--archive")
+                        .arg("--no-target-directory")
+                        .arg(&link)
+                        .arg(&target)
+                        .status()?;
+                    if !status.success() {
+                        bail!("cp command failed with {}", status)
+                    }
+                    remove_dir_all(&link)?;
+                }
+                Err(_) => create_dir_all(&target)?,
+            }
+            symlink(Path::new(Self::DIR).join(name), &link)?;
+        }
+        Ok(())
+    }
+
+    /// Encrypt the current secrets into the root
+    pub fn seal(config: &Config) -> Fallible<()> {
+        if *config.secret_backend() == SecretBackend::Plain {
+            return Ok(());
+        }
+        info!("Sealing secrets via {} backend", config.secret_backend());
+        Self::encrypt(
+            Self::cipher(config, false)?,
+            &config.root().join(Self::DIR),
+            &config.root().join(Self::SEALED),
+        )
+    }
+
+    /// Archive the provided directory and encrypt it via the cipher into the
+    /// sealed file
+    fn encrypt(mut cipher: Command, dir: &Path, sealed: &Path) -> Fallible<()> {
+        let tmp = sealed.with_extension("tmp");
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)?;
+
+        let mut tar = Command::new("tar")
+            .arg("--create")
+            .arg(format!("--directory={}", dir.display()))
+            .arg("--file=-")
+            .arg(".")
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = tar
+            .stdout
+            .take()
+            .ok_or_else(|| format_err!("Unable to get tar stdout"))?;
+        let status = cipher.stdin(stdout).stdout(file).status()?;
+        let tar_status = tar.wait()?;
+        if !tar_status.success() {
+            bail!("tar command failed with {}", tar_status)
+        }
+        if !status.success() {
+            bail!("Unable to encrypt '{}'", sealed.display())
+        }
+        rename(tmp, sealed)?;
+        Ok(())
+    }
+
+    /// Decrypt the sealed file via the cipher and extract it into the
+    /// provided directory
+    fn decrypt(mut cipher: Command, sealed: &Path, dir: &Path) -> Fallible<()> {
+        let mut cipher = cipher
+            .stdin(File::open(sealed)?)
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = cipher
+            .stdout
+            .take()
+            .ok_or_else(|| format_err!("Unable to get cipher stdout"))?;
+        let status = Command::new("tar")
+            .arg("--extract")
+            .arg(format!("--directory={}", dir.display()))
+            .arg("--file=-")
+            .stdin(stdout)
+            .status()?;
+        if !cipher.wait()?.success() {
+            bail!("Unable to decrypt '{}'", sealed.display())
+        }
+        if !status.success() {
+            bail!("tar command failed with {}", status)
+        }
+        Ok(())
+    }
+
+    /// Create the command which encrypts or decrypts from stdin to stdout
+    fn cipher(config: &Config, decrypt: bool) -> Fallible<Command> {
+        match config.secret_backend() {
+            SecretBackend::Age => {
+                let identity = config
+                    .secret_age_identity()
+                    .as_ref()
+                    .ok_or_else(|| format_err!("No age identity configured"))?;
+                Ok(Self::age_cipher(identity, decrypt))
+            }
+            SecretBackend::Keyring => Ok(Self::keyring_cipher(
+                &Self::keyring_key(config, !decrypt)?,
+                decrypt,
+            )),
+            SecretBackend::Plain => bail!("The plain secret backend does not encrypt"),
+        }
+    }
+
+    /// Create the cipher of the age backend for the provided identity
+    fn age_cipher(identity: &Path, decrypt: bool) -> Command {
+        let mut cmd = Command::new("age");
+        cmd.arg(if decrypt { "--decrypt" } else { "--encrypt" })
+            .arg("--identity")
+            .arg(identity);
+        cmd
+    }
+
+    /// Create the cipher of the keyring backend for the provided key
+    fn keyring_cipher(key: &str, decrypt: bool) -> Command {
+        let mut cmd = Command::new("openssl");
+        cmd.arg("enc").arg("-aes-256-cbc").arg("-pbkdf2");
+        if decrypt {
+            cmd.arg("-d");
+        }
+        cmd.arg("-pass")
+            .arg(format!("env:{}", Self::KEY_ENV))
+            .env(Self::KEY_ENV, key);
+        cmd
+    }
+
+    /// Retrieve the key of the root from the user keyring of the kernel,
+    /// which gets created if requested
+    fn keyring_key(config: &Config, create: bool) -> Fallible<String> {
+        let description = format!("kubernix:{}", config.root().display());
+        let output = Command::new("keyctl")
+            .arg("search")
+            .arg("@u")
+            .arg("user")
+            .arg(&description)
+            .output()?;
+        if output.status.success() {
+            let id = String::from_utf8(output.stdout)?;
+            let output = Command::new("keyctl").arg("pipe").arg(id.trim()).output()?;
+            if !output.status.success() {
+                debug!("keyctl stderr: {}", String::from_utf8(output.stderr)?);
+                bail!("keyctl pipe command failed");
+            }
+            return Ok(String::from_utf8(output.stdout)?);
+        }
+        if !create {
+            bail!(
+                "Key '{}' not found in the kernel keyring, which does not survive reboots",
+                description
+            )
+        }
+
+        debug!("Adding key '{}' to the kernel keyring", description);
+        let key = encode(&thread_rng().gen::<[u8; 32]>());
+        let mut keyctl = Command::new("keyctl")
+            .arg("padd")
+            .arg("user")
+            .arg(&description)
+            .arg("@u")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()?;
+        keyctl
+            .stdin
+            .take()
+            .ok_or_else(|| format_err!("Unable to get keyctl stdin"))?
+            .write_all(key.as_bytes())?;
+        let status = keyctl.wait()?;
+        if !status.success() {
+            bail!("keyctl padd command failed with {}", status)
+        }
+        Ok(key)
+    }
+
+    /// Check if the provided path is a mount point
+    fn is_mounted(path: &Path) -> Fallible<bool> {
+        Ok(MountIter::new()?
+            .filter_map(|x| x.ok())
+            .any(|x| x.dest == path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn secret_backend_from_str_success() -> Fallible<()> {
+        assert_eq!("plain".parse::<SecretBackend>()?, SecretBackend::Plain);
+        assert_eq!("age".parse::<SecretBackend>()?, SecretBackend::Age);
+        assert_eq!("keyring".parse::<SecretBackend>()?, SecretBackend::Keyring);
+        Ok(())
+    }
+
+    #[test]
+    fn secret_backend_from_str_failure() {
+        assert!("sops".parse::<SecretBackend>().is_err());
+    }
+
+    /// Seal the provided secret via the cipher and unseal it again
+    fn roundtrip(encrypt: Command, decrypt: Command) -> Fallible<()> {
+        let d = tempdir()?;
+        let secrets = d.path().join("secrets");
+        let unsealed = d.path().join("unsealed");
+        let sealed = d.path().join(Secrets::SEALED);
+        create_dir_all(secrets.join(Pki::DIR))?;
+        create_dir_all(&unsealed)?;
+        fs::write(secrets.join(Pki::DIR).join("ca-key.pem"), "secret")?;
+
+        Secrets::encrypt(encrypt, &secrets, &sealed)?;
+        assert!(!fs::read(&sealed)?.windows(6).any(|x| x == b"secret"));
+
+        Secrets::decrypt(decrypt, &sealed, &unsealed)?;
+        assert_eq!(
+            fs::read_to_string(unsealed.join(Pki::DIR).join("ca-key.pem"))?,
+            "secret"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_success_plain() -> Fallible<()> {
+        let c = test_config()?;
+        let file = c.root().join(Pki::DIR).join("ca-key.pem");
+        create_dir_all(c.root().join(Pki::DIR))?;
+        fs::write(&file, "secret")?;
+
+        Secrets::seal(&c)?;
+        assert!(!c.root().join(Secrets::SEALED).exists());
+        Secrets::unseal(&c)?;
+        assert_eq!(fs::read_to_string(&file)?, "secret");
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_success_age() -> Fallible<()> {
+        // age is only part of the environment if the backend is configured
+        let d = tempdir()?;
+        let identity = d.path().join("identity.txt");
+        match Command::new("age-keygen").arg("-o").arg(&identity).output() {
+            Ok(x) if x.status.success() => {}
+            _ => return Ok(()),
+        }
+        roundtrip(
+            Secrets::age_cipher(&identity, false),
+            Secrets::age_cipher(&identity, true),
+        )
+    }
+
+    #[test]
+    fn roundtrip_success_keyring() -> Fallible<()> {
+        roundtrip(
+            Secrets::keyring_cipher("key", false),
+            Secrets::keyring_cipher("key", true),
+        )
+    }
+
+    #[test]
+    fn roundtrip_failure_keyring_wrong_key() -> Fallible<()> {
+        assert!(roundtrip(
+            Secrets::keyring_cipher("key", false),
+            Secrets::keyring_cipher("wrong", true),
+        )
+        .is_err());
+        Ok(())
+    }
+}
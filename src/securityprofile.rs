@@ -0,0 +1,115 @@
+use crate::{process::Process, Config, Fallible};
+use log::{debug, info, warn};
+use std::{
+    fs::{self, create_dir_all, read_to_string},
+    path::{Path, PathBuf},
+};
+
+pub struct SecurityProfile;
+
+impl SecurityProfile {
+    /// The Nix package providing the AppArmor parser
+    pub const APPARMOR_PACKAGE: &'static str = "apparmor-parser";
+
+    /// The kernel parameter indicating if AppArmor is enabled
+    const APPARMOR_ENABLED: &'static str = "/sys/module/apparmor/parameters/enabled";
+
+    /// Install the seccomp profiles into the profile root and load the
+    /// AppArmor profiles, if supported by the host
+    pub fn setup(config: &Config) -> Fallible<()> {
+        if !config.seccomp_profiles().is_empty() {
+            let dir = Self::seccomp_dir(config);
+            create_dir_all(&dir)?;
+            for profile in config.seccomp_profiles() {
+                let target = dir.join(Self::file_name(profile)?);
+                fs::copy(profile, &target).map_err(|e| {
+                    format_err!(
+                        "Unable to install seccomp profile '{}': {}",
+                        profile.display(),
+                        e
+                    )
+                })?;
+                debug!("Installed seccomp profile '{}'", target.display());
+            }
+            info!(
+                "Seccomp profiles available as 'localhost/<file>' in '{}'",
+                dir.display()
+            );
+        }
+
+        if !config.apparmor_profiles().is_empty() {
+            if !Self::apparmor_enabled() {
+                warn!("AppArmor is not enabled on this host, skipping profiles");
+                return Ok(());
+            }
+            for profile in config.apparmor_profiles() {
+                Process::run_to_completion("apparmor_parser")
+                    .arg("--replace")
+                    .arg(profile)
+                    .run()
+                    .map_err(|_| {
+                        format_err!("Unable to load AppArmor profile '{}'", profile.display())
+                    })?;
+                debug!("Loaded AppArmor profile '{}'", profile.display());
+            }
+        }
+        Ok(())
+    }
+
+    /// The directory containing the seccomp profiles
+    pub fn seccomp_dir(config: &Config) -> PathBuf {
+        config.root().join("seccomp")
+    }
+
+    /// Retrieve the CRI-O arguments for the default profiles
+    pub fn crio_args(config: &Config) -> Fallible<Vec<String>> {
+        let mut args = vec![];
+        if let Some(name) = config.default_seccomp_profile() {
+            if !config
+                .seccomp_profiles()
+                .iter()
+                .any(|x| x.file_name().map_or(false, |f| f == name.as_str()))
+            {
+                bail!(
+                    "Default seccomp profile '{}' is not one of the provided profiles",
+                    name
+                );
+            }
+            args.push(format!(
+                "--seccomp-profile={}",
+                Self::seccomp_dir(config).join(name).display()
+            ));
+        }
+        if let Some(name) = config.default_apparmor_profile() {
+            args.push(format!("--apparmor-profile={}", name));
+        }
+        Ok(args)
+    }
+
+    fn apparmor_enabled() -> bool {
+        read_to_string(Self::APPARMOR_ENABLED)
+            .map(|x| x.trim() == "Y")
+            .unwrap_or(false)
+    }
+
+    fn file_name(path: &Path) -> Fallible<String> {
+        path.file_name()
+            .and_then(|x| x.to_str())
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| format_err!("Invalid profile path '{}'", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+
+    #[test]
+    fn crio_args_success_disabled() -> Fallible<()> {
+        let c = test_config()?;
+        SecurityProfile::setup(&c)?;
+        assert!(SecurityProfile::crio_args(&c)?.is_empty());
+        Ok(())
+    }
+}
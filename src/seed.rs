@@ -0,0 +1,89 @@
+use crate::Config;
+use rand::{thread_rng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+/// The source of generated secrets and names, which is deterministic if a
+/// seed is configured
+pub struct Seed;
+
+impl Seed {
+    /// Retrieve a random number generator for the provided purpose, whereas
+    /// every purpose gets an independent stream of the configured seed. The
+    /// ChaCha20 generator is used because its output is guaranteed to stay
+    /// the same across releases of the rand crate.
+    pub fn rng(config: &Config, purpose: &str) -> Box<dyn RngCore> {
+        match config.seed() {
+            Some(seed) => Box::new(ChaCha20Rng::seed_from_u64(Self::derive(*seed, purpose))),
+            None => Box::new(thread_rng()),
+        }
+    }
+
+    /// Retrieve a certificate serial number for the provided purpose if a
+    /// seed is configured, formatted as hex value like openssl accepts it
+    pub fn serial(config: &Config, purpose: &str) -> Option<String> {
+        config.seed().map(|_| {
+            let mut bytes = Self::rng(config, purpose).gen::<[u8; 16]>();
+            // Keep the serial positive and of full length
+            bytes[0] = bytes[0] & 0x7f | 0x40;
+            bytes
+                .iter()
+                .fold("0x".to_owned(), |x, b| format!("{}{:02x}", x, b))
+        })
+    }
+
+    /// Check if kubeconfigs should embed the certificates, which are random
+    /// even with a seed and would otherwise change the kubeconfig on every run
+    pub fn embed_certs(config: &Config) -> bool {
+        config.seed().is_none()
+    }
+
+    /// Mix the purpose into the seed via FNV-1a, which stays stable across
+    /// Rust releases in contrast to the standard library hasher
+    fn derive(seed: u64, purpose: &str) -> u64 {
+        purpose
+            .bytes()
+            .fold(seed ^ 0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::tests::{test_config, test_config_seed},
+        Fallible,
+    };
+
+    #[test]
+    fn derive_success() {
+        assert_eq!(Seed::derive(0, ""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(Seed::derive(0, "a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(Seed::derive(42, "x"), Seed::derive(42, "x"));
+        assert_ne!(Seed::derive(42, "x"), Seed::derive(42, "y"));
+        assert_ne!(Seed::derive(42, "x"), Seed::derive(43, "x"));
+    }
+
+    #[test]
+    fn rng_success_seeded() -> Fallible<()> {
+        let c = test_config_seed()?;
+        let a = Seed::rng(&c, "x").gen::<u64>();
+        assert_eq!(a, Seed::rng(&c, "x").gen::<u64>());
+        assert_ne!(a, Seed::rng(&c, "y").gen::<u64>());
+        Ok(())
+    }
+
+    #[test]
+    fn serial_success() -> Fallible<()> {
+        assert!(Seed::serial(&test_config()?, "ca").is_none());
+
+        let c = test_config_seed()?;
+        let serial = Seed::serial(&c, "ca").ok_or_else(|| format_err!("no serial"))?;
+        assert_eq!(serial.len(), 34);
+        assert!(serial.starts_with("0x"));
+        assert_eq!(Some(serial), Seed::serial(&c, "ca"));
+        assert_ne!(Seed::serial(&c, "ca"), Seed::serial(&c, "admin"));
+        Ok(())
+    }
+}
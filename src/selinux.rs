@@ -0,0 +1,183 @@
+use crate::{Config, Error, Fallible, CRIO_DIR};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs::{create_dir_all, read_to_string},
+    path::Path,
+    process::Command,
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The available SELinux behaviors
+pub enum SelinuxMode {
+    /// Integrate with SELinux if it is enforcing on the host
+    Auto,
+
+    /// Never integrate with SELinux, but warn if it is enforcing
+    PermissiveWarn,
+
+    /// Always integrate with SELinux and require it to be enforcing
+    Enforcing,
+}
+
+impl Display for SelinuxMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SelinuxMode::Auto => "auto",
+            SelinuxMode::PermissiveWarn => "permissive-warn",
+            SelinuxMode::Enforcing => "enforcing",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SelinuxMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "auto" => Ok(SelinuxMode::Auto),
+            "permissive-warn" => Ok(SelinuxMode::PermissiveWarn),
+            "enforcing" => Ok(SelinuxMode::Enforcing),
+            _ => Err(format_err!("Unknown SELinux mode '{}'", s)),
+        }
+    }
+}
+
+pub struct Selinux;
+
+impl Selinux {
+    /// The Nix package providing setfattr for labeling
+    pub const PACKAGE: &'static str = "attr";
+
+    /// The kernel file indicating if SELinux is enforcing
+    const ENFORCE: &'static str = "/sys/fs/selinux/enforce";
+
+    /// The boolean allowing containers to manage their cgroups
+    const CGROUP_BOOLEAN: &'static str = "/sys/fs/selinux/booleans/container_manage_cgroup";
+
+    /// The label of the container storage
+    const STORAGE_LABEL: &'static str = "system_u:object_r:container_var_lib_t:s0";
+
+    /// The label of files shared with containers, like volumes
+    const FILE_LABEL: &'static str = "system_u:object_r:container_file_t:s0";
+
+    /// Check if kubernix should integrate with SELinux
+    pub fn enabled(config: &Config) -> bool {
+        match config.selinux() {
+            SelinuxMode::Auto => Self::enforcing(),
+            SelinuxMode::PermissiveWarn => false,
+            SelinuxMode::Enforcing => true,
+        }
+    }
+
+    /// Verify that the host matches the configured SELinux mode
+    pub fn preflight(config: &Config) -> Fallible<()> {
+        let enforcing = Self::enforcing();
+        match config.selinux() {
+            SelinuxMode::Enforcing if !enforcing => bail!(
+                "SELinux is not enforcing on this host, please enable it via \
+                 `setenforce 1` or use --selinux auto"
+            ),
+            SelinuxMode::PermissiveWarn if enforcing => warn!(
+                "SELinux is enforcing but the root does not get labeled, which may result in \
+                 permission errors. Please use --selinux auto or switch to permissive mode via \
+                 `setenforce 0`"
+            ),
+            _ => {}
+        }
+
+        if Self::enabled(config) && !Self::cgroup_boolean() {
+            let hint = "containers are not allowed to manage their cgroups, please enable \
+                        it via `setsebool -P container_manage_cgroup on`";
+            if *config.selinux() == SelinuxMode::Enforcing {
+                bail!("SELinux boolean 'container_manage_cgroup' is off: {}", hint)
+            }
+            warn!("SELinux boolean 'container_manage_cgroup' is off: {}", hint);
+        }
+        Ok(())
+    }
+
+    /// Label the container storage and all directories shared with
+    /// containers inside the root
+    pub fn setup(config: &Config) -> Fallible<()> {
+        if !Self::enabled(config) {
+            return Ok(());
+        }
+        info!("Labeling the root for SELinux");
+        Self::label(&config.root().join(CRIO_DIR), Self::STORAGE_LABEL)?;
+        Self::label(&config.root().join("kubelet"), Self::FILE_LABEL)?;
+        if *config.persist_volumes() {
+            Self::label(config.volumes_dir(), Self::FILE_LABEL)?;
+        }
+        Ok(())
+    }
+
+    /// Retrieve the CRI-O arguments to enable SELinux support
+    pub fn crio_args(config: &Config) -> Vec<String> {
+        if Self::enabled(config) {
+            vec!["--selinux".into()]
+        } else {
+            vec![]
+        }
+    }
+
+    /// Recursively label the provided directory, whereas new files inherit
+    /// the label from their parent directory
+    fn label(dir: &Path, label: &str) -> Fallible<()> {
+        create_dir_all(dir)?;
+        debug!("Labeling '{}' as {}", dir.display(), label);
+        let output = Command::new("find")
+            .arg(dir)
+            .arg("-exec")
+            .arg("setfattr")
+            .arg("--no-dereference")
+            .arg("--name=security.selinux")
+            .arg(format!("--value={}", label))
+            .arg("{}")
+            .arg("+")
+            .output()?;
+        if !output.status.success() {
+            debug!("find stderr: {}", String::from_utf8(output.stderr)?);
+            bail!("Unable to label '{}' for SELinux", dir.display());
+        }
+        Ok(())
+    }
+
+    fn enforcing() -> bool {
+        read_to_string(Self::ENFORCE)
+            .map(|x| x.trim() == "1")
+            .unwrap_or(false)
+    }
+
+    fn cgroup_boolean() -> bool {
+        // The file contains the current and the pending value
+        read_to_string(Self::CGROUP_BOOLEAN)
+            .map(|x| x.split_whitespace().next() == Some("1"))
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selinux_mode_from_str_success() -> Fallible<()> {
+        assert_eq!("auto".parse::<SelinuxMode>()?, SelinuxMode::Auto);
+        assert_eq!(
+            "permissive-warn".parse::<SelinuxMode>()?,
+            SelinuxMode::PermissiveWarn
+        );
+        assert_eq!("enforcing".parse::<SelinuxMode>()?, SelinuxMode::Enforcing);
+        Ok(())
+    }
+
+    #[test]
+    fn selinux_mode_from_str_failure() {
+        assert!("permissive".parse::<SelinuxMode>().is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::{Error, Fallible};
+use log::{debug, error, warn};
+use nix::{
+    sys::signal::{kill, SigSet, Signal},
+    unistd::{getpid, Pid},
+};
+use psutil::process;
+use std::{
+    process::exit,
+    sync::atomic::{AtomicI32, Ordering},
+    thread::spawn,
+};
+
+/// The last received termination signal, or zero if none
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+/// Termination signal handling, which converts the first signal into a clean
+/// teardown and the second one into an immediate kill of all children
+pub struct Signals;
+
+impl Signals {
+    /// The signals which lead to a cluster teardown
+    const HANDLED: [Signal; 3] = [Signal::SIGINT, Signal::SIGTERM, Signal::SIGHUP];
+
+    /// Install the signal handler. This has to be called before spawning
+    /// any threads, since they inherit the blocked signals.
+    pub fn install() -> Fallible<()> {
+        let mut set = SigSet::empty();
+        for signal in &Self::HANDLED {
+            set.add(*signal);
+        }
+        set.thread_block()?;
+
+        spawn(move || loop {
+            let signal = match set.wait() {
+                Ok(x) => x,
+                Err(e) => {
+                    error!("Unable to wait for signals: {}", e);
+                    return;
+                }
+            };
+            let previous = RECEIVED.swap(signal as i32, Ordering::SeqCst);
+            if previous == 0 {
+                warn!(
+                    "Received {:?}, tearing down the cluster (repeat to force)",
+                    signal
+                );
+            } else {
+                error!("Received {:?} again, killing all processes", signal);
+                Self::kill_children();
+                exit(128 + signal as i32);
+            }
+        });
+        Ok(())
+    }
+
+    /// Returns an error if a termination signal has been received
+    pub fn check() -> Fallible<()> {
+        match RECEIVED.load(Ordering::SeqCst) {
+            0 => Ok(()),
+            x => Err(Error::Interrupted(x)),
+        }
+    }
+
+    /// Send SIGKILL to all child processes and their process groups
+    fn kill_children() {
+        let pid = getpid().as_raw();
+        match process::all() {
+            Err(e) => error!("Unable to retrieve processes: {}", e),
+            Ok(procs) => {
+                for p in procs.iter().filter(|p| p.ppid == pid) {
+                    debug!("Killing process {}", p.pid);
+                    // Spawned components are process group leaders
+                    let _ = kill(Pid::from_raw(-p.pid), Signal::SIGKILL);
+                    let _ = kill(Pid::from_raw(p.pid), Signal::SIGKILL);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        env::{current_exe, var_os},
+        fs::read_to_string,
+        io,
+        os::unix::process::CommandExt,
+        path::Path,
+        process::Command,
+        thread::sleep,
+        time::{Duration, Instant},
+    };
+
+    /// Set for the separate test process receiving the signals
+    const CHILD_ENV: &str = "KUBERNIX_TEST_SIGNALS_CHILD";
+
+    /// The output prefix of the PID of the child to be killed
+    const CHILD_PREFIX: &str = "child: ";
+
+    #[test]
+    fn check_success() -> Fallible<()> {
+        Signals::check()
+    }
+
+    /// The received signal is sticky and the second one exits the process,
+    /// which is why the signals get raised within a separate test process
+    #[test]
+    fn install_success_escalation() -> Fallible<()> {
+        if var_os(CHILD_ENV).is_some() {
+            return raise_signals();
+        }
+
+        let mut command = Command::new(current_exe()?);
+        command
+            .args(&[
+                "--exact",
+                "signals::tests::install_success_escalation",
+                "--nocapture",
+            ])
+            .env(CHILD_ENV, "1");
+        // All threads of the test harness have to block the signals as well
+        unsafe {
+            command.pre_exec(|| {
+                let mut set = SigSet::empty();
+                set.add(Signal::SIGTERM);
+                set.thread_block().map_err(|_| io::Error::last_os_error())
+            });
+        }
+        let output = command.output()?;
+        let stdout = String::from_utf8(output.stdout)?;
+        assert_eq!(
+            output.status.code(),
+            Some(128 + Signal::SIGTERM as i32),
+            "{}",
+            stdout
+        );
+
+        // The child got killed and reaped after its parent exited, whereas the
+        // test harness prints the test name on the same line as the PID
+        let pid = stdout
+            .lines()
+            .filter_map(|x| x.split(CHILD_PREFIX).nth(1))
+            .map(str::trim)
+            .next()
+            .ok_or_else(|| format_err!("No child PID in output: {}", stdout))?;
+        let stat = Path::new("/proc").join(pid).join("stat");
+        let now = Instant::now();
+        while read_to_string(&stat).map_or(false, |x| !x.contains(") Z ")) {
+            assert!(now.elapsed() < Duration::from_secs(5));
+            sleep(Duration::from_millis(100));
+        }
+        Ok(())
+    }
+
+    fn raise_signals() -> Fallible<()> {
+        Signals::install()?;
+        let child = Command::new("sleep").arg("500").spawn()?;
+        println!("{}{}", CHILD_PREFIX, child.id());
+
+        // The first signal only gets recorded
+        kill(getpid(), Signal::SIGTERM)?;
+        let now = Instant::now();
+        while Signals::check().is_ok() {
+            if now.elapsed() >= Duration::from_secs(5) {
+                bail!("First signal not received")
+            }
+            sleep(Duration::from_millis(10));
+        }
+        match Signals::check() {
+            Err(Error::Interrupted(x)) if x == Signal::SIGTERM as i32 => {}
+            x => bail!("Unexpected signal check result: {:?}", x),
+        }
+
+        // The second one kills all children and exits
+        kill(getpid(), Signal::SIGTERM)?;
+        sleep(Duration::from_secs(10));
+        bail!("Process did not exit on the second signal")
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{component::Health, Config, Fallible};
+use serde::Serialize;
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Serialize)]
+/// A single entry of the health history
+struct Record<'a> {
+    time: u64,
+    component: &'a str,
+    health: String,
+    restarted: bool,
+}
+
+pub struct Soak;
+
+impl Soak {
+    /// The interval between two health checks
+    pub const INTERVAL: Duration = Duration::from_secs(30);
+
+    /// The file name of the health history within the root directory
+    const FILENAME: &'static str = "health.log";
+
+    /// The path of the health history
+    pub fn file(config: &Config) -> PathBuf {
+        config.root().join(Self::FILENAME)
+    }
+
+    /// Append the provided component health to the history, whereas every
+    /// line is a single JSON record
+    pub fn record(
+        config: &Config,
+        status: &[(String, Health)],
+        restarted: &[String],
+    ) -> Fallible<()> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or_default();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Self::file(config))?;
+        for (name, health) in status {
+            let record = Record {
+                time,
+                component: name,
+                health: health.to_string(),
+                restarted: restarted.contains(name),
+            };
+            writeln!(file, "{}", serde_json::to_string(&record)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+    use std::fs::read_to_string;
+
+    #[test]
+    fn record_success() -> Fallible<()> {
+        let c = test_config()?;
+        let status = vec![
+            ("etcd".to_owned(), Health::Ready),
+            ("kubelet".to_owned(), Health::NotReady),
+        ];
+        Soak::record(&c, &status, &["kubelet".to_owned()])?;
+        let history = read_to_string(Soak::file(&c))?;
+        assert_eq!(history.lines().count(), 2);
+        assert!(history.contains(r#""component":"kubelet","health":"not ready","restarted":true"#));
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{Config, Fallible};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs::{self, read_to_string},
+    path::PathBuf,
+};
+use tracing::info_span;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// The persisted phases of the cluster bootstrap in their execution order
+pub enum Phase {
+    /// Generating the certificates
+    Pki,
+
+    /// Creating the kubeconfigs
+    KubeConfig,
+
+    /// Creating the encryption config
+    EncryptionConfig,
+
+    /// Resetting the etcd data directory
+    EtcdReset,
+
+    /// Applying the cluster addons
+    Addons,
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Phase::Pki => "pki",
+            Phase::KubeConfig => "kube-config",
+            Phase::EncryptionConfig => "encryption-config",
+            Phase::EtcdReset => "etcd-reset",
+            Phase::Addons => "addons",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+/// A successfully completed phase together with its created artifacts
+struct Completed {
+    phase: Phase,
+    artifacts: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+/// The bootstrap state of a cluster, which allows resuming a crashed run
+pub struct State {
+    #[serde(skip)]
+    file: PathBuf,
+
+    #[serde(default)]
+    completed: Vec<Completed>,
+}
+
+impl State {
+    /// The file name of the state within the root directory
+    const FILENAME: &'static str = "state.toml";
+
+    /// Create a new state for the provided configuration. The state of a
+    /// previous run is only loaded if the run should be continued.
+    pub fn new(config: &Config) -> Fallible<State> {
+        let file = config.root().join(Self::FILENAME);
+        let mut state = if *config.resume() && file.exists() {
+            info!("Continuing previous run");
+            toml::from_str(&read_to_string(&file)?)
+                .map_err(|e| format_err!("Unable to load state file '{}': {}", file.display(), e))?
+        } else {
+            State::default()
+        };
+        state.file = file;
+        state.write()?;
+        Ok(state)
+    }
+
+    /// Run the provided phase if not already completed, whereas `load`
+    /// retrieves the result of a completed phase. The phase is considered
+    /// as not completed if one of its artifacts does not exist any more.
+    /// Running a phase invalidates all later phases, since their artifacts
+    /// depend on the ones of the phase.
+    pub fn run<T, N, L>(
+        &mut self,
+        phase: Phase,
+        artifacts: &[PathBuf],
+        new: N,
+        load: L,
+    ) -> Fallible<T>
+    where
+        N: FnOnce() -> Fallible<T>,
+        L: FnOnce() -> Fallible<T>,
+    {
+        if let Some(x) = self.completed.iter().find(|x| x.phase == phase) {
+            if x.artifacts.iter().all(|a| a.exists()) {
+                info!("Skipping already completed phase {}", phase);
+                return load();
+            }
+            debug!("Artifacts of phase {} missing, running again", phase);
+        }
+
+        let span = info_span!("phase", phase = %phase);
+        let result = span.in_scope(new)?;
+        self.completed.retain(|x| x.phase < phase);
+        self.completed.push(Completed {
+            phase,
+            artifacts: artifacts.to_vec(),
+        });
+        self.write()?;
+        Ok(result)
+    }
+
+    fn write(&self) -> Fallible<()> {
+        fs::write(&self.file, toml::to_string(&self)?)
+            .map_err(|e| format_err!("Unable to write state file: {}", e))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn test_state() -> Fallible<State> {
+        Ok(State {
+            file: tempdir()?.into_path().join(State::FILENAME),
+            completed: vec![],
+        })
+    }
+
+    #[test]
+    fn run_success() -> Fallible<()> {
+        let mut s = test_state()?;
+        let artifacts = vec![s.file.clone()];
+        assert_eq!(s.run(Phase::Pki, &artifacts, || Ok(1), || Ok(2))?, 1);
+        assert_eq!(s.run(Phase::Pki, &artifacts, || Ok(1), || Ok(2))?, 2);
+
+        let loaded: State = toml::from_str(&read_to_string(&s.file)?)?;
+        assert_eq!(loaded.completed, s.completed);
+        Ok(())
+    }
+
+    #[test]
+    fn run_success_missing_artifact() -> Fallible<()> {
+        let mut s = test_state()?;
+        let artifacts = vec![PathBuf::from("/invalid")];
+        s.run(Phase::Pki, &artifacts, || Ok(1), || Ok(2))?;
+        assert_eq!(s.run(Phase::Pki, &artifacts, || Ok(1), || Ok(2))?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn run_success_missing_artifact_reruns_later_phases() -> Fallible<()> {
+        let mut s = test_state()?;
+        let dir = tempdir()?;
+        let pki = vec![dir.path().join("pki")];
+        let kubeconfig = vec![dir.path().join("kubeconfig")];
+        fs::write(&pki[0], "")?;
+        fs::write(&kubeconfig[0], "")?;
+        s.run(Phase::Pki, &pki, || Ok(1), || Ok(2))?;
+        s.run(Phase::KubeConfig, &kubeconfig, || Ok(1), || Ok(2))?;
+        s.run(Phase::Addons, &[], || Ok(1), || Ok(2))?;
+
+        fs::remove_file(&pki[0])?;
+        assert_eq!(s.run(Phase::Pki, &pki, || Ok(1), || Ok(2))?, 1);
+        assert_eq!(
+            s.run(Phase::KubeConfig, &kubeconfig, || Ok(1), || Ok(2))?,
+            1
+        );
+        assert_eq!(s.run(Phase::Addons, &[], || Ok(1), || Ok(2))?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn run_failure() -> Fallible<()> {
+        let mut s = test_state()?;
+        assert!(s
+            .run(
+                Phase::Pki,
+                &[],
+                || -> Fallible<()> { bail!("failed") },
+                || Ok(())
+            )
+            .is_err());
+        assert!(s.completed.is_empty());
+        Ok(())
+    }
+}
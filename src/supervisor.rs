@@ -0,0 +1,80 @@
+use crate::Fallible;
+use lazy_static::lazy_static;
+use std::future::Future;
+use tokio::{
+    runtime::{Builder, Handle, Runtime},
+    task::JoinHandle,
+};
+
+lazy_static! {
+    /// The runtime driving all supervised processes. It gets created on first
+    /// usage, which is after the signal handler got installed and its worker
+    /// threads inherit the blocked signals.
+    static ref RUNTIME: Result<Runtime, String> = Builder::new()
+        .threaded_scheduler()
+        .core_threads(Supervisor::THREADS)
+        .thread_name("kubernix-supervisor")
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string());
+}
+
+/// The asynchronous supervision of processes, which watches all of them
+/// with a small amount of threads
+pub struct Supervisor;
+
+impl Supervisor {
+    /// The number of worker threads, which is independent from the number of
+    /// supervised processes
+    const THREADS: usize = 2;
+
+    /// Spawn the provided future onto the supervisor
+    pub fn spawn<F>(future: F) -> Fallible<JoinHandle<F::Output>>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        Ok(Self::handle()?.spawn(future))
+    }
+
+    /// Run the provided future to completion, blocking the current thread
+    pub fn block_on<F: Future>(future: F) -> Fallible<F::Output> {
+        Ok(Self::handle()?.block_on(future))
+    }
+
+    /// Run the provided closure within the context of the supervisor, which
+    /// is required to create asynchronous resources like child processes
+    pub fn enter<F: FnOnce() -> R, R>(f: F) -> Fallible<R> {
+        Ok(Self::handle()?.enter(f))
+    }
+
+    fn handle() -> Fallible<&'static Handle> {
+        match &*RUNTIME {
+            Ok(runtime) => Ok(runtime.handle()),
+            Err(e) => bail!("Unable to create supervisor runtime: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::delay_for;
+
+    #[test]
+    fn block_on_success() -> Fallible<()> {
+        assert_eq!(Supervisor::block_on(async { 42 })?, 42);
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_success() -> Fallible<()> {
+        let handle = Supervisor::spawn(async {
+            delay_for(Duration::from_millis(10)).await;
+            42
+        })?;
+        assert_eq!(Supervisor::block_on(handle)?.ok(), Some(42));
+        Ok(())
+    }
+}
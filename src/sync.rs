@@ -0,0 +1,202 @@
+use crate::{
+    config::Sync as SyncArgs, kubeconfig::KubeConfig, process::Process, Config, Error, Fallible,
+};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs::{metadata, read_dir, remove_file},
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    thread::sleep,
+    time::SystemTime,
+};
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+/// The pod directory to be synchronized, like `namespace/pod:/path`
+pub struct SyncTarget {
+    namespace: String,
+    pod: String,
+    path: String,
+}
+
+impl Display for SyncTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}:{}", self.namespace, self.pod, self.path)
+    }
+}
+
+impl FromStr for SyncTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let invalid = || {
+            format_err!(
+                "Invalid sync target '{}', expected '[namespace/]pod:/path'",
+                s
+            )
+        };
+        let mut split = s.splitn(2, ':');
+        let (pod, path) = match (split.next(), split.next()) {
+            (Some(pod), Some(path)) if !pod.is_empty() && path.starts_with('/') => (pod, path),
+            _ => return Err(invalid()),
+        };
+        let (namespace, pod) = match pod.find('/') {
+            Some(i) => (&pod[..i], &pod[i + 1..]),
+            None => ("default", pod),
+        };
+        if namespace.is_empty() || pod.is_empty() || pod.contains('/') {
+            return Err(invalid());
+        }
+        Ok(SyncTarget {
+            namespace: namespace.into(),
+            pod: pod.into(),
+            path: path.into(),
+        })
+    }
+}
+
+/// The state of a local directory, which changes if any file gets added,
+/// removed or modified
+type Snapshot = Vec<(PathBuf, SystemTime, u64)>;
+
+/// The synchronization of a local directory into a running pod
+pub struct PodSync<'a> {
+    args: &'a SyncArgs,
+    admin: PathBuf,
+    archive: PathBuf,
+}
+
+impl<'a> PodSync<'a> {
+    /// Copy the local directory into the pod, repeatedly if watching
+    pub fn run(config: &Config, args: &SyncArgs) -> Fallible<()> {
+        if !args.local().is_dir() {
+            bail!(
+                "Local directory '{}' does not exist",
+                args.local().display()
+            )
+        }
+        let sync = PodSync {
+            args,
+            admin: KubeConfig::admin_path(config),
+            archive: config.root().join(format!("sync-{}.tar", process::id())),
+        };
+
+        let mut snapshot = Self::snapshot(args.local())?;
+        sync.copy()?;
+        if !args.watch() {
+            return Ok(());
+        }
+
+        info!("Watching '{}' for changes", args.local().display());
+        loop {
+            sleep(args.interval().duration());
+            let current = Self::snapshot(args.local())?;
+            if current != snapshot {
+                debug!("Change detected in '{}'", args.local().display());
+                sync.copy()?;
+                snapshot = current;
+            }
+        }
+    }
+
+    /// Stream the local directory as tar archive into the pod
+    fn copy(&self) -> Fallible<()> {
+        let target = self.args.target();
+        Process::run_to_completion("tar")
+            .arg("--create")
+            .arg(format!("--directory={}", self.args.local().display()))
+            .arg(format!("--file={}", self.archive.display()))
+            .arg(".")
+            .run()?;
+        let result = Process::run_to_completion("kubectl")
+            .arg(format!("--kubeconfig={}", self.admin.display()))
+            .arg(format!("--namespace={}", target.namespace))
+            .arg("exec")
+            .arg("--stdin")
+            .arg(&target.pod)
+            .arg("--")
+            .arg("sh")
+            .arg("-c")
+            .arg(format!(
+                "mkdir -p '{0}' && tar -x -f - -C '{0}'",
+                target.path
+            ))
+            .stdin(&self.archive)
+            .output();
+        remove_file(&self.archive)?;
+        let output = result?;
+        if !output.success() {
+            bail!("kubectl exec command failed: {}", output.stderr().trim())
+        }
+        info!(
+            "Synchronized '{}' to '{}'",
+            self.args.local().display(),
+            target
+        );
+        Ok(())
+    }
+
+    /// Collect the modification times and sizes of all files below the path
+    fn snapshot(path: &Path) -> Fallible<Snapshot> {
+        let mut snapshot = vec![];
+        let mut dirs = vec![path.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            for entry in read_dir(&dir)? {
+                let path = entry?.path();
+                let meta = metadata(&path)?;
+                if meta.is_dir() {
+                    dirs.push(path.clone());
+                }
+                snapshot.push((path, meta.modified()?, meta.len()));
+            }
+        }
+        snapshot.sort();
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn target_from_str_success() -> Fallible<()> {
+        let target: SyncTarget = "dev/app:/srv/app".parse()?;
+        assert_eq!(target.namespace, "dev");
+        assert_eq!(target.pod, "app");
+        assert_eq!(target.path, "/srv/app");
+        assert_eq!(target.to_string(), "dev/app:/srv/app");
+
+        let target: SyncTarget = "app:/srv".parse()?;
+        assert_eq!(target.namespace, "default");
+        Ok(())
+    }
+
+    #[test]
+    fn target_from_str_failure() {
+        assert!("app".parse::<SyncTarget>().is_err());
+        assert!("app:srv".parse::<SyncTarget>().is_err());
+        assert!("/app:/srv".parse::<SyncTarget>().is_err());
+        assert!("dev/app/x:/srv".parse::<SyncTarget>().is_err());
+    }
+
+    #[test]
+    fn snapshot_success() -> Fallible<()> {
+        let dir = tempdir()?;
+        let empty = PodSync::snapshot(dir.path())?;
+        assert!(empty.is_empty());
+
+        write(dir.path().join("file"), "content")?;
+        let snapshot = PodSync::snapshot(dir.path())?;
+        assert_eq!(snapshot.len(), 1);
+        assert_ne!(empty, snapshot);
+
+        write(dir.path().join("file"), "changed content")?;
+        assert_ne!(PodSync::snapshot(dir.path())?, snapshot);
+        Ok(())
+    }
+}
@@ -1,10 +1,11 @@
-use failure::{bail, format_err, Fallible};
-use log::{debug, info};
-use std::{net::IpAddr, process::Command};
+use crate::{process::Process, Fallible};
+use log::{debug, info, warn};
+use std::{fs::read_to_string, net::IpAddr, path::Path};
 
 pub struct System {
     modules: Vec<String>,
     sysctls: Vec<String>,
+    relaxed: bool,
 }
 
 impl System {
@@ -22,20 +23,44 @@ impl System {
                 "net.ipv4.conf.all.route_localnet".to_owned(),
                 "net.ipv4.ip_forward".to_owned(),
             ],
+            relaxed: false,
         }
     }
 
+    /// Only verify the kernel modules and sysctls instead of changing them,
+    /// since they belong to the host when running inside a container
+    pub fn relax(&mut self) {
+        self.relaxed = true;
+    }
+
+    /// Add further kernel modules to be loaded
+    pub fn add_modules(&mut self, modules: &[&str]) {
+        self.modules.extend(modules.iter().map(|x| (*x).to_owned()));
+    }
+
     /// Retrieve the local hosts IP via the default route
     pub fn ip(&self) -> Fallible<String> {
-        let cmd = Command::new("ip")
+        let ip = self.current_ip()?;
+        info!("Using local IP {}", ip);
+        Ok(ip)
+    }
+
+    /// Retrieve the local hosts IP via the default route without logging it,
+    /// which is used for periodic checks
+    pub fn current_ip(&self) -> Fallible<String> {
+        let output = Process::run_to_completion("ip")
             .arg("route")
             .arg("get")
             .arg("1.2.3.4")
             .output()?;
-        if !cmd.status.success() {
+        if !output.success() {
             bail!("Unable to obtain `ip` output")
         }
-        let output = String::from_utf8(cmd.stdout)?;
+        Self::parse_ip(output.stdout())
+    }
+
+    /// Parse the source IP of the `ip route get` output
+    fn parse_ip(output: &str) -> Fallible<String> {
         let ip = output
             .split_whitespace()
             .nth(6)
@@ -43,7 +68,6 @@ impl System {
         if let Err(e) = ip.parse::<IpAddr>() {
             bail!("Unable to parse IP '{}': {}", ip, e);
         }
-        info!("Using local IP {}", ip);
         Ok(ip.to_owned())
     }
 
@@ -57,6 +81,11 @@ impl System {
 
     /// Load all required kernel modules and configure the system
     pub fn prepare(&self) -> Fallible<()> {
+        if self.relaxed {
+            self.verify();
+            return Ok(());
+        }
+
         // Load the modules
         for module in &self.modules {
             self.modprobe(module)?;
@@ -70,15 +99,41 @@ impl System {
         Ok(())
     }
 
+    /// Warn about all kernel modules and sysctls not prepared by the host
+    fn verify(&self) {
+        for module in &self.modules {
+            if !Path::new("/sys/module").join(module).exists() {
+                warn!(
+                    "Kernel module '{}' seems not to be loaded, please load it on the host",
+                    module
+                );
+            }
+        }
+        for sysctl in &self.sysctls {
+            let file = Path::new("/proc/sys").join(sysctl.replace('.', "/"));
+            if read_to_string(file)
+                .map(|x| x.trim() != "1")
+                .unwrap_or(true)
+            {
+                warn!(
+                    "Sysctl '{}' is not enabled, please set it on the host",
+                    sysctl
+                );
+            }
+        }
+    }
+
     /// Load a single kernel module via 'modprobe'
     fn modprobe(&self, module: &str) -> Fallible<()> {
         debug!("Loading kernel module '{}'", module);
-        let output = Command::new("modprobe").arg(module).output()?;
-        if !output.status.success() {
+        let output = Process::run_to_completion("modprobe")
+            .arg(module)
+            .output()?;
+        if !output.success() {
             bail!(
                 "Unable to load '{}' kernel module: {}",
                 module,
-                String::from_utf8(output.stderr)?,
+                output.stderr(),
             );
         }
         Ok(())
@@ -88,10 +143,12 @@ impl System {
     fn sysctl_enable(&self, key: &str) -> Fallible<()> {
         debug!("Enabling sysctl '{}'", key);
         let enable_arg = format!("{}=1", key);
-        let output = Command::new("sysctl").arg("-w").arg(&enable_arg).output()?;
-        let stderr = String::from_utf8(output.stderr)?;
-        if !stderr.is_empty() {
-            bail!("Unable to set sysctl '{}': {}", enable_arg, stderr);
+        let output = Process::run_to_completion("sysctl")
+            .arg("-w")
+            .arg(&enable_arg)
+            .output()?;
+        if !output.stderr().is_empty() {
+            bail!("Unable to set sysctl '{}': {}", enable_arg, output.stderr());
         }
         Ok(())
     }
@@ -109,6 +166,15 @@ mod tests {
         system.prepare()
     }
 
+    #[test]
+    fn prepare_success_relaxed() -> Fallible<()> {
+        let mut system = System::new();
+        system.modules = vec!["invalid".into()];
+        system.sysctls = vec!["invalid".into()];
+        system.relax();
+        system.prepare()
+    }
+
     #[test]
     fn module_failure() {
         let system = System::new();
@@ -126,6 +192,16 @@ mod tests {
         assert!(System::new().ip().is_ok());
     }
 
+    #[test]
+    fn parse_ip_success() -> Fallible<()> {
+        let ip = System::parse_ip(
+            "1.2.3.4 via 192.168.1.1 dev wlp3s0 src 192.168.1.23 uid 1000\n    cache\n",
+        )?;
+        assert_eq!(ip, "192.168.1.23");
+        assert!(System::parse_ip("unreachable").is_err());
+        Ok(())
+    }
+
     #[test]
     fn hostname_success() {
         assert!(System::new().hostname().is_ok());
@@ -0,0 +1,344 @@
+use crate::{Config, Fallible};
+use log::{debug, warn};
+use rand::random;
+use serde_json::{json, Value};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt, mem,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{
+    dispatcher,
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    subscriber::set_global_default,
+    Event, Metadata, Subscriber,
+};
+
+thread_local! {
+    /// The stack of entered spans of the current thread
+    static CURRENT: RefCell<Vec<Id>> = RefCell::new(vec![]);
+}
+
+/// A span which is recorded until it gets closed
+struct SpanData {
+    name: &'static str,
+    trace_id: u128,
+    span_id: u64,
+    parent_id: Option<u64>,
+    start: u128,
+    end: u128,
+    attributes: Vec<(String, String)>,
+    references: usize,
+}
+
+/// Collects the attributes of a span
+struct Visitor<'a>(&'a mut Vec<(String, String)>);
+
+impl<'a> Visit for Visitor<'a> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name().into(), value.into()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.push((field.name().into(), format!("{:?}", value)));
+    }
+}
+
+/// The messages processed by the exporter thread
+enum Message {
+    /// A finished span to be exported with the next batch
+    Span(SpanData),
+
+    /// Export all pending spans and acknowledge it afterwards
+    Flush(Sender<()>),
+}
+
+/// A tracing subscriber, which exports the finished spans in batches to an
+/// OpenTelemetry collector
+pub struct Telemetry {
+    next_id: AtomicU64,
+    spans: Mutex<HashMap<u64, SpanData>>,
+    exporter: Mutex<Sender<Message>>,
+}
+
+impl Telemetry {
+    /// The maximum amount of spans exported at once
+    const MAX_BATCH: usize = 512;
+
+    /// The maximum time a finished span waits for its export
+    const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+    /// Install the OTLP exporter if an endpoint is configured
+    pub fn init(config: &Config) -> Fallible<()> {
+        if let Some(endpoint) = config.otlp_endpoint() {
+            debug!("Exporting traces to '{}'", endpoint);
+            let url = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+            let (tx, rx) = channel();
+            thread::spawn(move || Self::export_loop(&url, &rx));
+            set_global_default(Telemetry {
+                next_id: AtomicU64::new(1),
+                spans: Mutex::new(HashMap::new()),
+                exporter: Mutex::new(tx),
+            })
+            .map_err(|e| format_err!("Unable to setup tracing: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Export all pending spans and wait until done, which should be done
+    /// before exiting the program
+    pub fn flush() {
+        dispatcher::get_default(|d| {
+            if let Some(telemetry) = d.downcast_ref::<Telemetry>() {
+                let (tx, rx) = channel();
+                if Self::lock(&telemetry.exporter)
+                    .send(Message::Flush(tx))
+                    .is_ok()
+                {
+                    rx.recv().ok();
+                }
+            }
+        });
+    }
+
+    /// Lock the provided mutex, whereas a poisoned one is still usable
+    /// because every update keeps its data consistent
+    fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+        mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Collect the finished spans and export them if either the batch is
+    /// full, the interval elapsed or a flush is requested
+    fn export_loop(url: &str, rx: &Receiver<Message>) {
+        let mut batch = vec![];
+        let mut deadline = Instant::now() + Self::EXPORT_INTERVAL;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let (export, ack) = match rx.recv_timeout(timeout) {
+                Ok(Message::Span(x)) => {
+                    batch.push(x);
+                    (batch.len() >= Self::MAX_BATCH, None)
+                }
+                Ok(Message::Flush(x)) => (true, Some(x)),
+                Err(RecvTimeoutError::Timeout) => (true, None),
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            if export {
+                if !batch.is_empty() {
+                    if let Err(e) = Self::export(url, &mem::replace(&mut batch, vec![])) {
+                        warn!("{}", e);
+                    }
+                }
+                deadline = Instant::now() + Self::EXPORT_INTERVAL;
+            }
+            if let Some(x) = ack {
+                x.send(()).ok();
+            }
+        }
+    }
+
+    /// The current time in nanoseconds since the unix epoch
+    fn now() -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_nanos())
+            .unwrap_or_default()
+    }
+
+    /// Encode the provided spans as OTLP JSON request
+    fn encode(spans: &[SpanData]) -> Value {
+        let spans: Vec<Value> = spans
+            .iter()
+            .map(|x| {
+                let attributes: Vec<Value> = x
+                    .attributes
+                    .iter()
+                    .map(|(k, v)| json!({"key": k, "value": {"stringValue": v}}))
+                    .collect();
+                json!({
+                    "traceId": format!("{:032x}", x.trace_id),
+                    "spanId": format!("{:016x}", x.span_id),
+                    "parentSpanId": x.parent_id.map(|p| format!("{:016x}", p)).unwrap_or_default(),
+                    "name": x.name,
+                    "kind": 1,
+                    "startTimeUnixNano": x.start.to_string(),
+                    "endTimeUnixNano": x.end.to_string(),
+                    "attributes": attributes,
+                })
+            })
+            .collect();
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        {"key": "service.name", "value": {"stringValue": "kubernix"}}
+                    ]
+                },
+                "scopeSpans": [{
+                    "scope": {"name": "kubernix"},
+                    "spans": spans,
+                }]
+            }]
+        })
+    }
+
+    /// Send the provided spans to the collector
+    fn export(url: &str, spans: &[SpanData]) -> Fallible<()> {
+        debug!("Exporting {} spans to '{}'", spans.len(), url);
+        let output = Command::new("curl")
+            .arg("--silent")
+            .arg("--show-error")
+            .arg("--fail")
+            .arg("--header")
+            .arg("Content-Type: application/json")
+            .arg("--data")
+            .arg(Self::encode(spans).to_string())
+            .arg(url)
+            .stdout(Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "Unable to export traces: {}",
+                String::from_utf8(output.stderr)?.trim()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Subscriber for Telemetry {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes) -> Id {
+        let parent = if span.is_contextual() {
+            CURRENT.with(|x| x.borrow().last().cloned())
+        } else {
+            span.parent().cloned()
+        };
+
+        let mut spans = Self::lock(&self.spans);
+        let (trace_id, parent_id) = match parent.and_then(|x| spans.get(&x.into_u64())) {
+            Some(p) => (p.trace_id, Some(p.span_id)),
+            None => (random(), None),
+        };
+        let mut data = SpanData {
+            name: span.metadata().name(),
+            trace_id,
+            span_id: random(),
+            parent_id,
+            start: Self::now(),
+            end: 0,
+            attributes: vec![],
+            references: 1,
+        };
+        span.record(&mut Visitor(&mut data.attributes));
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        spans.insert(id, data);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record) {
+        if let Some(x) = Self::lock(&self.spans).get_mut(&span.into_u64()) {
+            values.record(&mut Visitor(&mut x.attributes));
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event) {}
+
+    fn enter(&self, span: &Id) {
+        CURRENT.with(|x| x.borrow_mut().push(span.clone()));
+    }
+
+    fn exit(&self, span: &Id) {
+        CURRENT.with(|x| {
+            let mut stack = x.borrow_mut();
+            if let Some(pos) = stack.iter().rposition(|x| x == span) {
+                stack.remove(pos);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(x) = Self::lock(&self.spans).get_mut(&span.into_u64()) {
+            x.references += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let mut data = {
+            let mut spans = Self::lock(&self.spans);
+            match spans.get_mut(&span.into_u64()) {
+                Some(x) if x.references > 1 => {
+                    x.references -= 1;
+                    return false;
+                }
+                Some(_) => spans.remove(&span.into_u64()).unwrap(),
+                None => return false,
+            }
+        };
+        data.end = Self::now();
+        Self::lock(&self.exporter).send(Message::Span(data)).ok();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_success() {
+        let spans = vec![SpanData {
+            name: "bootstrap",
+            trace_id: 1,
+            span_id: 2,
+            parent_id: None,
+            start: 3,
+            end: 4,
+            attributes: vec![("component".into(), "etcd".into())],
+            references: 0,
+        }];
+        let v = Telemetry::encode(&spans);
+        let span = &v["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["traceId"], "00000000000000000000000000000001");
+        assert_eq!(span["spanId"], "0000000000000002");
+        assert_eq!(span["parentSpanId"], "");
+        assert_eq!(span["startTimeUnixNano"], "3");
+        assert_eq!(span["attributes"][0]["value"]["stringValue"], "etcd");
+    }
+
+    #[test]
+    fn lock_success_poisoned() {
+        let mutex = Mutex::new(1);
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = mutex.lock().unwrap();
+            panic!("poison");
+        });
+        assert!(mutex.is_poisoned());
+        assert_eq!(*Telemetry::lock(&mutex), 1);
+    }
+
+    #[test]
+    fn export_loop_success_flush() {
+        let (tx, rx) = channel();
+        thread::spawn(move || Telemetry::export_loop("http://invalid", &rx));
+        let (ack_tx, ack_rx) = channel();
+        tx.send(Message::Flush(ack_tx)).unwrap();
+        assert!(ack_rx.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+}
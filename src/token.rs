@@ -0,0 +1,102 @@
+use crate::{config::Token, kubeconfig::KubeConfig, process::Process, Config, Fallible};
+use log::{debug, info};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Mints service account tokens for consumers outside of the cluster
+pub struct TokenRequest;
+
+impl TokenRequest {
+    /// The shortest lifetime accepted by the API Server
+    const MIN_DURATION: Duration = Duration::from_secs(600);
+
+    /// Request a token for the provided service account and print it
+    pub fn run(config: &Config, args: &Token) -> Fallible<()> {
+        if args.duration().duration() < Self::MIN_DURATION {
+            bail!(
+                "Token duration {} is shorter than the minimum of 10m",
+                args.duration()
+            )
+        }
+        info!(
+            "Requesting token for service account '{}/{}'",
+            args.namespace(),
+            args.service_account()
+        );
+
+        let output = Process::run_to_completion("kubectl")
+            .arg(format!(
+                "--kubeconfig={}",
+                KubeConfig::admin_path(config).display()
+            ))
+            .arg("create")
+            .arg("--raw")
+            .arg(Self::path(args))
+            .arg("--filename=-")
+            .input(&Self::body(args).to_string())
+            .output()?;
+        if !output.success() {
+            debug!("kubectl create stderr: {}", output.stderr());
+            bail!(
+                "Unable to request token for service account '{}/{}'",
+                args.namespace(),
+                args.service_account()
+            )
+        }
+
+        println!("{}", Self::token(output.stdout())?);
+        Ok(())
+    }
+
+    /// The API path of the token subresource
+    fn path(args: &Token) -> String {
+        format!(
+            "/api/v1/namespaces/{}/serviceaccounts/{}/token",
+            args.namespace(),
+            args.service_account()
+        )
+    }
+
+    /// Build the TokenRequest, whereas no audiences result in the ones of the
+    /// API Server
+    fn body(args: &Token) -> Value {
+        json!({
+            "apiVersion": "authentication.k8s.io/v1",
+            "kind": "TokenRequest",
+            "spec": {
+                "audiences": args.audiences(),
+                "expirationSeconds": args.duration().duration().as_secs(),
+            },
+        })
+    }
+
+    /// Extract the token of the TokenRequest response
+    fn token(response: &str) -> Fallible<String> {
+        let value: Value = serde_json::from_str(response)?;
+        value["status"]["token"]
+            .as_str()
+            .map(|x| x.to_owned())
+            .ok_or_else(|| format_err!("No token found in TokenRequest response"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_success() -> Fallible<()> {
+        let token = TokenRequest::token(
+            r#"{"kind": "TokenRequest", "status":
+                {"token": "eyJhbGciOiJSUzI1NiJ9", "expirationTimestamp": "2020-01-01T00:10:00Z"}}"#,
+        )?;
+        assert_eq!(token, "eyJhbGciOiJSUzI1NiJ9");
+        Ok(())
+    }
+
+    #[test]
+    fn token_failure() {
+        assert!(TokenRequest::token(r#"{"kind": "Status", "status": "Failure"}"#).is_err());
+        assert!(TokenRequest::token("").is_err());
+    }
+}
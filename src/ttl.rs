@@ -0,0 +1,85 @@
+use crate::Config;
+use log::{debug, info, warn};
+use std::{
+    process::Command,
+    time::{Duration, Instant},
+};
+
+/// The lifetime of the cluster, after which it gets torn down automatically
+pub struct Ttl {
+    deadline: Option<Instant>,
+    warned: bool,
+}
+
+impl Ttl {
+    /// The time before the teardown where all users get warned
+    const WARNING: Duration = Duration::from_secs(5 * 60);
+
+    /// Create a new lifetime, which starts now
+    pub fn new(config: &Config) -> Self {
+        let deadline = config.ttl().map(|x| {
+            info!("Cluster gets torn down automatically after {}", x);
+            Instant::now() + x.duration()
+        });
+        Self {
+            deadline,
+            warned: false,
+        }
+    }
+
+    /// Check if the lifetime is over and warn once shortly before
+    pub fn expired(&mut self) -> bool {
+        let remaining = match self.deadline {
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+            None => return false,
+        };
+        if remaining == Duration::from_secs(0) {
+            warn!("Cluster lifetime exceeded, tearing down the cluster");
+            return true;
+        }
+        if !self.warned && remaining <= Self::WARNING {
+            Self::broadcast(remaining);
+            self.warned = true;
+        }
+        false
+    }
+
+    /// Warn the logs and all logged in terminals about the upcoming teardown
+    fn broadcast(remaining: Duration) {
+        let message = format!(
+            "kubernix: cluster lifetime ends in {}s, the cluster gets torn down afterwards",
+            remaining.as_secs()
+        );
+        warn!("{}", message);
+        if let Err(e) = Command::new("wall").arg(&message).status() {
+            debug!("Unable to broadcast lifetime warning: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expired_success() {
+        let mut ttl = Ttl {
+            deadline: None,
+            warned: false,
+        };
+        assert!(!ttl.expired());
+
+        ttl.deadline = Some(Instant::now());
+        assert!(ttl.expired());
+    }
+
+    #[test]
+    fn expired_not_yet() {
+        let mut ttl = Ttl {
+            deadline: Some(Instant::now() + Duration::from_secs(3600)),
+            warned: false,
+        };
+        assert!(!ttl.expired());
+        assert!(!ttl.warned);
+    }
+}
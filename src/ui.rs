@@ -0,0 +1,375 @@
+use crate::{control::Control, Config, Fallible};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use std::{
+    collections::HashMap,
+    io::{stdout, Stdout},
+    time::{Duration, Instant},
+};
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState},
+    Frame, Terminal,
+};
+
+/// An interactive terminal dashboard of the running cluster, which is built
+/// on top of the control API
+pub struct Ui<'a> {
+    config: &'a Config,
+    entries: Vec<Entry>,
+    cpu: HashMap<String, (f64, Instant)>,
+    state: TableState,
+    logs: Vec<String>,
+    scroll: usize,
+    uptime: u64,
+    message: String,
+    confirm_stop: bool,
+}
+
+/// A single component of the dashboard
+struct Entry {
+    name: String,
+    health: String,
+    pid: Option<i64>,
+    memory: Option<u64>,
+    cpu: Option<f64>,
+}
+
+impl<'a> Ui<'a> {
+    /// The interval of refreshing the components and logs
+    const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+    /// The maximum time to wait for user input before redrawing
+    const TICK: Duration = Duration::from_millis(250);
+
+    /// The number of log lines retrieved for the selected component
+    const LOG_LINES: usize = 500;
+
+    /// The number of lines to scroll the logs by a single page
+    const PAGE: usize = 10;
+
+    /// Run the dashboard until the user quits
+    pub fn run(config: &Config) -> Fallible<()> {
+        let mut ui = Ui {
+            config,
+            entries: vec![],
+            cpu: HashMap::new(),
+            state: TableState::default(),
+            logs: vec![],
+            scroll: 0,
+            uptime: 0,
+            message: String::new(),
+            confirm_stop: false,
+        };
+
+        // Fail before touching the terminal if no cluster is running
+        ui.refresh()?;
+
+        enable_raw_mode()?;
+        let mut stdout = stdout();
+        execute!(stdout, EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+        terminal.hide_cursor()?;
+
+        let result = ui.event_loop(&mut terminal);
+
+        // Always restore the terminal, even on failure
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()?;
+        result
+    }
+
+    /// Draw the dashboard and handle the user input
+    fn event_loop(&mut self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Fallible<()> {
+        let mut last_refresh = Instant::now();
+        loop {
+            terminal.draw(|f| self.draw(f))?;
+            if event::poll(Self::TICK)? {
+                if let Event::Key(key) = event::read()? {
+                    if !self.handle_key(key) {
+                        return Ok(());
+                    }
+                }
+            }
+            if last_refresh.elapsed() >= Self::REFRESH_INTERVAL {
+                if let Err(e) = self.refresh() {
+                    self.message = e.to_string();
+                }
+                last_refresh = Instant::now();
+            }
+        }
+    }
+
+    /// Handle a single key press and return if the dashboard should keep
+    /// running
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        // Stopping the cluster has to be confirmed
+        if self.confirm_stop {
+            self.confirm_stop = false;
+            if key.code == KeyCode::Char('y') {
+                return match Control::request(self.config, "POST", "/v1/stop") {
+                    Ok(_) => false,
+                    Err(e) => {
+                        self.message = e.to_string();
+                        true
+                    }
+                };
+            }
+            self.message.clear();
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.select(1),
+            KeyCode::PageUp => self.scroll += Self::PAGE,
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(Self::PAGE),
+            KeyCode::Home => self.scroll = self.logs.len(),
+            KeyCode::End => self.scroll = 0,
+            KeyCode::Char('r') => self.component_action("restart", "Restarted"),
+            KeyCode::Char('s') => self.component_action("stop", "Stopped"),
+            KeyCode::Char('S') => {
+                self.confirm_stop = true;
+                self.message = "Press 'y' to stop the cluster".into();
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Move the selection by the provided offset
+    fn select(&mut self, offset: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let current = self.state.selected().unwrap_or(0) as isize;
+        let max = self.entries.len() as isize - 1;
+        self.state
+            .select(Some((current + offset).max(0).min(max) as usize));
+        self.scroll = 0;
+        if let Err(e) = self.refresh_logs() {
+            self.message = e.to_string();
+        }
+    }
+
+    /// Run the provided action on the selected component
+    fn component_action(&mut self, action: &str, done: &str) {
+        let name = match self.selected() {
+            Some(x) => x.name.clone(),
+            None => return,
+        };
+        let path = format!("/v1/components/{}/{}", name, action);
+        self.message = match Control::request(self.config, "POST", &path) {
+            Ok(_) => format!("{} component '{}'", done, name),
+            Err(e) => e.to_string(),
+        };
+        if let Err(e) = self.refresh() {
+            self.message = e.to_string();
+        }
+    }
+
+    /// The currently selected component
+    fn selected(&self) -> Option<&Entry> {
+        self.state.selected().and_then(|x| self.entries.get(x))
+    }
+
+    /// Retrieve the current status and logs of the cluster
+    fn refresh(&mut self) -> Fallible<()> {
+        let status = Control::request(self.config, "GET", "/v1/status")?;
+        self.uptime = status["uptime"].as_u64().unwrap_or_default();
+        let now = Instant::now();
+        let mut entries = vec![];
+        for component in status["components"].as_array().unwrap_or(&vec![]) {
+            let name = component["name"].as_str().unwrap_or_default().to_owned();
+            let cpu_time = component["usage"]["cpu"].as_f64();
+            let cpu = match (cpu_time, self.cpu.get(&name)) {
+                (Some(current), Some((previous, at))) => {
+                    Self::cpu_percent(*previous, current, now.duration_since(*at))
+                }
+                _ => None,
+            };
+            match cpu_time {
+                Some(x) => self.cpu.insert(name.clone(), (x, now)),
+                None => self.cpu.remove(&name),
+            };
+            entries.push(Entry {
+                health: component["health"].as_str().unwrap_or_default().to_owned(),
+                pid: component["pid"].as_i64(),
+                memory: component["usage"]["memory"].as_u64(),
+                cpu,
+                name,
+            });
+        }
+        self.entries = entries;
+        if self.selected().is_none() && !self.entries.is_empty() {
+            self.state.select(Some(0));
+        }
+        self.refresh_logs()
+    }
+
+    /// Retrieve the last log lines of the selected component
+    fn refresh_logs(&mut self) -> Fallible<()> {
+        let name = match self.selected() {
+            Some(x) => x.name.clone(),
+            None => return Ok(()),
+        };
+        self.logs = Control::text(
+            self.config,
+            &format!("/v1/components/{}/logs?tail={}", name, Self::LOG_LINES),
+        )?
+        .lines()
+        .map(ToOwned::to_owned)
+        .collect();
+        Ok(())
+    }
+
+    /// Render the whole dashboard
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Length(self.entries.len() as u16 + 3),
+                    Constraint::Min(3),
+                    Constraint::Length(1),
+                ]
+                .as_ref(),
+            )
+            .split(f.size());
+        self.draw_components(f, chunks[0]);
+        self.draw_logs(f, chunks[1]);
+
+        let help = if self.message.is_empty() {
+            "↑/↓ select  PgUp/PgDn scroll  r restart  s stop  S stop cluster  q quit"
+        } else {
+            &self.message
+        };
+        f.render_widget(Paragraph::new(help), chunks[2]);
+    }
+
+    /// Render the table of components
+    fn draw_components<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let header = Row::new(vec!["COMPONENT", "HEALTH", "PID", "CPU", "MEMORY"]).style(bold);
+        let rows = self.entries.iter().map(|x| {
+            let color = if x.health == "ready" {
+                Color::Green
+            } else {
+                Color::Red
+            };
+            Row::new(vec![
+                Cell::from(x.name.as_str()),
+                Cell::from(x.health.as_str()).style(Style::default().fg(color)),
+                Cell::from(x.pid.map_or_else(|| "-".into(), |x| x.to_string())),
+                Cell::from(x.cpu.map_or_else(|| "-".into(), |x| format!("{:.1}%", x))),
+                Cell::from(x.memory.map_or_else(|| "-".into(), Self::format_memory)),
+            ])
+        });
+        let widths = [
+            Constraint::Percentage(30),
+            Constraint::Percentage(20),
+            Constraint::Percentage(15),
+            Constraint::Percentage(15),
+            Constraint::Percentage(20),
+        ];
+        let title = format!(
+            " kubernix: {} (up {}s) ",
+            self.config.root().display(),
+            self.uptime
+        );
+        let table = Table::new(rows)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .widths(&widths)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(table, area, &mut self.state);
+    }
+
+    /// Render the logs of the selected component
+    fn draw_logs<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let height = area.height.saturating_sub(2) as usize;
+        // Do not scroll beyond the first line
+        self.scroll = self.logs.len().saturating_sub(height).min(self.scroll);
+        let (start, end) = Self::visible(self.logs.len(), height, self.scroll);
+        let lines: Vec<Spans> = self.logs[start..end]
+            .iter()
+            .map(|x| Spans::from(Span::raw(x.as_str())))
+            .collect();
+        let title = match self.selected() {
+            Some(x) => format!(" {} logs ", x.name),
+            None => " logs ".into(),
+        };
+        f.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+            area,
+        );
+    }
+
+    /// Retrieve the range of visible log lines for the provided height,
+    /// whereas the scroll offset counts from the last line
+    fn visible(len: usize, height: usize, scroll: usize) -> (usize, usize) {
+        let end = len - scroll.min(len.saturating_sub(height));
+        (end.saturating_sub(height), end)
+    }
+
+    /// Calculate the CPU usage in percent between two measurements of the
+    /// consumed CPU time
+    fn cpu_percent(previous: f64, current: f64, elapsed: Duration) -> Option<f64> {
+        let elapsed = elapsed.as_secs_f64();
+        if elapsed <= 0.0 || current < previous {
+            None
+        } else {
+            Some((current - previous) / elapsed * 100.0)
+        }
+    }
+
+    /// Format the provided amount of bytes in a human readable way
+    fn format_memory(bytes: u64) -> String {
+        let units = [(1 << 30, "GiB"), (1 << 20, "MiB"), (1 << 10, "KiB")];
+        match units.iter().find(|(x, _)| bytes >= *x) {
+            Some((x, unit)) => format!("{:.1} {}", bytes as f64 / *x as f64, unit),
+            None => format!("{} B", bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visible_success() {
+        assert_eq!(Ui::visible(100, 10, 0), (90, 100));
+        assert_eq!(Ui::visible(100, 10, 5), (85, 95));
+        assert_eq!(Ui::visible(100, 10, 500), (0, 10));
+        assert_eq!(Ui::visible(5, 10, 3), (0, 5));
+        assert_eq!(Ui::visible(0, 10, 0), (0, 0));
+    }
+
+    #[test]
+    fn cpu_percent_success() {
+        assert_eq!(
+            Ui::cpu_percent(1.0, 2.0, Duration::from_secs(2)),
+            Some(50.0)
+        );
+        assert_eq!(Ui::cpu_percent(2.0, 1.0, Duration::from_secs(2)), None);
+        assert_eq!(Ui::cpu_percent(1.0, 2.0, Duration::from_secs(0)), None);
+    }
+
+    #[test]
+    fn format_memory_success() {
+        assert_eq!(Ui::format_memory(512), "512 B");
+        assert_eq!(Ui::format_memory(1536), "1.5 KiB");
+        assert_eq!(Ui::format_memory(300 << 20), "300.0 MiB");
+        assert_eq!(Ui::format_memory(2 << 30), "2.0 GiB");
+    }
+}
@@ -0,0 +1,377 @@
+use crate::{config::Upgrade, process::Process, Config, Error, Fallible, Kubernix, NIX_DIR};
+use getset::Getters;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_string_pretty};
+use std::{
+    fmt::{self, Display},
+    fs::{self, create_dir_all, read_to_string, remove_file},
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// An upgrade request sent to the running kubernix instance
+pub enum UpgradeRequest {
+    /// Upgrade to the Kubernetes version contained in the closure
+    Upgrade {
+        /// The target Kubernetes version
+        version: Version,
+
+        /// The Nix closure containing the target Kubernetes version
+        closure: PathBuf,
+
+        /// Allow upgrading to a lower version than the running one
+        allow_downgrade: bool,
+    },
+
+    /// Restore the state recorded before the last upgrade
+    Rollback,
+
+    /// Reload the configuration file and restart all components with it
+    Reconfigure,
+}
+
+impl UpgradeRequest {
+    const DIR: &'static str = "upgrade";
+    const REQUEST: &'static str = "request.json";
+    const RESULT: &'static str = "result";
+    const RESULT_OK: &'static str = "ok";
+
+    /// The maximum time in seconds to wait for the request being picked up
+    const PICKUP_TIMEOUT: u64 = 30;
+
+    /// The maximum time in seconds an upgrade is allowed to take, which
+    /// includes a possible rollback
+    const UPGRADE_TIMEOUT: u64 = 1200;
+
+    /// The maximum time in seconds the upgraded cluster may take to become
+    /// healthy before it gets rolled back
+    pub const HEALTH_TIMEOUT: u64 = 120;
+
+    /// The maximum time in seconds building a Kubernetes version may take
+    const BUILD_TIMEOUT: u64 = 3600;
+
+    /// Build the requested version, send the request to the running instance
+    /// and wait for its result
+    pub fn send(config: &Config, args: &Upgrade) -> Fallible<()> {
+        let request = match (args.to(), *args.rollback()) {
+            (Some(to), false) => {
+                let version = to.parse()?;
+                let sha256 = args.sha256().as_ref().ok_or_else(|| {
+                    format_err!("Please specify the sha256 of the Kubernetes sources via --sha256")
+                })?;
+                UpgradeRequest::Upgrade {
+                    closure: Self::build(config, &version, sha256)?,
+                    version,
+                    allow_downgrade: *args.allow_downgrade(),
+                }
+            }
+            (None, true) => {
+                // Fail early if there is nothing to roll back to
+                let record = UpgradeRecord::load(config)?;
+                info!("Rolling back to Kubernetes {}", record.version());
+                UpgradeRequest::Rollback
+            }
+            _ => bail!("Please specify either a target version or a rollback"),
+        };
+        request.submit(config)
+    }
+
+    /// Send the request to the running instance and wait for its result
+    pub fn submit(&self, config: &Config) -> Fallible<()> {
+        let dir = config.root().join(Self::DIR);
+        create_dir_all(&dir)?;
+        let request_file = dir.join(Self::REQUEST);
+        if request_file.exists() {
+            bail!("Another upgrade is already in progress")
+        }
+
+        let result_file = dir.join(Self::RESULT);
+        if result_file.exists() {
+            remove_file(&result_file)?;
+        }
+        fs::write(&request_file, to_string_pretty(self)?)?;
+
+        // Wait for the running instance to pick up the request
+        info!("Waiting for the running cluster to pick up the upgrade");
+        if !Self::wait_for(|| !request_file.exists(), Self::PICKUP_TIMEOUT) {
+            remove_file(&request_file)?;
+            bail!("No running kubernix instance picked up the upgrade request")
+        }
+
+        // Wait for the result
+        info!("Upgrade in progress");
+        if !Self::wait_for(|| result_file.exists(), Self::UPGRADE_TIMEOUT) {
+            return Err(Error::Timeout("waiting for the upgrade to finish".into()));
+        }
+        let result = read_to_string(&result_file)?;
+        if result != Self::RESULT_OK {
+            bail!("Upgrade failed: {}", result)
+        }
+
+        info!("Upgrade done");
+        Ok(())
+    }
+
+    /// Retrieve a pending upgrade request, which gets removed from the root
+    pub fn pending(config: &Config) -> Fallible<Option<UpgradeRequest>> {
+        let request_file = config.root().join(Self::DIR).join(Self::REQUEST);
+        if !request_file.exists() {
+            return Ok(None);
+        }
+        let request = from_str(&read_to_string(&request_file)?)?;
+        remove_file(&request_file)?;
+        Ok(Some(request))
+    }
+
+    /// Finish the upgrade by reporting its result back to the requester
+    pub fn finish(config: &Config, result: &Fallible<()>) -> Fallible<()> {
+        let result_file = config.root().join(Self::DIR).join(Self::RESULT);
+        match result {
+            Ok(()) => fs::write(result_file, Self::RESULT_OK)?,
+            Err(e) => fs::write(result_file, e.to_string())?,
+        }
+        Ok(())
+    }
+
+    /// Build the Nix closure of the requested Kubernetes version, whereas its
+    /// sources have to match the provided sha256
+    fn build(config: &Config, version: &Version, sha256: &str) -> Fallible<PathBuf> {
+        info!("Building Kubernetes {}, this may take a while", version);
+        let nix_dir = config.root().join(NIX_DIR);
+        if !nix_dir.exists() {
+            bail!("Nix environment not found, please bootstrap the cluster first")
+        }
+
+        let nix_file = nix_dir.join("kubernetes.nix");
+        fs::write(&nix_file, include_str!("../nix/kubernetes.nix"))?;
+
+        let nix_build = Kubernix::find_executable("nix-build")?;
+        let output = Process::run_to_completion(&nix_build.display().to_string())
+            .arg(&nix_file)
+            .arg("--argstr")
+            .arg("version")
+            .arg(version.to_string())
+            .arg("--argstr")
+            .arg("sha256")
+            .arg(sha256)
+            .arg("--out-link")
+            .arg(nix_dir.join(format!("kubernetes-{}", version)))
+            .timeout(Duration::from_secs(Self::BUILD_TIMEOUT))
+            .run()?;
+
+        let closure = output
+            .lines()
+            .last()
+            .map(|x| Path::new(x.trim()).to_path_buf())
+            .ok_or_else(|| format_err!("Unable to retrieve Kubernetes closure"))?;
+        debug!("Built Kubernetes closure {}", closure.display());
+        Ok(closure)
+    }
+
+    /// Wait until the condition is true or the timeout in seconds is reached
+    fn wait_for<F>(condition: F, timeout: u64) -> bool
+    where
+        F: Fn() -> bool,
+    {
+        let now = Instant::now();
+        while now.elapsed().as_secs() < timeout {
+            if condition() {
+                return true;
+            }
+            sleep(Duration::from_secs(1));
+        }
+        false
+    }
+}
+
+#[derive(Deserialize, Getters, Serialize)]
+/// The state recorded before an upgrade, which is used for rollbacks
+pub struct UpgradeRecord {
+    #[get = "pub"]
+    /// The Kubernetes version before the upgrade
+    version: Version,
+
+    #[get = "pub"]
+    /// The Nix closure containing the Kubernetes version before the upgrade
+    closure: PathBuf,
+
+    #[get = "pub"]
+    /// The etcd snapshot taken before the upgrade
+    snapshot: PathBuf,
+}
+
+impl UpgradeRecord {
+    const FILENAME: &'static str = "previous.json";
+
+    /// Create a new record for the currently running version
+    pub fn new(config: &Config) -> Fallible<UpgradeRecord> {
+        let apiserver = Kubernix::find_executable("kube-apiserver")?;
+        let output = Process::run_to_completion(&apiserver.display().to_string())
+            .arg("--version")
+            .output()?;
+        if !output.success() {
+            bail!("Unable to retrieve the running Kubernetes version")
+        }
+
+        // The output looks like 'Kubernetes v1.15.4'
+        let version: Version = output
+            .stdout()
+            .split_whitespace()
+            .last()
+            .ok_or_else(|| format_err!("Unexpected kube-apiserver version output"))?
+            .parse()?;
+        let closure = apiserver
+            .parent()
+            .and_then(|x| x.parent())
+            .ok_or_else(|| format_err!("Unable to retrieve the running Kubernetes closure"))?
+            .to_path_buf();
+        let snapshot = config
+            .root()
+            .join(UpgradeRequest::DIR)
+            .join(format!("etcd-{}.db", version));
+
+        Ok(UpgradeRecord {
+            version,
+            closure,
+            snapshot,
+        })
+    }
+
+    /// Load the record of the last upgrade
+    pub fn load(config: &Config) -> Fallible<UpgradeRecord> {
+        Self::load_from(config.root())
+    }
+
+    /// Load the record of the last upgrade from the provided root
+    pub fn load_from(root: &Path) -> Fallible<UpgradeRecord> {
+        let file = Self::file(root);
+        let content = read_to_string(&file)
+            .map_err(|e| format_err!("No previous upgrade found to roll back to: {}", e))?;
+        Ok(from_str(&content)?)
+    }
+
+    /// Persist the record into the root
+    pub fn save(&self, config: &Config) -> Fallible<()> {
+        create_dir_all(config.root().join(UpgradeRequest::DIR))?;
+        fs::write(Self::file(config.root()), to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn file(root: &Path) -> PathBuf {
+        root.join(UpgradeRequest::DIR).join(Self::FILENAME)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+/// A semantic Kubernetes version
+pub struct Version {
+    major: u64,
+    minor: u64,
+    patch: u64,
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let err = || format_err!("Invalid Kubernetes version '{}'", s);
+
+        // Strip the leading 'v' as well as any pre-release or build suffix
+        let core = s
+            .trim_start_matches('v')
+            .split(|c| c == '-' || c == '+')
+            .next()
+            .ok_or_else(err)?;
+        let parts = core
+            .split('.')
+            .map(|x| x.parse().map_err(|_| err()))
+            .collect::<Fallible<Vec<u64>>>()?;
+        match parts.as_slice() {
+            [major, minor, patch] => Ok(Version {
+                major: *major,
+                minor: *minor,
+                patch: *patch,
+            }),
+            _ => Err(err()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::test_config;
+
+    #[test]
+    fn pending_success_none() -> Fallible<()> {
+        let c = test_config()?;
+        assert!(UpgradeRequest::pending(&c)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn pending_success() -> Fallible<()> {
+        let c = test_config()?;
+        let dir = c.root().join(UpgradeRequest::DIR);
+        create_dir_all(&dir)?;
+        fs::write(
+            dir.join(UpgradeRequest::REQUEST),
+            to_string_pretty(&UpgradeRequest::Rollback)?,
+        )?;
+
+        match UpgradeRequest::pending(&c)? {
+            Some(UpgradeRequest::Rollback) => {}
+            _ => bail!("No pending rollback request"),
+        }
+        assert!(!dir.join(UpgradeRequest::REQUEST).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn finish_success() -> Fallible<()> {
+        let c = test_config()?;
+        create_dir_all(c.root().join(UpgradeRequest::DIR))?;
+        UpgradeRequest::finish(&c, &Err(format_err!("failed")))?;
+        assert_eq!(
+            read_to_string(
+                c.root()
+                    .join(UpgradeRequest::DIR)
+                    .join(UpgradeRequest::RESULT)
+            )?,
+            "failed"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn load_failure() -> Fallible<()> {
+        let c = test_config()?;
+        assert!(UpgradeRecord::load(&c).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn version_from_str_success() -> Fallible<()> {
+        assert_eq!("v1.15.4".parse::<Version>()?.to_string(), "1.15.4");
+        assert_eq!("1.16.0-beta.1".parse::<Version>()?.to_string(), "1.16.0");
+        assert!("1.16.0".parse::<Version>()? > "1.15.10".parse::<Version>()?);
+        Ok(())
+    }
+
+    #[test]
+    fn version_from_str_failure() {
+        assert!("1.16".parse::<Version>().is_err());
+        assert!("invalid".parse::<Version>().is_err());
+    }
+}
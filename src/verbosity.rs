@@ -0,0 +1,112 @@
+use crate::{component::ComponentKind, Config, Error, Fallible};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+/// The log verbosity of a single component
+pub struct Verbosity {
+    component: ComponentKind,
+    level: u8,
+}
+
+impl Verbosity {
+    /// The default `--v` level of the Kubernetes components
+    const DEFAULT_LEVEL: u8 = 2;
+
+    /// Retrieve the configured level for the provided component, whereas
+    /// later entries take precedence
+    fn level(config: &Config, kind: ComponentKind) -> Option<u8> {
+        config
+            .component_verbosity()
+            .iter()
+            .rev()
+            .find(|x| x.component == kind)
+            .map(|x| x.level)
+    }
+
+    /// Retrieve the verbosity arguments for the provided component
+    pub fn args(config: &Config, kind: ComponentKind) -> Vec<String> {
+        let level = Self::level(config, kind);
+        match kind {
+            // CRI-O and etcd use named log levels instead of numeric ones
+            ComponentKind::Crio => vec![format!(
+                "--log-level={}",
+                match level {
+                    None => "debug",
+                    Some(0) => "error",
+                    Some(1) => "warn",
+                    Some(2) => "info",
+                    Some(3) | Some(4) => "debug",
+                    Some(_) => "trace",
+                }
+            )],
+            ComponentKind::Etcd => level
+                .map(|x| {
+                    format!(
+                        "--log-level={}",
+                        match x {
+                            0 => "error",
+                            1 => "warn",
+                            2 | 3 => "info",
+                            _ => "debug",
+                        }
+                    )
+                })
+                .into_iter()
+                .collect(),
+            // The proxy logs enough by default
+            ComponentKind::Proxy => level.map(|x| format!("--v={}", x)).into_iter().collect(),
+            _ => vec![format!("--v={}", level.unwrap_or(Self::DEFAULT_LEVEL))],
+        }
+    }
+}
+
+impl Display for Verbosity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.component, self.level)
+    }
+}
+
+impl FromStr for Verbosity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let mut split = s.splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some(component), Some(level)) => Ok(Verbosity {
+                component: component.trim().parse()?,
+                level: level.trim().parse().map_err(|_| {
+                    format_err!("Invalid verbosity level '{}' for '{}'", level, component)
+                })?,
+            }),
+            _ => bail!(
+                "Invalid component verbosity '{}', expected '<component>=<level>'",
+                s
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_success() -> Fallible<()> {
+        let v: Verbosity = "apiserver=4".parse()?;
+        assert_eq!(v.component, ComponentKind::ApiServer);
+        assert_eq!(v.level, 4);
+        assert_eq!(v.to_string(), "apiserver=4");
+        Ok(())
+    }
+
+    #[test]
+    fn from_str_failure() {
+        assert!("apiserver".parse::<Verbosity>().is_err());
+        assert!("apiserver=high".parse::<Verbosity>().is_err());
+        assert!("unknown=2".parse::<Verbosity>().is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{
+    config::Verify as VerifyArgs, kubeconfig::KubeConfig, process::Process, Config, Fallible,
+};
+use log::{debug, error, info};
+use std::{
+    io::{BufRead, BufReader},
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    thread::sleep,
+    time::Duration,
+};
+
+/// The verification of the API Server to kubelet streaming paths
+pub struct Verify<'a> {
+    args: &'a VerifyArgs,
+    admin: PathBuf,
+}
+
+impl<'a> Verify<'a> {
+    /// The name of the verification pod
+    const POD: &'static str = "kubernix-verify";
+
+    /// The port the verification pod serves on
+    const PORT: u16 = 8080;
+
+    /// The message written by the verification pod
+    const MESSAGE: &'static str = "kubernix-verify";
+
+    /// Run all checks with the provided arguments
+    pub fn run(config: &Config, args: &VerifyArgs) -> Fallible<()> {
+        let verify = Verify {
+            args,
+            admin: KubeConfig::admin_path(config),
+        };
+
+        info!(
+            "Creating verification pod in namespace '{}'",
+            args.namespace()
+        );
+        verify.setup()?;
+        let checks = vec![
+            ("logs", verify.logs()),
+            ("exec", verify.exec()),
+            ("attach", verify.attach()),
+            ("port-forward", verify.port_forward()),
+        ];
+        let mut failed = vec![];
+        for (name, result) in checks {
+            match result {
+                Ok(()) => info!("Verified {}", name),
+                Err(e) => {
+                    error!("Unable to verify {}: {}", name, e);
+                    failed.push(name);
+                }
+            }
+        }
+
+        if let Err(e) = verify.kubectl(&["delete", "namespace", args.namespace(), "--wait=false"]) {
+            debug!("Unable to remove verification namespace: {}", e);
+        }
+        if !failed.is_empty() {
+            bail!("Verification failed for: {}", failed.join(", "));
+        }
+        info!("All verifications succeeded");
+        Ok(())
+    }
+
+    /// Create the namespace and the pod and wait for it to be ready
+    fn setup(&self) -> Fallible<()> {
+        // The namespace may already exist from a previous run
+        if let Err(e) = self.kubectl(&["create", "namespace", self.args.namespace()]) {
+            debug!("{}", e);
+        }
+        self.kubectl(&[
+            "run",
+            Self::POD,
+            "--restart=Never",
+            "--stdin",
+            &format!("--image={}", self.args.image()),
+            &format!("--port={}", Self::PORT),
+            "--command",
+            "--",
+            "sh",
+            "-c",
+            &format!(
+                "echo {} && exec httpd -f -p {} -h /etc",
+                Self::MESSAGE,
+                Self::PORT
+            ),
+        ])?;
+        self.kubectl(&[
+            "wait",
+            "--for=condition=Ready",
+            &format!("pod/{}", Self::POD),
+            &format!("--timeout={}s", self.args.timeout().duration().as_secs()),
+        ])?;
+        Ok(())
+    }
+
+    /// Retrieve the logs of the pod
+    fn logs(&self) -> Fallible<()> {
+        let output = self.kubectl(&["logs", Self::POD])?;
+        if !output.contains(Self::MESSAGE) {
+            bail!("Unexpected logs '{}'", output.trim())
+        }
+        Ok(())
+    }
+
+    /// Run a command inside the pod
+    fn exec(&self) -> Fallible<()> {
+        let output = self.kubectl(&["exec", Self::POD, "--", "echo", Self::MESSAGE])?;
+        if output.trim() != Self::MESSAGE {
+            bail!("Unexpected exec output '{}'", output.trim())
+        }
+        Ok(())
+    }
+
+    /// Attach to the running pod, which has to keep the connection open
+    fn attach(&self) -> Fallible<()> {
+        let mut child = self
+            .command(&["attach", Self::POD])
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        sleep(Duration::from_secs(3));
+        Self::finish(&mut child, "attach")
+    }
+
+    /// Forward a local port to the pod and request a file through it
+    fn port_forward(&self) -> Fallible<()> {
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let mut child = self
+            .command(&[
+                "port-forward",
+                Self::POD,
+                &format!("{}:{}", port, Self::PORT),
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Wait until the forwarding is established
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| format_err!("Unable to get port-forward stdout"))?;
+        let mut line = String::new();
+        BufReader::new(stdout).read_line(&mut line)?;
+        if !line.starts_with("Forwarding from") {
+            Self::finish(&mut child, "port-forward")?;
+            bail!("Unexpected port-forward output '{}'", line.trim())
+        }
+
+        let output = Process::run_to_completion("curl")
+            .arg("--silent")
+            .arg("--show-error")
+            .arg(format!("http://127.0.0.1:{}/hostname", port))
+            .output();
+        Self::finish(&mut child, "port-forward")?;
+        let output = output?;
+        if !output.success() || output.stdout().trim() != Self::POD {
+            debug!("curl stderr: {}", output.stderr());
+            bail!("Unexpected response '{}'", output.stdout().trim())
+        }
+        Ok(())
+    }
+
+    /// Stop a long running kubectl process, which must not have exited on
+    /// its own
+    fn finish(child: &mut Child, name: &str) -> Fallible<()> {
+        if let Some(status) = child.try_wait()? {
+            let mut stderr = String::new();
+            if let Some(x) = child.stderr.take() {
+                BufReader::new(x).read_line(&mut stderr)?;
+            }
+            bail!("kubectl {} exited with {}: {}", name, status, stderr.trim())
+        }
+        child.kill()?;
+        child.wait()?;
+        Ok(())
+    }
+
+    /// Build a long running kubectl command, which gets stopped via `finish`
+    /// instead of running to completion
+    fn command(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new("kubectl");
+        cmd.arg(format!("--kubeconfig={}", self.admin.display()))
+            .arg(format!("--namespace={}", self.args.namespace()))
+            .args(args);
+        cmd
+    }
+
+    fn kubectl(&self, args: &[&str]) -> Fallible<String> {
+        let output = Process::run_to_completion("kubectl")
+            .arg(format!("--kubeconfig={}", self.admin.display()))
+            .arg(format!("--namespace={}", self.args.namespace()))
+            .args(args)
+            // Leave waiting for the pod enough time to time out on its own
+            .timeout(self.args.timeout().duration() + Duration::from_secs(30))
+            .output()?;
+        if !output.success() {
+            bail!(
+                "kubectl {} command failed: {}",
+                args[0],
+                output.stderr().trim()
+            );
+        }
+        Ok(output.stdout().to_owned())
+    }
+}
@@ -0,0 +1,420 @@
+use crate::{
+    kubeconfig::KubeConfig,
+    process::{Process, Stoppable},
+    Config, Fallible, Kubernix,
+};
+use log::{debug, info, warn};
+use std::{
+    env::{consts::ARCH, current_exe},
+    fs::{self, canonicalize, create_dir_all, File},
+    net::{Ipv4Addr, TcpListener},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
+};
+use toml::Value;
+
+/// A lightweight virtual machine running the cluster, for example on
+/// non-Linux hosts
+pub struct Vm {
+    process: Process,
+    dir: PathBuf,
+    ssh_port: u16,
+}
+
+impl Vm {
+    /// The directory of the VM within the root
+    const DIR: &'static str = "vm";
+
+    /// The root of kubernix inside the VM
+    const GUEST_ROOT: &'static str = "/var/lib/kubernix";
+
+    /// The size of the VM disk, which grows on demand
+    const DISK_SIZE: &'static str = "40G";
+
+    /// The maximum time to wait for the VM to boot
+    const BOOT_TIMEOUT: Duration = Duration::from_secs(300);
+
+    /// Boot the VM, copy kubernix into it and run the cluster inside, until
+    /// the cluster shell exits
+    pub fn run(config: &Config) -> Fallible<()> {
+        let closure = Self::closure(config)?;
+        let vm = Self::start(config)?;
+        vm.wait_ssh()?;
+
+        info!("Copying kubernix closure {} into the VM", closure.display());
+        let output = Command::new(Kubernix::find_executable("nix")?)
+            .arg("copy")
+            .arg("--to")
+            .arg(format!("ssh://root@{}", Ipv4Addr::LOCALHOST))
+            .arg(&closure)
+            .env("NIX_SSHOPTS", vm.ssh_opts().join(" "))
+            .output()?;
+        if !output.status.success() {
+            debug!("nix copy stderr: {}", String::from_utf8(output.stderr)?);
+            bail!("Unable to copy kubernix closure into the VM");
+        }
+        vm.copy_config(config)?;
+
+        // The credentials appear as soon as the cluster got bootstrapped
+        let done = Arc::new(AtomicBool::new(false));
+        let fetcher = {
+            let done = done.clone();
+            let ssh = vm.ssh_opts();
+            let target = config.root().join(KubeConfig::DIR).join("admin.kubeconfig");
+            let port = *config.ports().apiserver();
+            spawn(move || Self::fetch_kubeconfig(&ssh, &target, port, &done))
+        };
+
+        info!("Starting kubernix inside the VM");
+        let status = vm
+            .ssh()
+            .arg("-t")
+            .arg(format!(
+                "{} --root {}",
+                closure.join("bin").join("kubernix").display(),
+                Self::GUEST_ROOT
+            ))
+            .status()?;
+        done.store(true, Ordering::SeqCst);
+        if fetcher.join().is_err() {
+            warn!("Unable to retrieve the kubeconfig of the VM");
+        }
+        if !status.success() {
+            bail!("kubernix inside the VM failed with {}", status)
+        }
+        Ok(())
+    }
+
+    /// Prepare the disk and cloud-init seed and boot the VM
+    fn start(config: &Config) -> Fallible<Vm> {
+        let image = config
+            .vm_image()
+            .as_ref()
+            .ok_or_else(|| format_err!("The VM backend requires --vm-image"))?;
+        let dir = config.root().join(Self::DIR);
+        create_dir_all(&dir)?;
+
+        // The disk is an overlay of the image, which keeps the Nix store of
+        // the VM across runs
+        let disk = dir.join("disk.qcow2");
+        if !disk.exists() {
+            debug!("Creating VM disk '{}'", disk.display());
+            let output = Command::new(Kubernix::find_executable("qemu-img")?)
+                .arg("create")
+                .arg("-f")
+                .arg("qcow2")
+                .arg("-F")
+                .arg("qcow2")
+                .arg("-b")
+                .arg(canonicalize(image)?)
+                .arg(&disk)
+                .arg(Self::DISK_SIZE)
+                .output()?;
+            if !output.status.success() {
+                debug!("qemu-img stderr: {}", String::from_utf8(output.stderr)?);
+                bail!("Unable to create VM disk from '{}'", image.display());
+            }
+        }
+        let seed = Self::seed(&dir)?;
+
+        // Use a free port for SSH, whereas the API Server keeps its port
+        let ssh_port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?
+            .local_addr()?
+            .port();
+        let accel = if cfg!(target_os = "macos") {
+            "hvf"
+        } else {
+            "kvm"
+        };
+
+        info!(
+            "Booting VM with {} CPUs and {} memory",
+            config.vm_cpus(),
+            config.vm_memory()
+        );
+        let mut args = vec![
+            "-name".to_owned(),
+            "kubernix".into(),
+            "-machine".into(),
+            format!("{},accel={}", Self::machine(), accel),
+            "-cpu".into(),
+            "host".into(),
+            "-smp".into(),
+            config.vm_cpus().to_string(),
+            "-m".into(),
+            format!("{}M", config.vm_memory().bytes() >> 20),
+            "-display".into(),
+            "none".into(),
+            "-serial".into(),
+            "stdio".into(),
+            "-drive".into(),
+            format!("if=virtio,format=qcow2,file={}", disk.display()),
+            "-drive".into(),
+            format!("if=virtio,format=raw,readonly=on,file={}", seed.display()),
+            "-netdev".into(),
+            format!(
+                "user,id=net0,hostfwd=tcp:{0}:{1}-:22,hostfwd=tcp:{0}:{2}-:{2}",
+                Ipv4Addr::LOCALHOST,
+                ssh_port,
+                config.ports().apiserver()
+            ),
+            "-device".into(),
+            "virtio-net-pci,netdev=net0".into(),
+        ];
+        if ARCH == "aarch64" {
+            args.push("-bios".into());
+            args.push("edk2-aarch64-code.fd".into());
+        }
+
+        let qemu = Kubernix::find_executable(format!("qemu-system-{}", ARCH))?;
+        let process = Process::builder(config, &dir, &qemu.display().to_string())
+            .name("qemu")
+            .args(&args)
+            .ready_on("login:")
+            .timeout(Self::BOOT_TIMEOUT)
+            .spawn()?;
+        Ok(Vm {
+            process,
+            dir,
+            ssh_port,
+        })
+    }
+
+    /// Create the cloud-init seed, which authorizes the SSH key of the root
+    fn seed(dir: &Path) -> Fallible<PathBuf> {
+        let key = dir.join("id_ed25519");
+        if !key.exists() {
+            let output = Command::new(Kubernix::find_executable("ssh-keygen")?)
+                .arg("-q")
+                .arg("-t")
+                .arg("ed25519")
+                .arg("-N")
+                .arg("")
+                .arg("-f")
+                .arg(&key)
+                .output()?;
+            if !output.status.success() {
+                bail!("Unable to create SSH key for the VM");
+            }
+        }
+
+        fs::write(
+            dir.join("user-data"),
+            format!(
+                "#cloud-config\ndisable_root: false\nusers:\n  - name: root\n    \
+                 ssh_authorized_keys:\n      - {}\n",
+                fs::read_to_string(key.with_extension("pub"))?.trim()
+            ),
+        )?;
+        fs::write(
+            dir.join("meta-data"),
+            "instance-id: kubernix\nlocal-hostname: kubernix\n",
+        )?;
+
+        let seed = dir.join("seed.iso");
+        let output = Command::new(Kubernix::find_executable("mkisofs")?)
+            .current_dir(dir)
+            .arg("-output")
+            .arg(&seed)
+            .arg("-volid")
+            .arg("cidata")
+            .arg("-joliet")
+            .arg("-rock")
+            .arg("user-data")
+            .arg("meta-data")
+            .output()?;
+        if !output.status.success() {
+            debug!("mkisofs stderr: {}", String::from_utf8(output.stderr)?);
+            bail!("Unable to create cloud-init seed for the VM");
+        }
+        Ok(seed)
+    }
+
+    /// Copy the configuration into the VM, whereas the root is replaced and
+    /// the VM backend disabled
+    fn copy_config(&self, config: &Config) -> Fallible<()> {
+        let mut value = Value::try_from(config)?;
+        if let Some(table) = value.as_table_mut() {
+            table.insert("root".into(), Value::String(Self::GUEST_ROOT.into()));
+            table.insert("vm".into(), Value::Boolean(false));
+        }
+        let file = self.dir.join(Config::FILENAME);
+        fs::write(&file, toml::to_string(&value)?)?;
+
+        let status = self
+            .ssh()
+            .arg(format!(
+                "mkdir -p {0} && cat > {0}/{1}",
+                Self::GUEST_ROOT,
+                Config::FILENAME
+            ))
+            .stdin(File::open(&file)?)
+            .status()?;
+        if !status.success() {
+            bail!("Unable to copy the configuration into the VM")
+        }
+        Ok(())
+    }
+
+    /// Wait until the VM accepts SSH connections
+    fn wait_ssh(&self) -> Fallible<()> {
+        debug!("Waiting for SSH on port {}", self.ssh_port);
+        let now = Instant::now();
+        while now.elapsed() < Self::BOOT_TIMEOUT {
+            let status = self
+                .ssh()
+                .arg("true")
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()?;
+            if status.success() {
+                return Ok(());
+            }
+            sleep(Duration::from_secs(2));
+        }
+        bail!("VM did not accept SSH connections in time")
+    }
+
+    /// Periodically try to fetch the admin kubeconfig of the VM, which gets
+    /// rewritten to the forwarded API Server port of the host
+    fn fetch_kubeconfig(ssh: &[String], target: &Path, port: u16, done: &AtomicBool) {
+        let remote = Path::new(Self::GUEST_ROOT)
+            .join(KubeConfig::DIR)
+            .join("admin.kubeconfig");
+        while !done.load(Ordering::SeqCst) {
+            sleep(Duration::from_secs(2));
+            let output = match Command::new("ssh")
+                .args(ssh)
+                .arg(format!("root@{}", Ipv4Addr::LOCALHOST))
+                .arg(format!("cat {}", remote.display()))
+                .stderr(Stdio::null())
+                .output()
+            {
+                Ok(x) if x.status.success() => x,
+                _ => continue,
+            };
+
+            let kubeconfig = Self::rewrite_server(&String::from_utf8_lossy(&output.stdout), port);
+            let result = target
+                .parent()
+                .map_or(Ok(()), create_dir_all)
+                .and_then(|_| fs::write(target, kubeconfig));
+            match result {
+                Ok(()) => info!(
+                    "The cluster is reachable from the host via \
+                     `export KUBECONFIG={}`",
+                    target.display()
+                ),
+                Err(e) => warn!("Unable to write kubeconfig of the VM: {}", e),
+            }
+            return;
+        }
+    }
+
+    /// Point all servers of the kubeconfig to the forwarded API Server port
+    fn rewrite_server(kubeconfig: &str, port: u16) -> String {
+        kubeconfig
+            .lines()
+            .map(|x| match x.find("server:") {
+                Some(i) => format!(
+                    "{}server: https://{}:{}",
+                    &x[..i],
+                    Ipv4Addr::LOCALHOST,
+                    port
+                ),
+                None => x.to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Retrieve the kubernix closure to be copied into the VM
+    fn closure(config: &Config) -> Fallible<PathBuf> {
+        if let Some(closure) = config.vm_closure() {
+            return Ok(closure.clone());
+        }
+        if !cfg!(target_os = "linux") {
+            bail!("Please provide a Linux build of kubernix via --vm-closure")
+        }
+
+        // The executable looks like /nix/store/<hash>-kubernix/bin/kubernix
+        let exe = canonicalize(current_exe()?)?;
+        let closure = exe.components().take(4).collect::<PathBuf>();
+        if !closure.starts_with("/nix/store") || closure.components().count() != 4 {
+            bail!("kubernix is not installed via Nix, please provide --vm-closure")
+        }
+        Ok(closure)
+    }
+
+    /// The QEMU machine type of the host architecture
+    fn machine() -> &'static str {
+        if ARCH == "aarch64" {
+            "virt"
+        } else {
+            "q35"
+        }
+    }
+
+    /// The SSH options to reach the VM
+    fn ssh_opts(&self) -> Vec<String> {
+        vec![
+            "-i".into(),
+            self.dir.join("id_ed25519").display().to_string(),
+            "-p".into(),
+            self.ssh_port.to_string(),
+            "-o".into(),
+            "StrictHostKeyChecking=no".into(),
+            "-o".into(),
+            "UserKnownHostsFile=/dev/null".into(),
+            "-o".into(),
+            "LogLevel=ERROR".into(),
+        ]
+    }
+
+    /// Create a SSH command to the root user of the VM
+    fn ssh(&self) -> Command {
+        let mut cmd = Command::new("ssh");
+        cmd.args(self.ssh_opts())
+            .arg(format!("root@{}", Ipv4Addr::LOCALHOST));
+        cmd
+    }
+}
+
+impl Drop for Vm {
+    fn drop(&mut self) {
+        info!("Shutting down VM");
+        let status = self
+            .ssh()
+            .arg("poweroff")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        if status.is_ok() {
+            sleep(Duration::from_secs(5));
+        }
+        if let Err(e) = self.process.stop() {
+            debug!("{}", e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrite_server_success() {
+        let kubeconfig =
+            "clusters:\n- cluster:\n    server: https://10.0.2.15:6443\n  name: kubernix";
+        assert_eq!(
+            Vm::rewrite_server(kubeconfig, 6443),
+            "clusters:\n- cluster:\n    server: https://127.0.0.1:6443\n  name: kubernix"
+        );
+    }
+}
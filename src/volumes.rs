@@ -0,0 +1,60 @@
+use crate::{config::Config, kubeconfig::KubeConfig, process::Process, Fallible};
+use log::info;
+use std::{
+    fs::{self, canonicalize, create_dir_all},
+    path::PathBuf,
+};
+
+/// The persistent volume area outside of the root directory
+pub struct Volumes;
+
+impl Volumes {
+    /// Deploy the local path provisioner, which stores the volumes below the
+    /// configured directory
+    pub fn apply(config: &Config, kubeconfig: &KubeConfig) -> Fallible<()> {
+        if !*config.persist_volumes() {
+            return Ok(());
+        }
+        let volumes_dir = Self::dir(config)?;
+        info!(
+            "Deploying local path provisioner for '{}'",
+            volumes_dir.display()
+        );
+
+        let dir = config.root().join("volumes");
+        create_dir_all(&dir)?;
+        let yml = format!(include_str!("assets/localpath.yml"), volumes_dir.display());
+        let yml_file = dir.join("localpath.yml");
+        fs::write(&yml_file, yml)?;
+
+        Process::run_to_completion("kubectl")
+            .arg("apply")
+            .arg(format!("--kubeconfig={}", kubeconfig.admin().display()))
+            .arg("-f")
+            .arg(yml_file)
+            .retries(3)
+            .run()?;
+        info!("Local path provisioner deployed");
+        Ok(())
+    }
+
+    /// The absolute volumes directory, which must not be part of the root
+    fn dir(config: &Config) -> Fallible<PathBuf> {
+        create_dir_all(config.volumes_dir())?;
+        let dir = canonicalize(config.volumes_dir()).map_err(|e| {
+            format_err!(
+                "Unable to canonicalize volumes directory '{}': {}",
+                config.volumes_dir().display(),
+                e
+            )
+        })?;
+        if dir.starts_with(config.root()) {
+            bail!(
+                "Volumes directory '{}' must not be part of the root '{}'",
+                dir.display(),
+                config.root().display()
+            )
+        }
+        Ok(dir)
+    }
+}